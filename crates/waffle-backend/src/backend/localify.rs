@@ -1,5 +1,12 @@
 //! Localification: a simple form of register allocation that picks
 //! locations for SSA values in Wasm locals.
+//!
+//! Each value's live range is an interval over the blocks laid out
+//! in RPO, and the intervals are colored by a linear scan that frees
+//! a value's locals at the end of its range. Values of the same type
+//! whose ranges do not overlap thus share a local, and the number of
+//! locals of each type is the most values of that type live at any
+//! one point. Function parameters keep their own locals.
 use crate::backend::treeify::Trees;
 use crate::CFGInfo;
 use crate::{EntityVec, PerEntity};
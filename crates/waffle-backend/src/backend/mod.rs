@@ -2,12 +2,15 @@
 use crate::CFGInfo;
 use crate::EntityRef;
 use crate::{ExportKind, FuncDecl, FunctionBody, ImportKind, Module, Type, Value, ValueDef};
-use crate::{HeapType, Operator, WithNullable};
+use crate::{DataSegment, HeapType, Operator, WithNullable};
 use anyhow::Result;
 use wax_core::build::InstructionSink;
 // use rayon::prelude::*;
 use alloc::borrow::Cow;
 use alloc::boxed::Box;
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+use alloc::sync::Arc;
 use alloc::vec;
 use alloc::vec::Vec;
 use waffle_ir::SignatureExt;
@@ -23,9 +26,50 @@ pub mod treeify;
 use treeify::Trees;
 pub mod localify;
 use localify::Localifier;
+/// A predicate choosing which operators the backend re-emits at
+/// every use rather than computing once into a local.
+pub type RematPredicate = Arc<dyn Fn(&Operator) -> bool + Send + Sync>;
+/// Options controlling how a function body is lowered to Wasm.
+#[derive(Clone)]
+pub struct BackendOptions {
+    /// Re-emit `iNN.const`/`fNN.const` operators at every use rather
+    /// than computing them once into a local. Turning this off can
+    /// shrink code that reuses large constants.
+    pub rematerialize_constants: bool,
+    /// When `rematerialize_constants` is off, constants whose
+    /// immediate encodes in at most this many bytes are rematerialized
+    /// anyway, as a `local.get` would be no smaller.
+    pub remat_size_threshold: usize,
+    /// If set, decides which operators are rematerialized instead of
+    /// the two options above. It is only consulted for operators with
+    /// no arguments and exactly one result, and must only accept
+    /// operators that produce the same value wherever they are
+    /// re-emitted, such as `global.get` of an immutable global or
+    /// `ref.func`.
+    pub remat: Option<RematPredicate>,
+}
+impl Default for BackendOptions {
+    fn default() -> Self {
+        BackendOptions {
+            rematerialize_constants: true,
+            remat_size_threshold: 1,
+            remat: None,
+        }
+    }
+}
+impl core::fmt::Debug for BackendOptions {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("BackendOptions")
+            .field("rematerialize_constants", &self.rematerialize_constants)
+            .field("remat_size_threshold", &self.remat_size_threshold)
+            .field("remat", &self.remat.as_ref().map(|_| "<predicate>"))
+            .finish()
+    }
+}
 pub struct WasmFuncBackend<'a> {
     body: Cow<'a, FunctionBody>,
     cfg: CFGInfo,
+    options: BackendOptions,
 }
 struct CompileContext<'a> {
     trees: Trees,
@@ -39,21 +83,55 @@ macro_rules! op {
 }
 impl<'a> WasmFuncBackend<'a> {
     pub fn compile(body: &'a FunctionBody) -> Result<wasm_encoder::Function> {
+        Self::new_with_options(body, BackendOptions::default())?.lower()
+    }
+    /// Prepare `body` for lowering with the given options; call
+    /// `lower()` on the result to produce the Wasm function.
+    pub fn new_with_options(body: &'a FunctionBody, options: BackendOptions) -> Result<Self> {
         body.validate()?;
         log::debug!("Backend compiling:\n{}\n", body.display_verbose("| ", None));
         // For ownership reasons (to avoid a self-referential struct
         // with the `Cow::Owned` case when the Reducifier modifies the
-        // body), we have to run the Reducifier first, own its result
-        // in this stack frame, then construct the `WasmFuncBackend`
-        // state and run the rest of the compilation in `lower()`.
+        // body), we have to run the Reducifier first and own its
+        // result, then run the rest of the compilation in `lower()`.
         let body = Reducifier::new(body).run();
         let cfg = CFGInfo::new(&body);
-        let state = WasmFuncBackend { body, cfg };
-        state.lower()
+        Ok(WasmFuncBackend { body, cfg, options })
+    }
+    /// Like `compile`, but also return a name for each Wasm local,
+    /// taken from the SSA value it was allocated for, suitable for
+    /// the name section's local-name subsection.
+    pub fn compile_with_local_names(
+        body: &'a FunctionBody,
+    ) -> Result<(wasm_encoder::Function, Vec<(u32, String)>)> {
+        Self::new_with_options(body, BackendOptions::default())?.lower_with_local_names()
     }
     pub fn lower(&self) -> Result<wasm_encoder::Function> {
+        self.lower_with_local_names().map(|(func, _)| func)
+    }
+    fn local_names(&self, locals: &Localifier) -> Vec<(u32, String)> {
+        let mut names: Vec<Option<String>> = vec![None; locals.locals.len()];
+        for value in self.body.values.iter() {
+            let value_locals = &locals.values[value];
+            for (i, &local) in value_locals.iter().enumerate() {
+                if names[local.index()].is_none() {
+                    names[local.index()] = Some(if value_locals.len() == 1 {
+                        format!("{}", value)
+                    } else {
+                        format!("{}.{}", value, i)
+                    });
+                }
+            }
+        }
+        names
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, name)| Some((i as u32, name?)))
+            .collect()
+    }
+    fn lower_with_local_names(&self) -> Result<(wasm_encoder::Function, Vec<(u32, String)>)> {
         log::debug!("CFG:\n{:?}\n", self.cfg);
-        let trees = Trees::compute(&self.body);
+        let trees = Trees::compute_with_options(&self.body, &self.options);
         log::debug!("Trees:\n{:?}\n", trees);
         let ctrl = StackifyContext::new(&self.body, &self.cfg)?.compute();
         log::debug!("Ctrl:\n{:?}\n", ctrl);
@@ -87,14 +165,14 @@ impl<'a> WasmFuncBackend<'a> {
         }
         func.instruction(&wasm_encoder::Instruction::End);
         log::debug!("Compiled to:\n{:?}\n", func);
-        Ok(func)
+        Ok((func, self.local_names(&ctx.locals)))
     }
     pub fn lower_to_sink<Ctx, E, S>(&self, sctx: &mut Ctx, sink: &mut S) -> Result<()>
     where
         E: Into<anyhow::Error>,
         S: InstructionSink<Ctx, E>,
     {
-        let trees = Trees::compute(&self.body);
+        let trees = Trees::compute_with_options(&self.body, &self.options);
         let ctrl = StackifyContext::new(&self.body, &self.cfg)?.compute();
         let locals = Localifier::compute(&self.body, &self.cfg, &trees);
         let ctx = CompileContext { trees, ctrl, locals };
@@ -648,6 +726,11 @@ impl<'a> WasmFuncBackend<'a> {
             Operator::MemoryFill { mem } => {
                 Some(wasm_encoder::Instruction::MemoryFill(mem.index() as u32))
             }
+            Operator::MemoryInit { mem, data_idx } => Some(wasm_encoder::Instruction::MemoryInit {
+                mem: mem.index() as u32,
+                data_index: *data_idx,
+            }),
+            Operator::DataDrop { data_idx } => Some(wasm_encoder::Instruction::DataDrop(*data_idx)),
             Operator::V128Load { memory } => Some(wasm_encoder::Instruction::V128Load(
                 wasm_encoder::MemArg::from(*memory),
             )),
@@ -1417,13 +1500,17 @@ where
     E: Into<anyhow::Error>,
     S: InstructionSink<Ctx, E>,
 {
-    body.validate()?;
-    let body = Reducifier::new(body).run();
-    let cfg = CFGInfo::new(&body);
-    let state = WasmFuncBackend { body, cfg };
-    state.lower_to_sink(sctx, sink)
+    WasmFuncBackend::new_with_options(body, BackendOptions::default())?.lower_to_sink(sctx, sink)
 }
 pub fn compile(module: &Module<'_>) -> anyhow::Result<wasm_encoder::Module> {
+    compile_inner(module, false)
+}
+/// Like `compile`, but additionally name the locals of each function
+/// compiled from IR in the name section.
+pub fn compile_with_local_names(module: &Module<'_>) -> anyhow::Result<wasm_encoder::Module> {
+    compile_inner(module, true)
+}
+fn compile_inner(module: &Module<'_>, local_names: bool) -> anyhow::Result<wasm_encoder::Module> {
     let mut into_mod = wasm_encoder::Module::new();
     let mut types = wasm_encoder::TypeSection::new();
     let recurses = module.signatures.iter().any(|s| s.is_backref(module));
@@ -1568,7 +1655,7 @@ pub fn compile(module: &Module<'_>) -> anyhow::Result<wasm_encoder::Module> {
         });
     }
     into_mod.section(&memories);
-    if module.control_tags.len() != 0 {
+    if module.control_tags.len() > num_tag_imports {
         let mut tags = wasm_encoder::TagSection::new();
         for tag_data in module.control_tags.values().skip(num_tag_imports) {
             tags.tag(TagType {
@@ -1675,6 +1762,52 @@ pub fn compile(module: &Module<'_>) -> anyhow::Result<wasm_encoder::Module> {
         }
     }
     into_mod.section(&elem);
+    // Data segments keep their indices (which `memory.init`,
+    // `data.drop` and the array data operators refer to); active
+    // segments not listed in `data_segments` follow them.
+    let mut data = wasm_encoder::DataSection::new();
+    let mut listed = BTreeSet::new();
+    for segment in &module.data_segments {
+        match segment {
+            DataSegment::Active { memory, segment } => {
+                let Some(mem_segment) = module
+                    .memories
+                    .get(*memory)
+                    .and_then(|mem_data| mem_data.segments.get(*segment))
+                else {
+                    anyhow::bail!(
+                        "data segment refers to missing {} segment {}",
+                        memory,
+                        segment
+                    );
+                };
+                data.active(
+                    memory.index() as u32,
+                    &wasm_encoder::ConstExpr::i32_const(mem_segment.offset as i32),
+                    mem_segment.data.iter().copied(),
+                );
+                listed.insert((*memory, *segment));
+            }
+            DataSegment::Passive { data: bytes } => {
+                data.passive(bytes.iter().copied());
+            }
+        }
+    }
+    for (mem, mem_data) in module.memories.entries() {
+        for (i, segment) in mem_data.segments.iter().enumerate() {
+            if listed.contains(&(mem, i)) {
+                continue;
+            }
+            data.active(
+                mem.index() as u32,
+                &wasm_encoder::ConstExpr::i32_const(segment.offset as i32),
+                segment.data.iter().copied(),
+            );
+        }
+    }
+    if !module.data_segments.is_empty() {
+        into_mod.section(&wasm_encoder::DataCountSection { count: data.len() });
+    }
     let mut code = wasm_encoder::CodeSection::new();
     let bodies = module
         .funcs
@@ -1687,32 +1820,28 @@ pub fn compile(module: &Module<'_>) -> anyhow::Result<wasm_encoder::Module> {
                 #[cfg(feature = "frontend")]
                 FuncDecl::Lazy(_, _name, reader) => {
                     let data = &module.orig_bytes.unwrap()[reader.range()];
-                    Ok(Cow::Borrowed(data))
+                    Ok((Cow::Borrowed(data), vec![]))
+                }
+                FuncDecl::Compiled(_, _name, bytes) => Ok((Cow::Borrowed(&bytes[..]), vec![])),
+                FuncDecl::Body(_, name, body) if local_names => {
+                    log::debug!("Compiling {} \"{}\"", func, name);
+                    WasmFuncBackend::compile_with_local_names(body)
+                        .map(|(func, names)| (Cow::Owned(func.into_raw_body()), names))
                 }
-                FuncDecl::Compiled(_, _name, bytes) => Ok(Cow::Borrowed(&bytes[..])),
                 FuncDecl::Body(_, name, body) => {
                     log::debug!("Compiling {} \"{}\"", func, name);
-                    WasmFuncBackend::compile(body).map(|func| Cow::Owned(func.into_raw_body()))
+                    WasmFuncBackend::compile(body)
+                        .map(|func| (Cow::Owned(func.into_raw_body()), vec![]))
                 }
                 FuncDecl::Import(_, _) => unreachable!("Should have skipped imports"),
                 f => panic!("{f:?} at compilation time"),
             }
         })
         .collect::<Result<Vec<_>>>()?;
-    for body in bodies {
-        code.raw(&body);
+    for (body, _) in bodies.iter() {
+        code.raw(body);
     }
     into_mod.section(&code);
-    let mut data = wasm_encoder::DataSection::new();
-    for (mem, mem_data) in module.memories.entries() {
-        for segment in &mem_data.segments {
-            data.active(
-                mem.index() as u32,
-                &wasm_encoder::ConstExpr::i32_const(segment.offset as i32),
-                segment.data.iter().copied(),
-            );
-        }
-    }
     into_mod.section(&data);
     let mut names = wasm_encoder::NameSection::new();
     let mut func_names = wasm_encoder::NameMap::new();
@@ -1720,6 +1849,20 @@ pub fn compile(module: &Module<'_>) -> anyhow::Result<wasm_encoder::Module> {
         func_names.append(func.index() as u32, decl.name());
     }
     names.functions(&func_names);
+    if local_names {
+        let mut locals = wasm_encoder::IndirectNameMap::new();
+        for (i, (_, local_names)) in bodies.iter().enumerate() {
+            if local_names.is_empty() {
+                continue;
+            }
+            let mut map = wasm_encoder::NameMap::new();
+            for (local, name) in local_names {
+                map.append(*local, name);
+            }
+            locals.append((num_func_imports + i) as u32, &map);
+        }
+        names.locals(&locals);
+    }
     into_mod.section(&names);
     for (n, e) in module.custom_sections.iter() {
         into_mod.section(&CustomSection {
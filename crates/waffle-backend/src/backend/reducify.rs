@@ -317,7 +317,7 @@ impl<'a> Reducifier<'a> {
                     let def = new_body.values[value.value].clone();
                     let new_value = new_body.values.push(def);
                     value_map.insert((ctx, value.value), new_value);
-                    new_body.blocks[new_block].insts.push({
+                    new_body.append_record_to_block(new_block, {
                         let mut value = value.clone();
                         value.value = new_value;
                         value
@@ -244,6 +244,8 @@ impl<'a, 'b> Context<'a, 'b> {
         let mut merge_node_children = self
             .cfg
             .dom_children(block)
+            .iter()
+            .copied()
             .filter(|child| self.merge_nodes.contains(child))
             .collect::<Vec<_>>();
         // Sort merge nodes so highest RPO number comes first.
@@ -1,8 +1,9 @@
 //! Treeification: placing some values "under" others if only used
 //! once, to generate more AST-like Wasm code.
 use crate::EntityRef;
-use crate::{Block, FunctionBody, Value, ValueDef};
+use super::BackendOptions;
 use crate::Operator;
+use crate::{Block, FunctionBody, Value, ValueDef};
 use alloc::vec;
 use alloc::vec::Vec;
 use core::convert::TryFrom;
@@ -21,18 +22,33 @@ pub struct Trees {
     /// Values that are regenerated every time they are used.
     pub remat: HashSet<Value>,
 }
-fn is_remat(op: &Operator) -> bool {
-    // Only ops with no args can be always-rematerialized.
-    match op {
-        Operator::I32Const { .. }
-        | Operator::I64Const { .. }
-        | Operator::F32Const { .. }
-        | Operator::F64Const { .. } => true,
-        _ => false,
+fn is_remat(op: &Operator, options: &BackendOptions) -> bool {
+    if let Some(remat) = &options.remat {
+        return remat(op);
     }
+    let size = match op {
+        Operator::I32Const { value } => sleb128_len(*value as i32 as i64),
+        Operator::I64Const { value } => sleb128_len(*value as i64),
+        Operator::F32Const { .. } => 4,
+        Operator::F64Const { .. } => 8,
+        _ => return false,
+    };
+    options.rematerialize_constants || size <= options.remat_size_threshold
+}
+/// The number of bytes in the signed LEB128 encoding of `value`.
+fn sleb128_len(mut value: i64) -> usize {
+    let mut len = 1;
+    while !(-64..64).contains(&value) {
+        value >>= 7;
+        len += 1;
+    }
+    len
 }
 impl Trees {
     pub fn compute(body: &FunctionBody) -> Trees {
+        Self::compute_with_options(body, &BackendOptions::default())
+    }
+    pub fn compute_with_options(body: &FunctionBody, options: &BackendOptions) -> Trees {
         let mut owner = HashMap::default();
         let mut owned = HashMap::default();
         let mut remat = HashSet::default();
@@ -41,15 +57,19 @@ impl Trees {
             let mut last_non_pure = None;
             for value in &block_def.insts {
                 match &body.values[value.value] {
-                    &ValueDef::Operator(op, args, _) => {
+                    &ValueDef::Operator(op, args, tys) => {
                         // Ignore operators with invalid args: these must
                         // always be unreachable.
                         if body.arg_pool[args].iter().any(|arg| arg.is_invalid()) {
                             continue;
                         }
-                        // If this is an always-rematerialized operator,
-                        // mark it as such and continue.
-                        if is_remat(&op) {
+                        // If this is an always-rematerialized operator
+                        // (only ops with no args and a single result
+                        // can be), mark it as such and continue.
+                        if body.arg_pool[args].is_empty()
+                            && body.type_pool[tys].len() == 1
+                            && is_remat(&op, options)
+                        {
                             remat.insert(value.value);
                             continue;
                         }
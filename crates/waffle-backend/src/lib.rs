@@ -25,6 +25,12 @@ pub fn to_wasm_bytes(module: &Module<'_>) -> Result<Vec<u8>> {
     backend::compile(module).map(|m| m.finish())
 }
 
+/// Compile a WAFFLE Module to Wasm bytes, naming the locals of IR
+/// function bodies in the name section as well as the functions.
+pub fn to_wasm_bytes_with_names(module: &Module<'_>) -> Result<Vec<u8>> {
+    backend::compile_with_local_names(module).map(|m| m.finish())
+}
+
 /// Compile a WAFFLE Module to a wasm_encoder::Module.
 pub fn to_encoded_module(module: &Module<'_>) -> Result<wasm_encoder::Module> {
     backend::compile(module)
@@ -35,6 +41,9 @@ pub trait ModuleExt<'a>{
     fn to_wasm_bytes(&self) -> Result<Vec<u8>>{
         return to_wasm_bytes(self.module());
     }
+    fn to_wasm_bytes_with_names(&self) -> Result<Vec<u8>>{
+        return to_wasm_bytes_with_names(self.module());
+    }
     fn to_encoded_module(&self) -> Result<wasm_encoder::Module>{
         return to_encoded_module(self.module());
     }
@@ -48,9 +57,391 @@ impl<'a> ModuleExt<'a> for Module<'a>{
 #[cfg(test)]
 mod test {
     use super::*;
+    use alloc::borrow::ToOwned;
+    use core::convert::TryFrom;
     #[test]
     fn empty_module_valid() {
         let module = Module::empty();
         let _ = module.to_wasm_bytes().unwrap();
     }
+
+    #[test]
+    fn builder_add_compiles() {
+        let mut module = Module::empty();
+        let sig = util::new_sig(
+            &mut module,
+            SignatureData::Func {
+                params: vec![Type::I32, Type::I32],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+        let mut b = FunctionBuilder::new(&module, sig);
+        let entry = b.entry();
+        let params = b.params(entry);
+        let sum = b.push_op(entry, Operator::I32Add, &params, &[Type::I32]);
+        b.ret(entry, &[sum]);
+        let body = b.finish();
+        body.validate().unwrap();
+        let add = module
+            .funcs
+            .push(FuncDecl::Body(sig, "add".to_owned(), body));
+        module.exports.push(Export {
+            name: "add".to_owned(),
+            kind: ExportKind::Func(add),
+        });
+
+        let bytes = module.to_wasm_bytes().unwrap();
+        let mut saw_add = false;
+        for payload in wasmparser::Parser::new(0).parse_all(&bytes) {
+            if let wasmparser::Payload::CodeSectionEntry(body) = payload.unwrap() {
+                for op in body.get_operators_reader().unwrap() {
+                    saw_add |= matches!(op.unwrap(), wasmparser::Operator::I32Add);
+                }
+            }
+        }
+        assert!(saw_add);
+    }
+
+    #[test]
+    fn programmatic_module_compiles() {
+        let mut module = Module::empty();
+        let sig = module.add_signature(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        let again = module.add_signature(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        assert_eq!(sig, again);
+        let mem = module.add_memory(MemoryData {
+            initial_pages: 1,
+            maximum_pages: None,
+            segments: vec![],
+            memory64: false,
+            shared: false,
+            page_size_log2: None,
+        });
+        let table = module.add_table(TableData {
+            ty: Type::Heap(WithNullable {
+                value: HeapType::FuncRef,
+                nullable: true,
+            }),
+            initial: 1,
+            max: None,
+            func_elements: Some(vec![]),
+            table64: false,
+        });
+        let global = module.add_global(GlobalData {
+            ty: Type::I32,
+            value: Some(7),
+            mutable: true,
+        });
+
+        let mut b = FunctionBuilder::new(&module, sig);
+        let entry = b.entry();
+        let x = b.params(entry)[0];
+        let g = b.push_op(
+            entry,
+            Operator::GlobalGet {
+                global_index: global,
+            },
+            &[],
+            &[Type::I32],
+        );
+        let sum = b.push_op(entry, Operator::I32Add, &[x, g], &[Type::I32]);
+        b.ret(entry, &[sum]);
+        let f = module.add_func(FuncDecl::Body(sig, "f".to_owned(), b.finish()));
+        module.tables[table].func_elements.as_mut().unwrap().push(f);
+        for (name, kind) in [
+            ("f", ExportKind::Func(f)),
+            ("mem", ExportKind::Memory(mem)),
+            ("table", ExportKind::Table(table)),
+            ("global", ExportKind::Global(global)),
+        ] {
+            module.exports.push(Export {
+                name: name.to_owned(),
+                kind,
+            });
+        }
+        module.validate().unwrap();
+
+        let bytes = module.to_wasm_bytes().unwrap();
+        let mut exports = vec![];
+        for payload in wasmparser::Parser::new(0).parse_all(&bytes) {
+            if let wasmparser::Payload::ExportSection(reader) = payload.unwrap() {
+                for export in reader {
+                    exports.push(export.unwrap().name.to_owned());
+                }
+            }
+        }
+        assert_eq!(exports, ["f", "mem", "table", "global"]);
+    }
+
+    /// Compile a function consisting of the single operator `op`
+    /// applied to the function's params, and check that the emitted
+    /// bytecode decodes back to the same operator.
+    fn check_emission(module: &mut Module, op: Operator, params: &[Type], rets: &[Type]) {
+        let sig = util::new_sig(
+            module,
+            SignatureData::Func {
+                params: params.to_vec(),
+                returns: rets.to_vec(),
+                shared: false,
+            },
+        );
+        let mut body = FunctionBody::new(module, sig);
+        let args = body.blocks[body.entry]
+            .params
+            .iter()
+            .map(|&(_, v)| v)
+            .collect::<Vec<_>>();
+        let v = body.add_op(body.entry, op.clone(), &args, rets);
+        let values = if rets.is_empty() { vec![] } else { vec![v] };
+        body.set_terminator(body.entry, Terminator::Return { values });
+        let bytes = WasmFuncBackend::compile(&body).unwrap().into_raw_body();
+        let reader = wasmparser::FunctionBody::new(wasmparser::BinaryReader::new(&bytes, 0));
+        let decoded = reader
+            .get_operators_reader()
+            .unwrap()
+            .into_iter()
+            .filter_map(|o| Operator::try_from(&o.unwrap()).ok())
+            .collect::<Vec<_>>();
+        assert!(
+            decoded.contains(&op),
+            "{} did not round-trip; decoded {:?}",
+            op,
+            decoded
+        );
+    }
+
+    #[test]
+    fn gc_and_atomic_emission() {
+        let mut module = Module::empty();
+        let i32_field = WithMutablility {
+            value: StorageType::Val(Type::I32),
+            mutable: true,
+        };
+        let st = util::new_sig(
+            &mut module,
+            SignatureData::Struct {
+                fields: vec![i32_field],
+                shared: false,
+            },
+        );
+        let arr = util::new_sig(
+            &mut module,
+            SignatureData::Array {
+                ty: i32_field,
+                shared: false,
+            },
+        );
+        let ref_of = |sig_index| {
+            Type::Heap(WithNullable {
+                value: HeapType::Sig { sig_index },
+                nullable: true,
+            })
+        };
+        let memarg = MemoryArg {
+            align: 2,
+            offset: 8,
+            memory: Memory::new(0),
+        };
+        check_emission(
+            &mut module,
+            Operator::StructNew { sig: st },
+            &[Type::I32],
+            &[ref_of(st)],
+        );
+        check_emission(
+            &mut module,
+            Operator::ArrayGet { sig: arr },
+            &[ref_of(arr), Type::I32],
+            &[Type::I32],
+        );
+        check_emission(
+            &mut module,
+            Operator::I32AtomicRmwCmpxchg { memarg },
+            &[Type::I32, Type::I32, Type::I32],
+            &[Type::I32],
+        );
+        check_emission(
+            &mut module,
+            Operator::MemoryAtomicWait32 { memarg },
+            &[Type::I32, Type::I32, Type::I64],
+            &[Type::I32],
+        );
+    }
+
+    #[test]
+    fn constants_reused_through_local() {
+        let mut module = Module::empty();
+        let sig = util::new_sig(
+            &mut module,
+            SignatureData::Func {
+                params: vec![Type::I64],
+                returns: vec![Type::I64],
+                shared: false,
+            },
+        );
+        let mut b = FunctionBuilder::new(&module, sig);
+        let entry = b.entry();
+        let x = b.params(entry)[0];
+        let big = b.const_i64(entry, 0x1234_5678_9abc_def0);
+        let y = b.push_op(entry, Operator::I64Add, &[x, big], &[Type::I64]);
+        let y = b.push_op(entry, Operator::I64Mul, &[y, big], &[Type::I64]);
+        let y = b.push_op(entry, Operator::I64Xor, &[y, big], &[Type::I64]);
+        b.ret(entry, &[y]);
+        let body = b.finish();
+
+        let compile = |rematerialize_constants| {
+            let options = BackendOptions {
+                rematerialize_constants,
+                ..BackendOptions::default()
+            };
+            let func = WasmFuncBackend::new_with_options(&body, options)
+                .unwrap()
+                .lower()
+                .unwrap();
+            let bytes = func.into_raw_body();
+            let reader = wasmparser::FunctionBody::new(wasmparser::BinaryReader::new(&bytes, 0));
+            let ops = reader
+                .get_operators_reader()
+                .unwrap()
+                .into_iter()
+                .map(|op| Operator::try_from(&op.unwrap()).ok())
+                .collect::<Vec<_>>();
+            let consts = ops
+                .iter()
+                .filter(|op| matches!(op, Some(Operator::I64Const { .. })))
+                .count();
+            (consts, bytes.len())
+        };
+        let (remat_consts, remat_len) = compile(true);
+        let (local_consts, local_len) = compile(false);
+        assert_eq!(remat_consts, 3);
+        assert_eq!(local_consts, 1);
+        assert!(local_len < remat_len);
+    }
+
+    #[test]
+    fn custom_remat_predicate_duplicates_ref_func() {
+        let mut module = Module::empty();
+        let funcref = Type::Heap(WithNullable {
+            value: HeapType::FuncRef,
+            nullable: true,
+        });
+        let sig = util::new_sig(
+            &mut module,
+            SignatureData::Func {
+                params: vec![],
+                returns: vec![funcref, funcref],
+                shared: false,
+            },
+        );
+        let mut b = FunctionBuilder::new(&module, sig);
+        let entry = b.entry();
+        let f = b.push_op(
+            entry,
+            Operator::RefFunc {
+                func_index: Func::new(0),
+            },
+            &[],
+            &[funcref],
+        );
+        b.ret(entry, &[f, f]);
+        let body = b.finish();
+
+        let compile = |remat: Option<RematPredicate>| {
+            let options = BackendOptions {
+                remat,
+                ..BackendOptions::default()
+            };
+            let func = WasmFuncBackend::new_with_options(&body, options)
+                .unwrap()
+                .lower()
+                .unwrap();
+            let bytes = func.into_raw_body();
+            let reader = wasmparser::FunctionBody::new(wasmparser::BinaryReader::new(&bytes, 0));
+            let ops = reader
+                .get_operators_reader()
+                .unwrap()
+                .into_iter()
+                .map(|op| op.unwrap())
+                .collect::<Vec<_>>();
+            let count =
+                |pred: fn(&wasmparser::Operator) -> bool| ops.iter().filter(|op| pred(op)).count();
+            (
+                count(|op| matches!(op, wasmparser::Operator::RefFunc { .. })),
+                count(|op| matches!(op, wasmparser::Operator::LocalGet { .. })),
+            )
+        };
+        assert_eq!(compile(None), (1, 2));
+        let remat: RematPredicate = alloc::sync::Arc::new(|op: &Operator| {
+            op_traits::op_rematerialize(op) || matches!(op, Operator::RefFunc { .. })
+        });
+        assert_eq!(compile(Some(remat)), (2, 0));
+    }
+
+    #[test]
+    fn name_section_round_trip() {
+        let mut module = Module::empty();
+        let sig = util::new_sig(
+            &mut module,
+            SignatureData::Func {
+                params: vec![Type::I32],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+        for name in ["first", "second"].iter() {
+            let mut body = FunctionBody::new(&module, sig);
+            let x = body.blocks[body.entry].params[0].1;
+            let y = body.add_op(body.entry, Operator::I32Popcnt, &[x], &[Type::I32]);
+            body.set_terminator(body.entry, Terminator::Return { values: vec![y] });
+            module.funcs.push(FuncDecl::Body(sig, (*name).into(), body));
+        }
+        let bytes = module.to_wasm_bytes_with_names().unwrap();
+
+        let mut seen_code = false;
+        let mut func_names = vec![];
+        let mut local_funcs = vec![];
+        for payload in wasmparser::Parser::new(0).parse_all(&bytes) {
+            match payload.unwrap() {
+                wasmparser::Payload::CodeSectionStart { .. } => seen_code = true,
+                wasmparser::Payload::CustomSection(reader) => {
+                    if let wasmparser::KnownCustom::Name(names) = reader.as_known() {
+                        assert!(seen_code, "name section precedes code section");
+                        for subsection in names {
+                            match subsection.unwrap() {
+                                wasmparser::Name::Function(map) => {
+                                    for naming in map {
+                                        let naming = naming.unwrap();
+                                        func_names.push((naming.index, naming.name.to_owned()));
+                                    }
+                                }
+                                wasmparser::Name::Local(map) => {
+                                    for naming in map {
+                                        let naming = naming.unwrap();
+                                        assert!(naming.names.count() > 0);
+                                        local_funcs.push(naming.index);
+                                    }
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(
+            func_names,
+            vec![(0, "first".to_owned()), (1, "second".to_owned())]
+        );
+        assert_eq!(local_funcs, vec![0, 1]);
+    }
 }
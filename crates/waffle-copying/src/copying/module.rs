@@ -4,8 +4,8 @@ use crate::arena_traits::IndexAlloc;
 use crate::op_traits::rewrite_mem;
 use crate::util::{add_start, new_sig};
 use crate::{
-    entity::EntityRef, i2x, x2i, ControlTag, ExportKind, Func, FuncDecl, FunctionBody, Global,
-    ImportKind, Memory, Module, Operator, Signature, SignatureData, Table, TableData, Type,
+    entity::EntityRef, i2x, x2i, ControlTag, DataSegment, ExportKind, Func, FuncDecl, FunctionBody,
+    Global, ImportKind, Memory, Module, Operator, Signature, SignatureData, Table, TableData, Type,
     ValueDef,
 };
 use crate::{HeapType, StorageType, WithNullable};
@@ -32,7 +32,12 @@ pub struct State<I, G = bool> {
     cache: HashMap<IKW, ImportKind>,
     fun_cache: BTreeMap<Func, Func>,
     table_cache: BTreeMap<Table, Table>,
+    /// Source tables whose element segments have been copied.
+    elem_cache: BTreeSet<Table>,
     sig_cache: BTreeMap<Signature, Signature>,
+    /// Source data segment indices already copied, and their indices
+    /// in the destination.
+    data_cache: BTreeMap<u32, u32>,
     ub_cache: BTreeMap<Signature, Func>,
     pub importmap: I,
     pub tables: BTreeSet<Table>,
@@ -46,10 +51,12 @@ impl<I, G> State<I, G> {
             cache: Default::default(),
             fun_cache: Default::default(),
             table_cache: Default::default(),
+            elem_cache: Default::default(),
             tables,
             invasive,
             // tm: Default::default(),
             sig_cache: Default::default(),
+            data_cache: Default::default(),
             ub_cache: Default::default(),
         };
     }
@@ -98,6 +105,7 @@ pub fn tree_shake_via(m: &mut Module, i: impl Imports) -> anyhow::Result<()> {
         Box::new(State::new(Box::new(i), BTreeSet::new(), true)),
     );
     s.render_imports()?;
+    s.copy_start()?;
     for x in exports.iter() {
         let i = x2i(x.kind.clone());
         let i = s.translate_import(i)?;
@@ -336,6 +344,67 @@ impl<
         // self.dest.tables[nt] = t;
         return Ok(self.dest.tables.push(t));
     }
+    /// Copy the element segments of the source table `tk` into its
+    /// counterpart in the destination, remapping every function
+    /// through the function cache (copying functions not yet copied).
+    /// Each element lands at its offset in the source table; entries
+    /// already in the destination table stay where they are, and an
+    /// empty source slot never overwrites one. Fails if a slot already
+    /// holds a different function. Returns the destination table.
+    /// Copying the elements of a table more than once has no further
+    /// effect.
+    pub fn copy_elements(&mut self, tk: Table) -> anyhow::Result<Table> {
+        let nt = self.translate_Table(tk)?;
+        if !self.state.elem_cache.insert(tk) {
+            return Ok(nt);
+        }
+        let Some(elements) = self.src.tables[tk].func_elements.clone() else {
+            return Ok(nt);
+        };
+        let mut translated = Vec::with_capacity(elements.len());
+        for f in elements {
+            translated.push(self.translate_Func(f)?);
+        }
+        let dest = self.dest.tables[nt].func_elements.get_or_insert(vec![]);
+        if dest.len() < translated.len() {
+            dest.resize(translated.len(), Func::invalid());
+        }
+        for (i, f) in translated.into_iter().enumerate() {
+            if f == Func::invalid() || dest[i] == f {
+                continue;
+            }
+            if dest[i] != Func::invalid() {
+                anyhow::bail!(
+                    "element {} of {} already holds {}, not {}",
+                    i,
+                    nt,
+                    dest[i],
+                    f
+                );
+            }
+            dest[i] = f;
+        }
+        Ok(nt)
+    }
+    /// Copy data segment `idx` of the source into the destination,
+    /// returning its index there. An active segment is already
+    /// dropped by the time any code can refer to it, so it is copied
+    /// as an empty passive segment; its contents travel with its
+    /// memory.
+    pub fn translate_data(&mut self, idx: u32) -> anyhow::Result<u32> {
+        if let Some(&d) = self.state.data_cache.get(&idx) {
+            return Ok(d);
+        }
+        let segment = match self.src.data_segments.get(idx as usize) {
+            Some(DataSegment::Active { .. }) => DataSegment::Passive { data: vec![] },
+            Some(passive) => passive.clone(),
+            None => anyhow::bail!("missing data segment {}", idx),
+        };
+        let d = self.dest.data_segments.len() as u32;
+        self.dest.data_segments.push(segment);
+        self.state.data_cache.insert(idx, d);
+        Ok(d)
+    }
     pub fn translate_type(&mut self, ty: &mut Type) -> anyhow::Result<()> {
         if let Type::Heap(WithNullable {
             value: HeapType::Sig { sig_index },
@@ -385,6 +454,15 @@ impl<
             self.dest.signatures[k] = d;
         }
     }
+    /// Copy the source module's start function, if any. It becomes
+    /// the destination's start function, or if the destination
+    /// already has one, runs before it from a new wrapper.
+    pub fn copy_start(&mut self) -> anyhow::Result<Option<Func>> {
+        match self.src.start_func() {
+            Some(f) => Ok(Some(self.translate_Func(f)?)),
+            None => Ok(None),
+        }
+    }
     pub fn internal_translate_func(&mut self, f: Func) -> anyhow::Result<Func> {
         {
             if f == Func::invalid() {
@@ -401,9 +479,7 @@ impl<
                     .get_or_insert(vec![])
                     .push(a);
             }
-            if Some(f) == self.src.start_func {
-                add_start(&mut self.dest, a);
-            }
+            let is_start = Some(f) == self.src.start_func();
             let mut f = self.state.invasive.get(&mut self.src, f);
             // let sig = self.translate_sig(f.sig())?;
             if let Some(b) = f.body_mut() {
@@ -471,6 +547,12 @@ impl<
                             crate::Operator::TableSize { table_index } => {
                                 *table_index = self.translate_Table(*table_index)?;
                             }
+                            crate::Operator::MemoryInit { data_idx, .. }
+                            | crate::Operator::DataDrop { data_idx }
+                            | crate::Operator::ArrayNewData { data_idx, .. }
+                            | crate::Operator::ArrayInitData { data_idx, .. } => {
+                                *data_idx = self.translate_data(*data_idx)?;
+                            }
                             _ => {}
                         }
                         *vs = b.arg_pool.from_iter(w.into_iter());
@@ -513,6 +595,12 @@ impl<
                 a => todo!("module copy {a:?}")
             }
             self.dest.funcs[a] = f;
+            if is_start {
+                match self.dest.start_func() {
+                    None => self.dest.set_start_func(Some(a)),
+                    Some(_) => add_start(&mut self.dest, a),
+                }
+            }
             return Ok(a);
         };
     }
@@ -525,4 +613,256 @@ impl<
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Terminator;
+
+    #[test]
+    fn copies_element_segments() {
+        let mut src = Module::empty();
+        let sig = new_sig(
+            &mut src,
+            SignatureData::Func {
+                params: vec![],
+                returns: vec![],
+                shared: false,
+            },
+        );
+        let leaf = |m: &Module, name: &str| {
+            let mut body = FunctionBody::new(m, sig);
+            body.set_terminator(body.entry, Terminator::Return { values: vec![] });
+            FuncDecl::Body(sig, name.into(), body)
+        };
+        let unused = leaf(&src, "unused");
+        src.funcs.push(unused);
+        let target = leaf(&src, "target");
+        let target = src.funcs.push(target);
+        let table = src.tables.push(TableData {
+            ty: Type::Heap(WithNullable {
+                value: HeapType::FuncRef,
+                nullable: true,
+            }),
+            initial: 2,
+            max: None,
+            func_elements: Some(vec![Func::invalid(), target]),
+            table64: false,
+        });
+        let mut body = FunctionBody::new(&src, sig);
+        let idx = body.add_op(
+            body.entry,
+            Operator::I32Const { value: 1 },
+            &[],
+            &[Type::I32],
+        );
+        body.add_op(
+            body.entry,
+            Operator::CallIndirect {
+                sig_index: sig,
+                table_index: table,
+            },
+            &[idx],
+            &[],
+        );
+        body.set_terminator(body.entry, Terminator::Return { values: vec![] });
+        let caller = src.funcs.push(FuncDecl::Body(sig, "caller".into(), body));
+
+        let mut dest = Module::empty();
+        let mut copier = Copier::new(
+            &src,
+            &mut dest,
+            Box::new(State::new(
+                Box::new(import_fn(|_, _, m, n| {
+                    Ok(Some(ImportBehavior::Passthrough(m, n)))
+                })),
+                BTreeSet::new(),
+                (),
+            )),
+        );
+        let new_caller = copier.translate_Func(caller).unwrap();
+        let new_table = copier.copy_elements(table).unwrap();
+        let new_target = copier.translate_Func(target).unwrap();
+        drop(copier);
+
+        assert_eq!(dest.tables.len(), 1);
+        let elements = dest.tables[new_table].func_elements.as_ref().unwrap();
+        assert_eq!(elements, &vec![Func::invalid(), new_target]);
+        assert_eq!(dest.funcs[new_target].name(), "target");
+        let body = dest.funcs[new_caller].body().unwrap();
+        let table_index = body
+            .values
+            .values()
+            .find_map(|def| match def {
+                ValueDef::Operator(Operator::CallIndirect { table_index, .. }, _, _) => {
+                    Some(*table_index)
+                }
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(table_index, new_table);
+    }
+
+    #[test]
+    fn copied_elements_keep_their_offsets() {
+        let funcref = Type::Heap(WithNullable {
+            value: HeapType::FuncRef,
+            nullable: true,
+        });
+        let mut src = Module::empty();
+        let sig = new_sig(
+            &mut src,
+            SignatureData::Func {
+                params: vec![],
+                returns: vec![],
+                shared: false,
+            },
+        );
+        let mut body = FunctionBody::new(&src, sig);
+        body.set_terminator(body.entry, Terminator::Return { values: vec![] });
+        let target = src.funcs.push(FuncDecl::Body(sig, "target".into(), body));
+        let table = src.tables.push(TableData {
+            ty: funcref,
+            initial: 2,
+            max: None,
+            func_elements: Some(vec![Func::invalid(), target]),
+            table64: false,
+        });
+        src.imports.push(crate::Import {
+            module: "env".into(),
+            name: "t".into(),
+            kind: ImportKind::Table(table),
+        });
+
+        // The imported table is bound to a destination table that every
+        // copied function is also appended to.
+        let mut dest = Module::empty();
+        let bound = dest.tables.push(TableData {
+            ty: funcref,
+            initial: 0,
+            max: None,
+            func_elements: Some(vec![]),
+            table64: false,
+        });
+        let mut copier = Copier::new(
+            &src,
+            &mut dest,
+            Box::new(State::new(
+                Box::new(import_fn(move |_, _, _, _| {
+                    Ok(Some(ImportBehavior::Bind(ImportKind::Table(bound))))
+                })),
+                core::iter::once(bound).collect(),
+                (),
+            )),
+        );
+        assert_eq!(copier.copy_elements(table).unwrap(), bound);
+        let new_target = copier.translate_Func(target).unwrap();
+        drop(copier);
+
+        // The appended entry stays at 0 and the copied element lands at
+        // its source offset, 1.
+        let elements = dest.tables[bound].func_elements.as_ref().unwrap();
+        assert_eq!(elements, &vec![new_target, new_target]);
+    }
+
+    #[test]
+    fn copies_start_function() {
+        let mut src = Module::empty();
+        let sig = new_sig(
+            &mut src,
+            SignatureData::Func {
+                params: vec![],
+                returns: vec![],
+                shared: false,
+            },
+        );
+        let leaf = |m: &Module, name: &str| {
+            let mut body = FunctionBody::new(m, sig);
+            body.set_terminator(body.entry, Terminator::Return { values: vec![] });
+            FuncDecl::Body(sig, name.into(), body)
+        };
+        for name in ["unused", "init", "main"] {
+            let decl = leaf(&src, name);
+            src.funcs.push(decl);
+        }
+        let init = Func::new(1);
+        src.set_start_func(Some(init));
+        src.exports.push(crate::Export {
+            name: "main".into(),
+            kind: ExportKind::Func(Func::new(2)),
+        });
+
+        let mut dest = Module::empty();
+        let mut copier = Copier::new(
+            &src,
+            &mut dest,
+            Box::new(State::new(
+                Box::new(import_fn(|_, _, m, n| {
+                    Ok(Some(ImportBehavior::Passthrough(m, n)))
+                })),
+                BTreeSet::new(),
+                (),
+            )),
+        );
+        let new_init = copier.copy_start().unwrap().unwrap();
+        assert_eq!(copier.translate_Func(init).unwrap(), new_init);
+        drop(copier);
+        assert_eq!(dest.start_func(), Some(new_init));
+        assert_eq!(dest.funcs[new_init].name(), "init");
+
+        // Tree shaking keeps the start function even though it is not
+        // exported.
+        tree_shake(&mut src).unwrap();
+        assert_eq!(src.funcs.len(), 2);
+        let start = src.start_func().unwrap();
+        assert_eq!(src.funcs[start].name(), "init");
+    }
+
+    #[test]
+    fn copies_and_remaps_data_segments() {
+        let mut src = Module::empty();
+        let sig = new_sig(
+            &mut src,
+            SignatureData::Func {
+                params: vec![],
+                returns: vec![],
+                shared: false,
+            },
+        );
+        src.data_segments
+            .push(DataSegment::Passive { data: vec![9] });
+        src.data_segments
+            .push(DataSegment::Passive { data: vec![1, 2] });
+        let mut body = FunctionBody::new(&src, sig);
+        body.add_op(body.entry, Operator::DataDrop { data_idx: 1 }, &[], &[]);
+        body.set_terminator(body.entry, Terminator::Return { values: vec![] });
+        let f = src.funcs.push(FuncDecl::Body(sig, "f".into(), body));
+
+        let mut dest = Module::empty();
+        dest.data_segments
+            .push(DataSegment::Passive { data: vec![] });
+        let mut copier = Copier::new(
+            &src,
+            &mut dest,
+            Box::new(State::new(
+                Box::new(import_fn(|_, _, m, n| {
+                    Ok(Some(ImportBehavior::Passthrough(m, n)))
+                })),
+                BTreeSet::new(),
+                (),
+            )),
+        );
+        let new_f = copier.translate_Func(f).unwrap();
+        assert_eq!(copier.translate_data(1).unwrap(), 1);
+        drop(copier);
+
+        assert_eq!(
+            dest.data_segments,
+            vec![
+                DataSegment::Passive { data: vec![] },
+                DataSegment::Passive { data: vec![1, 2] },
+            ]
+        );
+        let body = dest.funcs[new_f].body().unwrap();
+        assert!(body.values.values().any(|def| matches!(
+            def,
+            ValueDef::Operator(Operator::DataDrop { data_idx: 1 }, _, _)
+        )));
+    }
 }
@@ -131,6 +131,20 @@ impl<Idx: EntityRef, T: Clone + Debug> EntityVec<Idx, T> {
             .enumerate()
             .map(|(index, t)| (Idx::new(index), t))
     }
+    /// Get an iterator over index, mutable-borrow-of-entity tuples, in
+    /// index order. Same as `entries_mut`.
+    pub fn iter_mut_enumerated(&mut self) -> impl DoubleEndedIterator<Item = (Idx, &mut T)> {
+        self.0
+            .iter_mut()
+            .enumerate()
+            .map(|(index, t)| (Idx::new(index), t))
+    }
+    /// Call `f` on every entity, in index order, with its index.
+    pub fn map_in_place(&mut self, mut f: impl FnMut(Idx, &mut T)) {
+        for (idx, t) in self.iter_mut_enumerated() {
+            f(idx, t);
+        }
+    }
     /// Typesafe element access, returning `None` if `idx` is the
     /// invalid index.
     pub fn get(&self, idx: Idx) -> Option<&T> {
@@ -141,6 +155,16 @@ impl<Idx: EntityRef, T: Clone + Debug> EntityVec<Idx, T> {
     pub fn get_mut(&mut self, idx: Idx) -> Option<&mut T> {
         self.0.get_mut(idx.maybe_index()?)
     }
+    /// Borrow the entities as a slice, in index order.
+    pub fn as_slice(&self) -> &[T] {
+        &self.0
+    }
+    /// Mutably borrow the entities as a slice, in index order. Useful
+    /// for handing out disjoint borrows, e.g. to process entities in
+    /// parallel.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.0
+    }
     /// Convert this `EntityVec` into the underlying `Vec` and return
     /// it.
     pub fn into_vec(self) -> Vec<T> {
@@ -198,3 +222,29 @@ impl<Idx: EntityRef, T: Clone + Debug + Default + PartialEq> PartialEq for PerEn
     }
 }
 impl<Idx: EntityRef, T: Clone + Debug + Default + PartialEq + Eq> Eq for PerEntity<Idx, T> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::string::String;
+
+    crate::declare_entity!(Thing, "thing");
+
+    #[test]
+    fn iter_mut_enumerated_indices() {
+        let mut things: EntityVec<Thing, String> = EntityVec::default();
+        for name in ["a", "b", "c"].iter() {
+            things.push((*name).into());
+        }
+        for (idx, name) in things.iter_mut_enumerated() {
+            name.push_str(&format!("{}", idx.index()));
+        }
+        things.map_in_place(|idx, name| {
+            assert_eq!(name[1..], format!("{}", idx.index()));
+            name.push('!');
+        });
+        let names = things.values().cloned().collect::<Vec<_>>();
+        assert_eq!(names, vec!["a0!", "b1!", "c2!"]);
+        assert_eq!(things[Thing::new(1)], "b1!");
+    }
+}
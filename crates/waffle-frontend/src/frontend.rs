@@ -22,9 +22,87 @@ pub struct FrontendOptions {
     /// Preserve DWARF debug-info. Otherwise, it is discarded if
     /// present.
     pub debug: bool,
+    /// Which post-MVP proposals the module may use.
+    pub features: ParseOptions,
+}
+/// Toggles for the WebAssembly proposals the frontend understands.
+/// All are enabled by default; with any disabled, the module is first
+/// run through `wasmparser`'s validator (see `wasm_features`), and a
+/// validation failure is reported as `FrontendError::UnsupportedFeature`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "rkyv-impl", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
+pub struct ParseOptions {
+    /// `return_call`, `return_call_indirect` and `return_call_ref`.
+    pub tail_call: bool,
+    /// Struct and array types and the `0xfb`-prefixed operators.
+    pub gc: bool,
+    /// `call_ref`, `return_call_ref`, `ref.as_non_null`,
+    /// `br_on_null` and `br_on_non_null`.
+    pub function_references: bool,
+    /// The `0xfd`-prefixed 128-bit SIMD operators.
+    pub simd: bool,
+    /// The `0xfe`-prefixed atomic operators.
+    pub threads: bool,
+    /// Memories and tables with 64-bit indices.
+    pub memory64: bool,
+    /// More than one memory, whether imported or defined.
+    pub multi_memory: bool,
+}
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            tail_call: true,
+            gc: true,
+            function_references: true,
+            simd: true,
+            threads: true,
+            memory64: true,
+            multi_memory: true,
+        }
+    }
+}
+impl ParseOptions {
+    fn all_enabled(&self) -> bool {
+        *self == ParseOptions::default()
+    }
+    /// The `wasmparser` feature set these options describe: every
+    /// proposal `wasmparser` knows, minus the disabled ones. `gc`
+    /// builds on `function_references`, and relaxed SIMD on `simd`,
+    /// so disabling the latter disables the former too.
+    pub fn wasm_features(&self) -> wasmparser::WasmFeatures {
+        use wasmparser::WasmFeatures;
+        let mut features = WasmFeatures::all();
+        features.set(WasmFeatures::TAIL_CALL, self.tail_call);
+        features.set(WasmFeatures::FUNCTION_REFERENCES, self.function_references);
+        features.set(WasmFeatures::GC, self.gc && self.function_references);
+        features.set(WasmFeatures::SIMD, self.simd);
+        features.set(WasmFeatures::RELAXED_SIMD, self.simd);
+        features.set(WasmFeatures::THREADS, self.threads);
+        features.set(WasmFeatures::MEMORY64, self.memory64);
+        features.set(WasmFeatures::MULTI_MEMORY, self.multi_memory);
+        features
+    }
+    /// Validate `bytes` against `wasm_features`.
+    fn validate(&self, bytes: &[u8]) -> Result<()> {
+        wasmparser::Validator::new_with_features(self.wasm_features())
+            .validate_all(bytes)
+            .map_err(|e| {
+                anyhow::anyhow!(FrontendError::UnsupportedFeature(format!(
+                    "{} (with {:?})",
+                    e.message(),
+                    self
+                )))
+            })?;
+        Ok(())
+    }
 }
 /// Convert the given bytecode to a `Module`.
 pub(crate) fn wasm_to_ir<'a>(bytes: &'a [u8], options: &FrontendOptions) -> Result<Module<'a>> {
+    // Only validate when some proposal is disabled, so that parsing
+    // with the defaults stays as permissive (and cheap) as ever.
+    if !options.features.all_enabled() {
+        options.features.validate(bytes)?;
+    }
     let mut module = Module::with_orig_bytes(bytes);
     let parser = Parser::new(0);
     let mut next_func = 0;
@@ -259,7 +337,11 @@ fn handle_payload<'a>(
             for segment in reader {
                 let segment = segment?;
                 match &segment.kind {
-                    DataKind::Passive => {}
+                    DataKind::Passive => {
+                        module.data_segments.push(DataSegment::Passive {
+                            data: segment.data.to_vec(),
+                        });
+                    }
                     DataKind::Active {
                         memory_index,
                         offset_expr,
@@ -267,9 +349,12 @@ fn handle_payload<'a>(
                         let data = segment.data.to_vec();
                         let memory = Memory::from(*memory_index);
                         let offset = parse_init_expr(offset_expr)?.unwrap_or(0) as usize;
-                        module.memories[memory]
-                            .segments
-                            .push(MemorySegment { offset, data });
+                        let segments = &mut module.memories[memory].segments;
+                        segments.push(MemorySegment { offset, data });
+                        module.data_segments.push(DataSegment::Active {
+                            memory,
+                            segment: segments.len() - 1,
+                        });
                     }
                 }
             }
@@ -332,10 +417,14 @@ fn handle_payload<'a>(
                 {
                     module
                         .custom_sections
-                        .insert(reader.name().to_owned(), reader.data().to_owned());
+                        .push((reader.name().to_owned(), reader.data().to_owned()));
                 }
             }
-            _ => {}
+            _ => {
+                module
+                    .custom_sections
+                    .push((reader.name().to_owned(), reader.data().to_owned()));
+            }
         },
         Payload::Version { .. } => {}
         Payload::ElementSection(reader) => {
@@ -1152,6 +1241,8 @@ impl<'a, 'b> FunctionBodyBuilder<'a, 'b> {
             | wasmparser::Operator::TableSize { .. }
             | wasmparser::Operator::MemoryCopy { .. }
             | wasmparser::Operator::MemoryFill { .. }
+            | wasmparser::Operator::MemoryInit { .. }
+            | wasmparser::Operator::DataDrop { .. }
             | wasmparser::Operator::V128Load { .. }
             | wasmparser::Operator::V128Load8x8S { .. }
             | wasmparser::Operator::V128Load8x8U { .. }
@@ -1390,7 +1481,82 @@ impl<'a, 'b> FunctionBodyBuilder<'a, 'b> {
             | wasmparser::Operator::F64x2PromoteLowF32x4
             | wasmparser::Operator::CallRef { .. }
             | wasmparser::Operator::RefIsNull
-            | wasmparser::Operator::RefFunc { .. } => {
+            | wasmparser::Operator::RefFunc { .. }
+            | wasmparser::Operator::RefEq
+            | wasmparser::Operator::RefTestNonNull { .. }
+            | wasmparser::Operator::RefTestNullable { .. }
+            | wasmparser::Operator::RefCastNonNull { .. }
+            | wasmparser::Operator::RefCastNullable { .. }
+            | wasmparser::Operator::RefI31
+            | wasmparser::Operator::I31GetS
+            | wasmparser::Operator::I31GetU
+            | wasmparser::Operator::MemoryAtomicNotify { .. }
+            | wasmparser::Operator::MemoryAtomicWait32 { .. }
+            | wasmparser::Operator::MemoryAtomicWait64 { .. }
+            | wasmparser::Operator::I32AtomicLoad { .. }
+            | wasmparser::Operator::I64AtomicLoad { .. }
+            | wasmparser::Operator::I32AtomicLoad8U { .. }
+            | wasmparser::Operator::I32AtomicLoad16U { .. }
+            | wasmparser::Operator::I64AtomicLoad8U { .. }
+            | wasmparser::Operator::I64AtomicLoad16U { .. }
+            | wasmparser::Operator::I64AtomicLoad32U { .. }
+            | wasmparser::Operator::I32AtomicStore { .. }
+            | wasmparser::Operator::I64AtomicStore { .. }
+            | wasmparser::Operator::I32AtomicStore8 { .. }
+            | wasmparser::Operator::I32AtomicStore16 { .. }
+            | wasmparser::Operator::I64AtomicStore8 { .. }
+            | wasmparser::Operator::I64AtomicStore16 { .. }
+            | wasmparser::Operator::I64AtomicStore32 { .. }
+            | wasmparser::Operator::I32AtomicRmwAdd { .. }
+            | wasmparser::Operator::I64AtomicRmwAdd { .. }
+            | wasmparser::Operator::I32AtomicRmw8AddU { .. }
+            | wasmparser::Operator::I32AtomicRmw16AddU { .. }
+            | wasmparser::Operator::I64AtomicRmw8AddU { .. }
+            | wasmparser::Operator::I64AtomicRmw16AddU { .. }
+            | wasmparser::Operator::I64AtomicRmw32AddU { .. }
+            | wasmparser::Operator::I32AtomicRmwSub { .. }
+            | wasmparser::Operator::I64AtomicRmwSub { .. }
+            | wasmparser::Operator::I32AtomicRmw8SubU { .. }
+            | wasmparser::Operator::I32AtomicRmw16SubU { .. }
+            | wasmparser::Operator::I64AtomicRmw8SubU { .. }
+            | wasmparser::Operator::I64AtomicRmw16SubU { .. }
+            | wasmparser::Operator::I64AtomicRmw32SubU { .. }
+            | wasmparser::Operator::I32AtomicRmwAnd { .. }
+            | wasmparser::Operator::I64AtomicRmwAnd { .. }
+            | wasmparser::Operator::I32AtomicRmw8AndU { .. }
+            | wasmparser::Operator::I32AtomicRmw16AndU { .. }
+            | wasmparser::Operator::I64AtomicRmw8AndU { .. }
+            | wasmparser::Operator::I64AtomicRmw16AndU { .. }
+            | wasmparser::Operator::I64AtomicRmw32AndU { .. }
+            | wasmparser::Operator::I32AtomicRmwOr { .. }
+            | wasmparser::Operator::I64AtomicRmwOr { .. }
+            | wasmparser::Operator::I32AtomicRmw8OrU { .. }
+            | wasmparser::Operator::I32AtomicRmw16OrU { .. }
+            | wasmparser::Operator::I64AtomicRmw8OrU { .. }
+            | wasmparser::Operator::I64AtomicRmw16OrU { .. }
+            | wasmparser::Operator::I64AtomicRmw32OrU { .. }
+            | wasmparser::Operator::I32AtomicRmwXor { .. }
+            | wasmparser::Operator::I64AtomicRmwXor { .. }
+            | wasmparser::Operator::I32AtomicRmw8XorU { .. }
+            | wasmparser::Operator::I32AtomicRmw16XorU { .. }
+            | wasmparser::Operator::I64AtomicRmw8XorU { .. }
+            | wasmparser::Operator::I64AtomicRmw16XorU { .. }
+            | wasmparser::Operator::I64AtomicRmw32XorU { .. }
+            | wasmparser::Operator::I32AtomicRmwXchg { .. }
+            | wasmparser::Operator::I64AtomicRmwXchg { .. }
+            | wasmparser::Operator::I32AtomicRmw8XchgU { .. }
+            | wasmparser::Operator::I32AtomicRmw16XchgU { .. }
+            | wasmparser::Operator::I64AtomicRmw8XchgU { .. }
+            | wasmparser::Operator::I64AtomicRmw16XchgU { .. }
+            | wasmparser::Operator::I64AtomicRmw32XchgU { .. }
+            | wasmparser::Operator::I32AtomicRmwCmpxchg { .. }
+            | wasmparser::Operator::I64AtomicRmwCmpxchg { .. }
+            | wasmparser::Operator::I32AtomicRmw8CmpxchgU { .. }
+            | wasmparser::Operator::I32AtomicRmw16CmpxchgU { .. }
+            | wasmparser::Operator::I64AtomicRmw8CmpxchgU { .. }
+            | wasmparser::Operator::I64AtomicRmw16CmpxchgU { .. }
+            | wasmparser::Operator::I64AtomicRmw32CmpxchgU { .. }
+            | wasmparser::Operator::AtomicFence => {
                 self.emit(Operator::try_from(&op).unwrap(), loc)?
             }
             wasmparser::Operator::Nop => {}
@@ -1495,7 +1661,8 @@ impl<'a, 'b> FunctionBodyBuilder<'a, 'b> {
                 else {
                     anyhow::bail!("invalid signature")
                 };
-                let retvals = self.pop_n(params.len());
+                // The table index is the last argument.
+                let retvals = self.pop_n(params.len() + 1);
                 self.emit_term(Terminator::ReturnCallIndirect {
                     sig: Signature::new(*type_index as usize),
                     table: Table::new(*table_index as usize),
@@ -1513,7 +1680,8 @@ impl<'a, 'b> FunctionBodyBuilder<'a, 'b> {
                 else {
                     anyhow::bail!("invalid signature")
                 };
-                let retvals = self.pop_n(params.len());
+                // The function reference is the last argument.
+                let retvals = self.pop_n(params.len() + 1);
                 self.emit_term(Terminator::ReturnCallRef {
                     sig: Signature::new(*type_index as usize),
                     // table: Table::new(*table_index as usize),
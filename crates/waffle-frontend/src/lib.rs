@@ -81,6 +81,17 @@ pub trait ModuleExt<'a>: Sized {
     fn expand_all_funcs(&mut self) -> Result<()> {
         expand_all_funcs(self.module_mut())
     }
+    /// Parse one function's original bytecode into IR, leaving every
+    /// other `FuncDecl::Lazy` untouched. Does nothing if the function
+    /// already has a body.
+    fn parse_func(&mut self, f: Func) -> Result<()> {
+        expand_func(self.module_mut(), f)?;
+        Ok(())
+    }
+    /// Parse all functions still held as `FuncDecl::Lazy`.
+    fn parse_all(&mut self) -> Result<()> {
+        expand_all_funcs(self.module_mut())
+    }
 }
 impl<'a> ModuleExt<'a> for Module<'a> {
     fn module(&self) -> &Module<'a> {
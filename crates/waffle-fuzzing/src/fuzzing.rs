@@ -1,5 +1,11 @@
 //! Fuzzing-specific utilities.
+use crate::{
+    ConstVal, ExportKind, Func, InterpContext, InterpResult, Module, SignatureData, TrapReason,
+    Type,
+};
+use alloc::vec::Vec;
 use libfuzzer_sys::arbitrary;
+use waffle_passes::{ModuleOptExt, OptOptions};
 /// Should this module be rejected early during fuzzing due to an
 /// unsupported feature?
 ///
@@ -83,3 +89,141 @@ impl<'a> arbitrary::Arbitrary<'a> for ArbitraryModule {
         Ok(Self(wasm_smith::Module::new(fuzzing_config(), u)?))
     }
 }
+/// Is `func` callable with made-up arguments, i.e. does its
+/// signature take and return only `i32`s and `i64`s?
+pub fn has_simple_sig(module: &Module<'_>, func: Func) -> bool {
+    match &module.signatures[module.funcs[func].sig()] {
+        SignatureData::Func {
+            params, returns, ..
+        } => params
+            .iter()
+            .chain(returns.iter())
+            .all(|ty| matches!(ty, Type::I32 | Type::I64)),
+        _ => false,
+    }
+}
+/// Exported functions with a simple signature (see
+/// `has_simple_sig`), in export order.
+pub fn simple_exports(module: &Module<'_>) -> Vec<Func> {
+    module
+        .exports
+        .iter()
+        .filter_map(|export| match &export.kind {
+            &ExportKind::Func(func) if has_simple_sig(module, func) => Some(func),
+            _ => None,
+        })
+        .collect()
+}
+/// Draw arguments for a function with a simple signature.
+pub fn arbitrary_args(
+    module: &Module<'_>,
+    func: Func,
+    u: &mut arbitrary::Unstructured<'_>,
+) -> arbitrary::Result<Vec<ConstVal>> {
+    let params = match &module.signatures[module.funcs[func].sig()] {
+        SignatureData::Func { params, .. } => params,
+        _ => return Err(arbitrary::Error::IncorrectFormat),
+    };
+    params
+        .iter()
+        .map(|ty| match ty {
+            Type::I32 => Ok(ConstVal::I32(u.arbitrary()?)),
+            Type::I64 => Ok(ConstVal::I64(u.arbitrary()?)),
+            _ => Err(arbitrary::Error::IncorrectFormat),
+        })
+        .collect()
+}
+/// The observable result of calling a function in the interpreter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The call returned the given values.
+    Returned(Vec<ConstVal>),
+    /// The call trapped for the given reason.
+    Trapped(TrapReason),
+}
+/// Call `func` with `args` in `module` and again in an optimized copy
+/// of `module`, and panic if the two disagree on the results, the
+/// trap reason, or the final memory and global state.
+///
+/// The optimized copy gets twice the fuel of the original, and
+/// running out of it is a failure too. Returns `None` without
+/// checking anything if the module can't be instantiated or the
+/// original call runs out of fuel.
+pub fn check_optimize_preserves(
+    module: &Module<'_>,
+    func: Func,
+    args: &[ConstVal],
+    fuel: u64,
+) -> Option<Outcome> {
+    let run = |module: &Module<'_>, fuel: u64| {
+        let mut ctx = InterpContext::new(module).ok()?;
+        ctx.fuel = fuel;
+        let outcome = match ctx.call(module, func, args) {
+            InterpResult::Ok(vals) => Outcome::Returned(vals.into_vec()),
+            InterpResult::Trap(_, _, _, reason) => Outcome::Trapped(reason),
+            InterpResult::OutOfFuel => return None,
+        };
+        Some((outcome, ctx))
+    };
+    let (orig, orig_ctx) = run(module, fuel)?;
+    let mut opt_module = module.clone();
+    opt_module.optimize(&OptOptions::default());
+    let (opt, opt_ctx) = match run(&opt_module, fuel.saturating_mul(2)) {
+        Some(result) => result,
+        None => panic!(
+            "optimized {} ran out of fuel; original gave {:?}",
+            func, orig
+        ),
+    };
+    assert_eq!(orig, opt, "optimize changed the outcome of {}", func);
+    assert_eq!(orig_ctx.memories, opt_ctx.memories);
+    assert_eq!(orig_ctx.globals, opt_ctx.globals);
+    Some(orig)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FuncDecl, FunctionBuilder, Operator};
+
+    #[test]
+    fn optimize_preserves_returns_and_traps() {
+        let mut module = Module::empty();
+        let sig = module.add_signature(SignatureData::Func {
+            params: vec![Type::I32, Type::I64],
+            returns: vec![Type::I64],
+            shared: false,
+        });
+        // f(x, y) = y / zext(x) * (3 + 4)
+        let mut b = FunctionBuilder::new(&module, sig);
+        let entry = b.entry();
+        let params = b.params(entry);
+        let x = b.push_op(entry, Operator::I64ExtendI32U, &[params[0]], &[Type::I64]);
+        let quot = b.push_op(entry, Operator::I64DivU, &[params[1], x], &[Type::I64]);
+        let three = b.const_i64(entry, 3);
+        let four = b.const_i64(entry, 4);
+        let seven = b.push_op(entry, Operator::I64Add, &[three, four], &[Type::I64]);
+        let prod = b.push_op(entry, Operator::I64Mul, &[quot, seven], &[Type::I64]);
+        b.ret(entry, &[prod]);
+        let func = module.add_func(FuncDecl::Body(sig, "f".into(), b.finish()));
+        module.exports.push(crate::Export {
+            name: "f".into(),
+            kind: ExportKind::Func(func),
+        });
+        assert_eq!(simple_exports(&module), vec![func]);
+
+        let outcome =
+            check_optimize_preserves(&module, func, &[ConstVal::I32(5), ConstVal::I64(100)], 100);
+        assert_eq!(outcome, Some(Outcome::Returned(vec![ConstVal::I64(140)])));
+        let outcome =
+            check_optimize_preserves(&module, func, &[ConstVal::I32(0), ConstVal::I64(1)], 100);
+        assert_eq!(
+            outcome,
+            Some(Outcome::Trapped(TrapReason::IntegerDivByZero))
+        );
+        // Too little fuel for the original: nothing to compare.
+        let outcome =
+            check_optimize_preserves(&module, func, &[ConstVal::I32(5), ConstVal::I64(100)], 1);
+        assert_eq!(outcome, None);
+    }
+}
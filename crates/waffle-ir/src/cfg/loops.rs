@@ -0,0 +1,153 @@
+//! Natural-loop detection and the loop-nest forest.
+//!
+//! A natural loop is identified by its header: a block `h` with at
+//! least one back edge `b -> h` where `h` dominates `b`. The loop body
+//! is `h` together with every block that can reach one of those back
+//! edges without passing through `h`. Cycles without a dominating
+//! header (irreducible control flow) are not reported.
+use super::CFGInfo;
+use crate::declare_entity;
+use crate::ir::Block;
+use crate::{EntityVec, PerEntity};
+use alloc::collections::BTreeSet;
+use alloc::vec;
+use alloc::vec::Vec;
+
+declare_entity!(Loop, "loop");
+
+/// A single natural loop.
+#[derive(Clone, Debug)]
+pub struct LoopData {
+    /// The loop header, which dominates every block in the body.
+    pub header: Block,
+    /// All blocks in the loop, including the header and the blocks of
+    /// any nested loops.
+    pub blocks: BTreeSet<Block>,
+    /// Blocks ending in a back edge to the header.
+    pub latches: Vec<Block>,
+    /// The innermost loop strictly enclosing this one, if any.
+    pub parent: Option<Loop>,
+}
+
+/// The natural loops of a function body, computed by
+/// [`CFGInfo::loops`]. Loops are numbered in reverse postorder of
+/// their headers, so an enclosing loop always precedes the loops
+/// nested within it.
+#[derive(Clone, Debug, Default)]
+pub struct Loops {
+    pub loops: EntityVec<Loop, LoopData>,
+    /// The loop headed by each block, if it is a loop header.
+    pub header_loop: PerEntity<Block, Option<Loop>>,
+    /// The innermost loop containing each block, if any.
+    pub innermost: PerEntity<Block, Option<Loop>>,
+}
+
+impl Loops {
+    pub(crate) fn compute(cfg: &CFGInfo) -> Loops {
+        let mut loops = Loops::default();
+        for header in cfg.rpo_blocks() {
+            let latches = cfg.preds[header]
+                .iter()
+                .cloned()
+                .filter(|&pred| cfg.rpo_pos[pred].is_some() && cfg.dominates(header, pred))
+                .collect::<Vec<_>>();
+            if latches.is_empty() {
+                continue;
+            }
+
+            let mut blocks = BTreeSet::new();
+            blocks.insert(header);
+            let mut workqueue = latches.clone();
+            while let Some(block) = workqueue.pop() {
+                if !blocks.insert(block) {
+                    continue;
+                }
+                for &pred in &cfg.preds[block] {
+                    if cfg.rpo_pos[pred].is_some() && !blocks.contains(&pred) {
+                        workqueue.push(pred);
+                    }
+                }
+            }
+
+            // Headers are visited in RPO, so any enclosing loop has
+            // already been recorded, and nested loops are strictly
+            // smaller than the loops around them.
+            let parent = loops
+                .loops
+                .entries()
+                .filter(|(_, data)| data.blocks.contains(&header))
+                .min_by_key(|(_, data)| data.blocks.len())
+                .map(|(id, _)| id);
+
+            let id = loops.loops.push(LoopData {
+                header,
+                blocks,
+                latches,
+                parent,
+            });
+            loops.header_loop[header] = Some(id);
+        }
+
+        // Outer loops come first, so later writes are more deeply
+        // nested.
+        for (id, data) in loops.loops.entries() {
+            for &block in &data.blocks {
+                loops.innermost[block] = Some(id);
+            }
+        }
+        loops
+    }
+
+    /// Iterate over all loops, outermost first.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = Loop> + 'a {
+        self.loops.iter()
+    }
+
+    /// The headers of all loops, outermost first.
+    pub fn headers(&self) -> Vec<Block> {
+        self.loops.values().map(|data| data.header).collect()
+    }
+
+    /// Whether `block` is a loop header.
+    pub fn is_header(&self, block: Block) -> bool {
+        self.header_loop[block].is_some()
+    }
+
+    /// Whether `block` is part of loop `lp`, including its nested
+    /// loops.
+    pub fn contains(&self, lp: Loop, block: Block) -> bool {
+        self.loops[lp].blocks.contains(&block)
+    }
+
+    /// The loops immediately nested within `lp`.
+    pub fn children(&self, lp: Loop) -> Vec<Loop> {
+        self.loops
+            .entries()
+            .filter(|(_, data)| data.parent == Some(lp))
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Nesting depth of `block`: zero outside all loops, one inside an
+    /// outermost loop, and so on.
+    pub fn depth(&self, block: Block) -> usize {
+        let mut depth = 0;
+        let mut lp = self.innermost[block];
+        while let Some(id) = lp {
+            depth += 1;
+            lp = self.loops[id].parent;
+        }
+        depth
+    }
+
+    /// The chain of loops enclosing `block`, innermost first.
+    pub fn enclosing(&self, block: Block) -> Vec<Loop> {
+        let mut chain = vec![];
+        let mut lp = self.innermost[block];
+        while let Some(id) = lp {
+            chain.push(id);
+            lp = self.loops[id].parent;
+        }
+        chain
+    }
+}
@@ -9,7 +9,9 @@ use alloc::vec;
 use alloc::vec::Vec;
 use smallvec::SmallVec;
 pub mod domtree;
+pub mod loops;
 pub mod postorder;
+pub use loops::{Loop, LoopData, Loops};
 declare_entity!(RPOIndex, "rpo");
 /// Auxiliary analyses of the control-flow graph.
 #[derive(Clone, Debug)]
@@ -25,8 +27,8 @@ pub struct CFGInfo {
     pub rpo_pos: PerEntity<Block, Option<RPOIndex>>,
     /// Domtree parents, indexed by block.
     pub domtree: PerEntity<Block, Block>,
-    /// Domtree children.
-    pub domtree_children: PerEntity<Block, DomtreeChildren>,
+    /// Domtree children, in block order.
+    pub domtree_children: PerEntity<Block, Vec<Block>>,
     /// Defining block for a given value.
     pub def_block: PerEntity<Value, Block>,
     /// Preds for a given block.
@@ -34,28 +36,6 @@ pub struct CFGInfo {
     /// A given block's position in each predecessor's successor list.
     pub pred_pos: PerEntity<Block, SmallVec<[usize; 4]>>,
 }
-#[derive(Clone, Debug, Default)]
-#[cfg_attr(feature = "rkyv-impl", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
-pub struct DomtreeChildren {
-    pub child: Block,
-    pub next: Block,
-}
-pub struct DomtreeChildIter<'a> {
-    domtree_children: &'a PerEntity<Block, DomtreeChildren>,
-    block: Block,
-}
-impl<'a> Iterator for DomtreeChildIter<'a> {
-    type Item = Block;
-    fn next(&mut self) -> Option<Block> {
-        if self.block.is_invalid() {
-            None
-        } else {
-            let block = self.block;
-            self.block = self.domtree_children[block].next;
-            Some(block)
-        }
-    }
-}
 impl CFGInfo {
     pub fn new(f: &FunctionBody) -> CFGInfo {
         let mut return_blocks = vec![];
@@ -75,13 +55,11 @@ impl CFGInfo {
         let postorder = postorder::calculate(f.entry, |block| &f.blocks[block].succs[..]);
         let domtree =
             domtree::calculate(|block| &f.blocks[block].preds[..], &postorder[..], f.entry);
-        let mut domtree_children: PerEntity<Block, DomtreeChildren> = PerEntity::default();
-        for block in f.blocks.iter().rev() {
+        let mut domtree_children: PerEntity<Block, Vec<Block>> = PerEntity::default();
+        for block in f.blocks.iter() {
             let idom = domtree[block];
             if idom.is_valid() {
-                let next = domtree_children[idom].child;
-                domtree_children[block].next = next;
-                domtree_children[idom].child = block;
+                domtree_children[idom].push(block);
             }
         }
         let mut def_block: PerEntity<Value, Block> = PerEntity::default();
@@ -123,10 +101,245 @@ impl CFGInfo {
     pub fn dominates(&self, a: Block, b: Block) -> bool {
         domtree::dominates(&self.domtree, a, b)
     }
-    pub fn dom_children<'a>(&'a self, block: Block) -> DomtreeChildIter<'a> {
-        DomtreeChildIter {
-            domtree_children: &self.domtree_children,
-            block: self.domtree_children[block].child,
+    /// The immediate dominator of `block`, or `None` for the entry
+    /// and for unreachable blocks.
+    pub fn idom(&self, block: Block) -> Option<Block> {
+        Some(self.domtree[block]).filter(|idom| idom.is_valid())
+    }
+    /// The blocks whose immediate dominator is `block`, in block
+    /// order.
+    pub fn dom_children(&self, block: Block) -> &[Block] {
+        &self.domtree_children[block][..]
+    }
+    /// Reachable blocks in reverse postorder: every block comes
+    /// before its successors, except along back-edges. Suitable for
+    /// forward dataflow analyses.
+    pub fn rpo_blocks<'a>(&'a self) -> impl DoubleEndedIterator<Item = Block> + 'a {
+        self.rpo.values().cloned()
+    }
+    /// Reachable blocks in postorder: every block comes after its
+    /// successors, except along back-edges. Suitable for backward
+    /// dataflow analyses.
+    pub fn po_blocks<'a>(&'a self) -> impl DoubleEndedIterator<Item = Block> + 'a {
+        self.rpo.values().rev().cloned()
+    }
+    /// Position of `block` in reverse postorder, or `None` if it is
+    /// unreachable.
+    pub fn rpo_index(&self, block: Block) -> Option<usize> {
+        self.rpo_pos[block].map(|pos| pos.index())
+    }
+    /// Compute the dominance frontier of every reachable block, using
+    /// the algorithm of Cooper, Harvey and Kennedy over the
+    /// already-computed dominator tree. Each frontier is sorted.
+    pub fn dominance_frontier(&self) -> PerEntity<Block, Vec<Block>> {
+        let mut frontier: PerEntity<Block, Vec<Block>> = PerEntity::default();
+        for block in self.rpo_blocks() {
+            let preds = &self.preds[block];
+            if preds.len() < 2 {
+                continue;
+            }
+            let idom = self.domtree[block];
+            for &pred in preds.iter() {
+                if self.rpo_pos[pred].is_none() {
+                    continue;
+                }
+                let mut runner = pred;
+                while runner.is_valid() && runner != idom {
+                    frontier[runner].push(block);
+                    runner = self.domtree[runner];
+                }
+            }
         }
+        for block in self.rpo_blocks() {
+            frontier[block].sort();
+            frontier[block].dedup();
+        }
+        frontier
+    }
+    /// Find the natural loops of the body: one per block targeted by
+    /// a back edge, i.e. an edge from a block it dominates.
+    pub fn loops(&self) -> Loops {
+        Loops::compute(self)
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ir::{BlockTarget, Module, SignatureData};
+    use crate::{Operator, Type};
+
+    /// entry -> (left | right) -> join -> return
+    pub(crate) fn diamond() -> (FunctionBody, [Block; 4]) {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let cond = body.blocks[entry].params[0].1;
+        let left = body.add_block();
+        let right = body.add_block();
+        let join = body.add_block();
+        let param = body.add_blockparam(join, Type::I32);
+        body.set_terminator(
+            entry,
+            Terminator::CondBr {
+                cond,
+                if_true: BlockTarget {
+                    block: left,
+                    args: vec![],
+                },
+                if_false: BlockTarget {
+                    block: right,
+                    args: vec![],
+                },
+            },
+        );
+        for &(block, value) in [(left, 1), (right, 2)].iter() {
+            let v = body.add_op(block, Operator::I32Const { value }, &[], &[Type::I32]);
+            body.set_terminator(
+                block,
+                Terminator::Br {
+                    target: BlockTarget {
+                        block: join,
+                        args: vec![v],
+                    },
+                },
+            );
+        }
+        body.set_terminator(
+            join,
+            Terminator::Return {
+                values: vec![param],
+            },
+        );
+        (body, [entry, left, right, join])
+    }
+
+    #[test]
+    fn rpo_orders_diamond() {
+        let (body, [entry, left, right, join]) = diamond();
+        let cfg = CFGInfo::new(&body);
+        let rpo = cfg.rpo_blocks().collect::<Vec<_>>();
+        assert_eq!(rpo.len(), 4);
+        assert_eq!(rpo[0], entry);
+        assert_eq!(rpo[3], join);
+        let mut po = cfg.po_blocks().collect::<Vec<_>>();
+        po.reverse();
+        assert_eq!(po, rpo);
+        for block in [entry, left, right, join].iter().cloned() {
+            for &succ in &body.blocks[block].succs {
+                if !cfg.dominates(succ, block) {
+                    assert!(cfg.rpo_index(block).unwrap() < cfg.rpo_index(succ).unwrap());
+                }
+            }
+        }
+        let dead = Block::new(body.blocks.len());
+        assert_eq!(cfg.rpo_index(dead), None);
+    }
+
+    #[test]
+    fn diamond_frontier() {
+        let (body, [entry, left, right, join]) = diamond();
+        let cfg = CFGInfo::new(&body);
+        let df = cfg.dominance_frontier();
+        assert_eq!(df[left], vec![join]);
+        assert_eq!(df[right], vec![join]);
+        assert!(df[entry].is_empty());
+        assert!(df[join].is_empty());
+    }
+
+    #[test]
+    fn diamond_domtree() {
+        let (body, [entry, left, right, join]) = diamond();
+        let cfg = CFGInfo::new(&body);
+        assert_eq!(cfg.idom(entry), None);
+        assert_eq!(cfg.idom(left), Some(entry));
+        // Neither arm dominates the join.
+        assert_eq!(cfg.idom(join), Some(entry));
+        assert_eq!(cfg.dom_children(entry), &[left, right, join]);
+        for block in [left, right, join] {
+            assert!(cfg.dom_children(block).is_empty());
+        }
+        let dead = Block::new(body.blocks.len());
+        assert_eq!(cfg.idom(dead), None);
+    }
+
+    /// entry -> outer -> inner <-> inner_latch; inner -> outer_latch
+    /// -> outer; outer -> exit
+    #[test]
+    fn nested_loops() {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![],
+            shared: false,
+        });
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let cond = body.blocks[entry].params[0].1;
+        let outer = body.add_block();
+        let inner = body.add_block();
+        let inner_latch = body.add_block();
+        let outer_latch = body.add_block();
+        let exit = body.add_block();
+        let br = |block| Terminator::Br {
+            target: BlockTarget {
+                block,
+                args: vec![],
+            },
+        };
+        let cond_br = |if_true, if_false| Terminator::CondBr {
+            cond,
+            if_true: BlockTarget {
+                block: if_true,
+                args: vec![],
+            },
+            if_false: BlockTarget {
+                block: if_false,
+                args: vec![],
+            },
+        };
+        body.set_terminator(entry, br(outer));
+        body.set_terminator(outer, cond_br(inner, exit));
+        body.set_terminator(inner, cond_br(inner_latch, outer_latch));
+        body.set_terminator(inner_latch, br(inner));
+        body.set_terminator(outer_latch, br(outer));
+        body.set_terminator(exit, Terminator::Return { values: vec![] });
+
+        let cfg = CFGInfo::new(&body);
+        let loops = cfg.loops();
+        assert_eq!(loops.headers(), vec![outer, inner]);
+        let outer_loop = loops.header_loop[outer].unwrap();
+        let inner_loop = loops.header_loop[inner].unwrap();
+        assert_eq!(
+            loops.loops[outer_loop]
+                .blocks
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>(),
+            vec![outer, inner, inner_latch, outer_latch]
+        );
+        assert_eq!(
+            loops.loops[inner_loop]
+                .blocks
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>(),
+            vec![inner, inner_latch]
+        );
+        assert_eq!(loops.loops[outer_loop].latches, vec![outer_latch]);
+        assert_eq!(loops.loops[inner_loop].latches, vec![inner_latch]);
+        assert_eq!(loops.loops[outer_loop].parent, None);
+        assert_eq!(loops.loops[inner_loop].parent, Some(outer_loop));
+        assert_eq!(loops.children(outer_loop), vec![inner_loop]);
+        assert_eq!(loops.innermost[outer_latch], Some(outer_loop));
+        assert_eq!(loops.innermost[inner_latch], Some(inner_loop));
+        assert_eq!(loops.depth(entry), 0);
+        assert_eq!(loops.depth(exit), 0);
+        assert_eq!(loops.depth(inner_latch), 2);
+        assert!(!loops.is_header(entry));
     }
 }
@@ -6,13 +6,17 @@ use alloc::boxed::Box;
 use alloc::sync::Arc;
 use alloc::vec;
 use alloc::vec::Vec;
+use core::convert::TryFrom;
 use hashbrown::HashMap;
 use smallvec::{smallvec, SmallVec};
 // mod wasi;
+mod simd;
 /// How large do we allow a Wasm memory to be when interpreting? Limit
 /// the size somewhat (apply an implementation limit) so we do not
 /// have unreasonably large state.
 const MAX_PAGES: usize = 2048; // 2048 * 64KiB = 128MiB
+/// Likewise, how many elements we allow a table to grow to.
+const MAX_TABLE_ELEMENTS: u64 = 1 << 20;
 /// Context for the IR interpreter. Corresponds roughly to Wasm module
 /// state.
 pub struct InterpContext {
@@ -22,7 +26,13 @@ pub struct InterpContext {
     pub tables: PerEntity<Table, InterpTable>,
     /// Values of globals.
     pub globals: PerEntity<Global, ConstVal>,
+    /// Contents of data segments, by data index. Active segments are
+    /// dropped at instantiation and, like segments dropped by
+    /// `data.drop`, are empty.
+    pub data_segments: Vec<Vec<u8>>,
     /// Fuel remaining: allows deterministic stopping of execution.
+    /// Each executed instruction or terminator uses one unit, and
+    /// execution stops with `OutOfFuel` once none is left.
     pub fuel: u64,
     pub trace_handler: Option<Box<dyn Fn(usize, Vec<ConstVal>) -> bool + Send>>,
     pub import_hander:
@@ -34,12 +44,52 @@ pub struct InterpContext {
 pub struct InterpMemory {
     pub data: Vec<u8>,
     pub max_pages: usize,
+    /// Whether the memory is indexed with `i64`s.
+    pub memory64: bool,
 }
 /// The state of one interpreter table.
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "rkyv-impl", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 pub struct InterpTable {
     pub elements: Vec<Func>,
+    /// The maximum size (in elements), if any.
+    pub max: Option<u64>,
+    /// Whether the table is indexed with `i64`s.
+    pub table64: bool,
+}
+impl InterpMemory {
+    /// Wrap an address or size as an `i64` or `i32` as appropriate.
+    fn index_val(&self, value: u64) -> ConstVal {
+        if self.memory64 {
+            ConstVal::I64(value)
+        } else {
+            ConstVal::I32(value as u32)
+        }
+    }
+    /// Unwrap an address or size operand, which must be an `i64` for
+    /// 64-bit memories and an `i32` otherwise.
+    fn index(&self, value: &ConstVal) -> Option<u64> {
+        match (self.memory64, *value) {
+            (false, ConstVal::I32(x)) => Some(x as u64),
+            (true, ConstVal::I64(x)) => Some(x),
+            _ => None,
+        }
+    }
+}
+impl InterpTable {
+    /// Wrap a table index or size as an `i64` or `i32` as appropriate.
+    fn index_val(&self, value: u64) -> ConstVal {
+        if self.table64 {
+            ConstVal::I64(value)
+        } else {
+            ConstVal::I32(value as u32)
+        }
+    }
+    /// Look up the function at `index`, if in bounds and non-null.
+    pub fn get_func(&self, index: ConstVal) -> Option<Func> {
+        let index = usize::try_from(mem_index(&index)?).ok()?;
+        self.elements.get(index).copied().filter(|f| f.is_valid())
+    }
 }
 /// One stack frame in the interpreted execution context.
 #[derive(Debug, Clone, Default)]
@@ -48,16 +98,57 @@ pub struct InterpStackFrame {
     cur_block: Block,
     values: HashMap<Value, SmallVec<[ConstVal; 2]>>,
 }
+/// Why execution trapped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TrapReason {
+    /// Integer division or remainder by zero.
+    IntegerDivByZero,
+    /// Signed division overflow, or a float-to-int truncation of a
+    /// NaN or out-of-range value.
+    IntegerOverflow,
+    /// A memory access or bulk memory operation out of bounds.
+    OutOfBoundsMemory,
+    /// An indirect call through an index past the end of the table.
+    UndefinedElement,
+    /// An indirect call through a null table entry.
+    UninitializedElement,
+    /// An indirect call to a function of the wrong signature.
+    CallIndirectTypeMismatch,
+    /// An `unreachable` instruction or terminator.
+    Unreachable,
+    /// A table access or bulk table operation out of bounds.
+    OutOfBoundsTable,
+    /// An access through a null reference, such as `i31.get_s` on
+    /// a null `i31ref`.
+    NullReference,
+    /// Any other failure, including operators the interpreter does
+    /// not implement.
+    Other,
+}
 /// The result of an interpreter session.
 #[derive(Clone, Debug)]
 pub enum InterpResult {
     /// The function returned with the given value(s).
     Ok(MultiVal),
-    /// The module trapped.
-    Trap(Func, Block, u32),
+    /// The module trapped at the given instruction index (`u32::MAX`
+    /// for a terminator).
+    Trap(Func, Block, u32, TrapReason),
     /// The module ran out of fuel.
     OutOfFuel,
 }
+/// The outcome of a single `InterpState::step`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StepResult {
+    /// Execution can continue with another step.
+    Continue,
+    /// The function returned with the given value(s).
+    Returned(Vec<ConstVal>),
+    /// Execution trapped at the given instruction index (`u32::MAX`
+    /// for a terminator).
+    Trap(Func, Block, u32, TrapReason),
+    /// The interpreter ran out of fuel.
+    OutOfFuel,
+}
 /// A constant concrete value during interpretation.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default, PartialOrd, Ord)]
 #[cfg_attr(feature = "rkyv-impl", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
@@ -69,6 +160,10 @@ pub enum ConstVal {
     #[default]
     None,
     Ref(Option<Func>),
+    /// An `i31ref`, holding its 31-bit payload in the low bits.
+    I31(u32),
+    /// A `v128`, as its bytes in little-endian lane order.
+    V128([u8; 16]),
 }
 /// Representation of multiple result values.
 type MultiVal = SmallVec<[ConstVal; 2]>;
@@ -82,6 +177,46 @@ impl InterpResult {
         }
     }
 }
+impl<'a> Module<'a> {
+    /// Run the function exported as `name` on `args` in a fresh
+    /// interpreter context, returning its results. Fails if there is
+    /// no such exported function, if `args` do not match its
+    /// parameter types, or if execution does not return normally.
+    pub fn interpret_export(&self, name: &str, args: &[ConstVal]) -> anyhow::Result<Vec<ConstVal>> {
+        let func = match self.exports().find(|&(export, _)| export == name) {
+            Some((_, ExportKind::Func(func))) => func,
+            Some(_) => anyhow::bail!("export {:?} is not a function", name),
+            None => anyhow::bail!("no export named {:?}", name),
+        };
+        if let FuncDecl::Import(..) = &self.funcs[func] {
+            anyhow::bail!("export {:?} is an imported function", name);
+        }
+        let SignatureData::Func { params, .. } = &self.signatures[self.funcs[func].sig()] else {
+            anyhow::bail!("export {:?} does not have a function signature", name);
+        };
+        if params.len() != args.len() {
+            anyhow::bail!(
+                "export {:?} takes {} arguments, but {} were given",
+                name,
+                params.len(),
+                args.len()
+            );
+        }
+        for (i, (&ty, arg)) in params.iter().zip(args).enumerate() {
+            if !arg.has_type(self, ty) {
+                anyhow::bail!(
+                    "argument {} of export {:?} should be {}, but is {:?}",
+                    i,
+                    name,
+                    ty,
+                    arg
+                );
+            }
+        }
+        let mut ctx = InterpContext::new(self)?;
+        Ok(ctx.call(self, func, args).ok()?.into_vec())
+    }
+}
 impl InterpContext {
     /// Construct a new interpreter context for the given module.
     pub fn new(module: &Module<'_>) -> anyhow::Result<Self> {
@@ -90,6 +225,7 @@ impl InterpContext {
             let mut interp_mem = InterpMemory {
                 data: vec![0; data.initial_pages * WASM_PAGE],
                 max_pages: data.maximum_pages.unwrap_or(MAX_PAGES),
+                memory64: data.memory64,
             };
             for segment in &data.segments {
                 let end = match segment.offset.checked_add(segment.data.len()) {
@@ -105,11 +241,25 @@ impl InterpContext {
         }
         let mut tables = PerEntity::default();
         for (table, data) in module.tables.entries() {
+            let mut elements = data.func_elements.clone().unwrap_or(vec![]);
+            if (elements.len() as u64) < data.initial {
+                elements.resize(data.initial as usize, Func::invalid());
+            }
             let interp_table = InterpTable {
-                elements: data.func_elements.clone().unwrap_or(vec![]),
+                elements,
+                max: data.max,
+                table64: data.table64,
             };
             tables[table] = interp_table;
         }
+        let data_segments = module
+            .data_segments
+            .iter()
+            .map(|segment| match segment {
+                DataSegment::Active { .. } => vec![],
+                DataSegment::Passive { data } => data.clone(),
+            })
+            .collect();
         let mut globals = PerEntity::default();
         for (global, data) in module.globals.entries() {
             globals[global] = match data.ty {
@@ -117,13 +267,20 @@ impl InterpContext {
                 Type::I64 => ConstVal::I64(data.value.unwrap_or(0)),
                 Type::F32 => ConstVal::F32(data.value.unwrap_or(0) as u32),
                 Type::F64 => ConstVal::F64(data.value.unwrap_or(0)),
-                _ => unimplemented!(),
+                Type::V128 => {
+                    let mut bytes = [0; 16];
+                    bytes[..8].copy_from_slice(&data.value.unwrap_or(0).to_le_bytes());
+                    ConstVal::V128(bytes)
+                }
+                // Reference-typed globals can only be initialized to null.
+                Type::Heap(_) => ConstVal::Ref(None),
             };
         }
         Ok(InterpContext {
             memories,
             tables,
             globals,
+            data_segments,
             fuel: u64::MAX,
             trace_handler: None,
             import_hander: Arc::new(|_, _, _, _| todo!()),
@@ -131,277 +288,21 @@ impl InterpContext {
     }
     /// Call the given function with the given args, running the
     /// interpreter until fuel is exhausted or the function returns.
-    pub fn call(&mut self, module: &Module<'_>, mut func: Func, args: &[ConstVal]) -> InterpResult {
-        let mut args = args.to_vec();
-        'redo: loop {
-            let body = match &module.funcs[func] {
-                #[cfg(feature = "frontend")]
-                FuncDecl::Lazy(..) => panic!("Un-expanded function"),
-                #[cfg(feature = "backend")]
-                FuncDecl::Compiled(..) => panic!("Already-compiled function"),
-                FuncDecl::Import(..) => {
-                    let import = &module.imports[func.index()];
-                    assert_eq!(import.kind, ImportKind::Func(func));
-                    // return self.call_import(module,&import.name[..], args);
-                    todo!()
-                }
-                FuncDecl::Body(_, _, body) => body,
-                FuncDecl::None { .. } => panic!("FuncDecl::None in call()"),
-            };
-            log::trace!(
-                "Interp: entering func {}:\n{}\n",
-                func,
-                body.display_verbose("| ", Some(module))
-            );
-            log::trace!("args: {:?}", args);
-            let mut frame = InterpStackFrame {
-                func,
-                cur_block: body.entry,
-                values: HashMap::new(),
-            };
-            for (&arg, &(_, blockparam)) in args.iter().zip(body.blocks[body.entry].params.iter()) {
-                log::trace!("Entry block param {} gets arg value {:?}", blockparam, arg);
-                frame.values.insert(blockparam, smallvec![arg]);
-            }
-            loop {
-                self.fuel -= 1;
-                if self.fuel == 0 {
-                    return InterpResult::OutOfFuel;
-                }
-                loop {
-                    self.fuel -= 1;
-                    if self.fuel == 0 {
-                        return InterpResult::OutOfFuel;
-                    }
-                    log::trace!("Interpreting block {}", frame.cur_block);
-                    for (inst_idx, &inst) in body.blocks[frame.cur_block]
-                        .insts
-                        .iter()
-                        .map(|a| &a.value)
-                        .enumerate()
-                    {
-                        log::trace!("Evaluating inst {}", inst);
-                        let result = match &body.values[inst] {
-                            &ValueDef::Alias(_) => smallvec![],
-                            &ValueDef::PickOutput(val, idx, _) => {
-                                let val = body.resolve_alias(val);
-                                smallvec![frame.values.get(&val).unwrap()[idx as usize]]
-                            }
-                            &ValueDef::Operator(Operator::Call { function_index }, args, _) => {
-                                let args = body.arg_pool[args]
-                                    .iter()
-                                    .map(|&arg| {
-                                        let arg = body.resolve_alias(arg);
-                                        let multivalue = frame.values.get(&arg).unwrap();
-                                        assert_eq!(multivalue.len(), 1);
-                                        multivalue[0]
-                                    })
-                                    .collect::<Vec<_>>();
-                                let result = self.call(module, function_index, &args[..]);
-                                match result {
-                                    InterpResult::Ok(vals) => vals,
-                                    _ => return result,
-                                }
-                            }
-                            &ValueDef::Operator(
-                                Operator::CallIndirect { table_index, .. },
-                                args,
-                                _,
-                            ) => {
-                                let args = body.arg_pool[args]
-                                    .iter()
-                                    .map(|&arg| {
-                                        let arg = body.resolve_alias(arg);
-                                        let multivalue = frame.values.get(&arg).unwrap();
-                                        assert_eq!(multivalue.len(), 1);
-                                        multivalue[0]
-                                    })
-                                    .collect::<Vec<_>>();
-                                let idx = args.last().unwrap().as_u32().unwrap() as usize;
-                                let func = self.tables[table_index].elements[idx];
-                                let result = self.call(module, func, &args[..args.len() - 1]);
-                                match result {
-                                    InterpResult::Ok(vals) => vals,
-                                    _ => return result,
-                                }
-                            }
-                            &ValueDef::Operator(Operator::CallRef { .. }, args, _) => {
-                                let args = body.arg_pool[args]
-                                    .iter()
-                                    .map(|&arg| {
-                                        let arg = body.resolve_alias(arg);
-                                        let multivalue = frame.values.get(&arg).unwrap();
-                                        assert_eq!(multivalue.len(), 1);
-                                        multivalue[0]
-                                    })
-                                    .collect::<Vec<_>>();
-                                let ConstVal::Ref(idx) = args.last().unwrap() else {
-                                    todo!()
-                                };
-                                let func = idx.unwrap();
-                                let result = self.call(module, func, &args[..args.len() - 1]);
-                                match result {
-                                    InterpResult::Ok(vals) => vals,
-                                    _ => return result,
-                                }
-                            }
-                            &ValueDef::Operator(ref op, args, _) => {
-                                let args = body.arg_pool[args]
-                                    .iter()
-                                    .map(|&arg| {
-                                        let arg = body.resolve_alias(arg);
-                                        let multivalue = frame
-                                            .values
-                                            .get(&arg)
-                                            .ok_or_else(|| format!("Unset SSA value: {}", arg))
-                                            .unwrap();
-                                        assert_eq!(multivalue.len(), 1);
-                                        multivalue[0]
-                                    })
-                                    .collect::<Vec<_>>();
-                                let result = match const_eval(op, &args[..], Some(self)) {
-                                    Some(result) => result,
-                                    None => {
-                                        log::trace!(
-                                            "const_eval failed on {:?} args {:?}",
-                                            op,
-                                            args
-                                        );
-                                        return InterpResult::Trap(
-                                            frame.func,
-                                            frame.cur_block,
-                                            inst_idx as u32,
-                                        );
-                                    }
-                                };
-                                smallvec![result]
-                            }
-                            &ValueDef::None
-                            | &ValueDef::Placeholder(..)
-                            | &ValueDef::BlockParam(..) => {
-                                unreachable!();
-                            }
-                        };
-                        log::trace!("Inst {} gets result {:?}", inst, result);
-                        frame.values.insert(inst, result);
-                    }
-                    match &body.blocks[frame.cur_block].terminator .terminator{
-                        crate::Terminator::UB => {
-                            return InterpResult::OutOfFuel;
-                        }
-                        &Terminator::ReturnCallIndirect {
-                            sig,
-                            table,
-                            args: ref args2,
-                        } => {
-                            let args2 = args2
-                                .iter()
-                                .map(|&arg| {
-                                    let arg = body.resolve_alias(arg);
-                                    let multivalue = frame.values.get(&arg).unwrap();
-                                    assert_eq!(multivalue.len(), 1);
-                                    multivalue[0]
-                                })
-                                .collect::<Vec<_>>();
-                            let idx = args2.last().unwrap().as_u32().unwrap() as usize;
-                            let fu = self.tables[table].elements[idx];
-                            func = fu;
-                            args = args2[..args2.len() - 1].to_vec();
-                            continue 'redo;
-                            // let result = self.call(module, func, &args[..args.len() - 1]);
-                            // return result;
-                        }
-                        &Terminator::ReturnCall {
-                            func: fu,
-                            args: ref args2,
-                        } => {
-                            let args2 = args2
-                                .iter()
-                                .map(|&arg| {
-                                    let arg = body.resolve_alias(arg);
-                                    let multivalue = frame.values.get(&arg).unwrap();
-                                    assert_eq!(multivalue.len(), 1);
-                                    multivalue[0]
-                                })
-                                .collect::<Vec<_>>();
-                            func = fu;
-                            args = args2;
-                            continue 'redo;
-                        }
-                        &Terminator::None => {
-                            return InterpResult::Trap(frame.func, frame.cur_block, u32::MAX)
-                        }
-                        &Terminator::Unreachable => {
-                            return InterpResult::Trap(frame.func, frame.cur_block, u32::MAX)
-                        }
-                        &Terminator::Br { ref target } => {
-                            frame.apply_target(&body, target);
-                        }
-                        &Terminator::CondBr {
-                            cond,
-                            ref if_true,
-                            ref if_false,
-                        } => {
-                            let cond = body.resolve_alias(cond);
-                            let cond = frame.values.get(&cond).unwrap();
-                            let cond = cond[0].as_u32().unwrap() != 0;
-                            if cond {
-                                frame.apply_target(&body, if_true);
-                            } else {
-                                frame.apply_target(&body, if_false);
-                            }
-                        }
-                        &Terminator::Select {
-                            value,
-                            ref targets,
-                            ref default,
-                        } => {
-                            let value = body.resolve_alias(value);
-                            let value = frame.values.get(&value).unwrap();
-                            let value = value[0].as_u32().unwrap() as usize;
-                            if value < targets.len() {
-                                frame.apply_target(&body, &targets[value]);
-                            } else {
-                                frame.apply_target(&body, default);
-                            }
-                        }
-                        &Terminator::Return { ref values } => {
-                            let values = values
-                                .iter()
-                                .map(|&value| {
-                                    let value = body.resolve_alias(value);
-                                    frame.values.get(&value).unwrap()[0]
-                                })
-                                .collect();
-                            log::trace!("returning from {}: {:?}", func, values);
-                            return InterpResult::Ok(values);
-                        }
-                        Terminator::ReturnCallRef {
-                            sig,
-                            args: ref args2,
-                        } => {
-                            let args2 = args2
-                                .iter()
-                                .map(|&arg| {
-                                    let arg = body.resolve_alias(arg);
-                                    let multivalue = frame.values.get(&arg).unwrap();
-                                    assert_eq!(multivalue.len(), 1);
-                                    multivalue[0]
-                                })
-                                .collect::<Vec<_>>();
-                            let ConstVal::Ref(Some(fu)) = args.last().unwrap() else {
-                                // return InterpResult::TraceHandlerQuit;
-                                todo!()
-                            };
-                            func = *fu;
-                            args = args2[..args2.len() - 1].to_vec();
-                            continue 'redo;
-                        }
-                    }
-                }
-            }
-            // break 'redo;
-        }
+    pub fn call(&mut self, module: &Module<'_>, func: Func, args: &[ConstVal]) -> InterpResult {
+        InterpState::new(self, module, func, args).run_to_completion()
+    }
+    /// Like `call`, but first set the fuel to `fuel` units, or to
+    /// unlimited with `None`. Whatever is left afterward stays in
+    /// `self.fuel`.
+    pub fn call_with_fuel(
+        &mut self,
+        module: &Module<'_>,
+        func: Func,
+        args: &[ConstVal],
+        fuel: Option<u64>,
+    ) -> InterpResult {
+        self.fuel = fuel.unwrap_or(u64::MAX);
+        self.call(module, func, args)
     }
     fn call_import(
         &mut self,
@@ -439,7 +340,349 @@ impl InterpStackFrame {
         self.cur_block = target.block;
     }
 }
+/// A suspended execution of one function, which can be advanced an
+/// instruction at a time. Calls made by the function are run to
+/// completion within a single step; tail calls replace the current
+/// frame.
+pub struct InterpState<'a, 'm> {
+    ctx: &'a mut InterpContext,
+    module: &'a Module<'m>,
+    frame: InterpStackFrame,
+    inst_idx: usize,
+}
+fn func_body<'a>(module: &'a Module<'_>, func: Func) -> &'a FunctionBody {
+    match &module.funcs[func] {
+        #[cfg(feature = "frontend")]
+        FuncDecl::Lazy(..) => panic!("Un-expanded function"),
+        #[cfg(feature = "backend")]
+        FuncDecl::Compiled(..) => panic!("Already-compiled function"),
+        FuncDecl::Import(..) => {
+            let import = &module.imports[func.index()];
+            assert_eq!(import.kind, ImportKind::Func(func));
+            // return self.call_import(module,&import.name[..], args);
+            todo!()
+        }
+        FuncDecl::Body(_, _, body) => body,
+        FuncDecl::None { .. } => panic!("FuncDecl::None in call()"),
+    }
+}
+impl<'a, 'm> InterpState<'a, 'm> {
+    /// Prepare to execute `func` with the given args, stopped before
+    /// the first instruction of its entry block.
+    pub fn new(
+        ctx: &'a mut InterpContext,
+        module: &'a Module<'m>,
+        func: Func,
+        args: &[ConstVal],
+    ) -> Self {
+        let mut state = InterpState {
+            ctx,
+            module,
+            frame: InterpStackFrame::default(),
+            inst_idx: 0,
+        };
+        state.enter(func, args);
+        state
+    }
+    fn enter(&mut self, func: Func, args: &[ConstVal]) {
+        let body = func_body(self.module, func);
+        log::trace!(
+            "Interp: entering func {}:\n{}\n",
+            func,
+            body.display_verbose("| ", Some(self.module))
+        );
+        log::trace!("args: {:?}", args);
+        self.frame = InterpStackFrame {
+            func,
+            cur_block: body.entry,
+            values: HashMap::new(),
+        };
+        self.inst_idx = 0;
+        for (&arg, &(_, blockparam)) in args.iter().zip(body.blocks[body.entry].params.iter()) {
+            log::trace!("Entry block param {} gets arg value {:?}", blockparam, arg);
+            self.frame.values.insert(blockparam, smallvec![arg]);
+        }
+    }
+    /// The function currently executing.
+    pub fn func(&self) -> Func {
+        self.frame.func
+    }
+    /// The block currently executing.
+    pub fn block(&self) -> Block {
+        self.frame.cur_block
+    }
+    /// The index of the next instruction to execute in the current
+    /// block; equal to the number of instructions when the
+    /// terminator is next.
+    pub fn inst_index(&self) -> usize {
+        self.inst_idx
+    }
+    /// The current value(s) of `value`, if it has been computed.
+    pub fn value(&self, value: Value) -> Option<&[ConstVal]> {
+        let value = func_body(self.module, self.frame.func).resolve_alias(value);
+        self.frame.values.get(&value).map(|vals| &vals[..])
+    }
+    /// The interpreter context, holding memories, tables and globals.
+    pub fn context(&mut self) -> &mut InterpContext {
+        &mut *self.ctx
+    }
+    /// Look up the target of an indirect call through `table` at
+    /// `index`, checking that it has signature `sig`.
+    fn indirect_callee(
+        &self,
+        table: Table,
+        sig: Signature,
+        index: ConstVal,
+    ) -> Result<Func, TrapReason> {
+        let elements = &self.ctx.tables[table].elements;
+        let func = mem_index(&index)
+            .and_then(|index| usize::try_from(index).ok())
+            .and_then(|index| elements.get(index).copied())
+            .ok_or(TrapReason::UndefinedElement)?;
+        if !func.is_valid() {
+            return Err(TrapReason::UninitializedElement);
+        }
+        let callee = self.module.funcs[func].sig();
+        if callee != sig && self.module.signatures[callee] != self.module.signatures[sig] {
+            return Err(TrapReason::CallIndirectTypeMismatch);
+        }
+        Ok(func)
+    }
+    fn arg_values(&self, body: &FunctionBody, args: &[Value]) -> Vec<ConstVal> {
+        args.iter()
+            .map(|&arg| {
+                let arg = body.resolve_alias(arg);
+                let multivalue = self
+                    .frame
+                    .values
+                    .get(&arg)
+                    .ok_or_else(|| format!("Unset SSA value: {}", arg))
+                    .unwrap();
+                assert_eq!(multivalue.len(), 1);
+                multivalue[0]
+            })
+            .collect()
+    }
+    fn eval_inst(&mut self, body: &FunctionBody, inst: Value) -> Result<MultiVal, InterpResult> {
+        let (func, block, idx) = (self.frame.func, self.frame.cur_block, self.inst_idx as u32);
+        let trap = move |reason| InterpResult::Trap(func, block, idx, reason);
+        Ok(match &body.values[inst] {
+            &ValueDef::Alias(_) => smallvec![],
+            &ValueDef::PickOutput(val, idx, _) => {
+                let val = body.resolve_alias(val);
+                smallvec![self.frame.values.get(&val).unwrap()[idx as usize]]
+            }
+            &ValueDef::Operator(Operator::Call { function_index }, args, _) => {
+                let args = self.arg_values(body, &body.arg_pool[args]);
+                match self.ctx.call(self.module, function_index, &args[..]) {
+                    InterpResult::Ok(vals) => vals,
+                    result => return Err(result),
+                }
+            }
+            &ValueDef::Operator(
+                Operator::CallIndirect {
+                    table_index,
+                    sig_index,
+                },
+                args,
+                _,
+            ) => {
+                let args = self.arg_values(body, &body.arg_pool[args]);
+                let func = self
+                    .indirect_callee(table_index, sig_index, *args.last().unwrap())
+                    .map_err(trap)?;
+                match self.ctx.call(self.module, func, &args[..args.len() - 1]) {
+                    InterpResult::Ok(vals) => vals,
+                    result => return Err(result),
+                }
+            }
+            &ValueDef::Operator(Operator::CallRef { .. }, args, _) => {
+                let args = self.arg_values(body, &body.arg_pool[args]);
+                let ConstVal::Ref(idx) = args.last().unwrap() else {
+                    todo!()
+                };
+                let func = idx.unwrap();
+                match self.ctx.call(self.module, func, &args[..args.len() - 1]) {
+                    InterpResult::Ok(vals) => vals,
+                    result => return Err(result),
+                }
+            }
+            &ValueDef::Operator(Operator::GlobalSet { global_index }, _, _)
+                if !self.module.globals[global_index].mutable =>
+            {
+                // Rejected by validation; trap rather than change an
+                // immutable global.
+                return Err(trap(TrapReason::Other));
+            }
+            &ValueDef::Operator(ref op, args, _) => {
+                let args = self.arg_values(body, &body.arg_pool[args]);
+                match const_eval(op, &args[..], Some(&mut *self.ctx)) {
+                    Some(result) => smallvec![result],
+                    None => {
+                        log::trace!("const_eval failed on {:?} args {:?}", op, args);
+                        return Err(trap(trap_reason(op, &args[..])));
+                    }
+                }
+            }
+            &ValueDef::None | &ValueDef::Placeholder(..) | &ValueDef::BlockParam(..) => {
+                unreachable!();
+            }
+        })
+    }
+    fn eval_terminator(&mut self, body: &FunctionBody) -> StepResult {
+        let (func, block) = (self.frame.func, self.frame.cur_block);
+        let trap = move |reason| StepResult::Trap(func, block, u32::MAX, reason);
+        match &body.blocks[self.frame.cur_block].terminator.terminator {
+            Terminator::UB => return StepResult::OutOfFuel,
+            Terminator::None | Terminator::Unreachable => return trap(TrapReason::Unreachable),
+            &Terminator::ReturnCallIndirect {
+                table,
+                sig,
+                ref args,
+            } => {
+                let args = self.arg_values(body, args);
+                let func = match self.indirect_callee(table, sig, *args.last().unwrap()) {
+                    Ok(func) => func,
+                    Err(reason) => return trap(reason),
+                };
+                self.enter(func, &args[..args.len() - 1]);
+            }
+            &Terminator::ReturnCall { func, ref args } => {
+                let args = self.arg_values(body, args);
+                self.enter(func, &args[..]);
+            }
+            Terminator::ReturnCallRef { args, .. } => {
+                let args = self.arg_values(body, args);
+                let ConstVal::Ref(Some(func)) = args.last().unwrap() else {
+                    // return InterpResult::TraceHandlerQuit;
+                    todo!()
+                };
+                let func = *func;
+                self.enter(func, &args[..args.len() - 1]);
+            }
+            Terminator::Br { target } => {
+                self.frame.apply_target(body, target);
+            }
+            &Terminator::CondBr {
+                cond,
+                ref if_true,
+                ref if_false,
+            } => {
+                let cond = body.resolve_alias(cond);
+                let cond = self.frame.values.get(&cond).unwrap();
+                let cond = cond[0].as_u32().unwrap() != 0;
+                if cond {
+                    self.frame.apply_target(body, if_true);
+                } else {
+                    self.frame.apply_target(body, if_false);
+                }
+            }
+            &Terminator::Select {
+                value,
+                ref targets,
+                ref default,
+            } => {
+                let value = body.resolve_alias(value);
+                let value = self.frame.values.get(&value).unwrap();
+                let value = value[0].as_u32().unwrap() as usize;
+                if value < targets.len() {
+                    self.frame.apply_target(body, &targets[value]);
+                } else {
+                    self.frame.apply_target(body, default);
+                }
+            }
+            Terminator::Return { values } => {
+                let values = values
+                    .iter()
+                    .map(|&value| {
+                        let value = body.resolve_alias(value);
+                        self.frame.values.get(&value).unwrap()[0]
+                    })
+                    .collect::<Vec<_>>();
+                log::trace!("returning from {}: {:?}", self.frame.func, values);
+                return StepResult::Returned(values);
+            }
+        }
+        self.inst_idx = 0;
+        StepResult::Continue
+    }
+    /// Execute the next instruction, or the terminator if the end of
+    /// the current block has been reached.
+    pub fn step(&mut self) -> StepResult {
+        let module = self.module;
+        let body = func_body(module, self.frame.func);
+        if self.ctx.fuel == 0 {
+            return StepResult::OutOfFuel;
+        }
+        self.ctx.fuel -= 1;
+        if self.inst_idx == 0 {
+            log::trace!("Interpreting block {}", self.frame.cur_block);
+        }
+        let insts = &body.blocks[self.frame.cur_block].insts;
+        if self.inst_idx == insts.len() {
+            return self.eval_terminator(body);
+        }
+        let inst = insts[self.inst_idx].value;
+        log::trace!("Evaluating inst {}", inst);
+        match self.eval_inst(body, inst) {
+            Ok(result) => {
+                log::trace!("Inst {} gets result {:?}", inst, result);
+                self.frame.values.insert(inst, result);
+                self.inst_idx += 1;
+                StepResult::Continue
+            }
+            Err(InterpResult::Trap(func, block, idx, reason)) => {
+                StepResult::Trap(func, block, idx, reason)
+            }
+            Err(InterpResult::OutOfFuel) => StepResult::OutOfFuel,
+            Err(InterpResult::Ok(_)) => unreachable!(),
+        }
+    }
+    /// Step until the function returns, traps or runs out of fuel.
+    pub fn run_to_completion(&mut self) -> InterpResult {
+        loop {
+            match self.step() {
+                StepResult::Continue => {}
+                StepResult::Returned(values) => return InterpResult::Ok(values.into()),
+                StepResult::Trap(func, block, idx, reason) => {
+                    return InterpResult::Trap(func, block, idx, reason)
+                }
+                StepResult::OutOfFuel => return InterpResult::OutOfFuel,
+            }
+        }
+    }
+}
 impl ConstVal {
+    /// Can this value be passed where a `ty` is expected? A null
+    /// matches any nullable reference type, a function reference the
+    /// `func` hierarchy and an `i31` the `any` hierarchy down to `i31`.
+    /// A function reference must name a function of `module`, and
+    /// matches a concrete `Sig` only if that is the function's exact
+    /// signature.
+    pub fn has_type(&self, module: &Module, ty: Type) -> bool {
+        match (self, ty) {
+            (ConstVal::I32(_), Type::I32)
+            | (ConstVal::I64(_), Type::I64)
+            | (ConstVal::F32(_), Type::F32)
+            | (ConstVal::F64(_), Type::F64)
+            | (ConstVal::V128(_), Type::V128) => true,
+            (ConstVal::Ref(None), Type::Heap(ty)) => ty.nullable,
+            (ConstVal::Ref(Some(f)), Type::Heap(ty)) => {
+                if f.is_invalid() || f.index() >= module.funcs.len() {
+                    return false;
+                }
+                match ty.value {
+                    HeapType::FuncRef => true,
+                    HeapType::Sig { sig_index } => module.funcs[*f].sig() == sig_index,
+                    _ => false,
+                }
+            }
+            (ConstVal::I31(_), Type::Heap(ty)) => {
+                matches!(ty.value, HeapType::Any | HeapType::Eq | HeapType::I31)
+            }
+            _ => false,
+        }
+    }
     pub fn as_u32(self) -> Option<u32> {
         match self {
             Self::I32(x) => Some(x),
@@ -455,6 +698,39 @@ impl ConstVal {
         }
     }
 }
+/// Classify why `op` trapped on `args`, given that `const_eval`
+/// failed on them.
+fn trap_reason(op: &Operator, args: &[ConstVal]) -> TrapReason {
+    match op {
+        Operator::I32DivS
+        | Operator::I32DivU
+        | Operator::I32RemS
+        | Operator::I32RemU
+        | Operator::I64DivS
+        | Operator::I64DivU
+        | Operator::I64RemS
+        | Operator::I64RemU => match args.get(1) {
+            Some(ConstVal::I32(0) | ConstVal::I64(0)) => TrapReason::IntegerDivByZero,
+            _ => TrapReason::IntegerOverflow,
+        },
+        Operator::I32TruncF32S
+        | Operator::I32TruncF32U
+        | Operator::I32TruncF64S
+        | Operator::I32TruncF64U
+        | Operator::I64TruncF32S
+        | Operator::I64TruncF32U
+        | Operator::I64TruncF64S
+        | Operator::I64TruncF64U => TrapReason::IntegerOverflow,
+        Operator::Unreachable => TrapReason::Unreachable,
+        Operator::MemoryCopy { .. } | Operator::MemoryFill { .. } | Operator::MemoryInit { .. } => {
+            TrapReason::OutOfBoundsMemory
+        }
+        Operator::TableGet { .. } | Operator::TableSet { .. } => TrapReason::OutOfBoundsTable,
+        Operator::I31GetS | Operator::I31GetU => TrapReason::NullReference,
+        op if crate::op_traits::memory_arg(op).is_some() => TrapReason::OutOfBoundsMemory,
+        _ => TrapReason::Other,
+    }
+}
 /// Constant-evaluate the given operator with the given arguments,
 /// returning a constant result if possible to know.
 pub fn const_eval(
@@ -986,303 +1262,311 @@ pub fn const_eval(
             global.globals[*global_index] = *x;
             ConstVal::None
         }),
-        (Operator::TableGet { table_index }, [ConstVal::I32(i)]) => ctx.and_then(|global| {
-            Some(ConstVal::Ref(
-                global.tables[*table_index]
-                    .elements
-                    .get(*i as usize)
-                    .and_then(|x| {
-                        if *x == Func::invalid() {
-                            None
-                        } else {
-                            Some(*x)
-                        }
-                    }),
-            ))
+        (Operator::TableGet { table_index }, [index]) => ctx.and_then(|global| {
+            let table = &global.tables[*table_index];
+            let elem = *table
+                .elements
+                .get(usize::try_from(mem_index(index)?).ok()?)?;
+            Some(ConstVal::Ref(if elem.is_valid() {
+                Some(elem)
+            } else {
+                None
+            }))
         }),
-        (Operator::TableSet { table_index }, [ConstVal::I32(i), ConstVal::Ref(r)]) => {
-            ctx.and_then(|global| {
-                global.tables[*table_index].elements[*i as usize] = r.unwrap_or_default();
-                Some(ConstVal::I32(0))
-            })
-        }
-        (Operator::TableGrow { table_index }, [ConstVal::I32(i)]) => ctx.and_then(|global| {
-            global.tables[*table_index]
+        (Operator::TableSet { table_index }, [index, ConstVal::Ref(r)]) => ctx.and_then(|global| {
+            let table = &mut global.tables[*table_index];
+            let elem = table
                 .elements
-                .extend((0..*i).map(|a| Func::default()));
-            Some(ConstVal::I32(0))
+                .get_mut(usize::try_from(mem_index(index)?).ok()?)?;
+            *elem = r.unwrap_or_default();
+            Some(ConstVal::None)
         }),
-        (Operator::TableSize { table_index }, []) => {
-            ctx.map(|global| ConstVal::I32(global.tables[*table_index].elements.len() as u32))
-        }
-        (Operator::MemorySize { mem }, []) => {
-            ctx.map(|global| ConstVal::I32((global.memories[*mem].data.len() / WASM_PAGE) as u32))
-        }
-        (Operator::MemoryGrow { mem }, [ConstVal::I32(amount)]) => ctx.and_then(|global| {
-            let cur_pages = global.memories[*mem].data.len() / WASM_PAGE;
-            let new_pages = cur_pages + (*amount as usize);
-            if new_pages > global.memories[*mem].max_pages || new_pages > MAX_PAGES {
+        (Operator::TableGrow { table_index }, [ConstVal::Ref(init), delta]) => ctx.map(|global| {
+            let table = &mut global.tables[*table_index];
+            let old = table.elements.len() as u64;
+            let new = mem_index(delta).and_then(|delta| old.checked_add(delta));
+            let result = match new {
+                Some(new)
+                    if new <= MAX_TABLE_ELEMENTS && table.max.map_or(true, |max| new <= max) =>
+                {
+                    table
+                        .elements
+                        .resize(new as usize, init.unwrap_or_default());
+                    old
+                }
+                _ => u64::MAX,
+            };
+            table.index_val(result)
+        }),
+        (Operator::TableSize { table_index }, []) => ctx.map(|global| {
+            let table = &global.tables[*table_index];
+            table.index_val(table.elements.len() as u64)
+        }),
+        (Operator::MemorySize { mem }, []) => ctx.map(|global| {
+            let memory = &global.memories[*mem];
+            memory.index_val((memory.data.len() / WASM_PAGE) as u64)
+        }),
+        (Operator::MemoryGrow { mem }, [amount]) => ctx.and_then(|global| {
+            let memory = &mut global.memories[*mem];
+            let amount = memory.index(amount)?;
+            let cur_pages = memory.data.len() / WASM_PAGE;
+            let new_pages = (cur_pages as u64).checked_add(amount)?;
+            if new_pages > memory.max_pages as u64 || new_pages > MAX_PAGES as u64 {
                 None
             } else {
-                global.memories[*mem].data.resize(new_pages * WASM_PAGE, 0);
-                Some(ConstVal::I32(cur_pages as u32))
+                memory.data.resize(new_pages as usize * WASM_PAGE, 0);
+                Some(memory.index_val(cur_pages as u64))
             }
         }),
         (Operator::Nop, []) => Some(ConstVal::None),
         (Operator::Unreachable, []) => None,
-        (Operator::I32Load { memory }, [ConstVal::I32(addr)]) => ctx.and_then(|global| {
-            let addr = addr.checked_add(memory.offset as u32)?;
-            if addr.checked_add(4)? > global.memories[memory.memory].data.len() as u32 {
-                return None;
-            }
-            Some(ConstVal::I32(read_u32(
-                &global.memories[memory.memory],
-                addr,
-            )))
+        (Operator::I32Load { memory }, [addr]) => ctx.and_then(|global| {
+            let mem = &global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 4)?;
+            Some(ConstVal::I32(read_u32(mem, addr)))
         }),
-        (Operator::I64Load { memory }, [ConstVal::I32(addr)]) => ctx.and_then(|global| {
-            let addr = addr.checked_add(memory.offset as u32)?;
-            if addr.checked_add(8)? > global.memories[memory.memory].data.len() as u32 {
-                return None;
-            }
-            Some(ConstVal::I64(read_u64(
-                &global.memories[memory.memory],
-                addr,
-            )))
+        (Operator::I64Load { memory }, [addr]) => ctx.and_then(|global| {
+            let mem = &global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 8)?;
+            Some(ConstVal::I64(read_u64(mem, addr)))
         }),
-        (Operator::F32Load { memory }, [ConstVal::I32(addr)]) => ctx.and_then(|global| {
-            let addr = addr.checked_add(memory.offset as u32)?;
-            if addr.checked_add(4)? > global.memories[memory.memory].data.len() as u32 {
-                return None;
-            }
-            Some(ConstVal::F32(read_u32(
-                &global.memories[memory.memory],
-                addr,
-            )))
+        (Operator::F32Load { memory }, [addr]) => ctx.and_then(|global| {
+            let mem = &global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 4)?;
+            Some(ConstVal::F32(read_u32(mem, addr)))
         }),
-        (Operator::F64Load { memory }, [ConstVal::I32(addr)]) => ctx.and_then(|global| {
-            let addr = addr.checked_add(memory.offset as u32)?;
-            if addr.checked_add(8)? > global.memories[memory.memory].data.len() as u32 {
-                return None;
-            }
-            Some(ConstVal::F64(read_u64(
-                &global.memories[memory.memory],
-                addr,
-            )))
+        (Operator::F64Load { memory }, [addr]) => ctx.and_then(|global| {
+            let mem = &global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 8)?;
+            Some(ConstVal::F64(read_u64(mem, addr)))
         }),
-        (Operator::I32Load8S { memory }, [ConstVal::I32(addr)]) => ctx.and_then(|global| {
-            let addr = addr.checked_add(memory.offset as u32)?;
-            if addr.checked_add(1)? > global.memories[memory.memory].data.len() as u32 {
-                return None;
-            }
-            Some(ConstVal::I32(
-                read_u8(&global.memories[memory.memory], addr) as i8 as i32 as u32,
-            ))
+        (Operator::I32Load8S { memory }, [addr]) => ctx.and_then(|global| {
+            let mem = &global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 1)?;
+            Some(ConstVal::I32(read_u8(mem, addr) as i8 as i32 as u32))
         }),
-        (Operator::I32Load8U { memory }, [ConstVal::I32(addr)]) => ctx.and_then(|global| {
-            let addr = addr.checked_add(memory.offset as u32)?;
-            if addr.checked_add(4)? > global.memories[memory.memory].data.len() as u32 {
-                return None;
-            }
-            Some(ConstVal::I32(
-                read_u8(&global.memories[memory.memory], addr) as u32,
-            ))
+        (Operator::I32Load8U { memory }, [addr]) => ctx.and_then(|global| {
+            let mem = &global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 1)?;
+            Some(ConstVal::I32(read_u8(mem, addr) as u32))
         }),
-        (Operator::I32Load16S { memory }, [ConstVal::I32(addr)]) => ctx.and_then(|global| {
-            let addr = addr.checked_add(memory.offset as u32)?;
-            if addr.checked_add(2)? > global.memories[memory.memory].data.len() as u32 {
-                return None;
-            }
-            Some(ConstVal::I32(
-                read_u16(&global.memories[memory.memory], addr) as i16 as i32 as u32,
-            ))
+        (Operator::I32Load16S { memory }, [addr]) => ctx.and_then(|global| {
+            let mem = &global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 2)?;
+            Some(ConstVal::I32(read_u16(mem, addr) as i16 as i32 as u32))
         }),
-        (Operator::I32Load16U { memory }, [ConstVal::I32(addr)]) => ctx.and_then(|global| {
-            let addr = addr.checked_add(memory.offset as u32)?;
-            if addr.checked_add(2)? > global.memories[memory.memory].data.len() as u32 {
-                return None;
-            }
-            Some(ConstVal::I32(
-                read_u16(&global.memories[memory.memory], addr) as u32,
-            ))
+        (Operator::I32Load16U { memory }, [addr]) => ctx.and_then(|global| {
+            let mem = &global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 2)?;
+            Some(ConstVal::I32(read_u16(mem, addr) as u32))
         }),
-        (Operator::I64Load8S { memory }, [ConstVal::I32(addr)]) => ctx.and_then(|global| {
-            let addr = addr.checked_add(memory.offset as u32)?;
-            if addr.checked_add(1)? > global.memories[memory.memory].data.len() as u32 {
-                return None;
-            }
-            Some(ConstVal::I64(
-                read_u8(&global.memories[memory.memory], addr) as i8 as i64 as u64,
-            ))
+        (Operator::I64Load8S { memory }, [addr]) => ctx.and_then(|global| {
+            let mem = &global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 1)?;
+            Some(ConstVal::I64(read_u8(mem, addr) as i8 as i64 as u64))
         }),
-        (Operator::I64Load8U { memory }, [ConstVal::I32(addr)]) => ctx.and_then(|global| {
-            let addr = addr.checked_add(memory.offset as u32)?;
-            if addr.checked_add(1)? > global.memories[memory.memory].data.len() as u32 {
-                return None;
-            }
-            Some(ConstVal::I64(
-                read_u8(&global.memories[memory.memory], addr) as u64,
-            ))
+        (Operator::I64Load8U { memory }, [addr]) => ctx.and_then(|global| {
+            let mem = &global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 1)?;
+            Some(ConstVal::I64(read_u8(mem, addr) as u64))
         }),
-        (Operator::I64Load16S { memory }, [ConstVal::I32(addr)]) => ctx.and_then(|global| {
-            let addr = addr.checked_add(memory.offset as u32)?;
-            if addr.checked_add(2)? > global.memories[memory.memory].data.len() as u32 {
-                return None;
-            }
-            Some(ConstVal::I64(
-                read_u16(&global.memories[memory.memory], addr) as i16 as i64 as u64,
-            ))
+        (Operator::I64Load16S { memory }, [addr]) => ctx.and_then(|global| {
+            let mem = &global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 2)?;
+            Some(ConstVal::I64(read_u16(mem, addr) as i16 as i64 as u64))
         }),
-        (Operator::I64Load16U { memory }, [ConstVal::I32(addr)]) => ctx.and_then(|global| {
-            let addr = addr.checked_add(memory.offset as u32)?;
-            if addr.checked_add(2)? > global.memories[memory.memory].data.len() as u32 {
-                return None;
-            }
-            Some(ConstVal::I64(
-                read_u16(&global.memories[memory.memory], addr) as u64,
-            ))
+        (Operator::I64Load16U { memory }, [addr]) => ctx.and_then(|global| {
+            let mem = &global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 2)?;
+            Some(ConstVal::I64(read_u16(mem, addr) as u64))
         }),
-        (Operator::I64Load32S { memory }, [ConstVal::I32(addr)]) => ctx.and_then(|global| {
-            let addr = addr.checked_add(memory.offset as u32)?;
-            if addr.checked_add(4)? > global.memories[memory.memory].data.len() as u32 {
-                return None;
-            }
-            Some(ConstVal::I64(
-                read_u32(&global.memories[memory.memory], addr) as i32 as i64 as u64,
-            ))
+        (Operator::I64Load32S { memory }, [addr]) => ctx.and_then(|global| {
+            let mem = &global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 4)?;
+            Some(ConstVal::I64(read_u32(mem, addr) as i32 as i64 as u64))
+        }),
+        (Operator::I64Load32U { memory }, [addr]) => ctx.and_then(|global| {
+            let mem = &global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 4)?;
+            Some(ConstVal::I64(read_u32(mem, addr) as u64))
+        }),
+        (Operator::I32Store { memory }, [addr, ConstVal::I32(data)]) => ctx.and_then(|global| {
+            let mem = &mut global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 4)?;
+            write_u32(mem, addr, *data);
+            Some(ConstVal::None)
+        }),
+        (Operator::I64Store { memory }, [addr, ConstVal::I64(data)]) => ctx.and_then(|global| {
+            let mem = &mut global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 8)?;
+            write_u64(mem, addr, *data);
+            Some(ConstVal::None)
         }),
-        (Operator::I64Load32U { memory }, [ConstVal::I32(addr)]) => ctx.and_then(|global| {
-            let addr = addr.checked_add(memory.offset as u32)?;
-            if addr.checked_add(4)? > global.memories[memory.memory].data.len() as u32 {
+        (Operator::I32Store8 { memory }, [addr, ConstVal::I32(data)]) => ctx.and_then(|global| {
+            let mem = &mut global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 1)?;
+            write_u8(mem, addr, *data as u8);
+            Some(ConstVal::None)
+        }),
+        (Operator::I32Store16 { memory }, [addr, ConstVal::I32(data)]) => ctx.and_then(|global| {
+            let mem = &mut global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 2)?;
+            write_u16(mem, addr, *data as u16);
+            Some(ConstVal::None)
+        }),
+        (Operator::I64Store8 { memory }, [addr, ConstVal::I64(data)]) => ctx.and_then(|global| {
+            let mem = &mut global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 1)?;
+            write_u8(mem, addr, *data as u8);
+            Some(ConstVal::None)
+        }),
+        (Operator::I64Store16 { memory }, [addr, ConstVal::I64(data)]) => ctx.and_then(|global| {
+            let mem = &mut global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 2)?;
+            write_u16(mem, addr, *data as u16);
+            Some(ConstVal::None)
+        }),
+        (Operator::I64Store32 { memory }, [addr, ConstVal::I64(data)]) => ctx.and_then(|global| {
+            let mem = &mut global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 4)?;
+            write_u32(mem, addr, *data as u32);
+            Some(ConstVal::None)
+        }),
+        (Operator::F32Store { memory }, [addr, ConstVal::F32(data)]) => ctx.and_then(|global| {
+            let mem = &mut global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 4)?;
+            write_u32(mem, addr, *data);
+            Some(ConstVal::None)
+        }),
+        (Operator::F64Store { memory }, [addr, ConstVal::F64(data)]) => ctx.and_then(|global| {
+            let mem = &mut global.memories[memory.memory];
+            let addr = effective_addr(mem, addr, memory.offset, 8)?;
+            write_u64(mem, addr, *data);
+            Some(ConstVal::None)
+        }),
+        (Operator::MemoryFill { mem }, [dst, value, len]) => ctx.and_then(|global| {
+            let memory = &global.memories[*mem];
+            let (dst, len) = (memory.index(dst)?, memory.index(len)?);
+            let ConstVal::I32(value) = value else {
+                return None;
+            };
+            if dst.checked_add(len)? > memory.data.len() as u64 {
                 return None;
             }
-            Some(ConstVal::I64(
-                read_u32(&global.memories[memory.memory], addr) as u64,
-            ))
+            let (dst, len) = (dst as usize, len as usize);
+            global.memories[*mem].data[dst..dst + len].fill(*value as u8);
+            Some(ConstVal::None)
         }),
-        (Operator::I32Store { memory }, [ConstVal::I32(addr), ConstVal::I32(data)]) => ctx
-            .and_then(|global| {
-                let addr = addr.checked_add(memory.offset as u32)?;
-                if addr.checked_add(4)? > global.memories[memory.memory].data.len() as u32 {
-                    return None;
-                }
-                write_u32(&mut global.memories[memory.memory], addr, *data);
-                Some(ConstVal::None)
-            }),
-        (Operator::I64Store { memory }, [ConstVal::I32(addr), ConstVal::I64(data)]) => ctx
-            .and_then(|global| {
-                let addr = addr.checked_add(memory.offset as u32)?;
-                if addr.checked_add(8)? > global.memories[memory.memory].data.len() as u32 {
-                    return None;
-                }
-                write_u64(&mut global.memories[memory.memory], addr, *data);
-                Some(ConstVal::None)
-            }),
-        (Operator::I32Store8 { memory }, [ConstVal::I32(addr), ConstVal::I32(data)]) => ctx
-            .and_then(|global| {
-                let addr = addr.checked_add(memory.offset as u32)?;
-                if addr.checked_add(1)? > global.memories[memory.memory].data.len() as u32 {
-                    return None;
-                }
-                write_u8(&mut global.memories[memory.memory], addr, *data as u8);
-                Some(ConstVal::None)
-            }),
-        (Operator::I32Store16 { memory }, [ConstVal::I32(addr), ConstVal::I32(data)]) => ctx
-            .and_then(|global| {
-                let addr = addr.checked_add(memory.offset as u32)?;
-                if addr.checked_add(2)? > global.memories[memory.memory].data.len() as u32 {
-                    return None;
-                }
-                write_u16(&mut global.memories[memory.memory], addr, *data as u16);
-                Some(ConstVal::None)
-            }),
-        (Operator::I64Store8 { memory }, [ConstVal::I32(addr), ConstVal::I64(data)]) => ctx
-            .and_then(|global| {
-                let addr = addr.checked_add(memory.offset as u32)?;
-                if addr.checked_add(1)? > global.memories[memory.memory].data.len() as u32 {
-                    return None;
-                }
-                write_u8(&mut global.memories[memory.memory], addr, *data as u8);
-                Some(ConstVal::None)
-            }),
-        (Operator::I64Store16 { memory }, [ConstVal::I32(addr), ConstVal::I64(data)]) => ctx
-            .and_then(|global| {
-                let addr = addr.checked_add(memory.offset as u32)?;
-                if addr.checked_add(2)? > global.memories[memory.memory].data.len() as u32 {
-                    return None;
-                }
-                write_u16(&mut global.memories[memory.memory], addr, *data as u16);
-                Some(ConstVal::None)
-            }),
-        (Operator::I64Store32 { memory }, [ConstVal::I32(addr), ConstVal::I64(data)]) => ctx
-            .and_then(|global| {
-                let addr = addr.checked_add(memory.offset as u32)?;
-                if addr.checked_add(4)? > global.memories[memory.memory].data.len() as u32 {
-                    return None;
-                }
-                write_u32(&mut global.memories[memory.memory], addr, *data as u32);
-                Some(ConstVal::None)
-            }),
-        (Operator::F32Store { memory }, [ConstVal::I32(addr), ConstVal::F32(data)]) => ctx
-            .and_then(|global| {
-                let addr = addr.checked_add(memory.offset as u32)?;
-                if addr.checked_add(4)? > global.memories[memory.memory].data.len() as u32 {
-                    return None;
-                }
-                write_u32(&mut global.memories[memory.memory], addr, *data);
-                Some(ConstVal::None)
-            }),
-        (Operator::F64Store { memory }, [ConstVal::I32(addr), ConstVal::F64(data)]) => ctx
-            .and_then(|global| {
-                let addr = addr.checked_add(memory.offset as u32)?;
-                if addr.checked_add(8)? > global.memories[memory.memory].data.len() as u32 {
+        (Operator::MemoryInit { mem, data_idx }, [dst, ConstVal::I32(src), ConstVal::I32(len)]) => {
+            ctx.and_then(|global| {
+                let segment = global.data_segments.get(*data_idx as usize)?;
+                let memory = &global.memories[*mem];
+                let (dst, src, len) = (memory.index(dst)?, *src as u64, *len as u64);
+                if dst.checked_add(len)? > memory.data.len() as u64
+                    || src + len > segment.len() as u64
+                {
                     return None;
                 }
-                write_u64(&mut global.memories[memory.memory], addr, *data);
+                let (dst, src, len) = (dst as usize, src as usize, len as usize);
+                let bytes = &segment[src..src + len];
+                global.memories[*mem].data[dst..dst + len].copy_from_slice(bytes);
                 Some(ConstVal::None)
-            }),
+            })
+        }
+        (Operator::DataDrop { data_idx }, []) => ctx.and_then(|global| {
+            *global.data_segments.get_mut(*data_idx as usize)? = vec![];
+            Some(ConstVal::None)
+        }),
+        (Operator::MemoryCopy { dst_mem, src_mem }, [dst, src, len]) => ctx.and_then(|global| {
+            // Each address has its own memory's index type; the length
+            // is an `i64` only when both memories are 64-bit.
+            let (to, from) = (&global.memories[*dst_mem], &global.memories[*src_mem]);
+            let len = match (to.memory64 && from.memory64, *len) {
+                (false, ConstVal::I32(len)) => len as u64,
+                (true, ConstVal::I64(len)) => len,
+                _ => return None,
+            };
+            let (dst, src) = (to.index(dst)?, from.index(src)?);
+            if dst.checked_add(len)? > to.data.len() as u64
+                || src.checked_add(len)? > from.data.len() as u64
+            {
+                return None;
+            }
+            let (dst, src, len) = (dst as usize, src as usize, len as usize);
+            if dst_mem == src_mem {
+                global.memories[*dst_mem]
+                    .data
+                    .copy_within(src..src + len, dst);
+            } else {
+                let bytes = global.memories[*src_mem].data[src..src + len].to_vec();
+                global.memories[*dst_mem].data[dst..dst + len].copy_from_slice(&bytes[..]);
+            }
+            Some(ConstVal::None)
+        }),
         (Operator::RefFunc { func_index }, []) => Some(ConstVal::Ref(Some(*func_index))),
         (Operator::RefNull { ty: Type::Heap(_) }, []) => Some(ConstVal::Ref(None)),
         (Operator::RefIsNull, [ConstVal::Ref(r)]) => {
             Some(ConstVal::I32(if r.is_none() { 1 } else { 0 }))
         }
+        (Operator::RefIsNull, [ConstVal::I31(_)]) => Some(ConstVal::I32(0)),
+        (
+            Operator::RefEq,
+            [a @ (ConstVal::Ref(_) | ConstVal::I31(_)), b @ (ConstVal::Ref(_) | ConstVal::I31(_))],
+        ) => Some(ConstVal::I32(if a == b { 1 } else { 0 })),
+        (Operator::RefI31, [ConstVal::I32(x)]) => Some(ConstVal::I31(x & 0x7fff_ffff)),
+        (Operator::I31GetS, [ConstVal::I31(x)]) => {
+            Some(ConstVal::I32((((x << 1) as i32) >> 1) as u32))
+        }
+        (Operator::I31GetU, [ConstVal::I31(x)]) => Some(ConstVal::I32(*x)),
         (_, args) if args.iter().any(|&arg| arg == ConstVal::None) => None,
+        _ => simd::const_eval_simd(op, vals),
+    }
+}
+/// An address, length or table-index operand, which is an `i64` for
+/// 64-bit memories and tables and an `i32` otherwise.
+fn mem_index(val: &ConstVal) -> Option<u64> {
+    match *val {
+        ConstVal::I32(x) => Some(x as u64),
+        ConstVal::I64(x) => Some(x),
         _ => None,
     }
 }
-pub(crate) fn read_u8(mem: &InterpMemory, addr: u32) -> u8 {
-    let addr = addr as usize;
+/// The effective address of a `size`-byte access at `addr` plus the
+/// static `offset`, or `None` if any of it is out of bounds. The sum
+/// is taken in 64 bits, so neither a 32-bit nor a 64-bit address can
+/// wrap around.
+fn effective_addr(mem: &InterpMemory, addr: &ConstVal, offset: u64, size: u64) -> Option<usize> {
+    let addr = mem.index(addr)?.checked_add(offset)?;
+    if addr.checked_add(size)? > mem.data.len() as u64 {
+        return None;
+    }
+    Some(addr as usize)
+}
+pub(crate) fn read_u8(mem: &InterpMemory, addr: usize) -> u8 {
     mem.data[addr]
 }
-pub(crate) fn read_u16(mem: &InterpMemory, addr: u32) -> u16 {
+pub(crate) fn read_u16(mem: &InterpMemory, addr: usize) -> u16 {
     use core::convert::TryInto;
-    let addr = addr as usize;
     u16::from_le_bytes(mem.data[addr..(addr + 2)].try_into().unwrap())
 }
-pub(crate) fn read_u32(mem: &InterpMemory, addr: u32) -> u32 {
+pub(crate) fn read_u32(mem: &InterpMemory, addr: usize) -> u32 {
     use core::convert::TryInto;
-    let addr = addr as usize;
     u32::from_le_bytes(mem.data[addr..(addr + 4)].try_into().unwrap())
 }
-pub(crate) fn read_u64(mem: &InterpMemory, addr: u32) -> u64 {
+pub(crate) fn read_u64(mem: &InterpMemory, addr: usize) -> u64 {
     use core::convert::TryInto;
-    let addr = addr as usize;
     u64::from_le_bytes(mem.data[addr..(addr + 8)].try_into().unwrap())
 }
-pub(crate) fn write_u8(mem: &mut InterpMemory, addr: u32, data: u8) {
-    let addr = addr as usize;
+pub(crate) fn write_u8(mem: &mut InterpMemory, addr: usize, data: u8) {
     mem.data[addr] = data;
 }
-pub(crate) fn write_u16(mem: &mut InterpMemory, addr: u32, data: u16) {
-    let addr = addr as usize;
+pub(crate) fn write_u16(mem: &mut InterpMemory, addr: usize, data: u16) {
     mem.data[addr..(addr + 2)].copy_from_slice(&data.to_le_bytes()[..]);
 }
-pub(crate) fn write_u32(mem: &mut InterpMemory, addr: u32, data: u32) {
-    let addr = addr as usize;
+pub(crate) fn write_u32(mem: &mut InterpMemory, addr: usize, data: u32) {
     mem.data[addr..(addr + 4)].copy_from_slice(&data.to_le_bytes()[..]);
 }
-pub(crate) fn write_u64(mem: &mut InterpMemory, addr: u32, data: u64) {
-    let addr = addr as usize;
+pub(crate) fn write_u64(mem: &mut InterpMemory, addr: usize, data: u64) {
     mem.data[addr..(addr + 8)].copy_from_slice(&data.to_le_bytes()[..]);
 }
 // Min/max implementations with proper handling for negative-zero (as
@@ -1348,3 +1632,1107 @@ fn f64_max(a: f64, b: f64) -> f64 {
         f64::NAN
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::MemoryArg;
+    use core::convert::TryInto;
+
+    /// Build a module with one page of memory and a single function
+    /// returning two `i32`s, whose body is filled in by `build`.
+    fn module_with_body<'a>(
+        build: impl FnOnce(&mut FunctionBody, Block, Memory) -> Vec<Value>,
+    ) -> (Module<'a>, Func) {
+        let mut module = Module::empty();
+        let mem = module.memories.push(MemoryData {
+            initial_pages: 1,
+            maximum_pages: None,
+            segments: vec![],
+            memory64: false,
+            shared: false,
+            page_size_log2: None,
+        });
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![],
+            returns: vec![Type::I32, Type::I32],
+            shared: false,
+        });
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let values = build(&mut body, entry, mem);
+        body.set_terminator(entry, Terminator::Return { values });
+        let func = module.funcs.push(FuncDecl::Body(sig, "f".into(), body));
+        (module, func)
+    }
+
+    fn i32_const(body: &mut FunctionBody, block: Block, value: u32) -> Value {
+        body.add_op(block, Operator::I32Const { value }, &[], &[Type::I32])
+    }
+
+    fn memarg(memory: Memory) -> MemoryArg {
+        MemoryArg {
+            align: 0,
+            offset: 0,
+            memory,
+        }
+    }
+
+    #[test]
+    fn interpret_export_checks_args() {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32, Type::I32],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let (x, y) = (
+            body.blocks[entry].params[0].1,
+            body.blocks[entry].params[1].1,
+        );
+        let sum = body.add_op(entry, Operator::I32Add, &[x, y], &[Type::I32]);
+        body.set_terminator(entry, Terminator::Return { values: vec![sum] });
+        let func = module.funcs.push(FuncDecl::Body(sig, "add".into(), body));
+        module.add_export("add", ExportKind::Func(func));
+        module.add_export("g", ExportKind::Global(Global::new(0)));
+        let import = module.funcs.push(FuncDecl::Import(sig, "imp".into()));
+        module.add_export("imp", ExportKind::Func(import));
+
+        assert_eq!(
+            module
+                .interpret_export("add", &[ConstVal::I32(2), ConstVal::I32(3)])
+                .unwrap(),
+            vec![ConstVal::I32(5)]
+        );
+        assert!(module
+            .interpret_export("add", &[ConstVal::I32(2), ConstVal::I64(3)])
+            .is_err());
+        assert!(module.interpret_export("add", &[ConstVal::I32(2)]).is_err());
+        assert!(module.interpret_export("g", &[]).is_err());
+        assert!(module.interpret_export("missing", &[]).is_err());
+        assert!(module
+            .interpret_export("imp", &[ConstVal::I32(2), ConstVal::I32(3)])
+            .is_err());
+    }
+
+    #[test]
+    fn ref_args_are_checked_by_heap_type() {
+        let heap = |value, nullable| Type::Heap(WithNullable { value, nullable });
+        let (mut module, f) = module_with_body(|body, block, _| vec![i32_const(body, block, 0); 2]);
+        let sig = module.funcs[f].sig();
+        let other = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![],
+            shared: false,
+        });
+        let func = ConstVal::Ref(Some(f));
+        let missing = ConstVal::Ref(Some(Func::new(1)));
+        let null = ConstVal::Ref(None);
+        let i31 = ConstVal::I31(7);
+        let module = &module;
+
+        assert!(func.has_type(module, heap(HeapType::FuncRef, false)));
+        assert!(func.has_type(module, heap(HeapType::Sig { sig_index: sig }, false)));
+        assert!(!func.has_type(module, heap(HeapType::Sig { sig_index: other }, true)));
+        assert!(!func.has_type(module, heap(HeapType::Eq, true)));
+        assert!(!missing.has_type(module, heap(HeapType::FuncRef, true)));
+        assert!(i31.has_type(module, heap(HeapType::Eq, false)));
+        assert!(i31.has_type(module, heap(HeapType::I31, false)));
+        assert!(!i31.has_type(module, heap(HeapType::FuncRef, true)));
+        assert!(!i31.has_type(module, heap(HeapType::Struct, true)));
+        assert!(null.has_type(module, heap(HeapType::FuncRef, true)));
+        assert!(!null.has_type(module, heap(HeapType::I31, false)));
+        assert!(!i31.has_type(module, Type::I32));
+    }
+
+    #[test]
+    fn ref_eq() {
+        let eq_ref = Type::Heap(WithNullable {
+            value: HeapType::Eq,
+            nullable: true,
+        });
+        let i31_ref = Type::Heap(WithNullable {
+            value: HeapType::I31,
+            nullable: false,
+        });
+        let run = |refs: [Option<u32>; 4]| {
+            let (module, func) = module_with_body(|body, block, _| {
+                let vals: Vec<Value> = refs
+                    .iter()
+                    .map(|r| match *r {
+                        Some(value) => {
+                            let value = i32_const(body, block, value);
+                            body.add_op(block, Operator::RefI31, &[value], &[i31_ref])
+                        }
+                        None => {
+                            body.add_op(block, Operator::RefNull { ty: eq_ref }, &[], &[eq_ref])
+                        }
+                    })
+                    .collect();
+                vals.chunks(2)
+                    .map(|pair| body.add_op(block, Operator::RefEq, pair, &[Type::I32]))
+                    .collect()
+            });
+            let mut ctx = InterpContext::new(&module).unwrap();
+            ctx.call(&module, func, &[]).ok().unwrap().to_vec()
+        };
+        let (f, g) = (Some(1), Some(2));
+        assert_eq!(
+            run([f, f, None, None]),
+            vec![ConstVal::I32(1), ConstVal::I32(1)]
+        );
+        assert_eq!(
+            run([f, g, f, None]),
+            vec![ConstVal::I32(0), ConstVal::I32(0)]
+        );
+    }
+
+    #[test]
+    fn i31_box_and_unbox() {
+        let eval = |op: Operator, arg: ConstVal| const_eval(&op, &[arg], None);
+        for (value, signed, unsigned) in [
+            (5, 5, 5),
+            // Bit 31 is dropped; bit 30 becomes the sign.
+            (0xffff_fffe, 0xffff_fffe, 0x7fff_fffe),
+            (0x4000_0000, 0xc000_0000, 0x4000_0000),
+            (0x8000_0001, 1, 1),
+        ] {
+            let boxed = eval(Operator::RefI31, ConstVal::I32(value)).unwrap();
+            assert_eq!(eval(Operator::I31GetS, boxed), Some(ConstVal::I32(signed)));
+            assert_eq!(
+                eval(Operator::I31GetU, boxed),
+                Some(ConstVal::I32(unsigned))
+            );
+            assert_eq!(eval(Operator::RefIsNull, boxed), Some(ConstVal::I32(0)));
+        }
+
+        let same = [
+            eval(Operator::RefI31, ConstVal::I32(0x8000_0007)).unwrap(),
+            eval(Operator::RefI31, ConstVal::I32(7)).unwrap(),
+        ];
+        assert_eq!(
+            const_eval(&Operator::RefEq, &same, None),
+            Some(ConstVal::I32(1))
+        );
+        let mixed = [same[0], ConstVal::Ref(None)];
+        assert_eq!(
+            const_eval(&Operator::RefEq, &mixed, None),
+            Some(ConstVal::I32(0))
+        );
+
+        assert_eq!(eval(Operator::I31GetU, ConstVal::Ref(None)), None);
+        assert_eq!(
+            trap_reason(&Operator::I31GetS, &[ConstVal::Ref(None)]),
+            TrapReason::NullReference
+        );
+    }
+
+    #[test]
+    fn simd_lanes() {
+        let (module, func) = module_with_body(|body, block, _| {
+            let seven = i32_const(body, block, 7);
+            let v = body.add_op(block, Operator::I32x4Splat, &[seven], &[Type::V128]);
+            let forty = i32_const(body, block, 40);
+            let v = body.add_op(
+                block,
+                Operator::I32x4ReplaceLane { lane: 2 },
+                &[v, forty],
+                &[Type::V128],
+            );
+            let ones = body.add_op(
+                block,
+                Operator::V128Const {
+                    value: 0x00000001_00000001_00000001_00000001,
+                },
+                &[],
+                &[Type::V128],
+            );
+            let sum = body.add_op(block, Operator::I32x4Add, &[v, ones], &[Type::V128]);
+            let a = body.add_op(
+                block,
+                Operator::I32x4ExtractLane { lane: 2 },
+                &[sum],
+                &[Type::I32],
+            );
+            let b = body.add_op(
+                block,
+                Operator::I32x4ExtractLane { lane: 0 },
+                &[sum],
+                &[Type::I32],
+            );
+            vec![a, b]
+        });
+        let mut ctx = InterpContext::new(&module).unwrap();
+        let result = ctx.call(&module, func, &[]).ok().unwrap();
+        assert_eq!(&result[..], &[ConstVal::I32(41), ConstVal::I32(8)]);
+    }
+
+    #[test]
+    fn simd_wraparound_and_sign() {
+        let max = const_eval(&Operator::I8x16Splat, &[ConstVal::I32(0xff)], None).unwrap();
+        let one = const_eval(&Operator::I8x16Splat, &[ConstVal::I32(1)], None).unwrap();
+        let sum = const_eval(&Operator::I8x16Add, &[max, one], None).unwrap();
+        assert_eq!(sum, ConstVal::V128([0; 16]));
+        let lane = |op: Operator| const_eval(&op, &[max], None).unwrap();
+        assert_eq!(
+            lane(Operator::I8x16ExtractLaneS { lane: 15 }),
+            ConstVal::I32(u32::MAX)
+        );
+        assert_eq!(
+            lane(Operator::I8x16ExtractLaneU { lane: 15 }),
+            ConstVal::I32(0xff)
+        );
+        assert_eq!(
+            const_eval(&Operator::I8x16ExtractLaneU { lane: 16 }, &[max], None),
+            None
+        );
+
+        let v = const_eval(
+            &Operator::V128Const {
+                value: 0x0f0e0d0c_0b0a0908_07060504_03020100,
+            },
+            &[],
+            None,
+        )
+        .unwrap();
+        let bytes: Vec<u8> = (0..16).collect();
+        assert_eq!(v, ConstVal::V128(bytes[..].try_into().unwrap()));
+        assert_eq!(
+            const_eval(&Operator::I64x2ExtractLane { lane: 1 }, &[v], None),
+            Some(ConstVal::I64(0x0f0e0d0c_0b0a0908))
+        );
+    }
+
+    #[test]
+    fn memory_fill() {
+        let (module, func) = module_with_body(|body, block, mem| {
+            let dst = i32_const(body, block, 16);
+            let val = i32_const(body, block, 0x1ab);
+            let len = i32_const(body, block, 8);
+            body.add_op(block, Operator::MemoryFill { mem }, &[dst, val, len], &[]);
+            let a = i32_const(body, block, 16);
+            let b = i32_const(body, block, 21);
+            let memory = memarg(mem);
+            let a = body.add_op(block, Operator::I32Load { memory }, &[a], &[Type::I32]);
+            let b = body.add_op(block, Operator::I32Load { memory }, &[b], &[Type::I32]);
+            vec![a, b]
+        });
+        let mut ctx = InterpContext::new(&module).unwrap();
+        let result = ctx.call(&module, func, &[]).ok().unwrap();
+        assert_eq!(
+            &result[..],
+            &[ConstVal::I32(0xabab_abab), ConstVal::I32(0x00ab_abab)]
+        );
+    }
+
+    #[test]
+    fn memory_copy_overlapping() {
+        let (module, func) = module_with_body(|body, block, mem| {
+            let memory = memarg(mem);
+            let lo = i32_const(body, block, 0);
+            let hi = i32_const(body, block, 4);
+            let lo_val = i32_const(body, block, 0x0403_0201);
+            let hi_val = i32_const(body, block, 0x0807_0605);
+            body.add_op(block, Operator::I32Store { memory }, &[lo, lo_val], &[]);
+            body.add_op(block, Operator::I32Store { memory }, &[hi, hi_val], &[]);
+            let dst = i32_const(body, block, 2);
+            let len = i32_const(body, block, 6);
+            body.add_op(
+                block,
+                Operator::MemoryCopy {
+                    dst_mem: mem,
+                    src_mem: mem,
+                },
+                &[dst, lo, len],
+                &[],
+            );
+            let a = body.add_op(block, Operator::I32Load { memory }, &[lo], &[Type::I32]);
+            let b = body.add_op(block, Operator::I32Load { memory }, &[hi], &[Type::I32]);
+            vec![a, b]
+        });
+        let mut ctx = InterpContext::new(&module).unwrap();
+        let result = ctx.call(&module, func, &[]).ok().unwrap();
+        assert_eq!(
+            &result[..],
+            &[ConstVal::I32(0x0201_0201), ConstVal::I32(0x0605_0403)]
+        );
+    }
+
+    #[test]
+    fn memory_copy_between_memories() {
+        let mut module = Module::empty();
+        let mut memory = |memory64| {
+            module.memories.push(MemoryData {
+                initial_pages: 1,
+                maximum_pages: None,
+                segments: vec![],
+                memory64,
+                shared: false,
+                page_size_log2: None,
+            })
+        };
+        let (src_mem, dst_mem) = (memory(false), memory(true));
+        let mut ctx = InterpContext::new(&module).unwrap();
+        ctx.memories[src_mem].data[16..20].copy_from_slice(&[1, 2, 3, 4]);
+        let before = ctx.memories[src_mem].clone();
+        let copy = Operator::MemoryCopy { dst_mem, src_mem };
+
+        // i64 destination, i32 source, and an i32 length since only
+        // one of the memories is 64-bit.
+        let args = [ConstVal::I64(100), ConstVal::I32(16), ConstVal::I32(4)];
+        assert_eq!(
+            const_eval(&copy, &args, Some(&mut ctx)),
+            Some(ConstVal::None)
+        );
+        assert_eq!(
+            &ctx.memories[dst_mem].data[98..106],
+            &[0, 0, 1, 2, 3, 4, 0, 0]
+        );
+        assert_eq!(ctx.memories[src_mem], before);
+
+        // Index types must follow each memory's own width.
+        let swapped = [ConstVal::I32(100), ConstVal::I64(16), ConstVal::I32(4)];
+        assert_eq!(const_eval(&copy, &swapped, Some(&mut ctx)), None);
+        let wide_len = [ConstVal::I64(100), ConstVal::I32(16), ConstVal::I64(4)];
+        assert_eq!(const_eval(&copy, &wide_len, Some(&mut ctx)), None);
+        // Bounds are checked against each memory separately.
+        let page = WASM_PAGE as u32;
+        let past_src = [ConstVal::I64(0), ConstVal::I32(page - 2), ConstVal::I32(4)];
+        assert_eq!(const_eval(&copy, &past_src, Some(&mut ctx)), None);
+        ctx.memories[dst_mem].data.truncate(64);
+        let past_dst = [ConstVal::I64(62), ConstVal::I32(16), ConstVal::I32(4)];
+        assert_eq!(const_eval(&copy, &past_dst, Some(&mut ctx)), None);
+    }
+
+    #[test]
+    fn table_grow_is_capped() {
+        let mut module = Module::empty();
+        let table = module.tables.push(TableData {
+            ty: Type::Heap(WithNullable {
+                value: HeapType::FuncRef,
+                nullable: true,
+            }),
+            initial: 1,
+            max: None,
+            func_elements: None,
+            table64: false,
+        });
+        let mut ctx = InterpContext::new(&module).unwrap();
+        let grow = Operator::TableGrow { table_index: table };
+        let mut grow_by = |delta| {
+            const_eval(
+                &grow,
+                &[ConstVal::Ref(None), ConstVal::I32(delta)],
+                Some(&mut ctx),
+            )
+        };
+        assert_eq!(grow_by(u32::MAX - 1), Some(ConstVal::I32(u32::MAX)));
+        assert_eq!(grow_by(3), Some(ConstVal::I32(1)));
+        assert_eq!(ctx.tables[table].elements.len(), 4);
+    }
+
+    #[test]
+    fn memory_init_and_data_drop() {
+        let mut module = Module::empty();
+        let mem = module.memories.push(MemoryData {
+            initial_pages: 1,
+            maximum_pages: None,
+            segments: vec![],
+            memory64: false,
+            shared: false,
+            page_size_log2: None,
+        });
+        module.data_segments.push(DataSegment::Active {
+            memory: mem,
+            segment: 0,
+        });
+        module.data_segments.push(DataSegment::Passive {
+            data: vec![1, 2, 3, 4],
+        });
+        let mut ctx = InterpContext::new(&module).unwrap();
+        let init = |data_idx| Operator::MemoryInit { mem, data_idx };
+        let args = |dst, src, len| [ConstVal::I32(dst), ConstVal::I32(src), ConstVal::I32(len)];
+
+        assert_eq!(
+            const_eval(&init(1), &args(10, 1, 3), Some(&mut ctx)),
+            Some(ConstVal::None)
+        );
+        assert_eq!(&ctx.memories[mem].data[9..14], &[0, 2, 3, 4, 0]);
+        // Past the end of the segment, or of memory.
+        assert_eq!(const_eval(&init(1), &args(0, 2, 3), Some(&mut ctx)), None);
+        let page = WASM_PAGE as u32;
+        assert_eq!(
+            const_eval(&init(1), &args(page - 1, 0, 2), Some(&mut ctx)),
+            None
+        );
+        // Active segments are already dropped: only empty copies work.
+        assert_eq!(const_eval(&init(0), &args(0, 0, 1), Some(&mut ctx)), None);
+        assert_eq!(
+            const_eval(&init(0), &args(0, 0, 0), Some(&mut ctx)),
+            Some(ConstVal::None)
+        );
+
+        let drop = Operator::DataDrop { data_idx: 1 };
+        assert_eq!(const_eval(&drop, &[], Some(&mut ctx)), Some(ConstVal::None));
+        assert_eq!(const_eval(&init(1), &args(0, 0, 1), Some(&mut ctx)), None);
+        assert_eq!(
+            const_eval(&init(1), &args(0, 0, 0), Some(&mut ctx)),
+            Some(ConstVal::None)
+        );
+        // Dropping twice is allowed.
+        assert_eq!(const_eval(&drop, &[], Some(&mut ctx)), Some(ConstVal::None));
+    }
+
+    #[test]
+    fn memory_fill_out_of_bounds() {
+        let (module, func) = module_with_body(|body, block, mem| {
+            let dst = i32_const(body, block, WASM_PAGE as u32 - 4);
+            let val = i32_const(body, block, 0);
+            let len = i32_const(body, block, 8);
+            body.add_op(block, Operator::MemoryFill { mem }, &[dst, val, len], &[]);
+            vec![dst, dst]
+        });
+        let mut ctx = InterpContext::new(&module).unwrap();
+        assert!(matches!(
+            ctx.call(&module, func, &[]),
+            InterpResult::Trap(..)
+        ));
+    }
+
+    #[test]
+    fn memory64_addresses() {
+        let mut module = Module::empty();
+        let mem = module.memories.push(MemoryData {
+            initial_pages: 1,
+            maximum_pages: None,
+            segments: vec![],
+            memory64: true,
+            shared: false,
+            page_size_log2: None,
+        });
+        let mut ctx = InterpContext::new(&module).unwrap();
+        let mut eval = |op: Operator, args: &[ConstVal]| const_eval(&op, args, Some(&mut ctx));
+        let memory = MemoryArg {
+            align: 3,
+            offset: 8,
+            memory: mem,
+        };
+
+        assert_eq!(
+            eval(Operator::MemoryGrow { mem }, &[ConstVal::I64(1)]),
+            Some(ConstVal::I64(1))
+        );
+        assert_eq!(
+            eval(Operator::MemorySize { mem }, &[]),
+            Some(ConstVal::I64(2))
+        );
+        let addr = ConstVal::I64(WASM_PAGE as u64);
+        assert_eq!(
+            eval(Operator::I64Store { memory }, &[addr, ConstVal::I64(42)]),
+            Some(ConstVal::None)
+        );
+        assert_eq!(
+            eval(Operator::I64Load { memory }, &[addr]),
+            Some(ConstVal::I64(42))
+        );
+        // The interpreter caps memories well below 4GiB, so accesses
+        // above 2^32 must trap rather than wrap around to low memory.
+        let high = ConstVal::I64((1 << 32) + WASM_PAGE as u64);
+        assert_eq!(
+            eval(Operator::I64Store { memory }, &[high, ConstVal::I64(7)]),
+            None
+        );
+        let far = MemoryArg {
+            offset: 1 << 32,
+            ..memory
+        };
+        assert_eq!(eval(Operator::I64Load { memory: far }, &[addr]), None);
+        assert_eq!(
+            eval(Operator::I64Load { memory }, &[addr]),
+            Some(ConstVal::I64(42))
+        );
+        // A 64-bit memory takes only 64-bit addresses.
+        assert_eq!(
+            eval(Operator::I64Load { memory }, &[ConstVal::I32(0)]),
+            None
+        );
+    }
+
+    #[test]
+    fn fuel_stops_infinite_loop() {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![],
+            returns: vec![],
+            shared: false,
+        });
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let header = body.add_block();
+        let i = body.add_blockparam(header, Type::I32);
+        let zero = i32_const(&mut body, entry, 0);
+        body.set_terminator(
+            entry,
+            Terminator::Br {
+                target: BlockTarget {
+                    block: header,
+                    args: vec![zero],
+                },
+            },
+        );
+        let one = i32_const(&mut body, header, 1);
+        let i2 = body.add_op(header, Operator::I32Add, &[i, one], &[Type::I32]);
+        body.set_terminator(
+            header,
+            Terminator::Br {
+                target: BlockTarget {
+                    block: header,
+                    args: vec![i2],
+                },
+            },
+        );
+        let func = module.funcs.push(FuncDecl::Body(sig, "spin".into(), body));
+
+        // Two steps to enter the loop, then three per iteration.
+        let fuel = 2 + 3 * 4;
+        let mut ctx = InterpContext::new(&module).unwrap();
+        let mut state = InterpState::new(&mut ctx, &module, func, &[]);
+        state.context().fuel = fuel;
+        let mut steps = 0;
+        loop {
+            match state.step() {
+                StepResult::Continue => steps += 1,
+                StepResult::OutOfFuel => break,
+                other => panic!("unexpected step result {:?}", other),
+            }
+        }
+        assert_eq!(steps, fuel);
+        assert_eq!(state.value(i), Some(&[ConstVal::I32(4)][..]));
+
+        assert!(matches!(
+            ctx.call_with_fuel(&module, func, &[], Some(100)),
+            InterpResult::OutOfFuel
+        ));
+        assert_eq!(ctx.fuel, 0);
+    }
+
+    #[test]
+    fn step_through_loop() {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let n = body.blocks[entry].params[0].1;
+        let header = body.add_block();
+        let exit = body.add_block();
+        let i = body.add_blockparam(header, Type::I32);
+        let acc = body.add_blockparam(header, Type::I32);
+        let result = body.add_blockparam(exit, Type::I32);
+        let zero = i32_const(&mut body, entry, 0);
+        body.set_terminator(
+            entry,
+            Terminator::Br {
+                target: BlockTarget {
+                    block: header,
+                    args: vec![zero, zero],
+                },
+            },
+        );
+        let acc2 = body.add_op(header, Operator::I32Add, &[acc, i], &[Type::I32]);
+        let one = i32_const(&mut body, header, 1);
+        let i2 = body.add_op(header, Operator::I32Add, &[i, one], &[Type::I32]);
+        let more = body.add_op(header, Operator::I32LtU, &[i2, n], &[Type::I32]);
+        body.set_terminator(
+            header,
+            Terminator::CondBr {
+                cond: more,
+                if_true: BlockTarget {
+                    block: header,
+                    args: vec![i2, acc2],
+                },
+                if_false: BlockTarget {
+                    block: exit,
+                    args: vec![acc2],
+                },
+            },
+        );
+        body.set_terminator(
+            exit,
+            Terminator::Return {
+                values: vec![result],
+            },
+        );
+        let func = module.funcs.push(FuncDecl::Body(sig, "f".into(), body));
+
+        let mut ctx = InterpContext::new(&module).unwrap();
+        let mut state = InterpState::new(&mut ctx, &module, func, &[ConstVal::I32(3)]);
+        let mut accs = vec![];
+        let returned = loop {
+            if state.block() == header && state.inst_index() == 0 {
+                accs.push((state.value(i).unwrap()[0], state.value(acc).unwrap()[0]));
+            }
+            match state.step() {
+                StepResult::Continue => {}
+                StepResult::Returned(values) => break values,
+                other => panic!("unexpected step result {:?}", other),
+            }
+        };
+        assert_eq!(
+            accs,
+            vec![
+                (ConstVal::I32(0), ConstVal::I32(0)),
+                (ConstVal::I32(1), ConstVal::I32(0)),
+                (ConstVal::I32(2), ConstVal::I32(1)),
+            ]
+        );
+        assert_eq!(returned, vec![ConstVal::I32(3)]);
+    }
+
+    /// Run a function returning one `i32`, whose body is filled in by
+    /// `build`, and return why it trapped. The module has one page of
+    /// memory and a three-entry funcref table holding a `() -> i32`
+    /// function, a null entry and an `(i32) -> i32` function;
+    /// `build` is given the memory, the table and the `() -> i32`
+    /// signature.
+    fn trap_reason_of(
+        build: impl FnOnce(&mut FunctionBody, Block, Memory, Table, Signature) -> Value,
+    ) -> TrapReason {
+        let mut module = Module::empty();
+        let mem = module.memories.push(MemoryData {
+            initial_pages: 1,
+            maximum_pages: None,
+            segments: vec![],
+            memory64: false,
+            shared: false,
+            page_size_log2: None,
+        });
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        let unary = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        let mut nullary_body = FunctionBody::new(&module, sig);
+        let entry = nullary_body.entry;
+        let k = i32_const(&mut nullary_body, entry, 1);
+        nullary_body.set_terminator(entry, Terminator::Return { values: vec![k] });
+        let nullary = module
+            .funcs
+            .push(FuncDecl::Body(sig, "nullary".into(), nullary_body));
+        let mut unary_body = FunctionBody::new(&module, unary);
+        let entry = unary_body.entry;
+        let x = unary_body.blocks[entry].params[0].1;
+        unary_body.set_terminator(entry, Terminator::Return { values: vec![x] });
+        let unary = module
+            .funcs
+            .push(FuncDecl::Body(unary, "unary".into(), unary_body));
+        let table = module.tables.push(TableData {
+            ty: Type::Heap(WithNullable {
+                value: HeapType::FuncRef,
+                nullable: true,
+            }),
+            initial: 3,
+            max: Some(3),
+            func_elements: Some(vec![nullary, Func::invalid(), unary]),
+            table64: false,
+        });
+
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let result = build(&mut body, entry, mem, table, sig);
+        if let Terminator::None = body.blocks[entry].terminator.terminator {
+            body.set_terminator(
+                entry,
+                Terminator::Return {
+                    values: vec![result],
+                },
+            );
+        }
+        let func = module.funcs.push(FuncDecl::Body(sig, "f".into(), body));
+        let mut ctx = InterpContext::new(&module).unwrap();
+        match ctx.call(&module, func, &[]) {
+            InterpResult::Trap(_, _, _, reason) => reason,
+            other => panic!("expected a trap, got {:?}", other),
+        }
+    }
+
+    /// Build `call_indirect` of the `() -> i32` signature through the
+    /// given table slot.
+    fn call_slot(
+        body: &mut FunctionBody,
+        block: Block,
+        table: Table,
+        sig: Signature,
+        slot: u32,
+    ) -> Value {
+        let idx = i32_const(body, block, slot);
+        body.add_op(
+            block,
+            Operator::CallIndirect {
+                sig_index: sig,
+                table_index: table,
+            },
+            &[idx],
+            &[Type::I32],
+        )
+    }
+
+    #[test]
+    fn trap_reasons() {
+        let binop = |op, a: u32, b: u32| {
+            trap_reason_of(move |body, block, _, _, _| {
+                let a = i32_const(body, block, a);
+                let b = i32_const(body, block, b);
+                body.add_op(block, op, &[a, b], &[Type::I32])
+            })
+        };
+        assert_eq!(binop(Operator::I32DivS, 1, 0), TrapReason::IntegerDivByZero);
+        assert_eq!(binop(Operator::I32RemU, 1, 0), TrapReason::IntegerDivByZero);
+        assert_eq!(
+            binop(Operator::I32DivS, i32::MIN as u32, -1i32 as u32),
+            TrapReason::IntegerOverflow
+        );
+        for value in [f32::NAN, 3e9] {
+            let reason = trap_reason_of(|body, block, _, _, _| {
+                let x = body.add_op(
+                    block,
+                    Operator::F32Const {
+                        value: value.to_bits(),
+                    },
+                    &[],
+                    &[Type::F32],
+                );
+                body.add_op(block, Operator::I32TruncF32S, &[x], &[Type::I32])
+            });
+            assert_eq!(reason, TrapReason::IntegerOverflow, "{}", value);
+        }
+        let reason = trap_reason_of(|body, block, mem, _, _| {
+            let addr = i32_const(body, block, WASM_PAGE as u32 - 2);
+            body.add_op(
+                block,
+                Operator::I32Load {
+                    memory: memarg(mem),
+                },
+                &[addr],
+                &[Type::I32],
+            )
+        });
+        assert_eq!(reason, TrapReason::OutOfBoundsMemory);
+        let reason = trap_reason_of(|body, block, mem, _, _| {
+            let addr = i32_const(body, block, WASM_PAGE as u32);
+            body.add_op(
+                block,
+                Operator::I32Store8 {
+                    memory: memarg(mem),
+                },
+                &[addr, addr],
+                &[],
+            );
+            addr
+        });
+        assert_eq!(reason, TrapReason::OutOfBoundsMemory);
+        let reason = trap_reason_of(|body, block, _, table, _| {
+            let idx = i32_const(body, block, 3);
+            let elem = body.add_op(
+                block,
+                Operator::TableGet { table_index: table },
+                &[idx],
+                &[Type::Heap(WithNullable {
+                    value: HeapType::FuncRef,
+                    nullable: true,
+                })],
+            );
+            body.add_op(block, Operator::RefIsNull, &[elem], &[Type::I32])
+        });
+        assert_eq!(reason, TrapReason::OutOfBoundsTable);
+
+        let call = |slot| {
+            trap_reason_of(move |body, block, _, table, sig| {
+                call_slot(body, block, table, sig, slot)
+            })
+        };
+        assert_eq!(call(3), TrapReason::UndefinedElement);
+        assert_eq!(call(1), TrapReason::UninitializedElement);
+        assert_eq!(call(2), TrapReason::CallIndirectTypeMismatch);
+        let reason = trap_reason_of(|body, block, _, table, sig| {
+            let idx = i32_const(body, block, 2);
+            body.set_terminator(
+                block,
+                Terminator::ReturnCallIndirect {
+                    sig,
+                    table,
+                    args: vec![idx],
+                },
+            );
+            idx
+        });
+        assert_eq!(reason, TrapReason::CallIndirectTypeMismatch);
+
+        let reason = trap_reason_of(|body, block, _, _, _| {
+            body.add_op(block, Operator::Unreachable, &[], &[]);
+            i32_const(body, block, 0)
+        });
+        assert_eq!(reason, TrapReason::Unreachable);
+        let reason = trap_reason_of(|body, block, _, _, _| {
+            body.set_terminator(block, Terminator::Unreachable);
+            i32_const(body, block, 0)
+        });
+        assert_eq!(reason, TrapReason::Unreachable);
+    }
+
+    #[test]
+    fn global_counter() {
+        let mut module = Module::empty();
+        let counter = module.globals.push(GlobalData {
+            ty: Type::I32,
+            value: Some(10),
+            mutable: true,
+        });
+        let fixed = module.globals.push(GlobalData {
+            ty: Type::I32,
+            value: Some(7),
+            mutable: false,
+        });
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+
+        // Increment `counter` once per iteration, `n` times, then
+        // return it.
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let n = body.blocks[entry].params[0].1;
+        let header = body.add_block();
+        let i = body.add_blockparam(header, Type::I32);
+        let exit = body.add_block();
+        let zero = i32_const(&mut body, entry, 0);
+        body.set_terminator(
+            entry,
+            Terminator::Br {
+                target: BlockTarget {
+                    block: header,
+                    args: vec![zero],
+                },
+            },
+        );
+        let get = |body: &mut FunctionBody, block| {
+            body.add_op(
+                block,
+                Operator::GlobalGet {
+                    global_index: counter,
+                },
+                &[],
+                &[Type::I32],
+            )
+        };
+        let old = get(&mut body, header);
+        let one = i32_const(&mut body, header, 1);
+        let new = body.add_op(header, Operator::I32Add, &[old, one], &[Type::I32]);
+        body.add_op(
+            header,
+            Operator::GlobalSet {
+                global_index: counter,
+            },
+            &[new],
+            &[],
+        );
+        let next = body.add_op(header, Operator::I32Add, &[i, one], &[Type::I32]);
+        let more = body.add_op(header, Operator::I32LtU, &[next, n], &[Type::I32]);
+        body.set_terminator(
+            header,
+            Terminator::CondBr {
+                cond: more,
+                if_true: BlockTarget {
+                    block: header,
+                    args: vec![next],
+                },
+                if_false: BlockTarget {
+                    block: exit,
+                    args: vec![],
+                },
+            },
+        );
+        let result = get(&mut body, exit);
+        body.set_terminator(
+            exit,
+            Terminator::Return {
+                values: vec![result],
+            },
+        );
+        let count = module.funcs.push(FuncDecl::Body(sig, "count".into(), body));
+
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let x = body.blocks[entry].params[0].1;
+        body.add_op(
+            entry,
+            Operator::GlobalSet {
+                global_index: fixed,
+            },
+            &[x],
+            &[],
+        );
+        body.set_terminator(entry, Terminator::Return { values: vec![x] });
+        let clobber = module
+            .funcs
+            .push(FuncDecl::Body(sig, "clobber".into(), body));
+
+        let mut ctx = InterpContext::new(&module).unwrap();
+        let result = ctx.call(&module, count, &[ConstVal::I32(5)]).ok().unwrap();
+        assert_eq!(&result[..], &[ConstVal::I32(15)]);
+        // The store persists across calls.
+        let result = ctx.call(&module, count, &[ConstVal::I32(1)]).ok().unwrap();
+        assert_eq!(&result[..], &[ConstVal::I32(16)]);
+        assert_eq!(ctx.globals[counter], ConstVal::I32(16));
+
+        assert!(matches!(
+            ctx.call(&module, clobber, &[ConstVal::I32(1)]),
+            InterpResult::Trap(_, _, _, TrapReason::Other)
+        ));
+        assert_eq!(ctx.globals[fixed], ConstVal::I32(7));
+    }
+
+    #[test]
+    fn call_with_two_results() {
+        let mut module = Module::empty();
+        let callee_sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![Type::I32, Type::I64],
+            shared: false,
+        });
+        let mut callee = FunctionBody::new(&module, callee_sig);
+        let entry = callee.entry;
+        let x = callee.blocks[entry].params[0].1;
+        let one = i32_const(&mut callee, entry, 1);
+        let inc = callee.add_op(entry, Operator::I32Add, &[x, one], &[Type::I32]);
+        let wide = callee.add_op(entry, Operator::I64ExtendI32U, &[x], &[Type::I64]);
+        callee.set_terminator(
+            entry,
+            Terminator::Return {
+                values: vec![inc, wide],
+            },
+        );
+        let callee = module
+            .funcs
+            .push(FuncDecl::Body(callee_sig, "callee".into(), callee));
+
+        // The caller consumes each result in a different instruction,
+        // and returns them in swapped order.
+        let caller_sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![Type::I64, Type::I32],
+            shared: false,
+        });
+        let mut caller = FunctionBody::new(&module, caller_sig);
+        let entry = caller.entry;
+        let x = caller.blocks[entry].params[0].1;
+        let call = caller.add_op(
+            entry,
+            Operator::Call {
+                function_index: callee,
+            },
+            &[x],
+            &[Type::I32, Type::I64],
+        );
+        let first = caller.add_value(ValueDef::PickOutput(call, 0, Type::I32));
+        caller.append_to_block(entry, first);
+        let second = caller.add_value(ValueDef::PickOutput(call, 1, Type::I64));
+        caller.append_to_block(entry, second);
+        let ten = i32_const(&mut caller, entry, 10);
+        let a = caller.add_op(entry, Operator::I32Mul, &[first, ten], &[Type::I32]);
+        let hundred = caller.add_op(entry, Operator::I64Const { value: 100 }, &[], &[Type::I64]);
+        let b = caller.add_op(entry, Operator::I64Add, &[second, hundred], &[Type::I64]);
+        caller.set_terminator(entry, Terminator::Return { values: vec![b, a] });
+        let caller = module
+            .funcs
+            .push(FuncDecl::Body(caller_sig, "caller".into(), caller));
+
+        let mut ctx = InterpContext::new(&module).unwrap();
+        let result = ctx.call(&module, callee, &[ConstVal::I32(4)]).ok().unwrap();
+        assert_eq!(&result[..], &[ConstVal::I32(5), ConstVal::I64(4)]);
+        let result = ctx.call(&module, caller, &[ConstVal::I32(4)]).ok().unwrap();
+        assert_eq!(&result[..], &[ConstVal::I64(104), ConstVal::I32(50)]);
+    }
+
+    #[test]
+    fn call_indirect_through_table() {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        let mut callee = FunctionBody::new(&module, sig);
+        let entry = callee.entry;
+        let k = i32_const(&mut callee, entry, 42);
+        callee.set_terminator(entry, Terminator::Return { values: vec![k] });
+        let callee = module
+            .funcs
+            .push(FuncDecl::Body(sig, "callee".into(), callee));
+        let table = module.tables.push(TableData {
+            ty: Type::Heap(WithNullable {
+                value: HeapType::FuncRef,
+                nullable: true,
+            }),
+            initial: 1,
+            max: Some(1),
+            func_elements: Some(vec![callee]),
+            table64: false,
+        });
+
+        let mut caller = FunctionBody::new(&module, sig);
+        let entry = caller.entry;
+        let idx = i32_const(&mut caller, entry, 0);
+        let result = caller.add_op(
+            entry,
+            Operator::CallIndirect {
+                sig_index: sig,
+                table_index: table,
+            },
+            &[idx],
+            &[Type::I32],
+        );
+        caller.set_terminator(
+            entry,
+            Terminator::Return {
+                values: vec![result],
+            },
+        );
+        let caller = module
+            .funcs
+            .push(FuncDecl::Body(sig, "caller".into(), caller));
+
+        let mut oob = FunctionBody::new(&module, sig);
+        let entry = oob.entry;
+        let idx = i32_const(&mut oob, entry, 5);
+        let elem = oob.add_op(
+            entry,
+            Operator::TableGet { table_index: table },
+            &[idx],
+            &[module.tables[table].ty],
+        );
+        let null = oob.add_op(entry, Operator::RefIsNull, &[elem], &[Type::I32]);
+        oob.set_terminator(entry, Terminator::Return { values: vec![null] });
+        let oob = module.funcs.push(FuncDecl::Body(sig, "oob".into(), oob));
+
+        let mut ctx = InterpContext::new(&module).unwrap();
+        let result = ctx.call(&module, caller, &[]).ok().unwrap();
+        assert_eq!(&result[..], &[ConstVal::I32(42)]);
+        assert!(matches!(
+            ctx.call(&module, oob, &[]),
+            InterpResult::Trap(..)
+        ));
+    }
+}
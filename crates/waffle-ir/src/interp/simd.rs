@@ -0,0 +1,149 @@
+//! Lane-wise evaluation of `v128` operators for the interpreter.
+//!
+//! Vectors are held as their 16 bytes in memory order, so lane `i` of
+//! width `w` occupies bytes `i*w..(i+1)*w`, little-endian.
+use crate::interp::ConstVal;
+use crate::ops::Operator;
+
+fn lane(v: &[u8; 16], width: usize, idx: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes[..width].copy_from_slice(&v[idx * width..(idx + 1) * width]);
+    u64::from_le_bytes(bytes)
+}
+
+fn set_lane(v: &mut [u8; 16], width: usize, idx: usize, x: u64) {
+    v[idx * width..(idx + 1) * width].copy_from_slice(&x.to_le_bytes()[..width]);
+}
+
+fn splat(width: usize, x: u64) -> ConstVal {
+    let mut v = [0u8; 16];
+    for idx in 0..16 / width {
+        set_lane(&mut v, width, idx, x);
+    }
+    ConstVal::V128(v)
+}
+
+/// Combine corresponding lanes of `a` and `b`. Only the low `width`
+/// bytes of each result are kept, which gives wrap-around semantics
+/// for `wrapping_*` integer ops.
+fn lanewise(a: &[u8; 16], b: &[u8; 16], width: usize, f: impl Fn(u64, u64) -> u64) -> ConstVal {
+    let mut v = [0u8; 16];
+    for idx in 0..16 / width {
+        let x = f(lane(a, width, idx), lane(b, width, idx));
+        set_lane(&mut v, width, idx, x);
+    }
+    ConstVal::V128(v)
+}
+
+fn extract(v: &[u8; 16], width: usize, idx: u8) -> Option<u64> {
+    let idx = idx as usize;
+    if idx >= 16 / width {
+        return None;
+    }
+    Some(lane(v, width, idx))
+}
+
+fn replace(v: &[u8; 16], width: usize, idx: u8, x: u64) -> Option<ConstVal> {
+    let idx = idx as usize;
+    if idx >= 16 / width {
+        return None;
+    }
+    let mut v = *v;
+    set_lane(&mut v, width, idx, x);
+    Some(ConstVal::V128(v))
+}
+
+/// Evaluate a `v128` operator, returning `None` for operators that
+/// are not SIMD operators or are not yet supported.
+pub(crate) fn const_eval_simd(op: &Operator, vals: &[ConstVal]) -> Option<ConstVal> {
+    match (op, vals) {
+        (Operator::V128Const { value }, []) => Some(ConstVal::V128(value.to_le_bytes())),
+
+        (Operator::I8x16Splat, [ConstVal::I32(x)]) => Some(splat(1, *x as u64)),
+        (Operator::I16x8Splat, [ConstVal::I32(x)]) => Some(splat(2, *x as u64)),
+        (Operator::I32x4Splat, [ConstVal::I32(x)]) => Some(splat(4, *x as u64)),
+        (Operator::I64x2Splat, [ConstVal::I64(x)]) => Some(splat(8, *x)),
+        (Operator::F32x4Splat, [ConstVal::F32(x)]) => Some(splat(4, *x as u64)),
+        (Operator::F64x2Splat, [ConstVal::F64(x)]) => Some(splat(8, *x)),
+
+        (Operator::I8x16ExtractLaneS { lane }, [ConstVal::V128(v)]) => Some(ConstVal::I32(
+            extract(v, 1, *lane)? as u8 as i8 as i32 as u32,
+        )),
+        (Operator::I8x16ExtractLaneU { lane }, [ConstVal::V128(v)]) => {
+            Some(ConstVal::I32(extract(v, 1, *lane)? as u32))
+        }
+        (Operator::I16x8ExtractLaneS { lane }, [ConstVal::V128(v)]) => Some(ConstVal::I32(
+            extract(v, 2, *lane)? as u16 as i16 as i32 as u32,
+        )),
+        (Operator::I16x8ExtractLaneU { lane }, [ConstVal::V128(v)]) => {
+            Some(ConstVal::I32(extract(v, 2, *lane)? as u32))
+        }
+        (Operator::I32x4ExtractLane { lane }, [ConstVal::V128(v)]) => {
+            Some(ConstVal::I32(extract(v, 4, *lane)? as u32))
+        }
+        (Operator::I64x2ExtractLane { lane }, [ConstVal::V128(v)]) => {
+            Some(ConstVal::I64(extract(v, 8, *lane)?))
+        }
+        (Operator::F32x4ExtractLane { lane }, [ConstVal::V128(v)]) => {
+            Some(ConstVal::F32(extract(v, 4, *lane)? as u32))
+        }
+        (Operator::F64x2ExtractLane { lane }, [ConstVal::V128(v)]) => {
+            Some(ConstVal::F64(extract(v, 8, *lane)?))
+        }
+
+        (Operator::I8x16ReplaceLane { lane }, [ConstVal::V128(v), ConstVal::I32(x)]) => {
+            replace(v, 1, *lane, *x as u64)
+        }
+        (Operator::I16x8ReplaceLane { lane }, [ConstVal::V128(v), ConstVal::I32(x)]) => {
+            replace(v, 2, *lane, *x as u64)
+        }
+        (Operator::I32x4ReplaceLane { lane }, [ConstVal::V128(v), ConstVal::I32(x)]) => {
+            replace(v, 4, *lane, *x as u64)
+        }
+        (Operator::I64x2ReplaceLane { lane }, [ConstVal::V128(v), ConstVal::I64(x)]) => {
+            replace(v, 8, *lane, *x)
+        }
+        (Operator::F32x4ReplaceLane { lane }, [ConstVal::V128(v), ConstVal::F32(x)]) => {
+            replace(v, 4, *lane, *x as u64)
+        }
+        (Operator::F64x2ReplaceLane { lane }, [ConstVal::V128(v), ConstVal::F64(x)]) => {
+            replace(v, 8, *lane, *x)
+        }
+
+        (Operator::I8x16Add, [ConstVal::V128(a), ConstVal::V128(b)]) => {
+            Some(lanewise(a, b, 1, u64::wrapping_add))
+        }
+        (Operator::I8x16Sub, [ConstVal::V128(a), ConstVal::V128(b)]) => {
+            Some(lanewise(a, b, 1, u64::wrapping_sub))
+        }
+        (Operator::I16x8Add, [ConstVal::V128(a), ConstVal::V128(b)]) => {
+            Some(lanewise(a, b, 2, u64::wrapping_add))
+        }
+        (Operator::I16x8Sub, [ConstVal::V128(a), ConstVal::V128(b)]) => {
+            Some(lanewise(a, b, 2, u64::wrapping_sub))
+        }
+        (Operator::I16x8Mul, [ConstVal::V128(a), ConstVal::V128(b)]) => {
+            Some(lanewise(a, b, 2, u64::wrapping_mul))
+        }
+        (Operator::I32x4Add, [ConstVal::V128(a), ConstVal::V128(b)]) => {
+            Some(lanewise(a, b, 4, u64::wrapping_add))
+        }
+        (Operator::I32x4Sub, [ConstVal::V128(a), ConstVal::V128(b)]) => {
+            Some(lanewise(a, b, 4, u64::wrapping_sub))
+        }
+        (Operator::I32x4Mul, [ConstVal::V128(a), ConstVal::V128(b)]) => {
+            Some(lanewise(a, b, 4, u64::wrapping_mul))
+        }
+        (Operator::I64x2Add, [ConstVal::V128(a), ConstVal::V128(b)]) => {
+            Some(lanewise(a, b, 8, u64::wrapping_add))
+        }
+        (Operator::I64x2Sub, [ConstVal::V128(a), ConstVal::V128(b)]) => {
+            Some(lanewise(a, b, 8, u64::wrapping_sub))
+        }
+        (Operator::I64x2Mul, [ConstVal::V128(a), ConstVal::V128(b)]) => {
+            Some(lanewise(a, b, 8, u64::wrapping_mul))
+        }
+
+        _ => None,
+    }
+}
@@ -0,0 +1,181 @@
+//! A convenience wrapper for constructing function bodies by hand.
+
+use super::{Block, BlockTarget, FunctionBody, Module, Signature, Terminator, Type, Value};
+use crate::Operator;
+use alloc::vec::Vec;
+
+/// Builds a [`FunctionBody`] one instruction at a time.
+///
+/// Argument and type lists are interned, instructions are recorded in
+/// `value_blocks`, and CFG edges are added as terminators are set, so
+/// the finished body is ready for use without calling
+/// `recompute_edges()`.
+pub struct FunctionBuilder {
+    body: FunctionBody,
+}
+
+impl FunctionBuilder {
+    /// Start a new body with the given signature. The entry block
+    /// already carries one blockparam per function parameter.
+    pub fn new(module: &Module, sig: Signature) -> FunctionBuilder {
+        FunctionBuilder {
+            body: FunctionBody::new(module, sig),
+        }
+    }
+
+    /// Continue building an existing body.
+    pub fn from_body(body: FunctionBody) -> FunctionBuilder {
+        FunctionBuilder { body }
+    }
+
+    /// The entry block.
+    pub fn entry(&self) -> Block {
+        self.body.entry
+    }
+
+    /// The blockparam values of `block`, in order. For the entry
+    /// block these are the function parameters.
+    pub fn params(&self, block: Block) -> Vec<Value> {
+        self.body.blocks[block]
+            .params
+            .iter()
+            .map(|&(_, value)| value)
+            .collect()
+    }
+
+    /// Add a new, empty block.
+    pub fn add_block(&mut self) -> Block {
+        self.body.add_block()
+    }
+
+    /// Add a blockparam of type `ty` to `block`.
+    pub fn add_blockparam(&mut self, block: Block, ty: Type) -> Value {
+        self.body.add_blockparam(block, ty)
+    }
+
+    /// Append `op` applied to `args` to `block`, returning its value.
+    /// An operator with several results should be followed by
+    /// `ValueDef::PickOutput`s to get at the individual results.
+    pub fn push_op(
+        &mut self,
+        block: Block,
+        op: Operator,
+        args: &[Value],
+        result_tys: &[Type],
+    ) -> Value {
+        self.body.add_op(block, op, args, result_tys)
+    }
+
+    /// Append an `i32.const` to `block`.
+    pub fn const_i32(&mut self, block: Block, value: u32) -> Value {
+        self.push_op(block, Operator::I32Const { value }, &[], &[Type::I32])
+    }
+
+    /// Append an `i64.const` to `block`.
+    pub fn const_i64(&mut self, block: Block, value: u64) -> Value {
+        self.push_op(block, Operator::I64Const { value }, &[], &[Type::I64])
+    }
+
+    /// End `from` with an unconditional branch to `to`.
+    pub fn br(&mut self, from: Block, to: Block, args: &[Value]) {
+        self.body.set_terminator(
+            from,
+            Terminator::Br {
+                target: target(to, args),
+            },
+        );
+    }
+
+    /// End `from` with a branch to `if_true` when `cond` is nonzero and
+    /// to `if_false` otherwise.
+    pub fn cond_br(
+        &mut self,
+        from: Block,
+        cond: Value,
+        if_true: (Block, &[Value]),
+        if_false: (Block, &[Value]),
+    ) {
+        self.body.set_terminator(
+            from,
+            Terminator::CondBr {
+                cond,
+                if_true: target(if_true.0, if_true.1),
+                if_false: target(if_false.0, if_false.1),
+            },
+        );
+    }
+
+    /// End `from` by returning `values`.
+    pub fn ret(&mut self, from: Block, values: &[Value]) {
+        self.body.set_terminator(
+            from,
+            Terminator::Return {
+                values: values.to_vec(),
+            },
+        );
+    }
+
+    /// End `from` with an arbitrary terminator.
+    pub fn set_terminator(&mut self, from: Block, terminator: Terminator) {
+        self.body.set_terminator(from, terminator);
+    }
+
+    /// The body built so far.
+    pub fn body(&self) -> &FunctionBody {
+        &self.body
+    }
+
+    /// Mutable access to the body built so far, for anything the
+    /// builder does not cover.
+    pub fn body_mut(&mut self) -> &mut FunctionBody {
+        &mut self.body
+    }
+
+    /// Finish building and return the body.
+    pub fn finish(self) -> FunctionBody {
+        self.body
+    }
+}
+
+fn target(block: Block, args: &[Value]) -> BlockTarget {
+    BlockTarget {
+        block,
+        args: args.to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::SignatureData;
+
+    #[test]
+    fn builds_diamond() {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        let mut b = FunctionBuilder::new(&module, sig);
+        let entry = b.entry();
+        let x = b.params(entry)[0];
+        let then_block = b.add_block();
+        let else_block = b.add_block();
+        let join = b.add_block();
+        let result = b.add_blockparam(join, Type::I32);
+        b.cond_br(entry, x, (then_block, &[]), (else_block, &[]));
+        let one = b.const_i32(then_block, 1);
+        b.br(then_block, join, &[one]);
+        let two = b.const_i32(else_block, 2);
+        b.br(else_block, join, &[two]);
+        b.ret(join, &[result]);
+        let body = b.finish();
+
+        body.validate().unwrap();
+        assert_eq!(body.value_blocks[one], then_block);
+        assert_eq!(body.value_blocks[two], else_block);
+        assert_eq!(body.blocks[entry].succs, vec![then_block, else_block]);
+        assert_eq!(body.blocks[join].preds, vec![then_block, else_block]);
+    }
+}
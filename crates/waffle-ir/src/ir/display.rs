@@ -1,5 +1,5 @@
 //! Displaying IR.
-use super::{FuncDecl, FunctionBody, Module, SourceLoc, ValueDef};
+use super::{DataSegment, FuncDecl, FunctionBody, Module, SourceLoc, Value, ValueDef};
 use crate::EntityRef;
 use alloc::borrow::ToOwned;
 use alloc::vec;
@@ -115,12 +115,14 @@ impl<'a> Display for FunctionBodyDisplay<'a> {
                 if let Some(local) = self.body.value_locals[*param] {
                     writeln!(f, "{}    # {}: {}", self.indent, param, local)?;
                 }
+                self.write_comment(f, *param)?;
             }
             for inst in &block.insts {
                 let inst = inst.value;
                 if let Some(local) = self.body.value_locals[inst] {
                     writeln!(f, "{}    # {}: {}", self.indent, inst, local)?;
                 }
+                self.write_comment(f, inst)?;
                 match &self.body.values[inst] {
                     ValueDef::Operator(op, args, tys) => {
                         let args = self.body.arg_pool[*args]
@@ -168,6 +170,21 @@ impl<'a> Display for FunctionBodyDisplay<'a> {
         Ok(())
     }
 }
+impl<'a> FunctionBodyDisplay<'a> {
+    /// In verbose mode, print `value`'s debug comment, if any, one
+    /// `# vN note: ...` line per line of the comment.
+    fn write_comment(&self, f: &mut Formatter, value: Value) -> FmtResult {
+        if !self.verbose {
+            return Ok(());
+        }
+        if let Some(comment) = &self.body.value_comments[value] {
+            for line in comment.lines() {
+                writeln!(f, "{}    # {} note: {}", self.indent, value, line)?;
+            }
+        }
+        Ok(())
+    }
+}
 pub struct ModuleDisplay<'a> {
     pub(crate) module: &'a Module<'a>,
 }
@@ -231,6 +248,11 @@ impl<'a> Display for ModuleDisplay<'a> {
                 )?;
             }
         }
+        for (i, segment) in self.module.data_segments.iter().enumerate() {
+            if let DataSegment::Passive { data } = segment {
+                writeln!(f, "  data{}: passive # {} bytes", i, data.len())?;
+            }
+        }
         for import in &self.module.imports {
             writeln!(
                 f,
@@ -121,9 +121,13 @@ impl<'a> FuncDecl<'a> {
     /// parse all `Lazy` `FuncDecl`s beforehand; here we just panic in
     /// that case, and rewrite the lifetime otherwise (because no
     /// borrow actually exists in the remaining variants).
+    /// Debug comments on values are dropped along the way.
     pub(crate) fn without_orig_bytes(self) -> FuncDecl<'static> {
         match self {
-            FuncDecl::Body(sig, name, body) => FuncDecl::Body(sig, name, body),
+            FuncDecl::Body(sig, name, mut body) => {
+                body.value_comments = PerEntity::default();
+                FuncDecl::Body(sig, name, body)
+            }
             FuncDecl::Import(sig, name) => FuncDecl::Import(sig, name),
             #[cfg(feature = "backend")]
             FuncDecl::Compiled(sig, name, func) => FuncDecl::Compiled(sig, name, func),
@@ -164,6 +168,10 @@ pub struct FunctionBody {
     pub value_locals: PerEntity<Value, Option<Local>>,
     /// Debug source locations of each value.
     pub source_locs: PerEntity<Value, SourceLoc>,
+    /// Free-form debug comments attached to values, e.g. by a pass
+    /// recording why it produced them. Shown by `display_verbose`;
+    /// never affects compilation.
+    pub value_comments: PerEntity<Value, Option<String>>,
     ///Is this function shared
     pub shared: bool,
 }
@@ -206,6 +214,7 @@ impl FunctionBody {
             value_blocks,
             value_locals: PerEntity::default(),
             source_locs: PerEntity::default(),
+            value_comments: PerEntity::default(),
             shared: *shared,
         }
     }
@@ -240,6 +249,28 @@ impl FunctionBody {
         self.blocks[to].pos_in_pred_succ.push(succ_pos);
         log::trace!("add_edge: from {} to {}", from, to);
     }
+    /// The edges into `block`: each predecessor, paired with the
+    /// index in that predecessor's `succs` of the edge to `block`.
+    /// There is one item per edge, so a predecessor branching to
+    /// `block` twice appears twice.
+    pub fn pred_edges(&self, block: Block) -> impl Iterator<Item = (Block, usize)> + '_ {
+        let block = &self.blocks[block];
+        block
+            .preds
+            .iter()
+            .copied()
+            .zip(block.pos_in_pred_succ.iter().copied())
+    }
+    /// The edges out of `block`: each successor, paired with the
+    /// index in that successor's `preds` of the edge from `block`.
+    pub fn succ_edges(&self, block: Block) -> impl Iterator<Item = (Block, usize)> + '_ {
+        let block = &self.blocks[block];
+        block
+            .succs
+            .iter()
+            .copied()
+            .zip(block.pos_in_succ_pred.iter().copied())
+    }
     /// Split a given edge (disambiguated with `succ_idx` since there
     /// may be multiple edges from `from` to `to`), creating an
     /// intermediate block with an unconditional branch and carrying
@@ -279,6 +310,38 @@ impl FunctionBody {
         self.blocks[to].pos_in_pred_succ[pred_idx] = 0;
         edge_block
     }
+    /// Split `block` before its `at`'th instruction. The instructions
+    /// from `at` onward and the terminator move to a new block, which
+    /// `block` now branches to unconditionally; the new block is
+    /// returned. Successors see the new block as their predecessor in
+    /// place of `block`.
+    pub fn split_block(&mut self, block: Block, at: usize) -> Block {
+        let tail = self.add_block();
+        let insts = self.blocks[block].insts.split_off(at);
+        for inst in &insts {
+            self.value_blocks[inst.value] = tail;
+        }
+        self.blocks[tail].insts = insts;
+        self.blocks[tail].terminator = core::mem::take(&mut self.blocks[block].terminator);
+        // Hand `block`'s outgoing edges over to the tail.
+        let succs = core::mem::take(&mut self.blocks[block].succs);
+        let pos_in_succ_pred = core::mem::take(&mut self.blocks[block].pos_in_succ_pred);
+        for (&succ, &pred_idx) in succs.iter().zip(pos_in_succ_pred.iter()) {
+            self.blocks[succ].preds[pred_idx] = tail;
+        }
+        self.blocks[tail].succs = succs;
+        self.blocks[tail].pos_in_succ_pred = pos_in_succ_pred;
+        self.set_terminator(
+            block,
+            Terminator::Br {
+                target: BlockTarget {
+                    block: tail,
+                    args: vec![],
+                },
+            },
+        );
+        tail
+    }
     /// Recompute all successor/predecessor lists according to the
     /// edges implied by terminator instructions. Must be updated
     /// after building a function body or mutating its CFG and prior
@@ -298,6 +361,57 @@ impl FunctionBody {
             });
         }
     }
+    /// Physically delete every block for which `keep` returns false,
+    /// renumbering the remaining blocks densely in their original
+    /// order, and return the old-to-new mapping (removed blocks map
+    /// to `Block::invalid()`). Values defined in removed blocks become
+    /// `ValueDef::None`. Kept blocks must not branch to removed ones.
+    pub fn compact_blocks(&mut self, keep: impl Fn(Block) -> bool) -> PerEntity<Block, Block> {
+        assert!(keep(self.entry), "cannot remove the entry block");
+        let mut remap: PerEntity<Block, Block> = PerEntity::default();
+        let old_blocks = core::mem::take(&mut self.blocks);
+        for (i, def) in old_blocks.into_vec().into_iter().enumerate() {
+            let block = Block::new(i);
+            if keep(block) {
+                remap[block] = self.blocks.push(def);
+            } else {
+                remap[block] = Block::invalid();
+                for &(_, param) in &def.params {
+                    self.values[param] = ValueDef::None;
+                }
+                for inst in &def.insts {
+                    self.values[inst.value] = ValueDef::None;
+                }
+            }
+        }
+        self.entry = remap[self.entry];
+        for block in self.blocks.values_mut() {
+            block.terminator.update_targets(|target| {
+                let new = remap[target.block];
+                assert!(new.is_valid(), "branch to removed block {}", target.block);
+                target.block = new;
+            });
+        }
+        for value in self.values.values_mut() {
+            if let ValueDef::BlockParam(block, _, _) = value {
+                *block = remap[*block];
+            }
+        }
+        for value in self.values.iter() {
+            let block = self.value_blocks[value];
+            if block.is_valid() {
+                self.value_blocks[value] = remap[block];
+            }
+        }
+        self.recompute_edges();
+        remap
+    }
+    /// Delete all blocks unreachable from the entry according to
+    /// `cfg`, returning the old-to-new block mapping as
+    /// `compact_blocks` does. `cfg` is stale afterward.
+    pub fn remove_unreachable_blocks(&mut self, cfg: &CFGInfo) -> PerEntity<Block, Block> {
+        self.compact_blocks(|block| cfg.rpo_pos[block].is_some())
+    }
     /// Add a new value node to the function (not yet in any block)
     /// and return its SSA value number.
     pub fn add_value(&mut self, value: ValueDef) -> Value {
@@ -323,6 +437,88 @@ impl FunctionBody {
         self.append_to_block(block, value);
         value
     }
+    /// A rough estimate of the size in bytes of this function's
+    /// compiled code: the sum of a per-instruction estimate (opcode
+    /// plus immediates) and a per-terminator estimate. Not exact, but
+    /// stable, and never smaller for a body with more instructions.
+    pub fn estimate_code_size(&self) -> usize {
+        let mut size = 0;
+        for block in self.blocks.values() {
+            for inst in &block.insts {
+                size += match &self.values[inst.value] {
+                    ValueDef::Operator(op, ..) => estimate_op_size(op),
+                    _ => 0,
+                };
+            }
+            size += match &block.terminator.terminator {
+                Terminator::Br { .. } => 2,
+                Terminator::CondBr { .. } => 4,
+                Terminator::Select { targets, .. } => 3 + targets.len(),
+                Terminator::Return { .. } | Terminator::Unreachable | Terminator::UB => 1,
+                Terminator::ReturnCall { .. } | Terminator::ReturnCallRef { .. } => 3,
+                Terminator::ReturnCallIndirect { .. } => 4,
+                Terminator::None => 0,
+            };
+        }
+        size
+    }
+    /// The operator instructions of `block`, in order, each with its
+    /// operator and argument list. Other instructions (`PickOutput`s
+    /// and aliases) are skipped.
+    pub fn block_ops<'a>(
+        &'a self,
+        block: Block,
+    ) -> impl Iterator<Item = (Value, &'a Operator, &'a [Value])> + 'a {
+        self.blocks[block]
+            .insts
+            .iter()
+            .filter_map(move |inst| match &self.values[inst.value] {
+                ValueDef::Operator(op, args, _) => Some((inst.value, op, &self.arg_pool[*args])),
+                _ => None,
+            })
+    }
+    /// Like `block_ops`, but with mutable access to each operator.
+    pub fn block_ops_mut<'a>(
+        &'a mut self,
+        block: Block,
+    ) -> impl Iterator<Item = (Value, &'a mut Operator, &'a [Value])> + 'a {
+        // Hand out disjoint `&mut`s into `values` by visiting the
+        // (distinct) instructions in value order and splitting the
+        // slice as we go, then restore block order.
+        let mut order = self.blocks[block]
+            .insts
+            .iter()
+            .enumerate()
+            .map(|(pos, inst)| (pos, inst.value))
+            .collect::<Vec<_>>();
+        order.sort_by_key(|&(_, value)| value.index());
+        let arg_pool = &self.arg_pool;
+        let mut rest = self.values.as_mut_slice();
+        let mut base = 0;
+        let mut ops = vec![];
+        for (pos, value) in order {
+            let (def, tail) = core::mem::take(&mut rest)[value.index() - base..]
+                .split_first_mut()
+                .expect("instruction out of range");
+            rest = tail;
+            base = value.index() + 1;
+            if let ValueDef::Operator(op, args, _) = def {
+                ops.push((pos, value, op, &arg_pool[*args]));
+            }
+        }
+        ops.sort_by_key(|&(pos, ..)| pos);
+        ops.into_iter()
+            .map(|(_, value, op, args)| (value, op, args))
+    }
+    /// Replace the operator of an operator value, keeping its
+    /// argument and result-type lists. Panics if `value` is not
+    /// defined by an operator.
+    pub fn set_operator(&mut self, value: Value, op: Operator) {
+        match &mut self.values[value] {
+            ValueDef::Operator(old, ..) => *old = op,
+            def => panic!("set_operator on non-operator value {}: {:?}", value, def),
+        }
+    }
     /// Make one value an alias to another. Panics on cycles.
     pub fn set_alias(&mut self, value: Value, to: Value) {
         log::trace!("set_alias: value {:?} to {:?}", value, to);
@@ -334,6 +530,27 @@ impl FunctionBody {
         }
         self.values[value] = ValueDef::Alias(to);
     }
+    /// Attach a debug comment to `value`, appending it on a new line
+    /// to any comment already there.
+    pub fn annotate(&mut self, value: Value, comment: String) {
+        match &mut self.value_comments[value] {
+            Some(existing) => {
+                existing.push('\n');
+                existing.push_str(&comment);
+            }
+            slot => *slot = Some(comment),
+        }
+    }
+    /// Give `to` the source location of `from`, for use when `to`
+    /// replaces `from`. A location `to` already has is kept, since it
+    /// describes the computation that survives; nothing happens if
+    /// `from` has no location either.
+    pub fn copy_source_loc(&mut self, from: Value, to: Value) {
+        let loc = self.source_locs[from];
+        if loc.is_valid() && self.source_locs[to].is_invalid() {
+            self.source_locs[to] = loc;
+        }
+    }
     /// Resolve the value through any alias references to the original
     /// value.
     pub fn resolve_alias(&self, value: Value) -> Value {
@@ -363,6 +580,95 @@ impl FunctionBody {
         }
         to
     }
+    /// Rewrite every use of a value -- operator and `PickOutput`
+    /// arguments as well as terminator uses -- to the end of its alias
+    /// chain, point every alias directly at its root, and drop `Alias`
+    /// definitions from block instruction lists. Idempotent.
+    pub fn apply_aliases(&mut self) {
+        let roots = self
+            .values
+            .iter()
+            .map(|value| self.resolve_alias(value))
+            .collect::<Vec<_>>();
+        let root = |value: &mut Value| {
+            if value.is_valid() {
+                *value = roots[value.index()];
+            }
+        };
+        for def in self.values.values_mut() {
+            match def {
+                ValueDef::None => {}
+                ValueDef::Alias(to) => root(to),
+                def => def.update_uses(&mut self.arg_pool, root),
+            }
+        }
+        let values = &self.values;
+        for block in self.blocks.values_mut() {
+            block.terminator.update_uses(root);
+            block
+                .insts
+                .retain(|inst| !matches!(values[inst.value], ValueDef::Alias(_)));
+        }
+    }
+    /// Rewrite every use of `old` -- operator and `PickOutput`
+    /// arguments as well as terminator uses -- to `new`. Unlike
+    /// `set_alias`, this updates the uses in place and leaves the
+    /// definition of `old` untouched. Uses are matched exactly, not
+    /// through aliases.
+    pub fn replace_all_uses(&mut self, old: Value, new: Value) {
+        let replace = |value: &mut Value| {
+            if *value == old {
+                *value = new;
+            }
+        };
+        for def in self.values.values_mut() {
+            if let ValueDef::Operator(..) | ValueDef::PickOutput(..) = def {
+                def.update_uses(&mut self.arg_pool, replace);
+            }
+        }
+        for block in self.blocks.values_mut() {
+            block.terminator.update_uses(replace);
+        }
+    }
+    /// Build a reverse-use index: for each value, the placed
+    /// instructions whose operator or `PickOutput` arguments use it,
+    /// in block and instruction order. Uses through aliases are
+    /// credited to the alias root, and an instruction using a value
+    /// more than once is listed once. This is a snapshot: any later
+    /// edit to the body invalidates it.
+    pub fn compute_uses(&self) -> PerEntity<Value, Vec<Value>> {
+        let mut uses: PerEntity<Value, Vec<Value>> = PerEntity::default();
+        for block in self.blocks.values() {
+            for inst in &block.insts {
+                let inst = inst.value;
+                if matches!(self.values[inst], ValueDef::Alias(_) | ValueDef::None) {
+                    continue;
+                }
+                self.values[inst].visit_uses(&self.arg_pool, |value| {
+                    let users = &mut uses[self.resolve_alias(value)];
+                    if users.last() != Some(&inst) {
+                        users.push(inst);
+                    }
+                });
+            }
+        }
+        uses
+    }
+    /// Like `compute_uses`, but for terminators: for each value, the
+    /// blocks whose terminator uses it, as a branch condition, call
+    /// or return operand, or block-target argument.
+    pub fn compute_terminator_uses(&self) -> PerEntity<Value, Vec<Block>> {
+        let mut uses: PerEntity<Value, Vec<Block>> = PerEntity::default();
+        for (block, def) in self.blocks.entries() {
+            def.terminator.visit_uses(|value| {
+                let users = &mut uses[self.resolve_alias(value)];
+                if users.last() != Some(&block) {
+                    users.push(block);
+                }
+            });
+        }
+        uses
+    }
     /// Add a new blockparam to the given block, returning its SSA
     /// value number.
     pub fn add_blockparam(&mut self, block: Block, ty: Type) -> Value {
@@ -443,6 +749,98 @@ impl FunctionBody {
             module,
         }
     }
+    /// Render the CFG as a Graphviz digraph: one node per block,
+    /// labeled with the block, its params and (the first few of) its
+    /// instructions, and one edge per successor. `CondBr` edges are
+    /// labeled `true`/`false` and `Select` edges by index (or
+    /// `default`). `module`, if provided, adds source locations to
+    /// the instructions as in `display`.
+    pub fn to_dot(&self, module: Option<&Module>) -> String {
+        use core::fmt::Write;
+        const MAX_INSTS: usize = 8;
+        fn escape(text: &str) -> String {
+            text.replace('\\', "\\\\").replace('"', "\\\"")
+        }
+
+        let mut out = String::new();
+        writeln!(out, "digraph {{").unwrap();
+        writeln!(out, "  node [shape=box, fontname=monospace];").unwrap();
+        for (block, data) in self.blocks.entries() {
+            let params = data
+                .params
+                .iter()
+                .map(|(ty, val)| format!("{}: {}", val, ty))
+                .collect::<Vec<_>>();
+            let mut label = format!("{}({})\\l", block, escape(&params.join(", ")));
+            for inst in data.insts.iter().take(MAX_INSTS) {
+                let inst = inst.value;
+                let text = match &self.values[inst] {
+                    ValueDef::Operator(op, args, _) => {
+                        let args = self.arg_pool[*args]
+                            .iter()
+                            .map(|arg| format!("{}", arg))
+                            .collect::<Vec<_>>();
+                        let loc = self.source_locs[inst];
+                        match module {
+                            Some(module) if loc != SourceLoc::invalid() => {
+                                let data = &module.debug.source_locs[loc];
+                                let filename = &module.debug.source_files[data.file];
+                                format!(
+                                    "{} = {} {} @ {}:{}",
+                                    inst,
+                                    op,
+                                    args.join(", "),
+                                    filename,
+                                    data.line
+                                )
+                            }
+                            _ => format!("{} = {} {}", inst, op, args.join(", ")),
+                        }
+                    }
+                    ValueDef::PickOutput(val, idx, _) => format!("{} = {}.{}", inst, val, idx),
+                    ValueDef::Alias(val) => format!("{} = {}", inst, val),
+                    def => format!("{} = {:?}", inst, def),
+                };
+                write!(label, "  {}\\l", escape(&text)).unwrap();
+            }
+            if data.insts.len() > MAX_INSTS {
+                write!(label, "  ... ({} more)\\l", data.insts.len() - MAX_INSTS).unwrap();
+            }
+            write!(label, "  {}\\l", escape(&format!("{}", data.terminator))).unwrap();
+            writeln!(out, "  {} [label=\"{}\"];", block, label).unwrap();
+
+            let mut edge = |target: &BlockTarget, edge_label: Option<String>| {
+                match edge_label {
+                    Some(edge_label) => writeln!(
+                        out,
+                        "  {} -> {} [label=\"{}\"];",
+                        block, target.block, edge_label
+                    ),
+                    None => writeln!(out, "  {} -> {};", block, target.block),
+                }
+                .unwrap();
+            };
+            match &data.terminator.terminator {
+                Terminator::CondBr {
+                    if_true, if_false, ..
+                } => {
+                    edge(if_true, Some("true".to_owned()));
+                    edge(if_false, Some("false".to_owned()));
+                }
+                Terminator::Select {
+                    targets, default, ..
+                } => {
+                    for (i, target) in targets.iter().enumerate() {
+                        edge(target, Some(format!("{}", i)));
+                    }
+                    edge(default, Some("default".to_owned()));
+                }
+                term => term.visit_targets(|target| edge(target, None)),
+            }
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
     /// Validate consistency of the IR against required invariants and properties:
     ///
     /// - Block successor and predecessor lists are accurate with
@@ -465,13 +863,50 @@ impl FunctionBody {
                 );
             }
         }
-        // Compute the location where every value is defined.
+        // Verify that no alias chain loops back on itself; the checks
+        // below resolve aliases and would otherwise hang.
+        let mut acyclic: PerEntity<Value, bool> = PerEntity::default();
+        for value in self.values.iter() {
+            let mut chain = HashSet::new();
+            let mut v = value;
+            while let ValueDef::Alias(target) = self.values[v] {
+                if acyclic[v] {
+                    break;
+                }
+                if !chain.insert(v) {
+                    anyhow::bail!("Alias cycle through {} reached from {}", v, value);
+                }
+                v = target;
+            }
+            for v in chain {
+                acyclic[v] = true;
+            }
+        }
+        // Compute the location where every value is defined. Each
+        // instruction must be placed once, in the block that
+        // `value_blocks` records for it.
         let mut block_inst: PerEntity<Value, Option<(Block, Option<usize>)>> = PerEntity::default();
         for (block, block_def) in self.blocks.entries() {
             for &(_, param) in &block_def.params {
                 block_inst[param] = Some((block, None));
             }
             for (i, inst) in block_def.insts.iter().enumerate() {
+                if let Some((other, _)) = block_inst[inst.value] {
+                    anyhow::bail!(
+                        "Value {} is defined in both {} and {}",
+                        inst.value,
+                        other,
+                        block
+                    );
+                }
+                if self.value_blocks[inst.value] != block {
+                    anyhow::bail!(
+                        "Value {} is defined in {} but value_blocks records {}",
+                        inst.value,
+                        block,
+                        self.value_blocks[inst.value]
+                    );
+                }
                 block_inst[inst.value] = Some((block, Some(i)));
             }
         }
@@ -968,12 +1403,11 @@ impl Terminator {
     pub fn visit_successors<F: FnMut(Block)>(&self, mut f: F) {
         self.visit_targets(|target| f(target.block));
     }
+    /// Visit every value this terminator uses: a branch condition or
+    /// selector first, then any call or return operands, then the
+    /// args of each target in `visit_targets` order. `update_uses`
+    /// and the `ssa_traits` `HasValues` impls use the same order.
     pub fn visit_uses<F: FnMut(Value)>(&self, mut f: F) {
-        self.visit_targets(|target| {
-            for &arg in &target.args {
-                f(arg);
-            }
-        });
         match self {
             &Terminator::CondBr { cond, .. } => f(cond),
             &Terminator::Select { value, .. } => f(value),
@@ -996,15 +1430,20 @@ impl Terminator {
                     f(*value);
                 }
             }
+            &Terminator::ReturnCallRef { sig, ref args } => {
+                for value in args {
+                    f(*value);
+                }
+            }
             _ => {}
         }
-    }
-    pub fn update_uses<F: FnMut(&mut Value)>(&mut self, mut f: F) {
-        self.update_targets(|target| {
-            for arg in &mut target.args {
+        self.visit_targets(|target| {
+            for &arg in &target.args {
                 f(arg);
             }
         });
+    }
+    pub fn update_uses<F: FnMut(&mut Value)>(&mut self, mut f: F) {
         match self {
             &mut Terminator::CondBr { ref mut cond, .. } => f(cond),
             &mut Terminator::Select { ref mut value, .. } => f(value),
@@ -1034,5 +1473,660 @@ impl Terminator {
             }
             _ => {}
         }
+        self.update_targets(|target| {
+            for arg in &mut target.args {
+                f(arg);
+            }
+        });
+    }
+}
+
+/// The estimated encoded size of one operator, for
+/// `FunctionBody::estimate_code_size`.
+fn estimate_op_size(op: &Operator) -> usize {
+    /// Bytes in the LEB128 encoding of `value`.
+    fn leb_size(mut value: u64) -> usize {
+        let mut size = 1;
+        while value >= 0x80 {
+            value >>= 7;
+            size += 1;
+        }
+        size
+    }
+    /// Bytes in the signed LEB128 encoding of `value`.
+    fn sleb_size(value: i64) -> usize {
+        let magnitude = (if value < 0 { !value } else { value }) as u64;
+        leb_size(magnitude << 1)
+    }
+    match op {
+        Operator::I32Const { value } => 1 + sleb_size(*value as i32 as i64),
+        Operator::I64Const { value } => 1 + sleb_size(*value as i64),
+        Operator::F32Const { .. } => 5,
+        Operator::F64Const { .. } => 9,
+        Operator::V128Const { .. } => 18,
+        Operator::Call { function_index } => 1 + leb_size(function_index.index() as u64),
+        Operator::CallIndirect { .. } => 4,
+        Operator::CallRef { .. } => 3,
+        // Opcode, alignment and offset.
+        _ => match crate::op_traits::memory_arg(op) {
+            Some(memarg) => 2 + leb_size(memarg.offset),
+            None => 1,
+        },
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// One instance of every terminator variant, using distinct
+    /// values so that a dropped or duplicated use shows up.
+    fn every_terminator() -> Vec<Terminator> {
+        let v = |i| Value::new(i);
+        let target = |block, args: &[usize]| BlockTarget {
+            block: Block::new(block),
+            args: args.iter().map(|&i| v(i)).collect(),
+        };
+        vec![
+            Terminator::Br {
+                target: target(1, &[0, 1]),
+            },
+            Terminator::CondBr {
+                cond: v(0),
+                if_true: target(1, &[1]),
+                if_false: target(2, &[2, 3]),
+            },
+            Terminator::Select {
+                value: v(0),
+                targets: vec![target(1, &[1]), target(2, &[])],
+                default: target(3, &[2]),
+            },
+            Terminator::Return {
+                values: vec![v(0), v(1)],
+            },
+            Terminator::ReturnCall {
+                func: Func::new(0),
+                args: vec![v(0)],
+            },
+            Terminator::ReturnCallIndirect {
+                sig: Signature::new(0),
+                table: Table::new(0),
+                args: vec![v(0), v(1)],
+            },
+            Terminator::ReturnCallRef {
+                sig: Signature::new(0),
+                args: vec![v(0), v(1)],
+            },
+            Terminator::Unreachable,
+            Terminator::UB,
+            Terminator::None,
+        ]
+    }
+
+    #[test]
+    fn terminator_uses_agree() {
+        for term in every_terminator() {
+            let mut visited = vec![];
+            term.visit_uses(|value| visited.push(value));
+            let mut updated = vec![];
+            term.clone().update_uses(|value| updated.push(*value));
+            assert_eq!(visited, updated, "{:?}", term);
+            let expected = match &term {
+                Terminator::Unreachable | Terminator::UB | Terminator::None => 0,
+                Terminator::Select { .. } => 3,
+                Terminator::ReturnCall { .. } => 1,
+                Terminator::CondBr { .. } => 4,
+                _ => 2,
+            };
+            assert_eq!(visited.len(), expected, "{:?}", term);
+        }
+    }
+
+    #[cfg(feature = "ssa-traits-02")]
+    #[test]
+    fn has_values_matches_visit_uses() {
+        use ssa_traits::HasValues;
+        let mut body = FunctionBody::default();
+        for mut term in every_terminator() {
+            let mut visited = vec![];
+            term.visit_uses(|value| visited.push(value));
+            let values = term.values(&body).collect::<Vec<_>>();
+            assert_eq!(values, visited, "{:?}", term);
+            let values = term.values_mut(&mut body).map(|v| *v).collect::<Vec<_>>();
+            assert_eq!(values, visited, "{:?}", term);
+        }
+    }
+
+    #[test]
+    fn uses_of_shared_operand() {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![Type::I32, Type::I32],
+            shared: false,
+        });
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let x = body.blocks[entry].params[0].1;
+        let sq = body.add_op(entry, Operator::I32Mul, &[x, x], &[Type::I32]);
+        let alias = body.add_value(ValueDef::Alias(x));
+        let sum = body.add_op(entry, Operator::I32Add, &[sq, alias], &[Type::I32]);
+        let unused = body.add_op(entry, Operator::I32Const { value: 1 }, &[], &[Type::I32]);
+        body.set_terminator(
+            entry,
+            Terminator::Return {
+                values: vec![sum, x],
+            },
+        );
+
+        let uses = body.compute_uses();
+        assert_eq!(uses[x], vec![sq, sum]);
+        assert_eq!(uses[sq], vec![sum]);
+        assert!(uses[sum].is_empty());
+        assert!(uses[unused].is_empty());
+        let term_uses = body.compute_terminator_uses();
+        assert_eq!(term_uses[x], vec![entry]);
+        assert_eq!(term_uses[sum], vec![entry]);
+        assert!(term_uses[sq].is_empty());
+    }
+
+    #[test]
+    fn edges_of_join_block() {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![],
+            shared: false,
+        });
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let x = body.blocks[entry].params[0].1;
+        let (mid, join) = (body.add_block(), body.add_block());
+        let target = |block| BlockTarget {
+            block,
+            args: vec![],
+        };
+        body.set_terminator(
+            entry,
+            Terminator::CondBr {
+                cond: x,
+                if_true: target(mid),
+                if_false: target(join),
+            },
+        );
+        body.set_terminator(
+            mid,
+            Terminator::Br {
+                target: target(join),
+            },
+        );
+        body.set_terminator(join, Terminator::Return { values: vec![] });
+
+        let preds = body.pred_edges(join).collect::<Vec<_>>();
+        assert_eq!(preds, vec![(entry, 1), (mid, 0)]);
+        for &(pred, succ_idx) in &preds {
+            assert_eq!(body.blocks[pred].succs[succ_idx], join);
+        }
+        let succs = body.succ_edges(entry).collect::<Vec<_>>();
+        assert_eq!(succs, vec![(mid, 0), (join, 0)]);
+        for &(succ, pred_idx) in &succs {
+            assert_eq!(body.blocks[succ].preds[pred_idx], entry);
+        }
+        assert_eq!(body.succ_edges(mid).collect::<Vec<_>>(), vec![(join, 1)]);
+        assert_eq!(body.pred_edges(entry).count(), 0);
+    }
+
+    #[test]
+    fn set_operator_keeps_args_and_types() {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32, Type::I32],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let a = body.blocks[entry].params[0].1;
+        let b = body.blocks[entry].params[1].1;
+        let sum = body.add_op(entry, Operator::I32Add, &[a, b], &[Type::I32]);
+        let ValueDef::Operator(_, args, tys) = body.values[sum] else {
+            unreachable!()
+        };
+
+        body.set_operator(sum, Operator::I32Or);
+        let ValueDef::Operator(op, new_args, new_tys) = body.values[sum] else {
+            panic!("no longer an operator");
+        };
+        assert_eq!(op, Operator::I32Or);
+        assert_eq!(new_args, args);
+        assert_eq!(new_tys, tys);
+        assert_eq!(&body.arg_pool[new_args], &[a, b]);
+        assert_eq!(&body.type_pool[new_tys], &[Type::I32]);
+    }
+
+    #[test]
+    fn dot_of_diamond() {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let x = body.blocks[entry].params[0].1;
+        let (left, right, join) = (body.add_block(), body.add_block(), body.add_block());
+        let result = body.add_blockparam(join, Type::I32);
+        let target = |block, args: Vec<Value>| BlockTarget { block, args };
+        body.set_terminator(
+            entry,
+            Terminator::CondBr {
+                cond: x,
+                if_true: target(left, vec![]),
+                if_false: target(right, vec![]),
+            },
+        );
+        let one = body.add_op(left, Operator::I32Const { value: 1 }, &[], &[Type::I32]);
+        body.set_terminator(
+            left,
+            Terminator::Br {
+                target: target(join, vec![one]),
+            },
+        );
+        body.set_terminator(
+            right,
+            Terminator::Br {
+                target: target(join, vec![x]),
+            },
+        );
+        body.set_terminator(
+            join,
+            Terminator::Return {
+                values: vec![result],
+            },
+        );
+
+        let dot = body.to_dot(None);
+        assert!(dot.starts_with("digraph {"));
+        assert_eq!(dot.matches("[label=").count(), 4 + 2);
+        assert_eq!(dot.matches(" -> ").count(), 4);
+        assert!(dot.contains("block0 -> block1 [label=\"true\"];"));
+        assert!(dot.contains("block0 -> block2 [label=\"false\"];"));
+        assert!(dot.contains("block1 -> block3;"));
+        assert!(dot.contains("block2 -> block3;"));
+        assert!(dot.contains(&format!("{} = i32const<1>", one)), "{}", dot);
+    }
+
+    #[test]
+    fn block_ops_skip_non_operators() {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let x = body.blocks[entry].params[0].1;
+        let one = body.add_op(entry, Operator::I32Const { value: 1 }, &[], &[Type::I32]);
+        let pair = body.add_op(entry, Operator::Select, &[x, one, x], &[Type::I32]);
+        let pick = body.add_value(ValueDef::PickOutput(pair, 0, Type::I32));
+        body.append_to_block(entry, pick);
+        let alias = body.add_value(ValueDef::Alias(one));
+        body.append_to_block(entry, alias);
+        // Placed before `one` in the block, though numbered after it.
+        let sum = body.add_value(ValueDef::None);
+        let args = body.arg_pool.from_iter([pick, alias].iter().cloned());
+        let tys = body.single_type_list(Type::I32);
+        body.values[sum] = ValueDef::Operator(Operator::I32Add, args, tys);
+        body.blocks[entry].insts.insert(0, ValueRecord::core(sum));
+
+        let ops = body
+            .block_ops(entry)
+            .map(|(value, op, args)| (value, op.clone(), args.to_vec()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ops,
+            vec![
+                (sum, Operator::I32Add, vec![pick, alias]),
+                (one, Operator::I32Const { value: 1 }, vec![]),
+                (pair, Operator::Select, vec![x, one, x]),
+            ]
+        );
+
+        for (_, op, args) in body.block_ops_mut(entry) {
+            if *op == Operator::I32Add {
+                assert_eq!(args, &[pick, alias]);
+                *op = Operator::I32Sub;
+            }
+        }
+        let values = body
+            .block_ops_mut(entry)
+            .map(|(value, ..)| value)
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![sum, one, pair]);
+        assert!(matches!(
+            body.values[sum],
+            ValueDef::Operator(Operator::I32Sub, ..)
+        ));
+    }
+
+    #[test]
+    fn code_size_grows_with_body() {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        let mut tiny = FunctionBody::new(&module, sig);
+        let x = tiny.blocks[tiny.entry].params[0].1;
+        tiny.set_terminator(tiny.entry, Terminator::Return { values: vec![x] });
+
+        let mut larger = FunctionBody::new(&module, sig);
+        let entry = larger.entry;
+        let x = larger.blocks[entry].params[0].1;
+        let small = larger.add_op(entry, Operator::I32Const { value: 1 }, &[], &[Type::I32]);
+        let big = larger.add_op(
+            entry,
+            Operator::I32Const { value: 1 << 30 },
+            &[],
+            &[Type::I32],
+        );
+        let sum = larger.add_op(entry, Operator::I32Add, &[x, small], &[Type::I32]);
+        let prod = larger.add_op(entry, Operator::I32Mul, &[sum, big], &[Type::I32]);
+        larger.set_terminator(entry, Terminator::Return { values: vec![prod] });
+
+        assert_eq!(tiny.estimate_code_size(), 1);
+        // Opcodes plus 1- and 5-byte immediates, and the return.
+        assert_eq!(larger.estimate_code_size(), (1 + 1) + (1 + 5) + 1 + 1 + 1);
+        assert_eq!(larger.estimate_code_size(), larger.estimate_code_size());
+    }
+
+    #[test]
+    fn validate_rejects_alias_cycle() {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let a = body.add_value(ValueDef::Alias(Value::invalid()));
+        let b = body.add_value(ValueDef::Alias(a));
+        body.values[a] = ValueDef::Alias(b);
+        body.set_terminator(entry, Terminator::Return { values: vec![a] });
+        let err = body.validate().unwrap_err();
+        assert!(format!("{}", err).contains("Alias cycle"), "{}", err);
+    }
+
+    #[test]
+    fn validate_rejects_value_defined_twice() {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let x = body.blocks[entry].params[0].1;
+        let next = body.add_block();
+        let sum = body.add_op(entry, Operator::I32Add, &[x, x], &[Type::I32]);
+        body.set_terminator(
+            entry,
+            Terminator::Br {
+                target: BlockTarget {
+                    block: next,
+                    args: vec![],
+                },
+            },
+        );
+        body.set_terminator(next, Terminator::Return { values: vec![sum] });
+        body.validate().unwrap();
+
+        // A pass that copies an instruction record without giving it a
+        // fresh value places the same value twice.
+        let record = body.blocks[entry].insts[0].clone();
+        body.blocks[next].insts.push(record.clone());
+        let err = format!("{}", body.validate().unwrap_err());
+        assert!(err.contains("defined in both block0 and block1"), "{}", err);
+
+        // Moving it without updating `value_blocks` is caught too.
+        body.blocks[entry].insts.clear();
+        let err = format!("{}", body.validate().unwrap_err());
+        assert!(err.contains("value_blocks records block0"), "{}", err);
+        body.value_blocks[sum] = next;
+        body.validate().unwrap();
+    }
+
+    #[test]
+    fn removes_dangling_block() {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let x = body.blocks[entry].params[0].1;
+        let dangling = body.add_block();
+        let exit = body.add_block();
+        let p = body.add_blockparam(exit, Type::I32);
+        let k = body.add_op(dangling, Operator::I32Const { value: 1 }, &[], &[Type::I32]);
+        body.set_terminator(
+            dangling,
+            Terminator::Br {
+                target: BlockTarget {
+                    block: exit,
+                    args: vec![k],
+                },
+            },
+        );
+        body.set_terminator(
+            entry,
+            Terminator::Br {
+                target: BlockTarget {
+                    block: exit,
+                    args: vec![x],
+                },
+            },
+        );
+        body.set_terminator(exit, Terminator::Return { values: vec![p] });
+
+        let cfg = CFGInfo::new(&body);
+        let remap = body.remove_unreachable_blocks(&cfg);
+        assert_eq!(body.blocks.len(), 2);
+        assert_eq!(remap[entry], body.entry);
+        assert_eq!(body.blocks[body.entry].params[0].1, x);
+        assert!(remap[dangling].is_invalid());
+        let exit = remap[exit];
+        assert_eq!(body.blocks[exit].preds, vec![body.entry]);
+        assert!(matches!(body.values[p], ValueDef::BlockParam(b, 0, _) if b == exit));
+        assert!(matches!(body.values[k], ValueDef::None));
+        body.validate().unwrap();
+    }
+
+    #[test]
+    fn replace_all_uses_rewrites_every_use() {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![Type::I32, Type::I32],
+            shared: false,
+        });
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let x = body.blocks[entry].params[0].1;
+        let old = body.add_op(entry, Operator::I32Const { value: 1 }, &[], &[Type::I32]);
+        let sum = body.add_op(entry, Operator::I32Add, &[old, x], &[Type::I32]);
+        let product = body.add_op(entry, Operator::I32Mul, &[sum, old], &[Type::I32]);
+        let pick = body.add_value(ValueDef::PickOutput(old, 0, Type::I32));
+        body.append_to_block(entry, pick);
+        body.set_terminator(
+            entry,
+            Terminator::Return {
+                values: vec![product, old],
+            },
+        );
+
+        body.replace_all_uses(old, x);
+        let args = |value: Value| match &body.values[value] {
+            ValueDef::Operator(_, args, _) => body.arg_pool[*args].to_vec(),
+            _ => unreachable!(),
+        };
+        assert_eq!(args(sum), vec![x, x]);
+        assert_eq!(args(product), vec![sum, x]);
+        assert!(matches!(body.values[pick], ValueDef::PickOutput(v, 0, _) if v == x));
+        assert!(matches!(
+            &body.blocks[entry].terminator.terminator,
+            Terminator::Return { values } if values == &vec![product, x]
+        ));
+        // The old definition stays as it was, and still placed.
+        assert!(matches!(
+            body.values[old],
+            ValueDef::Operator(Operator::I32Const { value: 1 }, ..)
+        ));
+        assert_eq!(body.blocks[entry].insts[0].value, old);
+        assert_eq!(body.compute_uses()[old], vec![]);
+    }
+
+    #[test]
+    fn apply_aliases_collapses_chains() {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let root = body.add_op(entry, Operator::I32Const { value: 1 }, &[], &[Type::I32]);
+        let mut chain = vec![];
+        let mut prev = root;
+        for _ in 0..3 {
+            let v = body.add_op(entry, Operator::I32Const { value: 2 }, &[], &[Type::I32]);
+            // Build the chain by hand: `set_alias` would short-circuit it.
+            body.values[v] = ValueDef::Alias(prev);
+            chain.push(v);
+            prev = v;
+        }
+        let sum = body.add_op(entry, Operator::I32Add, &[prev, prev], &[Type::I32]);
+        let pick = body.add_value(ValueDef::PickOutput(prev, 0, Type::I32));
+        body.set_terminator(entry, Terminator::Return { values: vec![prev] });
+
+        for _ in 0..2 {
+            body.apply_aliases();
+            let ValueDef::Operator(_, args, _) = &body.values[sum] else {
+                panic!("sum is no longer an operator");
+            };
+            assert_eq!(&body.arg_pool[*args], &[root, root]);
+            assert!(matches!(body.values[pick], ValueDef::PickOutput(v, 0, _) if v == root));
+            assert!(matches!(
+                &body.blocks[entry].terminator.terminator,
+                Terminator::Return { values } if values == &vec![root]
+            ));
+            for &v in &chain {
+                assert!(matches!(body.values[v], ValueDef::Alias(to) if to == root));
+                assert_eq!(body.resolve_alias(v), root);
+            }
+            let insts = body.blocks[entry]
+                .insts
+                .iter()
+                .map(|inst| inst.value)
+                .collect::<Vec<_>>();
+            assert_eq!(insts, vec![root, sum]);
+        }
+    }
+
+    #[test]
+    fn return_call_uses() {
+        let args = vec![Value::new(0), Value::new(1), Value::new(2)];
+        let terminators = vec![
+            Terminator::ReturnCall {
+                func: Func::new(0),
+                args: args.clone(),
+            },
+            Terminator::ReturnCallIndirect {
+                sig: Signature::new(0),
+                table: Table::new(0),
+                args: args.clone(),
+            },
+            Terminator::ReturnCallRef {
+                sig: Signature::new(0),
+                args: args.clone(),
+            },
+        ];
+        for mut term in terminators {
+            let mut visited = vec![];
+            term.visit_uses(|u| visited.push(u));
+            assert_eq!(visited, args, "visit_uses on {}", term);
+            let mut updated = vec![];
+            term.update_uses(|u| updated.push(*u));
+            assert_eq!(updated, visited, "update_uses on {}", term);
+            assert_eq!(term.num_targets(), 0);
+        }
+    }
+
+    #[test]
+    fn split_block_in_middle() {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let x = body.blocks[entry].params[0].1;
+        let exit = body.add_block();
+        let p = body.add_blockparam(exit, Type::I32);
+        let a = body.add_op(entry, Operator::I32Const { value: 1 }, &[], &[Type::I32]);
+        let b = body.add_op(entry, Operator::I32Add, &[x, a], &[Type::I32]);
+        let c = body.add_op(entry, Operator::I32Mul, &[b, b], &[Type::I32]);
+        let d = body.add_op(entry, Operator::I32Sub, &[c, a], &[Type::I32]);
+        body.set_terminator(
+            entry,
+            Terminator::Br {
+                target: BlockTarget {
+                    block: exit,
+                    args: vec![d],
+                },
+            },
+        );
+        body.set_terminator(exit, Terminator::Return { values: vec![p] });
+
+        let tail = body.split_block(entry, 2);
+        let insts = |body: &FunctionBody, block: Block| {
+            body.blocks[block]
+                .insts
+                .iter()
+                .map(|inst| inst.value)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(insts(&body, entry), vec![a, b]);
+        assert_eq!(insts(&body, tail), vec![c, d]);
+        assert_eq!(body.value_blocks[b], entry);
+        assert_eq!(body.value_blocks[c], tail);
+        assert_eq!(body.value_blocks[d], tail);
+        assert!(matches!(
+            &body.blocks[entry].terminator.terminator,
+            Terminator::Br { target } if target.block == tail && target.args.is_empty()
+        ));
+        assert!(matches!(
+            &body.blocks[tail].terminator.terminator,
+            Terminator::Br { target } if target.block == exit && target.args == vec![d]
+        ));
+        assert_eq!(body.blocks[entry].succs, vec![tail]);
+        assert_eq!(body.blocks[tail].preds, vec![entry]);
+        assert_eq!(body.blocks[tail].succs, vec![exit]);
+        assert_eq!(body.blocks[exit].preds, vec![tail]);
+        body.validate().unwrap();
+
+        let cfg = CFGInfo::new(&body);
+        assert!(cfg.dominates(entry, tail));
+        assert!(cfg.dominates(tail, exit));
+        assert!(!cfg.dominates(tail, entry));
     }
 }
@@ -14,3 +14,7 @@ mod display;
 pub use display::*;
 mod debug;
 pub use debug::*;
+mod builder;
+pub use builder::*;
+mod parse;
+pub use parse::*;
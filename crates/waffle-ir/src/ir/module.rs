@@ -1,28 +1,34 @@
 use super::{
     ControlTag, Func, FuncDecl, Global, HeapType, Memory, ModuleDisplay, Signature, StorageType,
-    Table, Type, WithMutablility,
+    Table, Type, WithMutablility, WithNullable,
+};
+use crate::op_traits::rewrite_sigs;
+use crate::{
+    op_inputs, op_outputs, EntityRef, EntityVec, Operator, PerEntity, Subtypes, Terminator,
+    ValueDef,
 };
-use crate::{EntityRef, EntityVec};
 // NOTE: FrontendOptions moved to frontend crate to avoid circular dependencies
 // pub use crate::frontend::FrontendOptions;
 use crate::ir::{Debug, DebugMap, FunctionBody};
 // NOTE: backend and frontend imports removed to avoid circular dependencies
 // use crate::{backend, frontend};
-use alloc::collections::BTreeMap;
+use alloc::borrow::ToOwned;
+use alloc::collections::BTreeSet;
 use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use core::default;
 use core::iter::{empty, once};
 use either::Either;
 use impl_trait_for_tuples::impl_for_tuples;
 use indexmap::IndexMap;
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Module<'a> {
     /// The original Wasm module this module was parsed from, if
     /// any. Used only for "lazy function bodies", which retain a
     /// range that can refer into this slice.
+    #[serde(skip)]
     pub orig_bytes: Option<&'a [u8]>,
     /// The functions in this module: imports, un-expanded ("lazily
     /// parsed") functions, functions as IR, or IR compiled into new
@@ -42,6 +48,10 @@ pub struct Module<'a> {
     pub exports: Vec<Export>,
     /// Memories/heapds that this module contains.
     pub memories: EntityVec<Memory, MemoryData>,
+    /// Data segments, in the module's data index space (the space
+    /// used by `memory.init`, `data.drop` and the array data
+    /// operators).
+    pub data_segments: Vec<DataSegment>,
     /// Control tags that this module contains
     pub control_tags: EntityVec<ControlTag, ControlTagData>,
     /// The "start function" invoked at instantiation, if any.
@@ -51,16 +61,41 @@ pub struct Module<'a> {
     pub debug: Debug,
     /// Maps from original Wasm bytecode offsets to source locations.
     pub debug_map: DebugMap,
-    pub custom_sections: BTreeMap<String, Vec<u8>>,
+    /// Custom sections not otherwise interpreted (everything but the
+    /// name section), as (name, contents) in their original order.
+    pub custom_sections: Vec<(String, Vec<u8>)>,
+}
+/// The parts of a `Module` other than its functions, borrowed
+/// immutably; see `Module::for_each_body_mut`.
+#[derive(Clone, Copy, Debug)]
+pub struct ModuleView<'m> {
+    pub signatures: &'m EntityVec<Signature, SignatureData>,
+    pub globals: &'m EntityVec<Global, GlobalData>,
+    pub tables: &'m EntityVec<Table, TableData>,
+    pub memories: &'m EntityVec<Memory, MemoryData>,
+    pub control_tags: &'m EntityVec<ControlTag, ControlTagData>,
 }
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(
+    Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
 #[cfg_attr(feature = "rkyv-impl", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 pub struct ControlTagData {
     ///The signature used when invoking this tag
     pub sig: Signature,
 }
 #[non_exhaustive]
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[derive(
+    Clone,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Default,
+    serde::Serialize,
+    serde::Deserialize,
+)]
 #[cfg_attr(feature = "rkyv-impl", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 pub enum SignatureData {
     Func {
@@ -107,11 +142,62 @@ impl SignatureData {
             SignatureData::None => todo!(),
         }
     }
+    /// Whether `self` and `other` are the same kind of signature with
+    /// the same types, once every signature either of them refers to
+    /// has been mapped through `resolve`. This lets types that refer
+    /// to distinct but equivalent signatures (say, from two modules
+    /// being merged) compare equal.
+    pub fn structurally_eq(
+        &self,
+        other: &SignatureData,
+        resolve: impl Fn(Signature) -> Signature,
+    ) -> bool {
+        let (mut a, mut b) = (self.clone(), other.clone());
+        a.update_sigs(|sig| *sig = resolve(*sig));
+        b.update_sigs(|sig| *sig = resolve(*sig));
+        a == b
+    }
+    /// Apply `f` to every signature referenced by this signature's
+    /// parameter, result, field or element types.
+    pub fn update_sigs(&mut self, mut f: impl FnMut(&mut Signature)) {
+        match self {
+            SignatureData::Func {
+                params, returns, ..
+            } => {
+                for ty in params.iter_mut().chain(returns.iter_mut()) {
+                    ty.update_sigs(&mut f);
+                }
+            }
+            SignatureData::Struct { fields, .. } => {
+                for field in fields {
+                    if let StorageType::Val(ty) = &mut field.value {
+                        ty.update_sigs(&mut f);
+                    }
+                }
+            }
+            SignatureData::Array { ty, .. } => {
+                if let StorageType::Val(ty) = &mut ty.value {
+                    ty.update_sigs(&mut f);
+                }
+            }
+            SignatureData::Import {
+                like: HeapType::Sig { sig_index },
+                ..
+            } => f(sig_index),
+            SignatureData::Import { .. } | SignatureData::None => {}
+        }
+    }
 }
+/// Leading bytes of every `Module::to_snapshot` blob: a tag and a
+/// format version, bumped whenever the serialized IR changes shape.
+#[cfg(feature = "snapshot")]
+const SNAPSHOT_MAGIC: [u8; 8] = *b"WAFFLE\x00\x02";
 /// The size of a single Wasm page, used in memory definitions.
 pub const WASM_PAGE: usize = 0x1_0000; // 64KiB
 /// A memory definition.
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(
+    Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
 #[cfg_attr(feature = "rkyv-impl", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 pub struct MemoryData {
     /// How many Wasm pages (64KiB size) in the initial memory size?
@@ -124,7 +210,9 @@ pub struct MemoryData {
     pub shared: bool,
     pub page_size_log2: Option<u32>,
 }
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(
+    Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
 #[cfg_attr(feature = "rkyv-impl", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 pub struct MemorySegment {
     /// The offset of this data.
@@ -132,7 +220,23 @@ pub struct MemorySegment {
     /// The data, overlaid on previously-existing data at this offset.
     pub data: Vec<u8>,
 }
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// An entry in the module's data index space.
+#[derive(
+    Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[cfg_attr(feature = "rkyv-impl", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
+pub enum DataSegment {
+    /// An active segment, copied into memory at instantiation and
+    /// dropped afterward. Its offset and bytes are entry `segment` of
+    /// `memory`'s `MemoryData::segments`.
+    Active { memory: Memory, segment: usize },
+    /// A passive segment, only copied into memory by `memory.init`
+    /// (or into an array by the array data operators).
+    Passive { data: Vec<u8> },
+}
+#[derive(
+    Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
 #[cfg_attr(feature = "rkyv-impl", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 pub struct TableData {
     /// The type of element in this table.
@@ -146,7 +250,9 @@ pub struct TableData {
     pub func_elements: Option<Vec<Func>>,
     pub table64: bool,
 }
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(
+    Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
 #[cfg_attr(feature = "rkyv-impl", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 pub struct GlobalData {
     /// The type of this global variable.
@@ -253,6 +359,29 @@ impl SignatureExt for Signature {
     }
 }
 impl Type {
+    /// A reference to `heap`, nullable or not.
+    pub fn heap(heap: HeapType, nullable: bool) -> Type {
+        Type::Heap(WithNullable {
+            value: heap,
+            nullable,
+        })
+    }
+    /// Is this a numeric type: `i32`, `i64`, `f32` or `f64`?
+    pub fn is_num(&self) -> bool {
+        matches!(self, Type::I32 | Type::I64 | Type::F32 | Type::F64)
+    }
+    /// Is this the vector type `v128`?
+    pub fn is_vec(&self) -> bool {
+        matches!(self, Type::V128)
+    }
+    /// Is this a reference type?
+    pub fn is_ref(&self) -> bool {
+        matches!(self, Type::Heap(_))
+    }
+    /// Is this a reference type that admits `null`?
+    pub fn is_nullable_ref(&self) -> bool {
+        matches!(self, Type::Heap(h) if h.nullable)
+    }
     pub fn sigs<'a>(&'a self) -> impl Iterator<Item = Signature> + 'a {
         match self {
             Type::Heap(h) => match &h.value {
@@ -262,8 +391,16 @@ impl Type {
             _ => Either::Left(empty()),
         }
     }
+    /// Apply `f` to the signature this type refers to, if any.
+    pub fn update_sigs(&mut self, mut f: impl FnMut(&mut Signature)) {
+        if let Type::Heap(h) = self {
+            if let HeapType::Sig { sig_index } = &mut h.value {
+                f(sig_index);
+            }
+        }
+    }
 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 #[cfg_attr(feature = "rkyv-impl", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 pub struct Import {
     /// The name of the module the import comes from.
@@ -274,7 +411,9 @@ pub struct Import {
     /// The kind of import and its specific entity index.
     pub kind: ImportKind,
 }
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(
+    Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
 #[cfg_attr(feature = "rkyv-impl", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 #[non_exhaustive]
 pub enum ImportKind {
@@ -304,7 +443,7 @@ impl core::fmt::Display for ImportKind {
         Ok(())
     }
 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 #[cfg_attr(feature = "rkyv-impl", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 pub struct Export {
     /// The name of this export.
@@ -312,7 +451,9 @@ pub struct Export {
     /// The kind of export and its specific entity index.
     pub kind: ExportKind,
 }
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(
+    Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
 #[cfg_attr(feature = "rkyv-impl", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 #[non_exhaustive]
 pub enum ExportKind {
@@ -389,6 +530,7 @@ impl<'a> Module<'a> {
             imports: vec![],
             exports: vec![],
             memories: EntityVec::default(),
+            data_segments: vec![],
             start_func: None,
             debug: Debug::default(),
             debug_map: DebugMap::default(),
@@ -396,6 +538,132 @@ impl<'a> Module<'a> {
             control_tags: Default::default(),
         }
     }
+    /// Add a signature, reusing an existing identical one if present.
+    pub fn add_signature(&mut self, data: SignatureData) -> Signature {
+        match self.signatures.entries().find(|(_, sig)| **sig == data) {
+            Some((sig, _)) => sig,
+            None => self.signatures.push(data),
+        }
+    }
+    /// Add a memory.
+    pub fn add_memory(&mut self, data: MemoryData) -> Memory {
+        self.memories.push(data)
+    }
+    /// Add a table.
+    pub fn add_table(&mut self, data: TableData) -> Table {
+        self.tables.push(data)
+    }
+    /// Add a global variable.
+    pub fn add_global(&mut self, data: GlobalData) -> Global {
+        self.globals.push(data)
+    }
+    /// The start function invoked at instantiation, if any.
+    pub fn start_func(&self) -> Option<Func> {
+        self.start_func
+    }
+    /// Set, or with `None` clear, the start function.
+    pub fn set_start_func(&mut self, func: Option<Func>) {
+        self.start_func = func;
+    }
+    /// Add a function. Imported functions should also be recorded in
+    /// `imports`.
+    pub fn add_func(&mut self, decl: FuncDecl<'a>) -> Func {
+        self.funcs.push(decl)
+    }
+    /// Iterate over the module's imports as `(module, name, kind)`
+    /// triples, in the order they will be emitted.
+    pub fn imports(&self) -> impl Iterator<Item = (&str, &str, ImportKind)> + '_ {
+        self.imports
+            .iter()
+            .map(|import| (&import.module[..], &import.name[..], import.kind.clone()))
+    }
+    /// Turn the imported function `f` into a function defined by
+    /// `body`, keeping its signature and name, and drop its import.
+    /// Returns the function's new index.
+    ///
+    /// The backend requires imported functions to precede all defined
+    /// ones, so if other imported functions follow `f`, it is moved
+    /// after them with `renumber_funcs`, shifting those imports down
+    /// by one; see there for which references are rewritten. That
+    /// needs every body expanded. Fails, leaving the module unchanged,
+    /// if `f` is not an imported function or if it must be moved while
+    /// lazy or compiled bodies remain.
+    pub fn define_imported_func(&mut self, f: Func, body: FunctionBody) -> Result<Func> {
+        if !matches!(self.funcs[f], FuncDecl::Import(..)) {
+            anyhow::bail!("{} is not an imported function", f);
+        }
+        let moves = self
+            .funcs
+            .values()
+            .skip(f.index() + 1)
+            .any(|decl| matches!(decl, FuncDecl::Import(..)));
+        if moves && !self.funcs_expanded() {
+            anyhow::bail!(
+                "{} must move after later imported functions, which needs all bodies expanded",
+                f
+            );
+        }
+        let decl = core::mem::take(&mut self.funcs[f]);
+        self.funcs[f] = match decl {
+            FuncDecl::Import(sig, name) => FuncDecl::Body(sig, name, body),
+            _ => unreachable!(),
+        };
+        self.imports
+            .retain(|import| import.kind != ImportKind::Func(f));
+        if !moves {
+            return Ok(f);
+        }
+        // Imports first, then the defined functions, where `f` now
+        // comes first, just after the last import.
+        let imported = |func: &Func| matches!(self.funcs[*func], FuncDecl::Import(..));
+        let order = self
+            .funcs
+            .iter()
+            .filter(imported)
+            .chain(self.funcs.iter().filter(|func| !imported(func)))
+            .collect::<Vec<_>>();
+        let remap = self.renumber_funcs(&order)?;
+        Ok(remap[f])
+    }
+    /// Iterate over the module's exports as `(name, kind)` pairs, in
+    /// the order they will be emitted.
+    pub fn exports(&self) -> impl Iterator<Item = (&str, ExportKind)> + '_ {
+        self.exports
+            .iter()
+            .map(|export| (&export.name[..], export.kind.clone()))
+    }
+    /// Export `kind` under `name`. Export names are unique, so an
+    /// existing export of that name is pointed at `kind` instead.
+    pub fn add_export(&mut self, name: &str, kind: ExportKind) {
+        match self.exports.iter_mut().find(|export| export.name == name) {
+            Some(export) => export.kind = kind,
+            None => self.exports.push(Export {
+                name: name.to_owned(),
+                kind,
+            }),
+        }
+    }
+    /// Remove the export called `name`. Returns whether it existed.
+    pub fn remove_export(&mut self, name: &str) -> bool {
+        let len = self.exports.len();
+        self.exports.retain(|export| export.name != name);
+        self.exports.len() != len
+    }
+    /// Rename the export `old` to `new`, keeping its position. Returns
+    /// false, changing nothing, if there is no export `old` or if a
+    /// different export is already called `new`.
+    pub fn rename_export(&mut self, old: &str, new: &str) -> bool {
+        if old != new && self.exports.iter().any(|export| export.name == new) {
+            return false;
+        }
+        match self.exports.iter_mut().find(|export| export.name == old) {
+            Some(export) => {
+                export.name = new.to_owned();
+                true
+            }
+            None => false,
+        }
+    }
     // NOTE: from_wasm_bytes moved to frontend crate to avoid circular dependencies
     /// Take this module and strip its reference to the original
     /// bytes, producing a module with the same logical contents.
@@ -407,6 +675,8 @@ impl<'a> Module<'a> {
     ///   recompiled into new bytecode. The bytecode should be
     ///   equivalent, but will not literally be the same bytecode as the
     ///   original module.
+    /// - Debug comments attached to values
+    ///   (`FunctionBody::value_comments`) are dropped.
     pub fn without_orig_bytes(self) -> Module<'static> {
         Module {
             orig_bytes: None,
@@ -423,6 +693,7 @@ impl<'a> Module<'a> {
             imports: self.imports,
             exports: self.exports,
             memories: self.memories,
+            data_segments: self.data_segments,
             start_func: self.start_func,
             debug: self.debug,
             debug_map: self.debug_map,
@@ -430,6 +701,34 @@ impl<'a> Module<'a> {
             control_tags: self.control_tags,
         }
     }
+    /// Serialize the whole module, including function bodies and
+    /// their pools, to a self-contained binary snapshot that
+    /// `from_snapshot` can reload, e.g. to cache IR between tool
+    /// runs. The original bytes are not included.
+    ///
+    /// `Lazy` function bodies refer into the original bytes and so
+    /// cannot be snapshotted: parse them first (e.g. with
+    /// `expand_all_funcs`). Fails if any remain.
+    #[cfg(feature = "snapshot")]
+    pub fn to_snapshot(&self) -> Result<Vec<u8>> {
+        let payload = bincode::serde::encode_to_vec(self, bincode::config::standard())
+            .map_err(|e| anyhow::anyhow!("Cannot snapshot module: {}", e))?;
+        let mut bytes = SNAPSHOT_MAGIC.to_vec();
+        bytes.extend(payload);
+        Ok(bytes)
+    }
+    /// Reload a module from a snapshot produced by `to_snapshot`.
+    #[cfg(feature = "snapshot")]
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Module<'static>> {
+        let Some(payload) = bytes.strip_prefix(&SNAPSHOT_MAGIC[..]) else {
+            anyhow::bail!("Not a module snapshot (or an unsupported snapshot version)");
+        };
+        let (module, len): (Module<'static>, usize) =
+            bincode::serde::decode_from_slice(payload, bincode::config::standard())
+                .map_err(|e| anyhow::anyhow!("Invalid module snapshot: {}", e))?;
+        anyhow::ensure!(len == payload.len(), "Trailing bytes after module snapshot");
+        Ok(module)
+    }
 }
 impl<'a> Module<'a> {
     // pub(crate) fn frontend_add_table(&mut self, ty: Type, initial: u64, max: Option<u64>) -> Table {
@@ -461,6 +760,24 @@ impl<'a> Module<'a> {
             self.funcs[func_decl] = x;
         }
     }
+    /// Call `f` on every function body that has been expanded to IR,
+    /// along with a view of the rest of the module for looking up
+    /// types, globals, tables and memories while the body is
+    /// borrowed mutably.
+    pub fn for_each_body_mut(&mut self, mut f: impl FnMut(&ModuleView, Func, &mut FunctionBody)) {
+        let view = ModuleView {
+            signatures: &self.signatures,
+            globals: &self.globals,
+            tables: &self.tables,
+            memories: &self.memories,
+            control_tags: &self.control_tags,
+        };
+        for (func, func_decl) in self.funcs.entries_mut() {
+            if let Some(body) = func_decl.body_mut() {
+                f(&view, func, body);
+            }
+        }
+    }
     pub fn try_per_func_body<F: FnMut(&mut FunctionBody) -> Result<(), E>, E>(
         &mut self,
         mut f: F,
@@ -498,6 +815,440 @@ impl<'a> Module<'a> {
     {
         ModuleDisplay { module: self }
     }
+    /// Type-check every IR function body: each operator's (resolved)
+    /// argument types must match `op_inputs`, and its declared result
+    /// types must match `op_outputs`. This complements
+    /// `FunctionBody::validate`, which checks only SSA structure.
+    ///
+    /// The signature table is checked first (see
+    /// `validate_signatures`).
+    pub fn validate(&self) -> Result<()> {
+        self.validate_signatures()?;
+        for (func, decl) in self.funcs.entries() {
+            let Some(body) = decl.body() else {
+                continue;
+            };
+            for (block, block_def) in body.blocks.entries() {
+                for inst in &block_def.insts {
+                    let value = inst.value;
+                    let ValueDef::Operator(op, args, tys) = &body.values[value] else {
+                        continue;
+                    };
+                    let stack = body.arg_pool[*args]
+                        .iter()
+                        .map(|&arg| {
+                            let arg = body.resolve_alias(arg);
+                            match body.values[arg].ty(&body.type_pool) {
+                                Some(ty) => Ok((ty, arg)),
+                                None => anyhow::bail!(
+                                    "{} in {} of {}: argument {} of {} does not have a single type",
+                                    value,
+                                    block,
+                                    func,
+                                    arg,
+                                    op
+                                ),
+                            }
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    let inputs = op_inputs(self, Some(&stack[..]), op)
+                        .with_context(|| format!("{} in {} of {}", value, block, func))?;
+                    let args_ok = inputs.len() == stack.len()
+                        && stack
+                            .iter()
+                            .zip(inputs.iter())
+                            .all(|(&(actual, _), expected)| {
+                                actual.subtypes(expected, self, &BTreeSet::new())
+                            });
+                    if !args_ok {
+                        anyhow::bail!(
+                            "{} in {} of {}: {} expects arguments {:?} but got {:?}",
+                            value,
+                            block,
+                            func,
+                            op,
+                            inputs,
+                            stack.iter().map(|&(ty, _)| ty).collect::<Vec<_>>()
+                        );
+                    }
+                    let outputs = op_outputs(self, Some(&stack[..]), op)
+                        .with_context(|| format!("{} in {} of {}", value, block, func))?;
+                    if &body.type_pool[*tys] != &outputs[..] {
+                        anyhow::bail!(
+                            "{} in {} of {}: {} produces {:?} but is declared as {:?}",
+                            value,
+                            block,
+                            func,
+                            op,
+                            outputs,
+                            &body.type_pool[*tys]
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Check that every heap-type reference inside a signature (a
+    /// function's parameters and results, a struct's fields, an array's
+    /// element type or a type import's bound) resolves to a defined
+    /// signature.
+    ///
+    /// Structs and arrays may refer to one another, or to themselves,
+    /// as a recursive type group does; but a type import whose bound
+    /// leads back to itself through other type imports never reaches a
+    /// concrete type and is rejected.
+    pub fn validate_signatures(&self) -> Result<()> {
+        for (sig, data) in self.signatures.entries() {
+            let mut bad = None;
+            data.clone().update_sigs(|target| {
+                let defined = target.is_valid()
+                    && target.index() < self.signatures.len()
+                    && !matches!(self.signatures[*target], SignatureData::None);
+                if !defined && bad.is_none() {
+                    bad = Some(*target);
+                }
+            });
+            if let Some(target) = bad {
+                anyhow::bail!("{}: reference to undefined signature {}", sig, target);
+            }
+        }
+        // Every bound now resolves, so the chains can be followed.
+        for sig in self.signatures.iter() {
+            let mut seen = BTreeSet::new();
+            let mut cur = sig;
+            while let SignatureData::Import {
+                like: HeapType::Sig { sig_index },
+                ..
+            } = &self.signatures[cur]
+            {
+                if !seen.insert(cur) {
+                    anyhow::bail!("{}: type import bound is cyclic", sig);
+                }
+                cur = *sig_index;
+            }
+        }
+        Ok(())
+    }
+    /// Remove every signature that is not reachable from the rest of
+    /// the module, renumbering the survivors in their original order.
+    ///
+    /// A signature is live if a function, control tag, global, table,
+    /// type import or type export refers to it, if any function body
+    /// does (through an operator, a terminator or a value's type), or
+    /// if a live signature mentions it in one of its own types; mutually
+    /// recursive struct and array types are handled. All references
+    /// are rewritten, and the returned map takes each old signature to
+    /// its new index, or to `Signature::invalid()` if it was removed.
+    ///
+    /// Un-expanded (lazy) and compiled bodies refer to signatures by
+    /// their original bytecode indices, which cannot be rewritten, so
+    /// if any remain nothing is removed and the identity map is
+    /// returned.
+    pub fn gc_signatures(&mut self) -> PerEntity<Signature, Signature> {
+        let mut remap = PerEntity::default();
+        if !self.sig_refs_rewritable() {
+            for sig in self.signatures.iter() {
+                remap[sig] = sig;
+            }
+            return remap;
+        }
+
+        let mut workqueue = vec![];
+        self.update_sig_refs(&mut |sig: &mut Signature| workqueue.push(*sig));
+        let mut live = BTreeSet::new();
+        while let Some(sig) = workqueue.pop() {
+            if sig.is_invalid() || sig.index() >= self.signatures.len() || !live.insert(sig) {
+                continue;
+            }
+            // Inserting before visiting the contents stops the walk at
+            // a signature already seen, so recursive types terminate.
+            self.signatures[sig]
+                .clone()
+                .update_sigs(|sig| workqueue.push(*sig));
+        }
+
+        let old = core::mem::take(&mut self.signatures);
+        for (sig, data) in old.entries() {
+            if live.contains(&sig) {
+                remap[sig] = self.signatures.push(data.clone());
+            }
+        }
+        self.rewrite_sigs(&remap);
+        remap
+    }
+    /// Collapse structurally identical function signatures into the
+    /// first of them, renumbering the survivors in their original
+    /// order. Two signatures match if `SignatureData::structurally_eq`
+    /// holds once already-collapsed references are resolved to their
+    /// survivor. Struct, array and type-import signatures are always
+    /// kept distinct. All references are rewritten, and the returned
+    /// map takes each old signature to its new index.
+    ///
+    /// As with `gc_signatures`, nothing changes (and the identity map
+    /// is returned) while any lazy or compiled body remains.
+    pub fn dedup_signatures(&mut self) -> PerEntity<Signature, Signature> {
+        let mut canon = PerEntity::default();
+        for sig in self.signatures.iter() {
+            canon[sig] = sig;
+        }
+        if !self.sig_refs_rewritable() {
+            return canon;
+        }
+
+        let len = self.signatures.len();
+        for (sig, data) in self.signatures.entries() {
+            if !matches!(data, SignatureData::Func { .. }) {
+                continue;
+            }
+            let resolve = |s: Signature| {
+                if s.is_valid() && s.index() < len {
+                    canon[s]
+                } else {
+                    s
+                }
+            };
+            let first = self
+                .signatures
+                .entries()
+                .take(sig.index())
+                .find(|&(prev, prev_data)| {
+                    canon[prev] == prev
+                        && matches!(prev_data, SignatureData::Func { .. })
+                        && data.structurally_eq(prev_data, resolve)
+                });
+            if let Some((prev, _)) = first {
+                canon[sig] = prev;
+            }
+        }
+
+        let mut remap = PerEntity::default();
+        let old = core::mem::take(&mut self.signatures);
+        for (sig, data) in old.entries() {
+            if canon[sig] == sig {
+                remap[sig] = self.signatures.push(data.clone());
+            }
+        }
+        for sig in old.iter() {
+            remap[sig] = remap[canon[sig]];
+        }
+        self.rewrite_sigs(&remap);
+        remap
+    }
+    /// Whether every signature reference in the module can be
+    /// rewritten: un-expanded (lazy) and compiled bodies refer to
+    /// signatures by their original bytecode indices instead.
+    fn sig_refs_rewritable(&self) -> bool {
+        self.funcs.values().all(|decl| {
+            matches!(
+                decl,
+                FuncDecl::Import(..) | FuncDecl::Body(..) | FuncDecl::None(..)
+            )
+        })
+    }
+    /// Rewrite every valid signature reference, both in the signature
+    /// table and elsewhere, through `remap`.
+    fn rewrite_sigs(&mut self, remap: &PerEntity<Signature, Signature>) {
+        let mut rewrite = |sig: &mut Signature| {
+            if sig.is_valid() {
+                *sig = remap[*sig];
+            }
+        };
+        for data in self.signatures.values_mut() {
+            data.update_sigs(&mut rewrite);
+        }
+        self.update_sig_refs(&mut rewrite);
+    }
+    /// Apply `f` to every signature reference in the module outside
+    /// the signature table itself.
+    fn update_sig_refs(&mut self, f: &mut dyn FnMut(&mut Signature)) {
+        for decl in self.funcs.values_mut() {
+            match decl {
+                FuncDecl::Import(sig, ..) => f(sig),
+                FuncDecl::Body(sig, _, body) => {
+                    f(sig);
+                    for ty in body.rets.iter_mut().chain(body.locals.values_mut()) {
+                        ty.update_sigs(&mut *f);
+                    }
+                    for ty in body.type_pool.storage.iter_mut() {
+                        ty.update_sigs(&mut *f);
+                    }
+                    body.single_type_dedup = core::mem::take(&mut body.single_type_dedup)
+                        .into_iter()
+                        .map(|(mut ty, list)| {
+                            ty.update_sigs(&mut *f);
+                            (ty, list)
+                        })
+                        .collect();
+                    for value in body.values.values_mut() {
+                        match value {
+                            ValueDef::Operator(op, ..) => rewrite_sigs(op, &mut *f),
+                            ValueDef::BlockParam(_, _, ty)
+                            | ValueDef::PickOutput(_, _, ty)
+                            | ValueDef::Placeholder(ty) => ty.update_sigs(&mut *f),
+                            ValueDef::Alias(_) | ValueDef::None => {}
+                        }
+                    }
+                    for block in body.blocks.values_mut() {
+                        for (ty, _) in &mut block.params {
+                            ty.update_sigs(&mut *f);
+                        }
+                        match &mut block.terminator.terminator {
+                            Terminator::ReturnCallIndirect { sig, .. }
+                            | Terminator::ReturnCallRef { sig, .. } => f(sig),
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        for tag in self.control_tags.values_mut() {
+            f(&mut tag.sig);
+        }
+        for global in self.globals.values_mut() {
+            global.ty.update_sigs(&mut *f);
+        }
+        for table in self.tables.values_mut() {
+            table.ty.update_sigs(&mut *f);
+        }
+        for import in &mut self.imports {
+            if let ImportKind::Type(sig) = &mut import.kind {
+                f(sig);
+            }
+        }
+        for export in &mut self.exports {
+            if let ExportKind::Type(sig) = &mut export.kind {
+                f(sig);
+            }
+        }
+    }
+    /// Remove every function not reachable from the module's roots:
+    /// exported functions, the start function, and table elements.
+    /// A function is reachable if a root or reachable body refers to
+    /// it through `call`, `ref.func` or a `return_call` terminator.
+    /// Unreachable imported functions are removed along with their
+    /// import entries. All references are rewritten, and the returned
+    /// map takes each old function to its new index, or to
+    /// `Func::invalid()` if it was removed.
+    ///
+    /// As with `gc_signatures`, the identity map is returned and
+    /// nothing is removed if any lazy or compiled bodies remain,
+    /// since their references cannot be seen or rewritten.
+    pub fn gc_functions(&mut self) -> PerEntity<Func, Func> {
+        if !self.funcs_expanded() {
+            let mut remap = PerEntity::default();
+            for func in self.funcs.iter() {
+                remap[func] = func;
+            }
+            return remap;
+        }
+
+        let mut workqueue = vec![];
+        for export in &self.exports {
+            if let ExportKind::Func(func) = export.kind {
+                workqueue.push(func);
+            }
+        }
+        workqueue.extend(self.start_func);
+        for table in self.tables.values() {
+            workqueue.extend(table.func_elements.iter().flatten().cloned());
+        }
+        let mut live = BTreeSet::new();
+        while let Some(func) = workqueue.pop() {
+            if func.is_invalid() || func.index() >= self.funcs.len() || !live.insert(func) {
+                continue;
+            }
+            if let Some(body) = self.funcs[func].body_mut() {
+                update_func_refs(body, &mut |callee| workqueue.push(*callee));
+            }
+        }
+
+        let order = self
+            .funcs
+            .iter()
+            .filter(|func| live.contains(func))
+            .collect::<Vec<_>>();
+        self.renumber_funcs(&order)
+            .expect("bodies were checked to be expanded")
+    }
+    /// Whether every function is an import or an expanded body, i.e.
+    /// none is lazy or compiled, so that all function references in
+    /// the module can be seen and rewritten.
+    pub fn funcs_expanded(&self) -> bool {
+        self.funcs.values().all(|decl| {
+            matches!(
+                decl,
+                FuncDecl::Import(..) | FuncDecl::Body(..) | FuncDecl::None(..)
+            )
+        })
+    }
+    /// Renumber functions so that `order[i]` becomes function `i`.
+    /// Functions missing from `order` are removed, imported ones along
+    /// with their import entries. References in bodies, imports,
+    /// exports, the start function and table elements are rewritten,
+    /// and the returned map takes each old function to its new index,
+    /// or to `Func::invalid()` if it was removed.
+    ///
+    /// Fails, leaving the module unchanged, if `order` names a function
+    /// twice or one that does not exist, or if any lazy or compiled
+    /// bodies remain (see `funcs_expanded`).
+    pub fn renumber_funcs(&mut self, order: &[Func]) -> Result<PerEntity<Func, Func>> {
+        if !self.funcs_expanded() {
+            anyhow::bail!("cannot renumber functions while lazy or compiled bodies remain");
+        }
+        let mut remap = PerEntity::default();
+        for func in self.funcs.iter() {
+            remap[func] = Func::invalid();
+        }
+        for (new, &func) in order.iter().enumerate() {
+            if func.is_invalid() || func.index() >= self.funcs.len() {
+                anyhow::bail!("{} is not a function of this module", func);
+            }
+            if remap[func].is_valid() {
+                anyhow::bail!("{} appears twice in the new order", func);
+            }
+            remap[func] = Func::new(new);
+        }
+
+        let mut old = core::mem::take(&mut self.funcs);
+        for &func in order {
+            self.funcs.push(core::mem::take(&mut old[func]));
+        }
+        let mut rewrite = |func: &mut Func| {
+            if func.is_valid() {
+                *func = remap[*func];
+            }
+        };
+        for decl in self.funcs.values_mut() {
+            if let Some(body) = decl.body_mut() {
+                update_func_refs(body, &mut rewrite);
+            }
+        }
+        self.imports.retain(|import| match import.kind {
+            ImportKind::Func(func) => remap[func].is_valid(),
+            _ => true,
+        });
+        for import in &mut self.imports {
+            if let ImportKind::Func(func) = &mut import.kind {
+                rewrite(func);
+            }
+        }
+        for export in &mut self.exports {
+            if let ExportKind::Func(func) = &mut export.kind {
+                rewrite(func);
+            }
+        }
+        if let Some(func) = &mut self.start_func {
+            rewrite(func);
+        }
+        for table in self.tables.values_mut() {
+            for func in table.func_elements.iter_mut().flatten() {
+                rewrite(func);
+            }
+        }
+        Ok(remap)
+    }
     /// Internal (used during parsing): create an empty module, with
     /// the given slice of original Wasm bytecode. Used during parsing
     /// and meant to be filled in as the Wasm bytecode is processed.
@@ -511,10 +1262,11 @@ impl<'a> Module<'a> {
             imports: vec![],
             exports: vec![],
             memories: EntityVec::default(),
+            data_segments: vec![],
             start_func: None,
             debug: Debug::default(),
             debug_map: DebugMap::default(),
-            custom_sections: BTreeMap::default(),
+            custom_sections: vec![],
             control_tags: EntityVec::default(),
         }
     }
@@ -523,8 +1275,376 @@ impl<'a> Module<'a> {
 pub trait FuncCollector {
     fn collect_func(&mut self, f: Func);
 }
+/// Apply `f` to every function a body refers to directly.
+fn update_func_refs(body: &mut FunctionBody, f: &mut dyn FnMut(&mut Func)) {
+    for value in body.values.values_mut() {
+        match value {
+            ValueDef::Operator(Operator::Call { function_index }, ..) => f(function_index),
+            ValueDef::Operator(Operator::RefFunc { func_index }, ..) => f(func_index),
+            _ => {}
+        }
+    }
+    for block in body.blocks.values_mut() {
+        if let Terminator::ReturnCall { func, .. } = &mut block.terminator.terminator {
+            f(func);
+        }
+    }
+}
 impl<'a, T: FuncCollector> FuncCollector for &'a mut T {
     fn collect_func(&mut self, f: Func) {
         FuncCollector::collect_func(&mut **self, f);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Operator, SignatureData, Terminator, Value, WithNullable};
+
+    fn module_with(
+        build: impl FnOnce(&mut FunctionBody, Value, Value) -> Value,
+    ) -> Module<'static> {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32, Type::I64],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let a = body.blocks[entry].params[0].1;
+        let b = body.blocks[entry].params[1].1;
+        let ret = build(&mut body, a, b);
+        body.set_terminator(entry, Terminator::Return { values: vec![ret] });
+        module.funcs.push(FuncDecl::Body(sig, "f".into(), body));
+        module
+    }
+
+    #[test]
+    fn well_typed_module_validates() {
+        let module = module_with(|body, a, b| {
+            let b = body.add_op(body.entry, Operator::I32WrapI64, &[b], &[Type::I32]);
+            body.add_op(body.entry, Operator::I32Add, &[a, b], &[Type::I32])
+        });
+        module.validate().unwrap();
+    }
+
+    #[test]
+    fn mistyped_argument_rejected() {
+        let module = module_with(|body, a, b| {
+            body.add_op(body.entry, Operator::I32Add, &[a, b], &[Type::I32])
+        });
+        let err = format!("{}", module.validate().unwrap_err());
+        assert!(err.contains("i32add"), "{}", err);
+        assert!(err.contains("expects arguments"), "{}", err);
+    }
+
+    #[test]
+    fn mistyped_result_rejected() {
+        let module =
+            module_with(|body, a, _| body.add_op(body.entry, Operator::I32Eqz, &[a], &[Type::I64]));
+        let err = format!("{}", module.validate().unwrap_err());
+        assert!(err.contains("declared as"), "{}", err);
+    }
+
+    #[test]
+    fn recursive_list_signature_validates() {
+        let mut module = Module::empty();
+        // (type $list (struct (field i32) (field (ref null $list))))
+        let list = Signature::new(0);
+        let pushed = module.signatures.push(SignatureData::Struct {
+            fields: vec![
+                WithMutablility {
+                    value: StorageType::Val(Type::I32),
+                    mutable: false,
+                },
+                WithMutablility {
+                    value: StorageType::Val(ref_to(list)),
+                    mutable: true,
+                },
+            ],
+            shared: false,
+        });
+        assert_eq!(pushed, list);
+        module.validate().unwrap();
+    }
+
+    #[test]
+    fn dangling_field_reference_rejected() {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(struct_of(ref_to(Signature::new(7))));
+        let err = format!("{}", module.validate().unwrap_err());
+        assert!(err.contains(&format!("{}", sig)), "{}", err);
+        assert!(err.contains("undefined signature sig7"), "{}", err);
+
+        module.signatures[sig] = SignatureData::Array {
+            ty: WithMutablility {
+                value: StorageType::Val(ref_to(Signature::new(1))),
+                mutable: false,
+            },
+            shared: false,
+        };
+        module.signatures.push(SignatureData::None);
+        assert!(module.validate().is_err());
+    }
+
+    #[test]
+    fn self_bounded_type_import_rejected() {
+        let mut module = Module::empty();
+        module.signatures.push(SignatureData::Import {
+            like: HeapType::Sig {
+                sig_index: Signature::new(1),
+            },
+            shared: false,
+        });
+        module.signatures.push(SignatureData::Import {
+            like: HeapType::Sig {
+                sig_index: Signature::new(0),
+            },
+            shared: false,
+        });
+        let err = format!("{}", module.validate().unwrap_err());
+        assert!(err.contains("bound is cyclic"), "{}", err);
+    }
+
+    #[test]
+    fn for_each_body_mut_sees_globals() {
+        let mut module = module_with(|body, _, _| {
+            body.add_op(
+                body.entry,
+                Operator::GlobalGet {
+                    global_index: Global::new(0),
+                },
+                &[],
+                &[Type::I32],
+            )
+        });
+        module.funcs.push(module.funcs[Func::new(0)].clone());
+        module.globals.push(GlobalData {
+            ty: Type::I32,
+            value: Some(7),
+            mutable: false,
+        });
+
+        // Replace reads of constant globals with their value.
+        let mut seen = vec![];
+        module.for_each_body_mut(|view, func, body| {
+            seen.push(func);
+            for def in body.values.values_mut() {
+                if let ValueDef::Operator(op, ..) = def {
+                    if let Operator::GlobalGet { global_index } = *op {
+                        let global = &view.globals[global_index];
+                        if let (false, Some(bits)) = (global.mutable, global.value) {
+                            *op = Operator::I32Const { value: bits as u32 };
+                        }
+                    }
+                }
+            }
+        });
+
+        assert_eq!(seen, vec![Func::new(0), Func::new(1)]);
+        for func_decl in module.funcs.values() {
+            let body = func_decl.body().unwrap();
+            let ret = match &body.blocks[body.entry].terminator.terminator {
+                Terminator::Return { values } => values[0],
+                _ => unreachable!(),
+            };
+            assert!(matches!(
+                body.values[ret],
+                ValueDef::Operator(Operator::I32Const { value: 7 }, ..)
+            ));
+        }
+        module.validate().unwrap();
+    }
+
+    fn ref_to(sig_index: Signature) -> Type {
+        Type::Heap(WithNullable {
+            value: HeapType::Sig { sig_index },
+            nullable: true,
+        })
+    }
+
+    fn struct_of(ty: Type) -> SignatureData {
+        SignatureData::Struct {
+            fields: vec![WithMutablility {
+                value: StorageType::Val(ty),
+                mutable: true,
+            }],
+            shared: false,
+        }
+    }
+
+    #[test]
+    fn dedup_signatures_merges_identical_funcs() {
+        let mut module = Module::empty();
+        let func = |params: Vec<Type>| SignatureData::Func {
+            params,
+            returns: vec![Type::I32],
+            shared: false,
+        };
+        let a = module.signatures.push(func(vec![Type::I32]));
+        let distinct = module.signatures.push(func(vec![Type::I64]));
+        let dup = module.signatures.push(func(vec![Type::I32]));
+        // Equal structs stay apart, but func signatures taking
+        // references to them still compare equal once resolved.
+        let s1 = module.signatures.push(struct_of(Type::I32));
+        let s2 = module.signatures.push(struct_of(Type::I32));
+        let takes_s1 = module.signatures.push(func(vec![ref_to(s1)]));
+        let takes_s1_again = module.signatures.push(func(vec![ref_to(s1)]));
+        let takes_s2 = module.signatures.push(func(vec![ref_to(s2)]));
+        let f = module.funcs.push(FuncDecl::Import(dup, "f".into()));
+        let g = module.funcs.push(FuncDecl::Import(distinct, "g".into()));
+
+        let remap = module.dedup_signatures();
+        assert_eq!(remap[dup], remap[a]);
+        assert_ne!(remap[distinct], remap[a]);
+        assert_ne!(remap[s1], remap[s2]);
+        assert_eq!(remap[takes_s1_again], remap[takes_s1]);
+        assert_ne!(remap[takes_s2], remap[takes_s1]);
+        assert_eq!(module.signatures.len(), 6);
+        assert_eq!(module.funcs[f].sig(), remap[a]);
+        assert_eq!(module.funcs[g].sig(), remap[distinct]);
+        assert_eq!(
+            module.signatures[remap[takes_s1]],
+            func(vec![ref_to(remap[s1])])
+        );
+        module.validate().unwrap();
+    }
+
+    #[test]
+    fn structurally_eq_resolves_references() {
+        let a = struct_of(ref_to(Signature::new(3)));
+        let b = struct_of(ref_to(Signature::new(5)));
+        assert!(!a.structurally_eq(&b, |sig| sig));
+        assert!(a.structurally_eq(&b, |sig| if sig.index() == 5 {
+            Signature::new(3)
+        } else {
+            sig
+        }));
+        assert!(!a.structurally_eq(&struct_of(Type::I32), |sig| sig));
+    }
+
+    #[test]
+    fn gc_signatures_drops_orphans() {
+        let mut module = Module::empty();
+        let orphan = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I64],
+            returns: vec![],
+            shared: false,
+        });
+        // Two structs that only refer to each other, reachable solely
+        // through the function's parameter type.
+        let a = Signature::new(1);
+        let b = Signature::new(2);
+        assert_eq!(module.signatures.push(struct_of(ref_to(b))), a);
+        assert_eq!(module.signatures.push(struct_of(ref_to(a))), b);
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![ref_to(a)],
+            returns: vec![ref_to(b)],
+            shared: false,
+        });
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let null = body.add_op(
+            entry,
+            Operator::RefNull { ty: ref_to(b) },
+            &[],
+            &[ref_to(b)],
+        );
+        body.set_terminator(entry, Terminator::Return { values: vec![null] });
+        let f = module.funcs.push(FuncDecl::Body(sig, "f".into(), body));
+
+        let remap = module.gc_signatures();
+        assert!(remap[orphan].is_invalid());
+        let (a, b, sig) = (remap[a], remap[b], remap[sig]);
+        assert_eq!((a.index(), b.index(), sig.index()), (0, 1, 2));
+        assert_eq!(module.signatures.len(), 3);
+        assert_eq!(module.signatures[a], struct_of(ref_to(b)));
+        assert_eq!(module.signatures[b], struct_of(ref_to(a)));
+        assert_eq!(module.funcs[f].sig(), sig);
+
+        let body = module.funcs[f].body().unwrap();
+        assert_eq!(body.blocks[entry].params[0].0, ref_to(a));
+        assert_eq!(body.rets, vec![ref_to(b)]);
+        assert!(matches!(
+            &body.values[null],
+            ValueDef::Operator(Operator::RefNull { ty }, _, _) if *ty == ref_to(b)
+        ));
+        module.validate().unwrap();
+    }
+
+    #[test]
+    fn gc_functions_drops_unreferenced() {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![],
+            returns: vec![],
+            shared: false,
+        });
+        for name in ["unused_import", "used_import"] {
+            let func = module.funcs.push(FuncDecl::Import(sig, name.into()));
+            module.imports.push(Import {
+                module: "env".into(),
+                name: name.into(),
+                kind: ImportKind::Func(func),
+            });
+        }
+        let used_import = Func::new(1);
+        let calling = |module: &Module, callees: &[Func]| {
+            let mut body = FunctionBody::new(module, sig);
+            for &callee in callees {
+                body.add_op(
+                    body.entry,
+                    Operator::Call {
+                        function_index: callee,
+                    },
+                    &[],
+                    &[],
+                );
+            }
+            body.set_terminator(body.entry, Terminator::Return { values: vec![] });
+            body
+        };
+        let leaf = calling(&module, &[]);
+        let unused = module
+            .funcs
+            .push(FuncDecl::Body(sig, "unused".into(), leaf));
+        let helper = calling(&module, &[used_import]);
+        let helper = module
+            .funcs
+            .push(FuncDecl::Body(sig, "helper".into(), helper));
+        let main = calling(&module, &[helper, helper]);
+        let main = module.funcs.push(FuncDecl::Body(sig, "main".into(), main));
+        module.exports.push(Export {
+            name: "main".into(),
+            kind: ExportKind::Func(main),
+        });
+
+        let remap = module.gc_functions();
+        assert!(remap[Func::new(0)].is_invalid());
+        assert!(remap[unused].is_invalid());
+        assert_eq!(remap[used_import], Func::new(0));
+        assert_eq!(remap[helper], Func::new(1));
+        assert_eq!(remap[main], Func::new(2));
+        assert_eq!(module.funcs.len(), 3);
+        assert_eq!(module.imports.len(), 1);
+        assert_eq!(module.imports[0].name, "used_import");
+        assert_eq!(module.imports[0].kind, ImportKind::Func(Func::new(0)));
+        assert_eq!(module.exports[0].kind, ExportKind::Func(Func::new(2)));
+        let callees = |func: Func| {
+            let body = module.funcs[func].body().unwrap();
+            body.values
+                .values()
+                .filter_map(|def| match def {
+                    ValueDef::Operator(Operator::Call { function_index }, ..) => {
+                        Some(*function_index)
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(callees(Func::new(2)), vec![Func::new(1), Func::new(1)]);
+        assert_eq!(callees(Func::new(1)), vec![Func::new(0)]);
+        module.validate().unwrap();
+    }
+}
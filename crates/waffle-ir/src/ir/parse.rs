@@ -0,0 +1,2119 @@
+//! Parsing the textual IR printed by `FunctionBody::display`.
+use super::{
+    Block, BlockDef, BlockTarget, FunctionBody, Global, HeapType, Local, Memory, Module, Signature,
+    SourceLoc, Table, Terminator, TerminatorRecord, Type, Value, ValueDef, WithNullable,
+};
+use crate::{EntityRef, EntityVec, ListRef, MemoryArg, Operator, PerEntity};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use anyhow::{anyhow, bail, Result};
+use core::convert::TryInto;
+use core::str::FromStr;
+use hashbrown::HashMap;
+
+/// How many value or block numbers each byte of input may account
+/// for. A printed body need not print its dead values, so the numbers
+/// it uses can run well past its length; the bound only keeps a short
+/// input from demanding a huge allocation.
+const MAX_INDEX_PER_BYTE: usize = 16;
+
+/// Parse a function body in the format printed by
+/// [`FunctionBody::display`] or [`FunctionBody::display_verbose`].
+///
+/// `sig` supplies the parameter and return types; the types in the
+/// printed header are not checked. Value and block numbers are kept
+/// as written, and any number that is referenced but never defined
+/// becomes a `ValueDef::None` or an empty block. Numbers must be below
+/// `MAX_INDEX_PER_BYTE` times the length of `text`. A value may be
+/// defined more than once only if every definition agrees, as when
+/// the verbose listing repeats a block's instructions. Block 0 is the
+/// entry.
+/// Edges are rebuilt from the terminators, so the `# preds` and
+/// `# succs` comments are ignored. `@loc` annotations must name
+/// locations interned in `module.debug`.
+pub fn parse_function_body(text: &str, module: &Module, sig: Signature) -> Result<FunctionBody> {
+    let mut parser = BodyParser {
+        ops: OperatorParser::new(),
+        module,
+        body: FunctionBody::new(module, sig),
+        defs: vec![],
+        current: None,
+        limit: text.len().saturating_mul(MAX_INDEX_PER_BYTE),
+    };
+    parser.body.blocks = EntityVec::default();
+    parser.body.value_blocks = PerEntity::default();
+    parser.parse(text)?;
+
+    let mut body = parser.body;
+    let mut defs = parser.defs;
+    let mut max_value = defs.len();
+    for block in body.blocks.values() {
+        block
+            .terminator
+            .visit_uses(|value| max_value = max_value.max(value.index() + 1));
+    }
+    for def in &defs {
+        if let ValueDef::Operator(..) | ValueDef::PickOutput(..) | ValueDef::Alias(_) = def {
+            def.visit_uses(&body.arg_pool, |value| {
+                max_value = max_value.max(value.index() + 1)
+            });
+        }
+    }
+    if max_value > parser.limit {
+        bail!("v{} is out of range", max_value - 1);
+    }
+    defs.resize(max_value, ValueDef::None);
+    body.values = EntityVec::from(defs);
+    if body.blocks.len() == 0 {
+        bail!("function body has no blocks");
+    }
+    body.entry = Block::new(0);
+    body.recompute_edges();
+    Ok(body)
+}
+
+/// Parse a single operator as printed by its `Display` impl, e.g.
+/// `i32add`, `i32load<memory0, align=2, offset=8>` or
+/// `struct_get<sig3@1>`.
+pub fn parse_operator(text: &str) -> Result<Operator> {
+    OperatorParser::new().parse(text)
+}
+
+struct BodyParser<'a, 'm> {
+    ops: OperatorParser,
+    module: &'a Module<'m>,
+    body: FunctionBody,
+    defs: Vec<ValueDef>,
+    current: Option<Block>,
+    /// Value and block numbers must be below this.
+    limit: usize,
+}
+
+impl<'a, 'm> BodyParser<'a, 'm> {
+    fn parse(&mut self, text: &str) -> Result<()> {
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+        match lines.next() {
+            Some(line) if line.starts_with("function(") && line.ends_with('{') => {}
+            _ => bail!("expected a `function(...) -> ... {{` header"),
+        }
+        let mut closed = false;
+        for line in lines {
+            if closed {
+                bail!("unexpected text after the closing brace: {}", line);
+            }
+            if line == "}" {
+                closed = true;
+            } else if let Some(comment) = line.strip_prefix('#') {
+                self.comment(comment.trim());
+            } else if line.starts_with("block") {
+                self.block_header(line)
+                    .map_err(|e| e.context(format!("in block header `{}`", line)))?;
+            } else if let Some((lhs, rhs)) = line.split_once(" = ") {
+                self.value(lhs, rhs)
+                    .map_err(|e| e.context(format!("in `{}`", line)))?;
+            } else {
+                let block = self
+                    .current
+                    .ok_or_else(|| anyhow!("terminator outside of a block: {}", line))?;
+                let terminator =
+                    parse_terminator(line).map_err(|e| e.context(format!("in `{}`", line)))?;
+                let mut succs = vec![];
+                terminator.visit_successors(|succ| succs.push(succ));
+                for succ in succs {
+                    self.ensure_block(succ)?;
+                }
+                if self.body.blocks[block].terminator.terminator != Terminator::None {
+                    bail!("{} has more than one terminator", block);
+                }
+                self.body.blocks[block].terminator = TerminatorRecord::core(terminator);
+            }
+        }
+        if !closed {
+            bail!("missing closing brace");
+        }
+        Ok(())
+    }
+
+    /// `# v3: local1` records a value's Wasm local; anything else is
+    /// an ordinary comment.
+    fn comment(&mut self, comment: &str) {
+        if let Some((value, note)) = comment.split_once(" note: ") {
+            if let Ok(value) = entity::<Value>(value, "v") {
+                if value.index() < self.limit {
+                    self.body.annotate(value, note.to_string());
+                }
+                return;
+            }
+        }
+        if let Some((value, local)) = comment.split_once(": ") {
+            if let (Ok(value), Ok(local)) =
+                (entity::<Value>(value, "v"), entity::<Local>(local, "local"))
+            {
+                if value.index() < self.limit {
+                    self.body.value_locals[value] = Some(local);
+                }
+            }
+        }
+    }
+
+    /// `block1(v3: i32, v4: f64): # description`
+    fn block_header(&mut self, line: &str) -> Result<()> {
+        let open = line
+            .find('(')
+            .ok_or_else(|| anyhow!("missing blockparam list"))?;
+        let block = entity::<Block>(&line[..open], "block")?;
+        let close = matching(line, open)?;
+        self.ensure_block(block)?;
+        if !self.body.blocks[block].params.is_empty() || !self.body.blocks[block].insts.is_empty() {
+            bail!("{} is defined twice", block);
+        }
+        for (i, param) in split_list(&line[open + 1..close]).into_iter().enumerate() {
+            let (value, ty) = param
+                .split_once(':')
+                .ok_or_else(|| anyhow!("expected `value: type`, got `{}`", param))?;
+            let value = entity::<Value>(value.trim(), "v")?;
+            let ty = parse_type(ty.trim())?;
+            self.define(value, ValueDef::BlockParam(block, i as u32, ty))?;
+            self.body.blocks[block].params.push((ty, value));
+            self.body.value_blocks[value] = block;
+        }
+        let rest = line[close + 1..]
+            .strip_prefix(':')
+            .ok_or_else(|| anyhow!("expected `:` after blockparams"))?
+            .trim_start();
+        let desc = rest.strip_prefix('#').unwrap_or(rest);
+        self.body.blocks[block].desc = desc.strip_prefix(' ').unwrap_or(desc).to_string();
+        self.current = Some(block);
+        Ok(())
+    }
+
+    /// A value definition. Inside a block, operators, `PickOutput`s
+    /// and aliases are also appended to the block.
+    fn value(&mut self, lhs: &str, rhs: &str) -> Result<()> {
+        let value = entity::<Value>(lhs, "v")?;
+        let (def, loc) = self.value_def(rhs)?;
+        let placed = matches!(
+            def,
+            ValueDef::Operator(..) | ValueDef::PickOutput(..) | ValueDef::Alias(_)
+        );
+        self.define(value, def)?;
+        if let Some(loc) = loc {
+            self.body.source_locs[value] = loc;
+        }
+        if let (true, Some(block)) = (placed, self.current) {
+            self.body.append_to_block(block, value);
+        }
+        Ok(())
+    }
+
+    fn value_def(&mut self, rhs: &str) -> Result<(ValueDef, Option<SourceLoc>)> {
+        if rhs == "none" {
+            return Ok((ValueDef::None, None));
+        }
+        let (lhs, annotation) = match rhs.find(" #") {
+            Some(i) => (rhs[..i].trim(), Some(rhs[i + 2..].trim())),
+            None => (rhs.trim(), None),
+        };
+        let annotation = match annotation {
+            Some(annotation) => annotation,
+            None => return Ok((ValueDef::Alias(entity(lhs, "v")?), None)),
+        };
+        if lhs == "placeholder" {
+            return Ok((ValueDef::Placeholder(parse_type(annotation)?), None));
+        }
+        if let Some(param) = lhs.strip_prefix("blockparam ") {
+            let (block, idx) = param
+                .split_once(", ")
+                .ok_or_else(|| anyhow!("expected `blockparam block, index`"))?;
+            let block = entity::<Block>(block, "block")?;
+            self.ensure_block(block)?;
+            return Ok((
+                ValueDef::BlockParam(block, number(idx)?, parse_type(annotation)?),
+                None,
+            ));
+        }
+        if lhs.starts_with('v') && !lhs.contains(&[' ', '<'][..]) {
+            if let Some((value, idx)) = lhs.split_once('.') {
+                return Ok((
+                    ValueDef::PickOutput(
+                        entity(value, "v")?,
+                        number(idx)?,
+                        parse_type(annotation)?,
+                    ),
+                    None,
+                ));
+            }
+        }
+
+        // An operator: `op args # types [@loc file:line:col]`.
+        let name_end = match (lhs.find('<'), lhs.find(' ')) {
+            (Some(lt), space) if space.map_or(true, |space| lt < space) => {
+                lhs[lt..].find('>').ok_or_else(|| anyhow!("unclosed `<`"))? + lt + 1
+            }
+            (_, space) => space.unwrap_or(lhs.len()),
+        };
+        let op = self.ops.parse(&lhs[..name_end])?;
+        let args = split_list(&lhs[name_end..])
+            .into_iter()
+            .map(|arg| entity::<Value>(arg, "v"))
+            .collect::<Result<Vec<_>>>()?;
+        let (tys, loc) = match annotation.find('@') {
+            Some(at) => (&annotation[..at], Some(&annotation[at + 1..])),
+            None => (annotation, None),
+        };
+        let tys = split_list(tys)
+            .into_iter()
+            .map(parse_type)
+            .collect::<Result<Vec<_>>>()?;
+        let loc = match loc {
+            Some(loc) => {
+                let loc = entity::<SourceLoc>(loc.split_whitespace().next().unwrap_or(""), "loc")?;
+                if self.module.debug.source_locs.get(loc).is_none() {
+                    bail!("unknown source location {}", loc);
+                }
+                Some(loc)
+            }
+            None => None,
+        };
+
+        let args = match args.len() {
+            0 => ListRef::default(),
+            _ => self.body.arg_pool.from_iter(args.into_iter()),
+        };
+        let tys = match tys.len() {
+            0 => ListRef::default(),
+            1 => self.body.single_type_list(tys[0]),
+            _ => self.body.type_pool.from_iter(tys.into_iter()),
+        };
+        Ok((ValueDef::Operator(op, args, tys), loc))
+    }
+
+    fn define(&mut self, value: Value, def: ValueDef) -> Result<()> {
+        if value.index() >= self.limit {
+            bail!("{} is out of range", value);
+        }
+        if self.defs.len() <= value.index() {
+            self.defs.resize(value.index() + 1, ValueDef::None);
+        }
+        let old = &self.defs[value.index()];
+        if *old != ValueDef::None && !self.same_def(old, &def) {
+            bail!("{} is defined twice", value);
+        }
+        self.defs[value.index()] = def;
+        Ok(())
+    }
+
+    /// Whether two definitions agree, comparing operator arguments
+    /// and types by content rather than by pool handle.
+    fn same_def(&self, a: &ValueDef, b: &ValueDef) -> bool {
+        match (a, b) {
+            (ValueDef::Operator(a_op, a_args, a_tys), ValueDef::Operator(b_op, b_args, b_tys)) => {
+                a_op == b_op
+                    && self.body.arg_pool[*a_args] == self.body.arg_pool[*b_args]
+                    && self.body.type_pool[*a_tys] == self.body.type_pool[*b_tys]
+            }
+            _ => a == b,
+        }
+    }
+
+    fn ensure_block(&mut self, block: Block) -> Result<()> {
+        if block.index() >= self.limit {
+            bail!("{} is out of range", block);
+        }
+        while self.body.blocks.len() <= block.index() {
+            self.body.blocks.push(BlockDef::default());
+        }
+        Ok(())
+    }
+}
+
+fn parse_terminator(line: &str) -> Result<Terminator> {
+    let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let rest = rest.trim();
+    Ok(match keyword {
+        "no_terminator" => Terminator::None,
+        "unreachable" => Terminator::Unreachable,
+        "undefined" => Terminator::UB,
+        "br" => Terminator::Br {
+            target: parse_target(rest)?,
+        },
+        "if" => {
+            let parts = split_list(rest);
+            if parts.len() != 3 {
+                bail!("expected `if cond, target, target`");
+            }
+            Terminator::CondBr {
+                cond: entity(parts[0], "v")?,
+                if_true: parse_target(parts[1])?,
+                if_false: parse_target(parts[2])?,
+            }
+        }
+        "select" => {
+            let parts = split_list(rest);
+            if parts.len() != 3 {
+                bail!("expected `select value, [targets], default`");
+            }
+            let targets = parts[1]
+                .strip_prefix('[')
+                .and_then(|targets| targets.strip_suffix(']'))
+                .ok_or_else(|| anyhow!("expected a bracketed target list"))?;
+            Terminator::Select {
+                value: entity(parts[0], "v")?,
+                targets: split_list(targets)
+                    .into_iter()
+                    .map(parse_target)
+                    .collect::<Result<Vec<_>>>()?,
+                default: parse_target(parts[2])?,
+            }
+        }
+        "return" => Terminator::Return {
+            values: values(rest)?,
+        },
+        "return_call" => {
+            let open = rest
+                .find('(')
+                .ok_or_else(|| anyhow!("missing argument list"))?;
+            Terminator::ReturnCall {
+                func: entity(&rest[..open], "func")?,
+                args: values(parenthesized(&rest[open..])?)?,
+            }
+        }
+        "return_call_indirect" => {
+            let close = matching(rest, 0)?;
+            let (sig, table) = parenthesized(&rest[..=close])?
+                .split_once(';')
+                .ok_or_else(|| anyhow!("expected `(sig;table)`"))?;
+            Terminator::ReturnCallIndirect {
+                sig: entity(sig, "sig")?,
+                table: entity(table, "table")?,
+                args: values(parenthesized(&rest[close + 1..])?)?,
+            }
+        }
+        "return_call_ref" => {
+            let close = matching(rest, 0)?;
+            Terminator::ReturnCallRef {
+                sig: entity(parenthesized(&rest[..=close])?, "sig")?,
+                args: values(parenthesized(&rest[close + 1..])?)?,
+            }
+        }
+        _ => bail!("unknown terminator `{}`", keyword),
+    })
+}
+
+/// `block3(v1, v2)`
+fn parse_target(text: &str) -> Result<BlockTarget> {
+    let open = text
+        .find('(')
+        .ok_or_else(|| anyhow!("missing argument list"))?;
+    Ok(BlockTarget {
+        block: entity(&text[..open], "block")?,
+        args: values(parenthesized(&text[open..])?)?,
+    })
+}
+
+/// Parse a type as printed by its `Display` impl, or a reference type
+/// in the spec-like form printed for `WithNullable<HeapType>`.
+pub fn parse_type(text: &str) -> Result<Type> {
+    Ok(match text {
+        "i32" => Type::I32,
+        "i64" => Type::I64,
+        "f32" => Type::F32,
+        "f64" => Type::F64,
+        "v128" => Type::V128,
+        _ if text.starts_with("(ref ") => parse_ref_type(text)?,
+        _ => {
+            let inner = text
+                .strip_prefix("ref(")
+                .and_then(|inner| inner.strip_suffix(')'))
+                .ok_or_else(|| anyhow!("unknown type `{}`", text))?;
+            let (nullable, heap) = inner
+                .split_once(' ')
+                .ok_or_else(|| anyhow!("expected `ref(null|not_null heaptype)`"))?;
+            let nullable = match nullable {
+                "null" => true,
+                "not_null" => false,
+                _ => bail!("expected `null` or `not_null`, got `{}`", nullable),
+            };
+            let value = match heap {
+                "funcref" => HeapType::FuncRef,
+                "externref" => HeapType::ExternRef,
+                "arrayref" => HeapType::Array,
+                "anyref" => HeapType::Any,
+                "eqref" => HeapType::Eq,
+                "i31ref" => HeapType::I31,
+                "structref" => HeapType::Struct,
+                "nullref" => HeapType::None,
+                "nullexternref" => HeapType::NoExtern,
+                "nullfuncref" => HeapType::NoFunc,
+                "exnref" => HeapType::Exn,
+                "nullexnref" => HeapType::NoExn,
+                _ => {
+                    let sig = heap
+                        .strip_prefix("sigref(")
+                        .and_then(|sig| sig.strip_suffix(')'))
+                        .ok_or_else(|| anyhow!("unknown heap type `{}`", heap))?;
+                    HeapType::Sig {
+                        sig_index: entity(sig, "sig")?,
+                    }
+                }
+            };
+            Type::Heap(WithNullable { value, nullable })
+        }
+    })
+}
+
+/// `(ref null $sig0)`, `(ref func)`, ...
+fn parse_ref_type(text: &str) -> Result<Type> {
+    let inner = text
+        .strip_prefix("(ref ")
+        .and_then(|inner| inner.strip_suffix(')'))
+        .ok_or_else(|| anyhow!("expected `(ref [null] heaptype)`, got `{}`", text))?;
+    let (nullable, heap) = match inner.strip_prefix("null ") {
+        Some(heap) => (true, heap),
+        None => (false, inner),
+    };
+    let value = match heap {
+        "func" => HeapType::FuncRef,
+        "extern" => HeapType::ExternRef,
+        "array" => HeapType::Array,
+        "any" => HeapType::Any,
+        "eq" => HeapType::Eq,
+        "i31" => HeapType::I31,
+        "struct" => HeapType::Struct,
+        "none" => HeapType::None,
+        "noextern" => HeapType::NoExtern,
+        "nofunc" => HeapType::NoFunc,
+        "exn" => HeapType::Exn,
+        "noexn" => HeapType::NoExn,
+        _ => {
+            let sig = heap
+                .strip_prefix('$')
+                .ok_or_else(|| anyhow!("unknown heap type `{}`", heap))?;
+            HeapType::Sig {
+                sig_index: entity(sig, "sig")?,
+            }
+        }
+    };
+    Ok(Type::heap(value, nullable))
+}
+
+/// The shapes of immediates shared by many operators. Operators with
+/// a shape of their own are handled directly in
+/// `OperatorParser::parse`.
+#[derive(Clone, Copy)]
+enum Template {
+    Nullary(Operator),
+    Memory(fn(MemoryArg) -> Operator),
+    Lane(fn(u8) -> Operator),
+    MemoryLane(fn(MemoryArg, u8) -> Operator),
+    Sig(fn(Signature) -> Operator),
+    Field(fn(Signature, usize) -> Operator),
+    Typed(fn(Type) -> Operator),
+    Table(fn(Table) -> Operator),
+    Mem(fn(Memory) -> Operator),
+    Global(fn(Global) -> Operator),
+}
+
+impl Template {
+    fn all() -> impl Iterator<Item = Template> {
+        NULLARY
+            .iter()
+            .map(|&op| Template::Nullary(op))
+            .chain(WITH_MEMORY.iter().map(|&f| Template::Memory(f)))
+            .chain(WITH_LANE.iter().map(|&f| Template::Lane(f)))
+            .chain(WITH_MEMORY_LANE.iter().map(|&f| Template::MemoryLane(f)))
+            .chain(WITH_SIG.iter().map(|&f| Template::Sig(f)))
+            .chain(WITH_FIELD.iter().map(|&f| Template::Field(f)))
+            .chain(WITH_TYPE.iter().map(|&f| Template::Typed(f)))
+            .chain(WITH_TABLE.iter().map(|&f| Template::Table(f)))
+            .chain(WITH_MEM.iter().map(|&f| Template::Mem(f)))
+            .chain(WITH_GLOBAL.iter().map(|&f| Template::Global(f)))
+    }
+
+    /// An instance with placeholder immediates, for finding its name.
+    fn example(self) -> Operator {
+        let memarg = MemoryArg {
+            align: 0,
+            offset: 0,
+            memory: Memory::new(0),
+        };
+        match self {
+            Template::Nullary(op) => op,
+            Template::Memory(f) => f(memarg),
+            Template::Lane(f) => f(0),
+            Template::MemoryLane(f) => f(memarg, 0),
+            Template::Sig(f) => f(Signature::new(0)),
+            Template::Field(f) => f(Signature::new(0), 0),
+            Template::Typed(f) => f(Type::I32),
+            Template::Table(f) => f(Table::new(0)),
+            Template::Mem(f) => f(Memory::new(0)),
+            Template::Global(f) => f(Global::new(0)),
+        }
+    }
+}
+
+/// The name an operator is printed with, without its immediates.
+fn operator_name(op: &Operator) -> String {
+    let mut name = op.to_string();
+    if let Some(lt) = name.find('<') {
+        name.truncate(lt);
+    }
+    name
+}
+
+struct OperatorParser {
+    templates: HashMap<String, Template>,
+}
+
+impl OperatorParser {
+    fn new() -> OperatorParser {
+        OperatorParser {
+            templates: Template::all()
+                .map(|template| (operator_name(&template.example()), template))
+                .collect(),
+        }
+    }
+
+    fn parse(&self, text: &str) -> Result<Operator> {
+        let (name, imm) = match text.find('<') {
+            Some(lt) => (
+                &text[..lt],
+                text[lt + 1..]
+                    .strip_suffix('>')
+                    .ok_or_else(|| anyhow!("unclosed `<` in `{}`", text))?,
+            ),
+            None => (text, ""),
+        };
+        let imm = Immediates {
+            parts: if name == "i8x16shuffle" {
+                vec![imm]
+            } else {
+                split_top(imm, &[',', ';', '@', '-'])
+            },
+        };
+        let op = match name {
+            "call" => Operator::Call {
+                function_index: imm.entity(0, "func")?,
+            },
+            "call_indirect" => Operator::CallIndirect {
+                sig_index: imm.entity(0, "sig")?,
+                table_index: imm.entity(1, "table")?,
+            },
+            "call_ref" => Operator::CallRef {
+                sig_index: imm.entity(0, "sig")?,
+            },
+            "ref_func" => Operator::RefFunc {
+                func_index: imm.entity(0, "func")?,
+            },
+            "i32const" => Operator::I32Const {
+                value: imm.number(0)?,
+            },
+            "i64const" => Operator::I64Const {
+                value: imm.number(0)?,
+            },
+            "f32const" => Operator::F32Const {
+                value: imm.number(0)?,
+            },
+            "f64const" => Operator::F64Const {
+                value: imm.number(0)?,
+            },
+            "v128const" => Operator::V128Const {
+                value: imm.number(0)?,
+            },
+            "i8x16shuffle" => {
+                let list = imm
+                    .get(0)?
+                    .strip_prefix('[')
+                    .and_then(|list| list.strip_suffix(']'))
+                    .ok_or_else(|| anyhow!("expected a bracketed lane list"))?;
+                let lanes = split_list(list)
+                    .into_iter()
+                    .map(number)
+                    .collect::<Result<Vec<u8>>>()?;
+                Operator::I8x16Shuffle {
+                    lanes: lanes
+                        .as_slice()
+                        .try_into()
+                        .map_err(|_| anyhow!("expected 16 lanes"))?,
+                }
+            }
+            "memory_copy" => Operator::MemoryCopy {
+                dst_mem: imm.entity(0, "memory")?,
+                src_mem: imm.entity(1, "memory")?,
+            },
+            "memory_init" => Operator::MemoryInit {
+                mem: imm.entity(0, "memory")?,
+                data_idx: imm.keyed(1, "data")?,
+            },
+            "data_drop" => Operator::DataDrop {
+                data_idx: imm.keyed(0, "data")?,
+            },
+            "array_new_fixed" => Operator::ArrayNewFixed {
+                sig: imm.entity(0, "sig")?,
+                num: imm.number(1)?,
+            },
+            "array_copy" => Operator::ArrayCopy {
+                dest: imm.entity(0, "sig")?,
+                src: imm.entity(1, "sig")?,
+            },
+            "array_new_data" => Operator::ArrayNewData {
+                sig: imm.entity(0, "sig")?,
+                data_idx: imm.keyed(1, "data")?,
+            },
+            "array_init_data" => Operator::ArrayInitData {
+                sig: imm.entity(0, "sig")?,
+                data_idx: imm.keyed(1, "data")?,
+            },
+            "array_new_elem" => Operator::ArrayNewElem {
+                sig: imm.entity(0, "sig")?,
+                elem_idx: imm.keyed(1, "elem")?,
+            },
+            "array_init_elem" => Operator::ArrayInitElem {
+                sig: imm.entity(0, "sig")?,
+                elem_idx: imm.keyed(1, "elem")?,
+            },
+            _ => match self.templates.get(name) {
+                Some(&Template::Nullary(op)) => op,
+                Some(&Template::Memory(f)) => f(imm.memarg(0)?),
+                Some(&Template::Lane(f)) => f(imm.number(0)?),
+                Some(&Template::MemoryLane(f)) => f(imm.memarg(0)?, imm.number(3)?),
+                Some(&Template::Sig(f)) => f(imm.entity(0, "sig")?),
+                Some(&Template::Field(f)) => f(imm.entity(0, "sig")?, imm.number(1)?),
+                Some(&Template::Typed(f)) => f(parse_type(imm.get(0)?)?),
+                Some(&Template::Table(f)) => f(imm.entity(0, "table")?),
+                Some(&Template::Mem(f)) => f(imm.entity(0, "memory")?),
+                Some(&Template::Global(f)) => f(imm.entity(0, "global")?),
+                None => bail!("unknown operator `{}`", name),
+            },
+        };
+        Ok(op)
+    }
+}
+
+/// The comma- (or `;`-, `@`-, `-`-) separated immediates of an
+/// operator.
+struct Immediates<'a> {
+    parts: Vec<&'a str>,
+}
+
+impl<'a> Immediates<'a> {
+    fn get(&self, i: usize) -> Result<&'a str> {
+        self.parts
+            .get(i)
+            .cloned()
+            .ok_or_else(|| anyhow!("missing immediate {}", i))
+    }
+    fn entity<T: EntityRef>(&self, i: usize, prefix: &str) -> Result<T> {
+        entity(self.get(i)?, prefix)
+    }
+    fn number<T: FromStr>(&self, i: usize) -> Result<T> {
+        number(self.get(i)?)
+    }
+    /// `key=value`
+    fn keyed<T: FromStr>(&self, i: usize, key: &str) -> Result<T> {
+        let part = self.get(i)?;
+        match part.split_once('=') {
+            Some((k, value)) if k == key => number(value),
+            _ => bail!("expected `{}=...`, got `{}`", key, part),
+        }
+    }
+    /// `memory0, align=2, offset=8`, as printed for `MemoryArg`.
+    fn memarg(&self, i: usize) -> Result<MemoryArg> {
+        Ok(MemoryArg {
+            memory: self.entity(i, "memory")?,
+            align: self.keyed(i + 1, "align")?,
+            offset: self.keyed(i + 2, "offset")?,
+        })
+    }
+}
+
+/// `v3`, `block1`, `sig0`, ...
+fn entity<T: EntityRef>(text: &str, prefix: &str) -> Result<T> {
+    let index = text
+        .strip_prefix(prefix)
+        .and_then(|index| index.parse::<usize>().ok())
+        .ok_or_else(|| anyhow!("expected `{}N`, got `{}`", prefix, text))?;
+    Ok(T::new(index))
+}
+
+fn number<T: FromStr>(text: &str) -> Result<T> {
+    text.parse()
+        .map_err(|_| anyhow!("expected a number, got `{}`", text))
+}
+
+fn values(text: &str) -> Result<Vec<Value>> {
+    split_list(text)
+        .into_iter()
+        .map(|value| entity(value, "v"))
+        .collect()
+}
+
+/// The contents of `(...)`, which must span all of `text`.
+fn parenthesized(text: &str) -> Result<&str> {
+    text.strip_prefix('(')
+        .and_then(|text| text.strip_suffix(')'))
+        .ok_or_else(|| anyhow!("expected `(...)`, got `{}`", text))
+}
+
+/// The index of the bracket closing the one at `open`.
+fn matching(text: &str, open: usize) -> Result<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices().skip_while(|&(i, _)| i < open) {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    bail!("unbalanced brackets in `{}`", text)
+}
+
+/// Split a comma-separated list, ignoring commas inside brackets.
+fn split_list(text: &str) -> Vec<&str> {
+    split_top(text, &[','])
+}
+
+fn split_top<'a>(text: &'a str, separators: &[char]) -> Vec<&'a str> {
+    let text = text.trim();
+    if text.is_empty() {
+        return vec![];
+    }
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            c if depth == 0 && separators.contains(&c) => {
+                parts.push(text[start..i].trim());
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(text[start..].trim());
+    parts
+}
+const NULLARY: &[Operator] = &[
+    Operator::Unreachable,
+    Operator::Nop,
+    Operator::Select,
+    Operator::I32Eqz,
+    Operator::I32Eq,
+    Operator::I32Ne,
+    Operator::I32LtS,
+    Operator::I32LtU,
+    Operator::I32GtS,
+    Operator::I32GtU,
+    Operator::I32LeS,
+    Operator::I32LeU,
+    Operator::I32GeS,
+    Operator::I32GeU,
+    Operator::I64Eqz,
+    Operator::I64Eq,
+    Operator::I64Ne,
+    Operator::I64LtS,
+    Operator::I64LtU,
+    Operator::I64GtU,
+    Operator::I64GtS,
+    Operator::I64LeS,
+    Operator::I64LeU,
+    Operator::I64GeS,
+    Operator::I64GeU,
+    Operator::F32Eq,
+    Operator::F32Ne,
+    Operator::F32Lt,
+    Operator::F32Gt,
+    Operator::F32Le,
+    Operator::F32Ge,
+    Operator::F64Eq,
+    Operator::F64Ne,
+    Operator::F64Lt,
+    Operator::F64Gt,
+    Operator::F64Le,
+    Operator::F64Ge,
+    Operator::I32Clz,
+    Operator::I32Ctz,
+    Operator::I32Popcnt,
+    Operator::I32Add,
+    Operator::I32Sub,
+    Operator::I32Mul,
+    Operator::I32DivS,
+    Operator::I32DivU,
+    Operator::I32RemS,
+    Operator::I32RemU,
+    Operator::I32And,
+    Operator::I32Or,
+    Operator::I32Xor,
+    Operator::I32Shl,
+    Operator::I32ShrS,
+    Operator::I32ShrU,
+    Operator::I32Rotl,
+    Operator::I32Rotr,
+    Operator::I64Clz,
+    Operator::I64Ctz,
+    Operator::I64Popcnt,
+    Operator::I64Add,
+    Operator::I64Sub,
+    Operator::I64Mul,
+    Operator::I64DivS,
+    Operator::I64DivU,
+    Operator::I64RemS,
+    Operator::I64RemU,
+    Operator::I64And,
+    Operator::I64Or,
+    Operator::I64Xor,
+    Operator::I64Shl,
+    Operator::I64ShrS,
+    Operator::I64ShrU,
+    Operator::I64Rotl,
+    Operator::I64Rotr,
+    Operator::F32Abs,
+    Operator::F32Neg,
+    Operator::F32Ceil,
+    Operator::F32Floor,
+    Operator::F32Trunc,
+    Operator::F32Nearest,
+    Operator::F32Sqrt,
+    Operator::F32Add,
+    Operator::F32Sub,
+    Operator::F32Mul,
+    Operator::F32Div,
+    Operator::F32Min,
+    Operator::F32Max,
+    Operator::F32Copysign,
+    Operator::F64Abs,
+    Operator::F64Neg,
+    Operator::F64Ceil,
+    Operator::F64Floor,
+    Operator::F64Trunc,
+    Operator::F64Nearest,
+    Operator::F64Sqrt,
+    Operator::F64Add,
+    Operator::F64Sub,
+    Operator::F64Mul,
+    Operator::F64Div,
+    Operator::F64Min,
+    Operator::F64Max,
+    Operator::F64Copysign,
+    Operator::I32WrapI64,
+    Operator::I32TruncF32S,
+    Operator::I32TruncF32U,
+    Operator::I32TruncF64S,
+    Operator::I32TruncF64U,
+    Operator::I64ExtendI32S,
+    Operator::I64ExtendI32U,
+    Operator::I64TruncF32S,
+    Operator::I64TruncF32U,
+    Operator::I64TruncF64S,
+    Operator::I64TruncF64U,
+    Operator::F32ConvertI32S,
+    Operator::F32ConvertI32U,
+    Operator::F32ConvertI64S,
+    Operator::F32ConvertI64U,
+    Operator::F32DemoteF64,
+    Operator::F64ConvertI32S,
+    Operator::F64ConvertI32U,
+    Operator::F64ConvertI64S,
+    Operator::F64ConvertI64U,
+    Operator::F64PromoteF32,
+    Operator::I32Extend8S,
+    Operator::I32Extend16S,
+    Operator::I64Extend8S,
+    Operator::I64Extend16S,
+    Operator::I64Extend32S,
+    Operator::I32TruncSatF32S,
+    Operator::I32TruncSatF32U,
+    Operator::I32TruncSatF64S,
+    Operator::I32TruncSatF64U,
+    Operator::I64TruncSatF32S,
+    Operator::I64TruncSatF32U,
+    Operator::I64TruncSatF64S,
+    Operator::I64TruncSatF64U,
+    Operator::F32ReinterpretI32,
+    Operator::F64ReinterpretI64,
+    Operator::I32ReinterpretF32,
+    Operator::I64ReinterpretF64,
+    Operator::I8x16Swizzle,
+    Operator::I8x16Splat,
+    Operator::I16x8Splat,
+    Operator::I32x4Splat,
+    Operator::I64x2Splat,
+    Operator::F32x4Splat,
+    Operator::F64x2Splat,
+    Operator::I8x16Eq,
+    Operator::I8x16Ne,
+    Operator::I8x16LtS,
+    Operator::I8x16LtU,
+    Operator::I8x16GtS,
+    Operator::I8x16GtU,
+    Operator::I8x16LeS,
+    Operator::I8x16LeU,
+    Operator::I8x16GeS,
+    Operator::I8x16GeU,
+    Operator::I16x8Eq,
+    Operator::I16x8Ne,
+    Operator::I16x8LtS,
+    Operator::I16x8LtU,
+    Operator::I16x8GtS,
+    Operator::I16x8GtU,
+    Operator::I16x8LeS,
+    Operator::I16x8LeU,
+    Operator::I16x8GeS,
+    Operator::I16x8GeU,
+    Operator::I32x4Eq,
+    Operator::I32x4Ne,
+    Operator::I32x4LtS,
+    Operator::I32x4LtU,
+    Operator::I32x4GtS,
+    Operator::I32x4GtU,
+    Operator::I32x4LeS,
+    Operator::I32x4LeU,
+    Operator::I32x4GeS,
+    Operator::I32x4GeU,
+    Operator::I64x2Eq,
+    Operator::I64x2Ne,
+    Operator::I64x2LtS,
+    Operator::I64x2GtS,
+    Operator::I64x2LeS,
+    Operator::I64x2GeS,
+    Operator::F32x4Eq,
+    Operator::F32x4Ne,
+    Operator::F32x4Lt,
+    Operator::F32x4Gt,
+    Operator::F32x4Le,
+    Operator::F32x4Ge,
+    Operator::F64x2Eq,
+    Operator::F64x2Ne,
+    Operator::F64x2Lt,
+    Operator::F64x2Gt,
+    Operator::F64x2Le,
+    Operator::F64x2Ge,
+    Operator::V128Not,
+    Operator::V128And,
+    Operator::V128AndNot,
+    Operator::V128Or,
+    Operator::V128Xor,
+    Operator::V128Bitselect,
+    Operator::V128AnyTrue,
+    Operator::I8x16Abs,
+    Operator::I8x16Neg,
+    Operator::I8x16Popcnt,
+    Operator::I8x16AllTrue,
+    Operator::I8x16Bitmask,
+    Operator::I8x16NarrowI16x8S,
+    Operator::I8x16NarrowI16x8U,
+    Operator::I8x16Shl,
+    Operator::I8x16ShrS,
+    Operator::I8x16ShrU,
+    Operator::I8x16Add,
+    Operator::I8x16AddSatS,
+    Operator::I8x16AddSatU,
+    Operator::I8x16Sub,
+    Operator::I8x16SubSatS,
+    Operator::I8x16SubSatU,
+    Operator::I8x16MinS,
+    Operator::I8x16MinU,
+    Operator::I8x16MaxS,
+    Operator::I8x16MaxU,
+    Operator::I8x16AvgrU,
+    Operator::I16x8ExtAddPairwiseI8x16S,
+    Operator::I16x8ExtAddPairwiseI8x16U,
+    Operator::I16x8Abs,
+    Operator::I16x8Neg,
+    Operator::I16x8Q15MulrSatS,
+    Operator::I16x8AllTrue,
+    Operator::I16x8Bitmask,
+    Operator::I16x8NarrowI32x4S,
+    Operator::I16x8NarrowI32x4U,
+    Operator::I16x8ExtendLowI8x16S,
+    Operator::I16x8ExtendHighI8x16S,
+    Operator::I16x8ExtendLowI8x16U,
+    Operator::I16x8ExtendHighI8x16U,
+    Operator::I16x8Shl,
+    Operator::I16x8ShrS,
+    Operator::I16x8ShrU,
+    Operator::I16x8Add,
+    Operator::I16x8AddSatS,
+    Operator::I16x8AddSatU,
+    Operator::I16x8Sub,
+    Operator::I16x8SubSatS,
+    Operator::I16x8SubSatU,
+    Operator::I16x8Mul,
+    Operator::I16x8MinS,
+    Operator::I16x8MinU,
+    Operator::I16x8MaxS,
+    Operator::I16x8MaxU,
+    Operator::I16x8AvgrU,
+    Operator::I16x8ExtMulLowI8x16S,
+    Operator::I16x8ExtMulHighI8x16S,
+    Operator::I16x8ExtMulLowI8x16U,
+    Operator::I16x8ExtMulHighI8x16U,
+    Operator::I32x4ExtAddPairwiseI16x8S,
+    Operator::I32x4ExtAddPairwiseI16x8U,
+    Operator::I32x4Abs,
+    Operator::I32x4Neg,
+    Operator::I32x4AllTrue,
+    Operator::I32x4Bitmask,
+    Operator::I32x4ExtendLowI16x8S,
+    Operator::I32x4ExtendHighI16x8S,
+    Operator::I32x4ExtendLowI16x8U,
+    Operator::I32x4ExtendHighI16x8U,
+    Operator::I32x4Shl,
+    Operator::I32x4ShrS,
+    Operator::I32x4ShrU,
+    Operator::I32x4Add,
+    Operator::I32x4Sub,
+    Operator::I32x4Mul,
+    Operator::I32x4MinS,
+    Operator::I32x4MinU,
+    Operator::I32x4MaxS,
+    Operator::I32x4MaxU,
+    Operator::I32x4DotI16x8S,
+    Operator::I32x4ExtMulLowI16x8S,
+    Operator::I32x4ExtMulHighI16x8S,
+    Operator::I32x4ExtMulLowI16x8U,
+    Operator::I32x4ExtMulHighI16x8U,
+    Operator::I64x2Abs,
+    Operator::I64x2Neg,
+    Operator::I64x2AllTrue,
+    Operator::I64x2Bitmask,
+    Operator::I64x2ExtendLowI32x4S,
+    Operator::I64x2ExtendHighI32x4S,
+    Operator::I64x2ExtendLowI32x4U,
+    Operator::I64x2ExtendHighI32x4U,
+    Operator::I64x2Shl,
+    Operator::I64x2ShrS,
+    Operator::I64x2ShrU,
+    Operator::I64x2Add,
+    Operator::I64x2Sub,
+    Operator::I64x2Mul,
+    Operator::I64x2ExtMulLowI32x4S,
+    Operator::I64x2ExtMulHighI32x4S,
+    Operator::I64x2ExtMulLowI32x4U,
+    Operator::I64x2ExtMulHighI32x4U,
+    Operator::F32x4Ceil,
+    Operator::F32x4Floor,
+    Operator::F32x4Trunc,
+    Operator::F32x4Nearest,
+    Operator::F32x4Abs,
+    Operator::F32x4Neg,
+    Operator::F32x4Sqrt,
+    Operator::F32x4Add,
+    Operator::F32x4Sub,
+    Operator::F32x4Mul,
+    Operator::F32x4Div,
+    Operator::F32x4Min,
+    Operator::F32x4Max,
+    Operator::F32x4PMin,
+    Operator::F32x4PMax,
+    Operator::F64x2Ceil,
+    Operator::F64x2Floor,
+    Operator::F64x2Trunc,
+    Operator::F64x2Nearest,
+    Operator::F64x2Abs,
+    Operator::F64x2Neg,
+    Operator::F64x2Sqrt,
+    Operator::F64x2Add,
+    Operator::F64x2Sub,
+    Operator::F64x2Mul,
+    Operator::F64x2Div,
+    Operator::F64x2Min,
+    Operator::F64x2Max,
+    Operator::F64x2PMin,
+    Operator::F64x2PMax,
+    Operator::I32x4TruncSatF32x4S,
+    Operator::I32x4TruncSatF32x4U,
+    Operator::F32x4ConvertI32x4S,
+    Operator::F32x4ConvertI32x4U,
+    Operator::I32x4TruncSatF64x2SZero,
+    Operator::I32x4TruncSatF64x2UZero,
+    Operator::F64x2ConvertLowI32x4S,
+    Operator::F64x2ConvertLowI32x4U,
+    Operator::F32x4DemoteF64x2Zero,
+    Operator::F64x2PromoteLowF32x4,
+    Operator::RefIsNull,
+    Operator::AtomicFence,
+    Operator::ArrayLen,
+    Operator::RefEq,
+    Operator::RefI31,
+    Operator::I31GetS,
+    Operator::I31GetU,
+    Operator::AnyConvertExtern,
+    Operator::ExternConvertAny,
+];
+const WITH_MEMORY: &[fn(MemoryArg) -> Operator] = &[
+    |memory| Operator::I32Load { memory },
+    |memory| Operator::I64Load { memory },
+    |memory| Operator::F32Load { memory },
+    |memory| Operator::F64Load { memory },
+    |memory| Operator::I32Load8S { memory },
+    |memory| Operator::I32Load8U { memory },
+    |memory| Operator::I32Load16S { memory },
+    |memory| Operator::I32Load16U { memory },
+    |memory| Operator::I64Load8S { memory },
+    |memory| Operator::I64Load8U { memory },
+    |memory| Operator::I64Load16S { memory },
+    |memory| Operator::I64Load16U { memory },
+    |memory| Operator::I64Load32S { memory },
+    |memory| Operator::I64Load32U { memory },
+    |memory| Operator::I32Store { memory },
+    |memory| Operator::I64Store { memory },
+    |memory| Operator::F32Store { memory },
+    |memory| Operator::F64Store { memory },
+    |memory| Operator::I32Store8 { memory },
+    |memory| Operator::I32Store16 { memory },
+    |memory| Operator::I64Store8 { memory },
+    |memory| Operator::I64Store16 { memory },
+    |memory| Operator::I64Store32 { memory },
+    |memory| Operator::V128Load { memory },
+    |memory| Operator::V128Load8x8S { memory },
+    |memory| Operator::V128Load8x8U { memory },
+    |memory| Operator::V128Load16x4S { memory },
+    |memory| Operator::V128Load16x4U { memory },
+    |memory| Operator::V128Load32x2S { memory },
+    |memory| Operator::V128Load32x2U { memory },
+    |memory| Operator::V128Load8Splat { memory },
+    |memory| Operator::V128Load16Splat { memory },
+    |memory| Operator::V128Load32Splat { memory },
+    |memory| Operator::V128Load64Splat { memory },
+    |memory| Operator::V128Load32Zero { memory },
+    |memory| Operator::V128Load64Zero { memory },
+    |memory| Operator::V128Store { memory },
+    |memarg| Operator::MemoryAtomicNotify { memarg },
+    |memarg| Operator::MemoryAtomicWait32 { memarg },
+    |memarg| Operator::MemoryAtomicWait64 { memarg },
+    |memarg| Operator::I32AtomicLoad { memarg },
+    |memarg| Operator::I64AtomicLoad { memarg },
+    |memarg| Operator::I32AtomicLoad8U { memarg },
+    |memarg| Operator::I32AtomicLoad16U { memarg },
+    |memarg| Operator::I64AtomicLoad8U { memarg },
+    |memarg| Operator::I64AtomicLoad16U { memarg },
+    |memarg| Operator::I64AtomicLoad32U { memarg },
+    |memarg| Operator::I32AtomicStore { memarg },
+    |memarg| Operator::I64AtomicStore { memarg },
+    |memarg| Operator::I32AtomicStore8 { memarg },
+    |memarg| Operator::I32AtomicStore16 { memarg },
+    |memarg| Operator::I64AtomicStore8 { memarg },
+    |memarg| Operator::I64AtomicStore16 { memarg },
+    |memarg| Operator::I64AtomicStore32 { memarg },
+    |memarg| Operator::I32AtomicRmwAdd { memarg },
+    |memarg| Operator::I64AtomicRmwAdd { memarg },
+    |memarg| Operator::I32AtomicRmw8AddU { memarg },
+    |memarg| Operator::I32AtomicRmw16AddU { memarg },
+    |memarg| Operator::I64AtomicRmw8AddU { memarg },
+    |memarg| Operator::I64AtomicRmw16AddU { memarg },
+    |memarg| Operator::I64AtomicRmw32AddU { memarg },
+    |memarg| Operator::I32AtomicRmwSub { memarg },
+    |memarg| Operator::I64AtomicRmwSub { memarg },
+    |memarg| Operator::I32AtomicRmw8SubU { memarg },
+    |memarg| Operator::I32AtomicRmw16SubU { memarg },
+    |memarg| Operator::I64AtomicRmw8SubU { memarg },
+    |memarg| Operator::I64AtomicRmw16SubU { memarg },
+    |memarg| Operator::I64AtomicRmw32SubU { memarg },
+    |memarg| Operator::I32AtomicRmwAnd { memarg },
+    |memarg| Operator::I64AtomicRmwAnd { memarg },
+    |memarg| Operator::I32AtomicRmw8AndU { memarg },
+    |memarg| Operator::I32AtomicRmw16AndU { memarg },
+    |memarg| Operator::I64AtomicRmw8AndU { memarg },
+    |memarg| Operator::I64AtomicRmw16AndU { memarg },
+    |memarg| Operator::I64AtomicRmw32AndU { memarg },
+    |memarg| Operator::I32AtomicRmwOr { memarg },
+    |memarg| Operator::I64AtomicRmwOr { memarg },
+    |memarg| Operator::I32AtomicRmw8OrU { memarg },
+    |memarg| Operator::I32AtomicRmw16OrU { memarg },
+    |memarg| Operator::I64AtomicRmw8OrU { memarg },
+    |memarg| Operator::I64AtomicRmw16OrU { memarg },
+    |memarg| Operator::I64AtomicRmw32OrU { memarg },
+    |memarg| Operator::I32AtomicRmwXor { memarg },
+    |memarg| Operator::I64AtomicRmwXor { memarg },
+    |memarg| Operator::I32AtomicRmw8XorU { memarg },
+    |memarg| Operator::I32AtomicRmw16XorU { memarg },
+    |memarg| Operator::I64AtomicRmw8XorU { memarg },
+    |memarg| Operator::I64AtomicRmw16XorU { memarg },
+    |memarg| Operator::I64AtomicRmw32XorU { memarg },
+    |memarg| Operator::I32AtomicRmwXchg { memarg },
+    |memarg| Operator::I64AtomicRmwXchg { memarg },
+    |memarg| Operator::I32AtomicRmw8XchgU { memarg },
+    |memarg| Operator::I32AtomicRmw16XchgU { memarg },
+    |memarg| Operator::I64AtomicRmw8XchgU { memarg },
+    |memarg| Operator::I64AtomicRmw16XchgU { memarg },
+    |memarg| Operator::I64AtomicRmw32XchgU { memarg },
+    |memarg| Operator::I32AtomicRmwCmpxchg { memarg },
+    |memarg| Operator::I64AtomicRmwCmpxchg { memarg },
+    |memarg| Operator::I32AtomicRmw8CmpxchgU { memarg },
+    |memarg| Operator::I32AtomicRmw16CmpxchgU { memarg },
+    |memarg| Operator::I64AtomicRmw8CmpxchgU { memarg },
+    |memarg| Operator::I64AtomicRmw16CmpxchgU { memarg },
+    |memarg| Operator::I64AtomicRmw32CmpxchgU { memarg },
+];
+const WITH_LANE: &[fn(u8) -> Operator] = &[
+    |lane| Operator::I8x16ExtractLaneS { lane },
+    |lane| Operator::I8x16ExtractLaneU { lane },
+    |lane| Operator::I8x16ReplaceLane { lane },
+    |lane| Operator::I16x8ExtractLaneS { lane },
+    |lane| Operator::I16x8ExtractLaneU { lane },
+    |lane| Operator::I16x8ReplaceLane { lane },
+    |lane| Operator::I32x4ExtractLane { lane },
+    |lane| Operator::I32x4ReplaceLane { lane },
+    |lane| Operator::I64x2ExtractLane { lane },
+    |lane| Operator::I64x2ReplaceLane { lane },
+    |lane| Operator::F32x4ExtractLane { lane },
+    |lane| Operator::F32x4ReplaceLane { lane },
+    |lane| Operator::F64x2ExtractLane { lane },
+    |lane| Operator::F64x2ReplaceLane { lane },
+];
+const WITH_MEMORY_LANE: &[fn(MemoryArg, u8) -> Operator] = &[
+    |memory, lane| Operator::V128Load8Lane { memory, lane },
+    |memory, lane| Operator::V128Load16Lane { memory, lane },
+    |memory, lane| Operator::V128Load32Lane { memory, lane },
+    |memory, lane| Operator::V128Load64Lane { memory, lane },
+    |memory, lane| Operator::V128Store8Lane { memory, lane },
+    |memory, lane| Operator::V128Store16Lane { memory, lane },
+    |memory, lane| Operator::V128Store32Lane { memory, lane },
+    |memory, lane| Operator::V128Store64Lane { memory, lane },
+];
+const WITH_SIG: &[fn(Signature) -> Operator] = &[
+    |sig| Operator::StructNew { sig },
+    |sig| Operator::ArrayNew { sig },
+    |sig| Operator::ArrayGet { sig },
+    |sig| Operator::ArraySet { sig },
+    |sig| Operator::ArrayFill { sig },
+    |sig| Operator::StructNewDefault { sig },
+    |sig| Operator::ArrayNewDefault { sig },
+    |sig| Operator::ArrayGetS { sig },
+    |sig| Operator::ArrayGetU { sig },
+];
+const WITH_FIELD: &[fn(Signature, usize) -> Operator] = &[
+    |sig, idx| Operator::StructGet { sig, idx },
+    |sig, idx| Operator::StructSet { sig, idx },
+    |sig, idx| Operator::StructGetS { sig, idx },
+    |sig, idx| Operator::StructGetU { sig, idx },
+];
+const WITH_TYPE: &[fn(Type) -> Operator] = &[
+    |ty| Operator::TypedSelect { ty },
+    |ty| Operator::RefNull { ty },
+    |ty| Operator::RefTest { ty },
+    |ty| Operator::RefCast { ty },
+];
+const WITH_TABLE: &[fn(Table) -> Operator] = &[
+    |table_index| Operator::TableGet { table_index },
+    |table_index| Operator::TableSet { table_index },
+    |table_index| Operator::TableGrow { table_index },
+    |table_index| Operator::TableSize { table_index },
+];
+const WITH_MEM: &[fn(Memory) -> Operator] = &[
+    |mem| Operator::MemorySize { mem },
+    |mem| Operator::MemoryGrow { mem },
+    |mem| Operator::MemoryFill { mem },
+];
+const WITH_GLOBAL: &[fn(Global) -> Operator] = &[
+    |global_index| Operator::GlobalGet { global_index },
+    |global_index| Operator::GlobalSet { global_index },
+];
+
+/// One instance of every operator: each template filled in with the
+/// given entities (and fixed lanes and field indices), plus the
+/// operators whose immediates no template covers. For tests that need
+/// to visit every `Operator` variant.
+#[cfg(test)]
+pub(crate) fn sample_operators(
+    sig: Signature,
+    memory: Memory,
+    table: Table,
+    global: Global,
+    func: crate::Func,
+) -> Vec<Operator> {
+    let memarg = MemoryArg {
+        align: 2,
+        offset: 8,
+        memory,
+    };
+    let mut ops = Template::all()
+        .map(|template| match template {
+            Template::Nullary(op) => op,
+            Template::Memory(f) => f(memarg),
+            Template::Lane(f) => f(3),
+            Template::MemoryLane(f) => f(memarg, 1),
+            Template::Sig(f) => f(sig),
+            Template::Field(f) => f(sig, 2),
+            Template::Typed(f) => f(Type::Heap(WithNullable {
+                value: HeapType::Sig { sig_index: sig },
+                nullable: false,
+            })),
+            Template::Table(f) => f(table),
+            Template::Mem(f) => f(memory),
+            Template::Global(f) => f(global),
+        })
+        .collect::<Vec<_>>();
+    ops.extend(
+        [
+            Operator::Call {
+                function_index: func,
+            },
+            Operator::CallIndirect {
+                sig_index: sig,
+                table_index: table,
+            },
+            Operator::CallRef { sig_index: sig },
+            Operator::RefFunc { func_index: func },
+            Operator::I32Const { value: u32::MAX },
+            Operator::I64Const { value: 7 },
+            Operator::F32Const {
+                value: (-0.0f32).to_bits(),
+            },
+            Operator::F64Const {
+                value: 1.5f64.to_bits(),
+            },
+            Operator::V128Const { value: u128::MAX },
+            Operator::I8x16Shuffle {
+                lanes: [0, 17, 2, 19, 4, 21, 6, 23, 8, 25, 10, 27, 12, 29, 14, 31],
+            },
+            Operator::MemoryCopy {
+                dst_mem: memory,
+                src_mem: Memory::new(0),
+            },
+            Operator::MemoryInit {
+                mem: memory,
+                data_idx: 3,
+            },
+            Operator::DataDrop { data_idx: 1 },
+            Operator::ArrayNewFixed { sig, num: 4 },
+            Operator::ArrayCopy {
+                dest: sig,
+                src: Signature::new(1),
+            },
+            Operator::ArrayNewData { sig, data_idx: 2 },
+            Operator::ArrayInitData { sig, data_idx: 0 },
+            Operator::ArrayNewElem { sig, elem_idx: 1 },
+            Operator::ArrayInitElem { sig, elem_idx: 3 },
+        ]
+        .iter()
+        .cloned(),
+    );
+    let mut sampled = [false; NUM_OPERATORS];
+    for op in &ops {
+        sampled[variant_index(op)] = true;
+    }
+    if let Some(missing) = sampled.iter().position(|&sampled| !sampled) {
+        panic!("no sample of operator variant {}", missing);
+    }
+    ops
+}
+
+/// The number of `Operator` variants.
+#[cfg(test)]
+const NUM_OPERATORS: usize = 516;
+
+/// The position of `op`'s variant in the `Operator` enum. There is
+/// deliberately no wildcard: a new variant fails to compile until it
+/// is listed here (bump `NUM_OPERATORS`), and `sample_operators` then
+/// fails until it produces an instance of it.
+#[cfg(test)]
+fn variant_index(op: &Operator) -> usize {
+    match op {
+        Operator::Unreachable => 0,
+        Operator::Nop => 1,
+        Operator::Call { .. } => 2,
+        Operator::CallIndirect { .. } => 3,
+        Operator::Select => 4,
+        Operator::TypedSelect { .. } => 5,
+        Operator::GlobalGet { .. } => 6,
+        Operator::GlobalSet { .. } => 7,
+        Operator::I32Load { .. } => 8,
+        Operator::I64Load { .. } => 9,
+        Operator::F32Load { .. } => 10,
+        Operator::F64Load { .. } => 11,
+        Operator::I32Load8S { .. } => 12,
+        Operator::I32Load8U { .. } => 13,
+        Operator::I32Load16S { .. } => 14,
+        Operator::I32Load16U { .. } => 15,
+        Operator::I64Load8S { .. } => 16,
+        Operator::I64Load8U { .. } => 17,
+        Operator::I64Load16S { .. } => 18,
+        Operator::I64Load16U { .. } => 19,
+        Operator::I64Load32S { .. } => 20,
+        Operator::I64Load32U { .. } => 21,
+        Operator::I32Store { .. } => 22,
+        Operator::I64Store { .. } => 23,
+        Operator::F32Store { .. } => 24,
+        Operator::F64Store { .. } => 25,
+        Operator::I32Store8 { .. } => 26,
+        Operator::I32Store16 { .. } => 27,
+        Operator::I64Store8 { .. } => 28,
+        Operator::I64Store16 { .. } => 29,
+        Operator::I64Store32 { .. } => 30,
+        Operator::I32Const { .. } => 31,
+        Operator::I64Const { .. } => 32,
+        Operator::F32Const { .. } => 33,
+        Operator::F64Const { .. } => 34,
+        Operator::I32Eqz => 35,
+        Operator::I32Eq => 36,
+        Operator::I32Ne => 37,
+        Operator::I32LtS => 38,
+        Operator::I32LtU => 39,
+        Operator::I32GtS => 40,
+        Operator::I32GtU => 41,
+        Operator::I32LeS => 42,
+        Operator::I32LeU => 43,
+        Operator::I32GeS => 44,
+        Operator::I32GeU => 45,
+        Operator::I64Eqz => 46,
+        Operator::I64Eq => 47,
+        Operator::I64Ne => 48,
+        Operator::I64LtS => 49,
+        Operator::I64LtU => 50,
+        Operator::I64GtU => 51,
+        Operator::I64GtS => 52,
+        Operator::I64LeS => 53,
+        Operator::I64LeU => 54,
+        Operator::I64GeS => 55,
+        Operator::I64GeU => 56,
+        Operator::F32Eq => 57,
+        Operator::F32Ne => 58,
+        Operator::F32Lt => 59,
+        Operator::F32Gt => 60,
+        Operator::F32Le => 61,
+        Operator::F32Ge => 62,
+        Operator::F64Eq => 63,
+        Operator::F64Ne => 64,
+        Operator::F64Lt => 65,
+        Operator::F64Gt => 66,
+        Operator::F64Le => 67,
+        Operator::F64Ge => 68,
+        Operator::I32Clz => 69,
+        Operator::I32Ctz => 70,
+        Operator::I32Popcnt => 71,
+        Operator::I32Add => 72,
+        Operator::I32Sub => 73,
+        Operator::I32Mul => 74,
+        Operator::I32DivS => 75,
+        Operator::I32DivU => 76,
+        Operator::I32RemS => 77,
+        Operator::I32RemU => 78,
+        Operator::I32And => 79,
+        Operator::I32Or => 80,
+        Operator::I32Xor => 81,
+        Operator::I32Shl => 82,
+        Operator::I32ShrS => 83,
+        Operator::I32ShrU => 84,
+        Operator::I32Rotl => 85,
+        Operator::I32Rotr => 86,
+        Operator::I64Clz => 87,
+        Operator::I64Ctz => 88,
+        Operator::I64Popcnt => 89,
+        Operator::I64Add => 90,
+        Operator::I64Sub => 91,
+        Operator::I64Mul => 92,
+        Operator::I64DivS => 93,
+        Operator::I64DivU => 94,
+        Operator::I64RemS => 95,
+        Operator::I64RemU => 96,
+        Operator::I64And => 97,
+        Operator::I64Or => 98,
+        Operator::I64Xor => 99,
+        Operator::I64Shl => 100,
+        Operator::I64ShrS => 101,
+        Operator::I64ShrU => 102,
+        Operator::I64Rotl => 103,
+        Operator::I64Rotr => 104,
+        Operator::F32Abs => 105,
+        Operator::F32Neg => 106,
+        Operator::F32Ceil => 107,
+        Operator::F32Floor => 108,
+        Operator::F32Trunc => 109,
+        Operator::F32Nearest => 110,
+        Operator::F32Sqrt => 111,
+        Operator::F32Add => 112,
+        Operator::F32Sub => 113,
+        Operator::F32Mul => 114,
+        Operator::F32Div => 115,
+        Operator::F32Min => 116,
+        Operator::F32Max => 117,
+        Operator::F32Copysign => 118,
+        Operator::F64Abs => 119,
+        Operator::F64Neg => 120,
+        Operator::F64Ceil => 121,
+        Operator::F64Floor => 122,
+        Operator::F64Trunc => 123,
+        Operator::F64Nearest => 124,
+        Operator::F64Sqrt => 125,
+        Operator::F64Add => 126,
+        Operator::F64Sub => 127,
+        Operator::F64Mul => 128,
+        Operator::F64Div => 129,
+        Operator::F64Min => 130,
+        Operator::F64Max => 131,
+        Operator::F64Copysign => 132,
+        Operator::I32WrapI64 => 133,
+        Operator::I32TruncF32S => 134,
+        Operator::I32TruncF32U => 135,
+        Operator::I32TruncF64S => 136,
+        Operator::I32TruncF64U => 137,
+        Operator::I64ExtendI32S => 138,
+        Operator::I64ExtendI32U => 139,
+        Operator::I64TruncF32S => 140,
+        Operator::I64TruncF32U => 141,
+        Operator::I64TruncF64S => 142,
+        Operator::I64TruncF64U => 143,
+        Operator::F32ConvertI32S => 144,
+        Operator::F32ConvertI32U => 145,
+        Operator::F32ConvertI64S => 146,
+        Operator::F32ConvertI64U => 147,
+        Operator::F32DemoteF64 => 148,
+        Operator::F64ConvertI32S => 149,
+        Operator::F64ConvertI32U => 150,
+        Operator::F64ConvertI64S => 151,
+        Operator::F64ConvertI64U => 152,
+        Operator::F64PromoteF32 => 153,
+        Operator::I32Extend8S => 154,
+        Operator::I32Extend16S => 155,
+        Operator::I64Extend8S => 156,
+        Operator::I64Extend16S => 157,
+        Operator::I64Extend32S => 158,
+        Operator::I32TruncSatF32S => 159,
+        Operator::I32TruncSatF32U => 160,
+        Operator::I32TruncSatF64S => 161,
+        Operator::I32TruncSatF64U => 162,
+        Operator::I64TruncSatF32S => 163,
+        Operator::I64TruncSatF32U => 164,
+        Operator::I64TruncSatF64S => 165,
+        Operator::I64TruncSatF64U => 166,
+        Operator::F32ReinterpretI32 => 167,
+        Operator::F64ReinterpretI64 => 168,
+        Operator::I32ReinterpretF32 => 169,
+        Operator::I64ReinterpretF64 => 170,
+        Operator::TableGet { .. } => 171,
+        Operator::TableSet { .. } => 172,
+        Operator::TableGrow { .. } => 173,
+        Operator::TableSize { .. } => 174,
+        Operator::MemorySize { .. } => 175,
+        Operator::MemoryGrow { .. } => 176,
+        Operator::V128Load { .. } => 177,
+        Operator::V128Load8x8S { .. } => 178,
+        Operator::V128Load8x8U { .. } => 179,
+        Operator::V128Load16x4S { .. } => 180,
+        Operator::V128Load16x4U { .. } => 181,
+        Operator::V128Load32x2S { .. } => 182,
+        Operator::V128Load32x2U { .. } => 183,
+        Operator::V128Load8Splat { .. } => 184,
+        Operator::V128Load16Splat { .. } => 185,
+        Operator::V128Load32Splat { .. } => 186,
+        Operator::V128Load64Splat { .. } => 187,
+        Operator::V128Load32Zero { .. } => 188,
+        Operator::V128Load64Zero { .. } => 189,
+        Operator::V128Store { .. } => 190,
+        Operator::V128Load8Lane { .. } => 191,
+        Operator::V128Load16Lane { .. } => 192,
+        Operator::V128Load32Lane { .. } => 193,
+        Operator::V128Load64Lane { .. } => 194,
+        Operator::V128Store8Lane { .. } => 195,
+        Operator::V128Store16Lane { .. } => 196,
+        Operator::V128Store32Lane { .. } => 197,
+        Operator::V128Store64Lane { .. } => 198,
+        Operator::V128Const { .. } => 199,
+        Operator::I8x16Shuffle { .. } => 200,
+        Operator::I8x16ExtractLaneS { .. } => 201,
+        Operator::I8x16ExtractLaneU { .. } => 202,
+        Operator::I8x16ReplaceLane { .. } => 203,
+        Operator::I16x8ExtractLaneS { .. } => 204,
+        Operator::I16x8ExtractLaneU { .. } => 205,
+        Operator::I16x8ReplaceLane { .. } => 206,
+        Operator::I32x4ExtractLane { .. } => 207,
+        Operator::I32x4ReplaceLane { .. } => 208,
+        Operator::I64x2ExtractLane { .. } => 209,
+        Operator::I64x2ReplaceLane { .. } => 210,
+        Operator::F32x4ExtractLane { .. } => 211,
+        Operator::F32x4ReplaceLane { .. } => 212,
+        Operator::F64x2ExtractLane { .. } => 213,
+        Operator::F64x2ReplaceLane { .. } => 214,
+        Operator::I8x16Swizzle => 215,
+        Operator::I8x16Splat => 216,
+        Operator::I16x8Splat => 217,
+        Operator::I32x4Splat => 218,
+        Operator::I64x2Splat => 219,
+        Operator::F32x4Splat => 220,
+        Operator::F64x2Splat => 221,
+        Operator::I8x16Eq => 222,
+        Operator::I8x16Ne => 223,
+        Operator::I8x16LtS => 224,
+        Operator::I8x16LtU => 225,
+        Operator::I8x16GtS => 226,
+        Operator::I8x16GtU => 227,
+        Operator::I8x16LeS => 228,
+        Operator::I8x16LeU => 229,
+        Operator::I8x16GeS => 230,
+        Operator::I8x16GeU => 231,
+        Operator::I16x8Eq => 232,
+        Operator::I16x8Ne => 233,
+        Operator::I16x8LtS => 234,
+        Operator::I16x8LtU => 235,
+        Operator::I16x8GtS => 236,
+        Operator::I16x8GtU => 237,
+        Operator::I16x8LeS => 238,
+        Operator::I16x8LeU => 239,
+        Operator::I16x8GeS => 240,
+        Operator::I16x8GeU => 241,
+        Operator::I32x4Eq => 242,
+        Operator::I32x4Ne => 243,
+        Operator::I32x4LtS => 244,
+        Operator::I32x4LtU => 245,
+        Operator::I32x4GtS => 246,
+        Operator::I32x4GtU => 247,
+        Operator::I32x4LeS => 248,
+        Operator::I32x4LeU => 249,
+        Operator::I32x4GeS => 250,
+        Operator::I32x4GeU => 251,
+        Operator::I64x2Eq => 252,
+        Operator::I64x2Ne => 253,
+        Operator::I64x2LtS => 254,
+        Operator::I64x2GtS => 255,
+        Operator::I64x2LeS => 256,
+        Operator::I64x2GeS => 257,
+        Operator::F32x4Eq => 258,
+        Operator::F32x4Ne => 259,
+        Operator::F32x4Lt => 260,
+        Operator::F32x4Gt => 261,
+        Operator::F32x4Le => 262,
+        Operator::F32x4Ge => 263,
+        Operator::F64x2Eq => 264,
+        Operator::F64x2Ne => 265,
+        Operator::F64x2Lt => 266,
+        Operator::F64x2Gt => 267,
+        Operator::F64x2Le => 268,
+        Operator::F64x2Ge => 269,
+        Operator::V128Not => 270,
+        Operator::V128And => 271,
+        Operator::V128AndNot => 272,
+        Operator::V128Or => 273,
+        Operator::V128Xor => 274,
+        Operator::V128Bitselect => 275,
+        Operator::V128AnyTrue => 276,
+        Operator::I8x16Abs => 277,
+        Operator::I8x16Neg => 278,
+        Operator::I8x16Popcnt => 279,
+        Operator::I8x16AllTrue => 280,
+        Operator::I8x16Bitmask => 281,
+        Operator::I8x16NarrowI16x8S => 282,
+        Operator::I8x16NarrowI16x8U => 283,
+        Operator::I8x16Shl => 284,
+        Operator::I8x16ShrS => 285,
+        Operator::I8x16ShrU => 286,
+        Operator::I8x16Add => 287,
+        Operator::I8x16AddSatS => 288,
+        Operator::I8x16AddSatU => 289,
+        Operator::I8x16Sub => 290,
+        Operator::I8x16SubSatS => 291,
+        Operator::I8x16SubSatU => 292,
+        Operator::I8x16MinS => 293,
+        Operator::I8x16MinU => 294,
+        Operator::I8x16MaxS => 295,
+        Operator::I8x16MaxU => 296,
+        Operator::I8x16AvgrU => 297,
+        Operator::I16x8ExtAddPairwiseI8x16S => 298,
+        Operator::I16x8ExtAddPairwiseI8x16U => 299,
+        Operator::I16x8Abs => 300,
+        Operator::I16x8Neg => 301,
+        Operator::I16x8Q15MulrSatS => 302,
+        Operator::I16x8AllTrue => 303,
+        Operator::I16x8Bitmask => 304,
+        Operator::I16x8NarrowI32x4S => 305,
+        Operator::I16x8NarrowI32x4U => 306,
+        Operator::I16x8ExtendLowI8x16S => 307,
+        Operator::I16x8ExtendHighI8x16S => 308,
+        Operator::I16x8ExtendLowI8x16U => 309,
+        Operator::I16x8ExtendHighI8x16U => 310,
+        Operator::I16x8Shl => 311,
+        Operator::I16x8ShrS => 312,
+        Operator::I16x8ShrU => 313,
+        Operator::I16x8Add => 314,
+        Operator::I16x8AddSatS => 315,
+        Operator::I16x8AddSatU => 316,
+        Operator::I16x8Sub => 317,
+        Operator::I16x8SubSatS => 318,
+        Operator::I16x8SubSatU => 319,
+        Operator::I16x8Mul => 320,
+        Operator::I16x8MinS => 321,
+        Operator::I16x8MinU => 322,
+        Operator::I16x8MaxS => 323,
+        Operator::I16x8MaxU => 324,
+        Operator::I16x8AvgrU => 325,
+        Operator::I16x8ExtMulLowI8x16S => 326,
+        Operator::I16x8ExtMulHighI8x16S => 327,
+        Operator::I16x8ExtMulLowI8x16U => 328,
+        Operator::I16x8ExtMulHighI8x16U => 329,
+        Operator::I32x4ExtAddPairwiseI16x8S => 330,
+        Operator::I32x4ExtAddPairwiseI16x8U => 331,
+        Operator::I32x4Abs => 332,
+        Operator::I32x4Neg => 333,
+        Operator::I32x4AllTrue => 334,
+        Operator::I32x4Bitmask => 335,
+        Operator::I32x4ExtendLowI16x8S => 336,
+        Operator::I32x4ExtendHighI16x8S => 337,
+        Operator::I32x4ExtendLowI16x8U => 338,
+        Operator::I32x4ExtendHighI16x8U => 339,
+        Operator::I32x4Shl => 340,
+        Operator::I32x4ShrS => 341,
+        Operator::I32x4ShrU => 342,
+        Operator::I32x4Add => 343,
+        Operator::I32x4Sub => 344,
+        Operator::I32x4Mul => 345,
+        Operator::I32x4MinS => 346,
+        Operator::I32x4MinU => 347,
+        Operator::I32x4MaxS => 348,
+        Operator::I32x4MaxU => 349,
+        Operator::I32x4DotI16x8S => 350,
+        Operator::I32x4ExtMulLowI16x8S => 351,
+        Operator::I32x4ExtMulHighI16x8S => 352,
+        Operator::I32x4ExtMulLowI16x8U => 353,
+        Operator::I32x4ExtMulHighI16x8U => 354,
+        Operator::I64x2Abs => 355,
+        Operator::I64x2Neg => 356,
+        Operator::I64x2AllTrue => 357,
+        Operator::I64x2Bitmask => 358,
+        Operator::I64x2ExtendLowI32x4S => 359,
+        Operator::I64x2ExtendHighI32x4S => 360,
+        Operator::I64x2ExtendLowI32x4U => 361,
+        Operator::I64x2ExtendHighI32x4U => 362,
+        Operator::I64x2Shl => 363,
+        Operator::I64x2ShrS => 364,
+        Operator::I64x2ShrU => 365,
+        Operator::I64x2Add => 366,
+        Operator::I64x2Sub => 367,
+        Operator::I64x2Mul => 368,
+        Operator::I64x2ExtMulLowI32x4S => 369,
+        Operator::I64x2ExtMulHighI32x4S => 370,
+        Operator::I64x2ExtMulLowI32x4U => 371,
+        Operator::I64x2ExtMulHighI32x4U => 372,
+        Operator::F32x4Ceil => 373,
+        Operator::F32x4Floor => 374,
+        Operator::F32x4Trunc => 375,
+        Operator::F32x4Nearest => 376,
+        Operator::F32x4Abs => 377,
+        Operator::F32x4Neg => 378,
+        Operator::F32x4Sqrt => 379,
+        Operator::F32x4Add => 380,
+        Operator::F32x4Sub => 381,
+        Operator::F32x4Mul => 382,
+        Operator::F32x4Div => 383,
+        Operator::F32x4Min => 384,
+        Operator::F32x4Max => 385,
+        Operator::F32x4PMin => 386,
+        Operator::F32x4PMax => 387,
+        Operator::F64x2Ceil => 388,
+        Operator::F64x2Floor => 389,
+        Operator::F64x2Trunc => 390,
+        Operator::F64x2Nearest => 391,
+        Operator::F64x2Abs => 392,
+        Operator::F64x2Neg => 393,
+        Operator::F64x2Sqrt => 394,
+        Operator::F64x2Add => 395,
+        Operator::F64x2Sub => 396,
+        Operator::F64x2Mul => 397,
+        Operator::F64x2Div => 398,
+        Operator::F64x2Min => 399,
+        Operator::F64x2Max => 400,
+        Operator::F64x2PMin => 401,
+        Operator::F64x2PMax => 402,
+        Operator::I32x4TruncSatF32x4S => 403,
+        Operator::I32x4TruncSatF32x4U => 404,
+        Operator::F32x4ConvertI32x4S => 405,
+        Operator::F32x4ConvertI32x4U => 406,
+        Operator::I32x4TruncSatF64x2SZero => 407,
+        Operator::I32x4TruncSatF64x2UZero => 408,
+        Operator::F64x2ConvertLowI32x4S => 409,
+        Operator::F64x2ConvertLowI32x4U => 410,
+        Operator::F32x4DemoteF64x2Zero => 411,
+        Operator::F64x2PromoteLowF32x4 => 412,
+        Operator::CallRef { .. } => 413,
+        Operator::RefIsNull => 414,
+        Operator::RefNull { .. } => 415,
+        Operator::RefFunc { .. } => 416,
+        Operator::MemoryCopy { .. } => 417,
+        Operator::MemoryFill { .. } => 418,
+        Operator::MemoryInit { .. } => 419,
+        Operator::DataDrop { .. } => 420,
+        Operator::MemoryAtomicNotify { .. } => 421,
+        Operator::MemoryAtomicWait32 { .. } => 422,
+        Operator::MemoryAtomicWait64 { .. } => 423,
+        Operator::AtomicFence => 424,
+        Operator::I32AtomicLoad { .. } => 425,
+        Operator::I64AtomicLoad { .. } => 426,
+        Operator::I32AtomicLoad8U { .. } => 427,
+        Operator::I32AtomicLoad16U { .. } => 428,
+        Operator::I64AtomicLoad8U { .. } => 429,
+        Operator::I64AtomicLoad16U { .. } => 430,
+        Operator::I64AtomicLoad32U { .. } => 431,
+        Operator::I32AtomicStore { .. } => 432,
+        Operator::I64AtomicStore { .. } => 433,
+        Operator::I32AtomicStore8 { .. } => 434,
+        Operator::I32AtomicStore16 { .. } => 435,
+        Operator::I64AtomicStore8 { .. } => 436,
+        Operator::I64AtomicStore16 { .. } => 437,
+        Operator::I64AtomicStore32 { .. } => 438,
+        Operator::I32AtomicRmwAdd { .. } => 439,
+        Operator::I64AtomicRmwAdd { .. } => 440,
+        Operator::I32AtomicRmw8AddU { .. } => 441,
+        Operator::I32AtomicRmw16AddU { .. } => 442,
+        Operator::I64AtomicRmw8AddU { .. } => 443,
+        Operator::I64AtomicRmw16AddU { .. } => 444,
+        Operator::I64AtomicRmw32AddU { .. } => 445,
+        Operator::I32AtomicRmwSub { .. } => 446,
+        Operator::I64AtomicRmwSub { .. } => 447,
+        Operator::I32AtomicRmw8SubU { .. } => 448,
+        Operator::I32AtomicRmw16SubU { .. } => 449,
+        Operator::I64AtomicRmw8SubU { .. } => 450,
+        Operator::I64AtomicRmw16SubU { .. } => 451,
+        Operator::I64AtomicRmw32SubU { .. } => 452,
+        Operator::I32AtomicRmwAnd { .. } => 453,
+        Operator::I64AtomicRmwAnd { .. } => 454,
+        Operator::I32AtomicRmw8AndU { .. } => 455,
+        Operator::I32AtomicRmw16AndU { .. } => 456,
+        Operator::I64AtomicRmw8AndU { .. } => 457,
+        Operator::I64AtomicRmw16AndU { .. } => 458,
+        Operator::I64AtomicRmw32AndU { .. } => 459,
+        Operator::I32AtomicRmwOr { .. } => 460,
+        Operator::I64AtomicRmwOr { .. } => 461,
+        Operator::I32AtomicRmw8OrU { .. } => 462,
+        Operator::I32AtomicRmw16OrU { .. } => 463,
+        Operator::I64AtomicRmw8OrU { .. } => 464,
+        Operator::I64AtomicRmw16OrU { .. } => 465,
+        Operator::I64AtomicRmw32OrU { .. } => 466,
+        Operator::I32AtomicRmwXor { .. } => 467,
+        Operator::I64AtomicRmwXor { .. } => 468,
+        Operator::I32AtomicRmw8XorU { .. } => 469,
+        Operator::I32AtomicRmw16XorU { .. } => 470,
+        Operator::I64AtomicRmw8XorU { .. } => 471,
+        Operator::I64AtomicRmw16XorU { .. } => 472,
+        Operator::I64AtomicRmw32XorU { .. } => 473,
+        Operator::I32AtomicRmwXchg { .. } => 474,
+        Operator::I64AtomicRmwXchg { .. } => 475,
+        Operator::I32AtomicRmw8XchgU { .. } => 476,
+        Operator::I32AtomicRmw16XchgU { .. } => 477,
+        Operator::I64AtomicRmw8XchgU { .. } => 478,
+        Operator::I64AtomicRmw16XchgU { .. } => 479,
+        Operator::I64AtomicRmw32XchgU { .. } => 480,
+        Operator::I32AtomicRmwCmpxchg { .. } => 481,
+        Operator::I64AtomicRmwCmpxchg { .. } => 482,
+        Operator::I32AtomicRmw8CmpxchgU { .. } => 483,
+        Operator::I32AtomicRmw16CmpxchgU { .. } => 484,
+        Operator::I64AtomicRmw8CmpxchgU { .. } => 485,
+        Operator::I64AtomicRmw16CmpxchgU { .. } => 486,
+        Operator::I64AtomicRmw32CmpxchgU { .. } => 487,
+        Operator::StructNew { .. } => 488,
+        Operator::StructGet { .. } => 489,
+        Operator::StructSet { .. } => 490,
+        Operator::ArrayNew { .. } => 491,
+        Operator::ArrayNewFixed { .. } => 492,
+        Operator::ArrayGet { .. } => 493,
+        Operator::ArraySet { .. } => 494,
+        Operator::ArrayFill { .. } => 495,
+        Operator::ArrayCopy { .. } => 496,
+        Operator::ArrayLen => 497,
+        Operator::RefTest { .. } => 498,
+        Operator::RefCast { .. } => 499,
+        Operator::StructNewDefault { .. } => 500,
+        Operator::StructGetS { .. } => 501,
+        Operator::StructGetU { .. } => 502,
+        Operator::ArrayNewDefault { .. } => 503,
+        Operator::ArrayNewData { .. } => 504,
+        Operator::ArrayNewElem { .. } => 505,
+        Operator::ArrayGetS { .. } => 506,
+        Operator::ArrayGetU { .. } => 507,
+        Operator::ArrayInitData { .. } => 508,
+        Operator::ArrayInitElem { .. } => 509,
+        Operator::RefEq => 510,
+        Operator::RefI31 => 511,
+        Operator::I31GetS => 512,
+        Operator::I31GetU => 513,
+        Operator::AnyConvertExtern => 514,
+        Operator::ExternConvertAny => 515,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Func, FuncDecl, FunctionBuilder, MemoryData, SignatureData};
+    use alloc::borrow::ToOwned;
+
+    #[test]
+    fn operator_names_are_unique() {
+        assert_eq!(
+            OperatorParser::new().templates.len(),
+            Template::all().count()
+        );
+    }
+
+    #[test]
+    fn operators_round_trip() {
+        let ops = sample_operators(
+            Signature::new(3),
+            Memory::new(1),
+            Table::new(1),
+            Global::new(4),
+            Func::new(5),
+        );
+        let parser = OperatorParser::new();
+        for op in ops {
+            assert_eq!(parser.parse(&op.to_string()).unwrap(), op, "{}", op);
+        }
+        assert!(parse_operator("i32frobnicate").is_err());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let mut module = Module::empty();
+        let memory = module.memories.push(MemoryData {
+            initial_pages: 1,
+            maximum_pages: None,
+            segments: vec![],
+            memory64: false,
+            shared: false,
+            page_size_log2: None,
+        });
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32, Type::F64],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        let pair_sig = module.signatures.push(SignatureData::Func {
+            params: vec![],
+            returns: vec![Type::I32, Type::I64],
+            shared: false,
+        });
+        let pair = module
+            .funcs
+            .push(FuncDecl::Import(pair_sig, "pair".to_owned()));
+        let file = module.debug.intern_file("a.c");
+        let loc = module.debug.intern_loc(file, 10, 4);
+
+        let mut b = FunctionBuilder::new(&module, sig);
+        let entry = b.entry();
+        let params = b.params(entry);
+        let left = b.add_block();
+        let right = b.add_block();
+        let join = b.add_block();
+        let result = b.add_blockparam(join, Type::I32);
+        let call = b.push_op(
+            entry,
+            Operator::Call {
+                function_index: pair,
+            },
+            &[],
+            &[Type::I32, Type::I64],
+        );
+        let first = b
+            .body_mut()
+            .add_value(ValueDef::PickOutput(call, 0, Type::I32));
+        b.body_mut().append_to_block(entry, first);
+        b.cond_br(entry, params[0], (left, &[]), (right, &[]));
+        let memarg = MemoryArg {
+            align: 2,
+            offset: 16,
+            memory,
+        };
+        let loaded = b.push_op(
+            left,
+            Operator::I32Load { memory: memarg },
+            &[params[0]],
+            &[Type::I32],
+        );
+        b.br(left, join, &[loaded]);
+        b.set_terminator(
+            right,
+            Terminator::Select {
+                value: first,
+                targets: vec![BlockTarget {
+                    block: join,
+                    args: vec![first],
+                }],
+                default: BlockTarget {
+                    block: left,
+                    args: vec![],
+                },
+            },
+        );
+        b.ret(join, &[result]);
+        let mut body = b.finish();
+        body.blocks[left].desc = "left arm".to_owned();
+        body.source_locs[loaded] = loc;
+        body.mark_value_as_local(result, Local::new(2));
+
+        let text = body.display("", Some(&module)).to_string();
+        let parsed = parse_function_body(&text, &module, sig).unwrap();
+        parsed.validate().unwrap();
+        assert_eq!(parsed.display("", Some(&module)).to_string(), text);
+        assert_eq!(parsed.source_locs[loaded], loc);
+        assert_eq!(parsed.value_locals[result], Some(Local::new(2)));
+        assert_eq!(parsed.blocks[left].preds, body.blocks[left].preds);
+
+        let verbose = body.display_verbose("", Some(&module)).to_string();
+        let parsed = parse_function_body(&verbose, &module, sig).unwrap();
+        assert_eq!(parsed.display("", Some(&module)).to_string(), text);
+    }
+
+    fn parse_i32_identity(body: &str) -> Result<FunctionBody> {
+        let mut module = Module::empty();
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        let text = format!("function(i32) -> i32 {{\n{}\n}}", body);
+        parse_function_body(&text, &module, sig)
+    }
+
+    #[test]
+    fn huge_numbers_are_rejected() {
+        parse_i32_identity("block0(v0: i32):\n  return v0").unwrap();
+        for body in [
+            "block0(v4000000000: i32):\n  return v4000000000",
+            "block0(v0: i32):\n  v4000000000 = v0\n  return v0",
+            "block0(v0: i32):\n  return v4000000000",
+            "block0(v0: i32):\n  v1 = v4000000000\n  return v1",
+            "block0(v0: i32):\n  v1 = v4000000000.0 # i32\n  return v1",
+            "block0(v0: i32):\n  br block4000000000()",
+            "block4000000000(v0: i32):\n  return v0",
+        ] {
+            let err = parse_i32_identity(body).unwrap_err();
+            assert!(format!("{:#}", err).contains("out of range"), "{:#}", err);
+        }
+    }
+
+    #[test]
+    fn undefined_values_become_none() {
+        for (body, undefined) in [
+            ("block0(v0: i32):\n  return v5", 5),
+            (
+                "block0(v0: i32):\n  v1 = i32add v0, v6 # i32\n  return v1",
+                6,
+            ),
+            ("block0(v0: i32):\n  v1 = v7\n  return v1", 7),
+            ("block0(v0: i32):\n  v1 = v8.0 # i32\n  return v1", 8),
+        ] {
+            let body = parse_i32_identity(body).unwrap();
+            assert_eq!(body.values.len(), undefined + 1, "{:?}", body.values);
+            assert_eq!(body.values[Value::new(undefined)], ValueDef::None);
+            body.compute_uses();
+        }
+    }
+
+    #[test]
+    fn redefinition_is_rejected() {
+        for body in [
+            "block0(v0: i32):\n  v0 = placeholder # i32\n  return v0",
+            "block0(v0: i32, v0: i32):\n  return v0",
+            "block0(v0: i32):\n  v1 = v0\n  v2 = v0\n  v1 = v2\n  return v1",
+        ] {
+            let err = parse_i32_identity(body).unwrap_err();
+            assert!(format!("{:#}", err).contains("defined twice"), "{:#}", err);
+        }
+    }
+}
@@ -189,35 +189,66 @@ impl core::fmt::Display for Type {
             Type::F32 => write!(f, "f32"),
             Type::F64 => write!(f, "f64"),
             Type::V128 => write!(f, "v128"),
-            Type::Heap(h) => write!(
-                f,
-                "ref({} {})",
-                if h.nullable { "null" } else { "not_null" },
-                &h.value
-            ),
+            Type::Heap(h) => {
+                write!(f, "ref({} ", if h.nullable { "null" } else { "not_null" })?;
+                write_ir_heap_type(f, &h.value)?;
+                write!(f, ")")
+            }
         }
     }
 }
+/// The heap type names used in `Type`'s (IR text) form, e.g.
+/// `ref(null sigref(sig0))`.
+fn write_ir_heap_type(f: &mut core::fmt::Formatter<'_>, heap: &HeapType) -> core::fmt::Result {
+    match heap {
+        HeapType::FuncRef => write!(f, "funcref"),
+        HeapType::ExternRef => write!(f, "externref"),
+        HeapType::Sig { sig_index } => write!(f, "sigref({})", sig_index),
+        HeapType::Array => write!(f, "arrayref"),
+        HeapType::Any => write!(f, "anyref"),
+        HeapType::Eq => write!(f, "eqref"),
+        HeapType::I31 => write!(f, "i31ref"),
+        HeapType::Struct => write!(f, "structref"),
+        HeapType::None => write!(f, "nullref"),
+        HeapType::NoExtern => write!(f, "nullexternref"),
+        HeapType::NoFunc => write!(f, "nullfuncref"),
+        HeapType::Exn => write!(f, "exnref"),
+        HeapType::NoExn => write!(f, "nullexnref"),
+        _ => write!(f, "<unknown heap type>"),
+    }
+}
+/// Heap types print as in the text format, with signatures named
+/// `$sigN`.
 impl core::fmt::Display for HeapType {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
-            HeapType::FuncRef => write!(f, "funcref"),
-            HeapType::ExternRef => write!(f, "externref"),
-            HeapType::Sig { sig_index } => write!(f, "sigref({})", sig_index),
-            HeapType::Array => write!(f, "arrayref"),
-            HeapType::Any => write!(f, "anyref"),
-            HeapType::Eq => write!(f, "eqref"),
-            HeapType::I31 => write!(f, "i31ref"),
-            HeapType::Struct => write!(f, "structref"),
-            HeapType::None => write!(f, "nullref"),
-            HeapType::NoExtern => write!(f, "nullexternref"),
-            HeapType::NoFunc => write!(f, "nullfuncref"),
-            HeapType::Exn => write!(f, "exnref"),
-            HeapType::NoExn => write!(f, "nullexnref"),
+            HeapType::FuncRef => write!(f, "func"),
+            HeapType::ExternRef => write!(f, "extern"),
+            HeapType::Sig { sig_index } => write!(f, "${}", sig_index),
+            HeapType::Array => write!(f, "array"),
+            HeapType::Any => write!(f, "any"),
+            HeapType::Eq => write!(f, "eq"),
+            HeapType::I31 => write!(f, "i31"),
+            HeapType::Struct => write!(f, "struct"),
+            HeapType::None => write!(f, "none"),
+            HeapType::NoExtern => write!(f, "noextern"),
+            HeapType::NoFunc => write!(f, "nofunc"),
+            HeapType::Exn => write!(f, "exn"),
+            HeapType::NoExn => write!(f, "noexn"),
             _ => write!(f, "<unknown heap type>"),
         }
     }
 }
+/// `(ref null $sig0)` or `(ref $sig0)`, as in the text format.
+impl core::fmt::Display for WithNullable<HeapType> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if self.nullable {
+            write!(f, "(ref null {})", self.value)
+        } else {
+            write!(f, "(ref {})", self.value)
+        }
+    }
+}
 impl From<Type> for wasm_encoder::ValType {
     fn from(ty: Type) -> wasm_encoder::ValType {
         match ty {
@@ -304,3 +335,75 @@ impl From<WithNullable<HeapType>> for wasm_encoder::RefType {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ir::parse_type;
+    use crate::Operator;
+    use alloc::string::ToString;
+
+    #[test]
+    fn heap_types_display_like_the_text_format() {
+        let sig = Signature::new(2);
+        let cases = [
+            (HeapType::Sig { sig_index: sig }, true, "(ref null $sig2)"),
+            (HeapType::Sig { sig_index: sig }, false, "(ref $sig2)"),
+            (HeapType::Struct, true, "(ref null struct)"),
+            (HeapType::Struct, false, "(ref struct)"),
+            (HeapType::Array, true, "(ref null array)"),
+            (HeapType::Array, false, "(ref array)"),
+            (HeapType::FuncRef, true, "(ref null func)"),
+            (HeapType::FuncRef, false, "(ref func)"),
+        ];
+        for &(heap, nullable, text) in cases.iter() {
+            let ty = Type::heap(heap, nullable);
+            assert_eq!(
+                ty,
+                Type::Heap(WithNullable {
+                    value: heap,
+                    nullable
+                })
+            );
+            let Type::Heap(h) = ty else { unreachable!() };
+            assert_eq!(h.to_string(), text);
+            assert_eq!(parse_type(text).unwrap(), ty);
+            // `Type` keeps its own IR text form.
+            assert_eq!(parse_type(&ty.to_string()).unwrap(), ty);
+            assert_eq!(
+                Operator::RefNull { ty }.to_string(),
+                format!("ref_null<{}>", text)
+            );
+        }
+        assert_eq!(
+            Type::heap(HeapType::Sig { sig_index: sig }, true).to_string(),
+            "ref(null sigref(sig2))"
+        );
+    }
+
+    #[test]
+    fn type_classes() {
+        // (type, is_num, is_vec, is_ref, is_nullable_ref)
+        let cases = [
+            (Type::I32, true, false, false, false),
+            (Type::I64, true, false, false, false),
+            (Type::F32, true, false, false, false),
+            (Type::F64, true, false, false, false),
+            (Type::V128, false, true, false, false),
+            (
+                Type::heap(HeapType::ExternRef, true),
+                false,
+                false,
+                true,
+                true,
+            ),
+            (Type::heap(HeapType::I31, false), false, false, true, false),
+        ];
+        for &(ty, num, vec, is_ref, nullable) in cases.iter() {
+            assert_eq!(ty.is_num(), num, "{}", ty);
+            assert_eq!(ty.is_vec(), vec, "{}", ty);
+            assert_eq!(ty.is_ref(), is_ref, "{}", ty);
+            assert_eq!(ty.is_nullable_ref(), nullable, "{}", ty);
+        }
+    }
+}
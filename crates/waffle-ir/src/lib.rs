@@ -38,7 +38,7 @@ pub use ir_types::*;
 pub use ir::*;
 pub use ir_subtypes::*;
 pub use op_traits::{SideEffect, op_inputs, op_outputs};
-pub use ops::{Ieee32, Ieee64, MemoryArg, Operator};
+pub use ops::{Ieee32, Ieee32Ext, Ieee64, Ieee64Ext, MemoryArg, Operator};
 pub use interp::*;
 
 #[cfg(feature = "rkyv-impl")]
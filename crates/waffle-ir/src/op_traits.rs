@@ -269,12 +269,12 @@ pub fn op_inputs(
         ]
         .into()),
         Operator::TableGrow { table_index } => Ok(vec![
+            module.tables[*table_index].ty,
             if module.tables[*table_index].table64 {
                 Type::I64
             } else {
                 Type::I32
             },
-            module.tables[*table_index].ty,
         ]
         .into()),
         Operator::TableSize { .. } => Ok(Cow::Borrowed(&[])),
@@ -669,7 +669,10 @@ pub fn op_inputs(
             Ok(params.into())
         }
         Operator::RefIsNull => {
-            Ok(vec![op_stack.context("in getting stack")?.last().unwrap().0].into())
+            let Some(&(ref_ty, _)) = op_stack.context("in getting stack")?.last() else {
+                anyhow::bail!("ref.is_null cannot be typed with an empty stack");
+            };
+            Ok(vec![ref_ty].into())
         }
         Operator::RefNull { ty } => Ok(Cow::Borrowed(&[])),
         Operator::RefFunc { .. } => Ok(Cow::Borrowed(&[])),
@@ -682,13 +685,14 @@ pub fn op_inputs(
             (true, false) => Ok(Cow::Borrowed(&[Type::I64, Type::I32, Type::I32])),
             (true, true) => Ok(Cow::Borrowed(&[Type::I64, Type::I64, Type::I32])),
         },
-        Operator::MemoryFill { mem, .. } => {
+        Operator::MemoryFill { mem, .. } | Operator::MemoryInit { mem, .. } => {
             if module.memories[*mem].memory64 {
                 Ok(Cow::Borrowed(&[Type::I64, Type::I32, Type::I32]))
             } else {
                 Ok(Cow::Borrowed(&[Type::I32, Type::I32, Type::I32]))
             }
         }
+        Operator::DataDrop { .. } => Ok(Cow::Borrowed(&[])),
         Operator::MemoryAtomicNotify { memarg } => {
             if module.memories[memarg.memory].memory64 {
                 Ok(Cow::Borrowed(&[Type::I64, Type::I32]))
@@ -1476,7 +1480,13 @@ pub fn op_outputs(
         Operator::I64ReinterpretF64 => Ok(Cow::Borrowed(&[Type::I64])),
         Operator::TableGet { table_index } => Ok(vec![module.tables[*table_index].ty].into()),
         Operator::TableSet { .. } => Ok(Cow::Borrowed(&[])),
-        Operator::TableGrow { .. } => Ok(Cow::Borrowed(&[])),
+        Operator::TableGrow { table_index } => {
+            Ok(Cow::Borrowed(if module.tables[*table_index].table64 {
+                &[Type::I64]
+            } else {
+                &[Type::I32]
+            }))
+        }
         Operator::TableSize { table_index } => {
             Ok(Cow::Borrowed(if module.tables[*table_index].table64 {
                 &[Type::I64]
@@ -1496,6 +1506,7 @@ pub fn op_outputs(
         }),
         Operator::MemoryCopy { .. } => Ok(Cow::Borrowed(&[])),
         Operator::MemoryFill { .. } => Ok(Cow::Borrowed(&[])),
+        Operator::MemoryInit { .. } | Operator::DataDrop { .. } => Ok(Cow::Borrowed(&[])),
         Operator::V128Load { .. } => Ok(Cow::Borrowed(&[Type::V128])),
         Operator::V128Load8x8S { .. } => Ok(Cow::Borrowed(&[Type::I32])),
         Operator::V128Load8x8U { .. } => Ok(Cow::Borrowed(&[Type::V128])),
@@ -1630,9 +1641,9 @@ pub fn op_outputs(
         Operator::I16x8ExtendHighI8x16S => Ok(Cow::Borrowed(&[Type::V128])),
         Operator::I16x8ExtendLowI8x16U => Ok(Cow::Borrowed(&[Type::V128])),
         Operator::I16x8ExtendHighI8x16U => Ok(Cow::Borrowed(&[Type::V128])),
-        Operator::I16x8Shl => Ok(Cow::Borrowed(&[Type::V128, Type::I32])),
-        Operator::I16x8ShrS => Ok(Cow::Borrowed(&[Type::V128, Type::I32])),
-        Operator::I16x8ShrU => Ok(Cow::Borrowed(&[Type::V128, Type::I32])),
+        Operator::I16x8Shl => Ok(Cow::Borrowed(&[Type::V128])),
+        Operator::I16x8ShrS => Ok(Cow::Borrowed(&[Type::V128])),
+        Operator::I16x8ShrU => Ok(Cow::Borrowed(&[Type::V128])),
         Operator::I16x8Add => Ok(Cow::Borrowed(&[Type::V128])),
         Operator::I16x8AddSatS => Ok(Cow::Borrowed(&[Type::V128])),
         Operator::I16x8AddSatU => Ok(Cow::Borrowed(&[Type::V128])),
@@ -1659,9 +1670,9 @@ pub fn op_outputs(
         Operator::I32x4ExtendHighI16x8S => Ok(Cow::Borrowed(&[Type::V128])),
         Operator::I32x4ExtendLowI16x8U => Ok(Cow::Borrowed(&[Type::V128])),
         Operator::I32x4ExtendHighI16x8U => Ok(Cow::Borrowed(&[Type::V128])),
-        Operator::I32x4Shl => Ok(Cow::Borrowed(&[Type::V128, Type::I32])),
-        Operator::I32x4ShrS => Ok(Cow::Borrowed(&[Type::V128, Type::I32])),
-        Operator::I32x4ShrU => Ok(Cow::Borrowed(&[Type::V128, Type::I32])),
+        Operator::I32x4Shl => Ok(Cow::Borrowed(&[Type::V128])),
+        Operator::I32x4ShrS => Ok(Cow::Borrowed(&[Type::V128])),
+        Operator::I32x4ShrU => Ok(Cow::Borrowed(&[Type::V128])),
         Operator::I32x4Add => Ok(Cow::Borrowed(&[Type::V128])),
         Operator::I32x4Sub => Ok(Cow::Borrowed(&[Type::V128])),
         Operator::I32x4Mul => Ok(Cow::Borrowed(&[Type::V128])),
@@ -1682,9 +1693,9 @@ pub fn op_outputs(
         Operator::I64x2ExtendHighI32x4S => Ok(Cow::Borrowed(&[Type::V128])),
         Operator::I64x2ExtendLowI32x4U => Ok(Cow::Borrowed(&[Type::V128])),
         Operator::I64x2ExtendHighI32x4U => Ok(Cow::Borrowed(&[Type::V128])),
-        Operator::I64x2Shl => Ok(Cow::Borrowed(&[Type::V128, Type::I32])),
-        Operator::I64x2ShrS => Ok(Cow::Borrowed(&[Type::V128, Type::I32])),
-        Operator::I64x2ShrU => Ok(Cow::Borrowed(&[Type::V128, Type::I32])),
+        Operator::I64x2Shl => Ok(Cow::Borrowed(&[Type::V128])),
+        Operator::I64x2ShrS => Ok(Cow::Borrowed(&[Type::V128])),
+        Operator::I64x2ShrU => Ok(Cow::Borrowed(&[Type::V128])),
         Operator::I64x2Add => Ok(Cow::Borrowed(&[Type::V128])),
         Operator::I64x2Sub => Ok(Cow::Borrowed(&[Type::V128])),
         Operator::I64x2Mul => Ok(Cow::Borrowed(&[Type::V128])),
@@ -1916,6 +1927,27 @@ pub fn op_outputs(
         })])),
     }
 }
+/// The net change in operand-stack height from executing `op`: its
+/// number of results minus its number of operands. Arities that are
+/// known from the operator alone are used directly; otherwise this
+/// falls back to [`op_inputs`] and [`op_outputs`], which need
+/// `op_stack` for the stack-dependent operators (`select`,
+/// `ref.is_null`).
+pub fn stack_delta(
+    module: &Module,
+    op_stack: Option<&[(Type, Value)]>,
+    op: &Operator,
+) -> Result<isize> {
+    let inputs = match op.input_count() {
+        Some(n) => n,
+        None => op_inputs(module, op_stack, op)?.len(),
+    };
+    let outputs = match op.result_count() {
+        Some(n) => n,
+        None => op_outputs(module, op_stack, op)?.len(),
+    };
+    Ok(outputs as isize - inputs as isize)
+}
 /// Side-effects that an operator may have.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "rkyv-impl", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
@@ -2124,6 +2156,10 @@ impl Operator {
             Operator::MemoryGrow { .. } => &[WriteMem, Trap],
             Operator::MemoryCopy { .. } => &[Trap, ReadMem, WriteMem],
             Operator::MemoryFill { .. } => &[Trap, WriteMem],
+            // As with the array data operators, a data segment's
+            // contents are modeled as global state.
+            Operator::MemoryInit { .. } => &[Trap, WriteMem, ReadGlobal],
+            Operator::DataDrop { .. } => &[WriteGlobal],
             Operator::V128Load { .. } => &[Trap, ReadMem],
             Operator::V128Load8x8S { .. } => &[Trap, ReadMem],
             Operator::V128Load8x8U { .. } => &[Trap, ReadMem],
@@ -2455,8 +2491,8 @@ impl Operator {
             Operator::ArrayInitElem { .. } => &[WriteGlobal, ReadGlobal],
             Operator::RefEq => &[],
             Operator::RefI31 => &[],
-            Operator::I31GetS => &[],
-            Operator::I31GetU => &[],
+            Operator::I31GetS => &[Trap],
+            Operator::I31GetU => &[Trap],
             Operator::AnyConvertExtern => &[],
             Operator::ExternConvertAny => &[],
         }
@@ -2465,6 +2501,34 @@ impl Operator {
     pub fn is_pure(&self) -> bool {
         self.effects().is_empty()
     }
+    /// Is the operator commutative in its two operands, such that
+    /// swapping them never changes the result (including any trap)?
+    pub fn is_commutative(&self) -> bool {
+        match self {
+            Operator::I32Add
+            | Operator::I32Mul
+            | Operator::I32And
+            | Operator::I32Or
+            | Operator::I32Xor
+            | Operator::I32Eq
+            | Operator::I32Ne
+            | Operator::I64Add
+            | Operator::I64Mul
+            | Operator::I64And
+            | Operator::I64Or
+            | Operator::I64Xor
+            | Operator::I64Eq
+            | Operator::I64Ne
+            | Operator::F32Eq
+            | Operator::F32Ne
+            | Operator::F64Eq
+            | Operator::F64Ne
+            | Operator::V128And
+            | Operator::V128Or
+            | Operator::V128Xor => true,
+            _ => false,
+        }
+    }
     /// Is the operator a direct or indirect call?
     pub fn is_call(&self) -> bool {
         match self {
@@ -2611,6 +2675,1184 @@ impl Operator {
     pub fn can_trap(&self) -> bool {
         self.effects().contains(&SideEffect::Trap)
     }
+    /// How many inputs does this operator take, if that can be known
+    /// without a module? Returns `None` for calls, struct/array
+    /// operators and untyped `select`, whose arity or typing depends
+    /// on signatures or the operand stack; see [`op_inputs`] for those.
+    pub fn input_count(&self) -> Option<usize> {
+        match self {
+            Operator::Unreachable
+            | Operator::Nop
+            | Operator::GlobalGet { .. }
+            | Operator::I32Const { .. }
+            | Operator::I64Const { .. }
+            | Operator::F32Const { .. }
+            | Operator::F64Const { .. }
+            | Operator::TableSize { .. }
+            | Operator::MemorySize { .. }
+            | Operator::V128Const { .. }
+            | Operator::RefNull { .. }
+            | Operator::RefFunc { .. }
+            | Operator::DataDrop { .. }
+            | Operator::AtomicFence => Some(0),
+            Operator::Call { .. }
+            | Operator::CallIndirect { .. }
+            | Operator::Select
+            | Operator::CallRef { .. }
+            | Operator::StructNew { .. }
+            | Operator::StructGet { .. }
+            | Operator::StructSet { .. }
+            | Operator::ArrayNew { .. }
+            | Operator::ArrayNewFixed { .. }
+            | Operator::ArrayGet { .. }
+            | Operator::ArraySet { .. }
+            | Operator::ArrayFill { .. }
+            | Operator::ArrayCopy { .. }
+            | Operator::ArrayLen
+            | Operator::StructNewDefault { .. }
+            | Operator::StructGetS { .. }
+            | Operator::StructGetU { .. }
+            | Operator::ArrayNewDefault { .. }
+            | Operator::ArrayNewData { .. }
+            | Operator::ArrayNewElem { .. }
+            | Operator::ArrayGetS { .. }
+            | Operator::ArrayGetU { .. }
+            | Operator::ArrayInitData { .. }
+            | Operator::ArrayInitElem { .. } => None,
+            Operator::TypedSelect { .. }
+            | Operator::V128Bitselect
+            | Operator::MemoryCopy { .. }
+            | Operator::MemoryFill { .. }
+            | Operator::MemoryInit { .. }
+            | Operator::MemoryAtomicWait32 { .. }
+            | Operator::MemoryAtomicWait64 { .. }
+            | Operator::I32AtomicRmwCmpxchg { .. }
+            | Operator::I64AtomicRmwCmpxchg { .. }
+            | Operator::I32AtomicRmw8CmpxchgU { .. }
+            | Operator::I32AtomicRmw16CmpxchgU { .. }
+            | Operator::I64AtomicRmw8CmpxchgU { .. }
+            | Operator::I64AtomicRmw16CmpxchgU { .. }
+            | Operator::I64AtomicRmw32CmpxchgU { .. } => Some(3),
+            Operator::GlobalSet { .. }
+            | Operator::I32Load { .. }
+            | Operator::I64Load { .. }
+            | Operator::F32Load { .. }
+            | Operator::F64Load { .. }
+            | Operator::I32Load8S { .. }
+            | Operator::I32Load8U { .. }
+            | Operator::I32Load16S { .. }
+            | Operator::I32Load16U { .. }
+            | Operator::I64Load8S { .. }
+            | Operator::I64Load8U { .. }
+            | Operator::I64Load16S { .. }
+            | Operator::I64Load16U { .. }
+            | Operator::I64Load32S { .. }
+            | Operator::I64Load32U { .. }
+            | Operator::I32Eqz
+            | Operator::I64Eqz
+            | Operator::I32Clz
+            | Operator::I32Ctz
+            | Operator::I32Popcnt
+            | Operator::I64Clz
+            | Operator::I64Ctz
+            | Operator::I64Popcnt
+            | Operator::F32Abs
+            | Operator::F32Neg
+            | Operator::F32Ceil
+            | Operator::F32Floor
+            | Operator::F32Trunc
+            | Operator::F32Nearest
+            | Operator::F32Sqrt
+            | Operator::F64Abs
+            | Operator::F64Neg
+            | Operator::F64Ceil
+            | Operator::F64Floor
+            | Operator::F64Trunc
+            | Operator::F64Nearest
+            | Operator::F64Sqrt
+            | Operator::I32WrapI64
+            | Operator::I32TruncF32S
+            | Operator::I32TruncF32U
+            | Operator::I32TruncF64S
+            | Operator::I32TruncF64U
+            | Operator::I64ExtendI32S
+            | Operator::I64ExtendI32U
+            | Operator::I64TruncF32S
+            | Operator::I64TruncF32U
+            | Operator::I64TruncF64S
+            | Operator::I64TruncF64U
+            | Operator::F32ConvertI32S
+            | Operator::F32ConvertI32U
+            | Operator::F32ConvertI64S
+            | Operator::F32ConvertI64U
+            | Operator::F32DemoteF64
+            | Operator::F64ConvertI32S
+            | Operator::F64ConvertI32U
+            | Operator::F64ConvertI64S
+            | Operator::F64ConvertI64U
+            | Operator::F64PromoteF32
+            | Operator::I32Extend8S
+            | Operator::I32Extend16S
+            | Operator::I64Extend8S
+            | Operator::I64Extend16S
+            | Operator::I64Extend32S
+            | Operator::I32TruncSatF32S
+            | Operator::I32TruncSatF32U
+            | Operator::I32TruncSatF64S
+            | Operator::I32TruncSatF64U
+            | Operator::I64TruncSatF32S
+            | Operator::I64TruncSatF32U
+            | Operator::I64TruncSatF64S
+            | Operator::I64TruncSatF64U
+            | Operator::F32ReinterpretI32
+            | Operator::F64ReinterpretI64
+            | Operator::I32ReinterpretF32
+            | Operator::I64ReinterpretF64
+            | Operator::TableGet { .. }
+            | Operator::MemoryGrow { .. }
+            | Operator::V128Load { .. }
+            | Operator::V128Load8x8S { .. }
+            | Operator::V128Load8x8U { .. }
+            | Operator::V128Load16x4S { .. }
+            | Operator::V128Load16x4U { .. }
+            | Operator::V128Load32x2S { .. }
+            | Operator::V128Load32x2U { .. }
+            | Operator::V128Load8Splat { .. }
+            | Operator::V128Load16Splat { .. }
+            | Operator::V128Load32Splat { .. }
+            | Operator::V128Load64Splat { .. }
+            | Operator::V128Load32Zero { .. }
+            | Operator::V128Load64Zero { .. }
+            | Operator::I8x16ExtractLaneS { .. }
+            | Operator::I8x16ExtractLaneU { .. }
+            | Operator::I16x8ExtractLaneS { .. }
+            | Operator::I16x8ExtractLaneU { .. }
+            | Operator::I32x4ExtractLane { .. }
+            | Operator::I64x2ExtractLane { .. }
+            | Operator::F32x4ExtractLane { .. }
+            | Operator::F64x2ExtractLane { .. }
+            | Operator::I8x16Splat
+            | Operator::I16x8Splat
+            | Operator::I32x4Splat
+            | Operator::I64x2Splat
+            | Operator::F32x4Splat
+            | Operator::F64x2Splat
+            | Operator::V128Not
+            | Operator::V128AnyTrue
+            | Operator::I8x16Abs
+            | Operator::I8x16Neg
+            | Operator::I8x16Popcnt
+            | Operator::I8x16AllTrue
+            | Operator::I8x16Bitmask
+            | Operator::I16x8ExtAddPairwiseI8x16S
+            | Operator::I16x8ExtAddPairwiseI8x16U
+            | Operator::I16x8Abs
+            | Operator::I16x8Neg
+            | Operator::I16x8AllTrue
+            | Operator::I16x8Bitmask
+            | Operator::I16x8ExtendLowI8x16S
+            | Operator::I16x8ExtendHighI8x16S
+            | Operator::I16x8ExtendLowI8x16U
+            | Operator::I16x8ExtendHighI8x16U
+            | Operator::I32x4ExtAddPairwiseI16x8S
+            | Operator::I32x4ExtAddPairwiseI16x8U
+            | Operator::I32x4Abs
+            | Operator::I32x4Neg
+            | Operator::I32x4AllTrue
+            | Operator::I32x4Bitmask
+            | Operator::I32x4ExtendLowI16x8S
+            | Operator::I32x4ExtendHighI16x8S
+            | Operator::I32x4ExtendLowI16x8U
+            | Operator::I32x4ExtendHighI16x8U
+            | Operator::I64x2Abs
+            | Operator::I64x2Neg
+            | Operator::I64x2AllTrue
+            | Operator::I64x2Bitmask
+            | Operator::I64x2ExtendLowI32x4S
+            | Operator::I64x2ExtendHighI32x4S
+            | Operator::I64x2ExtendLowI32x4U
+            | Operator::I64x2ExtendHighI32x4U
+            | Operator::F32x4Ceil
+            | Operator::F32x4Floor
+            | Operator::F32x4Trunc
+            | Operator::F32x4Nearest
+            | Operator::F32x4Abs
+            | Operator::F32x4Neg
+            | Operator::F32x4Sqrt
+            | Operator::F64x2Ceil
+            | Operator::F64x2Floor
+            | Operator::F64x2Trunc
+            | Operator::F64x2Nearest
+            | Operator::F64x2Abs
+            | Operator::F64x2Neg
+            | Operator::F64x2Sqrt
+            | Operator::I32x4TruncSatF32x4S
+            | Operator::I32x4TruncSatF32x4U
+            | Operator::F32x4ConvertI32x4S
+            | Operator::F32x4ConvertI32x4U
+            | Operator::I32x4TruncSatF64x2SZero
+            | Operator::I32x4TruncSatF64x2UZero
+            | Operator::F64x2ConvertLowI32x4S
+            | Operator::F64x2ConvertLowI32x4U
+            | Operator::F32x4DemoteF64x2Zero
+            | Operator::F64x2PromoteLowF32x4
+            | Operator::RefIsNull
+            | Operator::I32AtomicLoad { .. }
+            | Operator::I64AtomicLoad { .. }
+            | Operator::I32AtomicLoad8U { .. }
+            | Operator::I32AtomicLoad16U { .. }
+            | Operator::I64AtomicLoad8U { .. }
+            | Operator::I64AtomicLoad16U { .. }
+            | Operator::I64AtomicLoad32U { .. }
+            | Operator::RefTest { .. }
+            | Operator::RefCast { .. }
+            | Operator::RefI31
+            | Operator::I31GetS
+            | Operator::I31GetU
+            | Operator::AnyConvertExtern
+            | Operator::ExternConvertAny => Some(1),
+            Operator::I32Store { .. }
+            | Operator::I64Store { .. }
+            | Operator::F32Store { .. }
+            | Operator::F64Store { .. }
+            | Operator::I32Store8 { .. }
+            | Operator::I32Store16 { .. }
+            | Operator::I64Store8 { .. }
+            | Operator::I64Store16 { .. }
+            | Operator::I64Store32 { .. }
+            | Operator::I32Eq
+            | Operator::I32Ne
+            | Operator::I32LtS
+            | Operator::I32LtU
+            | Operator::I32GtS
+            | Operator::I32GtU
+            | Operator::I32LeS
+            | Operator::I32LeU
+            | Operator::I32GeS
+            | Operator::I32GeU
+            | Operator::I64Eq
+            | Operator::I64Ne
+            | Operator::I64LtS
+            | Operator::I64LtU
+            | Operator::I64GtU
+            | Operator::I64GtS
+            | Operator::I64LeS
+            | Operator::I64LeU
+            | Operator::I64GeS
+            | Operator::I64GeU
+            | Operator::F32Eq
+            | Operator::F32Ne
+            | Operator::F32Lt
+            | Operator::F32Gt
+            | Operator::F32Le
+            | Operator::F32Ge
+            | Operator::F64Eq
+            | Operator::F64Ne
+            | Operator::F64Lt
+            | Operator::F64Gt
+            | Operator::F64Le
+            | Operator::F64Ge
+            | Operator::I32Add
+            | Operator::I32Sub
+            | Operator::I32Mul
+            | Operator::I32DivS
+            | Operator::I32DivU
+            | Operator::I32RemS
+            | Operator::I32RemU
+            | Operator::I32And
+            | Operator::I32Or
+            | Operator::I32Xor
+            | Operator::I32Shl
+            | Operator::I32ShrS
+            | Operator::I32ShrU
+            | Operator::I32Rotl
+            | Operator::I32Rotr
+            | Operator::I64Add
+            | Operator::I64Sub
+            | Operator::I64Mul
+            | Operator::I64DivS
+            | Operator::I64DivU
+            | Operator::I64RemS
+            | Operator::I64RemU
+            | Operator::I64And
+            | Operator::I64Or
+            | Operator::I64Xor
+            | Operator::I64Shl
+            | Operator::I64ShrS
+            | Operator::I64ShrU
+            | Operator::I64Rotl
+            | Operator::I64Rotr
+            | Operator::F32Add
+            | Operator::F32Sub
+            | Operator::F32Mul
+            | Operator::F32Div
+            | Operator::F32Min
+            | Operator::F32Max
+            | Operator::F32Copysign
+            | Operator::F64Add
+            | Operator::F64Sub
+            | Operator::F64Mul
+            | Operator::F64Div
+            | Operator::F64Min
+            | Operator::F64Max
+            | Operator::F64Copysign
+            | Operator::TableSet { .. }
+            | Operator::TableGrow { .. }
+            | Operator::V128Store { .. }
+            | Operator::V128Load8Lane { .. }
+            | Operator::V128Load16Lane { .. }
+            | Operator::V128Load32Lane { .. }
+            | Operator::V128Load64Lane { .. }
+            | Operator::V128Store8Lane { .. }
+            | Operator::V128Store16Lane { .. }
+            | Operator::V128Store32Lane { .. }
+            | Operator::V128Store64Lane { .. }
+            | Operator::I8x16Shuffle { .. }
+            | Operator::I8x16ReplaceLane { .. }
+            | Operator::I16x8ReplaceLane { .. }
+            | Operator::I32x4ReplaceLane { .. }
+            | Operator::I64x2ReplaceLane { .. }
+            | Operator::F32x4ReplaceLane { .. }
+            | Operator::F64x2ReplaceLane { .. }
+            | Operator::I8x16Swizzle
+            | Operator::I8x16Eq
+            | Operator::I8x16Ne
+            | Operator::I8x16LtS
+            | Operator::I8x16LtU
+            | Operator::I8x16GtS
+            | Operator::I8x16GtU
+            | Operator::I8x16LeS
+            | Operator::I8x16LeU
+            | Operator::I8x16GeS
+            | Operator::I8x16GeU
+            | Operator::I16x8Eq
+            | Operator::I16x8Ne
+            | Operator::I16x8LtS
+            | Operator::I16x8LtU
+            | Operator::I16x8GtS
+            | Operator::I16x8GtU
+            | Operator::I16x8LeS
+            | Operator::I16x8LeU
+            | Operator::I16x8GeS
+            | Operator::I16x8GeU
+            | Operator::I32x4Eq
+            | Operator::I32x4Ne
+            | Operator::I32x4LtS
+            | Operator::I32x4LtU
+            | Operator::I32x4GtS
+            | Operator::I32x4GtU
+            | Operator::I32x4LeS
+            | Operator::I32x4LeU
+            | Operator::I32x4GeS
+            | Operator::I32x4GeU
+            | Operator::I64x2Eq
+            | Operator::I64x2Ne
+            | Operator::I64x2LtS
+            | Operator::I64x2GtS
+            | Operator::I64x2LeS
+            | Operator::I64x2GeS
+            | Operator::F32x4Eq
+            | Operator::F32x4Ne
+            | Operator::F32x4Lt
+            | Operator::F32x4Gt
+            | Operator::F32x4Le
+            | Operator::F32x4Ge
+            | Operator::F64x2Eq
+            | Operator::F64x2Ne
+            | Operator::F64x2Lt
+            | Operator::F64x2Gt
+            | Operator::F64x2Le
+            | Operator::F64x2Ge
+            | Operator::V128And
+            | Operator::V128AndNot
+            | Operator::V128Or
+            | Operator::V128Xor
+            | Operator::I8x16NarrowI16x8S
+            | Operator::I8x16NarrowI16x8U
+            | Operator::I8x16Shl
+            | Operator::I8x16ShrS
+            | Operator::I8x16ShrU
+            | Operator::I8x16Add
+            | Operator::I8x16AddSatS
+            | Operator::I8x16AddSatU
+            | Operator::I8x16Sub
+            | Operator::I8x16SubSatS
+            | Operator::I8x16SubSatU
+            | Operator::I8x16MinS
+            | Operator::I8x16MinU
+            | Operator::I8x16MaxS
+            | Operator::I8x16MaxU
+            | Operator::I8x16AvgrU
+            | Operator::I16x8Q15MulrSatS
+            | Operator::I16x8NarrowI32x4S
+            | Operator::I16x8NarrowI32x4U
+            | Operator::I16x8Shl
+            | Operator::I16x8ShrS
+            | Operator::I16x8ShrU
+            | Operator::I16x8Add
+            | Operator::I16x8AddSatS
+            | Operator::I16x8AddSatU
+            | Operator::I16x8Sub
+            | Operator::I16x8SubSatS
+            | Operator::I16x8SubSatU
+            | Operator::I16x8Mul
+            | Operator::I16x8MinS
+            | Operator::I16x8MinU
+            | Operator::I16x8MaxS
+            | Operator::I16x8MaxU
+            | Operator::I16x8AvgrU
+            | Operator::I16x8ExtMulLowI8x16S
+            | Operator::I16x8ExtMulHighI8x16S
+            | Operator::I16x8ExtMulLowI8x16U
+            | Operator::I16x8ExtMulHighI8x16U
+            | Operator::I32x4Shl
+            | Operator::I32x4ShrS
+            | Operator::I32x4ShrU
+            | Operator::I32x4Add
+            | Operator::I32x4Sub
+            | Operator::I32x4Mul
+            | Operator::I32x4MinS
+            | Operator::I32x4MinU
+            | Operator::I32x4MaxS
+            | Operator::I32x4MaxU
+            | Operator::I32x4DotI16x8S
+            | Operator::I32x4ExtMulLowI16x8S
+            | Operator::I32x4ExtMulHighI16x8S
+            | Operator::I32x4ExtMulLowI16x8U
+            | Operator::I32x4ExtMulHighI16x8U
+            | Operator::I64x2Shl
+            | Operator::I64x2ShrS
+            | Operator::I64x2ShrU
+            | Operator::I64x2Add
+            | Operator::I64x2Sub
+            | Operator::I64x2Mul
+            | Operator::I64x2ExtMulLowI32x4S
+            | Operator::I64x2ExtMulHighI32x4S
+            | Operator::I64x2ExtMulLowI32x4U
+            | Operator::I64x2ExtMulHighI32x4U
+            | Operator::F32x4Add
+            | Operator::F32x4Sub
+            | Operator::F32x4Mul
+            | Operator::F32x4Div
+            | Operator::F32x4Min
+            | Operator::F32x4Max
+            | Operator::F32x4PMin
+            | Operator::F32x4PMax
+            | Operator::F64x2Add
+            | Operator::F64x2Sub
+            | Operator::F64x2Mul
+            | Operator::F64x2Div
+            | Operator::F64x2Min
+            | Operator::F64x2Max
+            | Operator::F64x2PMin
+            | Operator::F64x2PMax
+            | Operator::MemoryAtomicNotify { .. }
+            | Operator::I32AtomicStore { .. }
+            | Operator::I64AtomicStore { .. }
+            | Operator::I32AtomicStore8 { .. }
+            | Operator::I32AtomicStore16 { .. }
+            | Operator::I64AtomicStore8 { .. }
+            | Operator::I64AtomicStore16 { .. }
+            | Operator::I64AtomicStore32 { .. }
+            | Operator::I32AtomicRmwAdd { .. }
+            | Operator::I64AtomicRmwAdd { .. }
+            | Operator::I32AtomicRmw8AddU { .. }
+            | Operator::I32AtomicRmw16AddU { .. }
+            | Operator::I64AtomicRmw8AddU { .. }
+            | Operator::I64AtomicRmw16AddU { .. }
+            | Operator::I64AtomicRmw32AddU { .. }
+            | Operator::I32AtomicRmwSub { .. }
+            | Operator::I64AtomicRmwSub { .. }
+            | Operator::I32AtomicRmw8SubU { .. }
+            | Operator::I32AtomicRmw16SubU { .. }
+            | Operator::I64AtomicRmw8SubU { .. }
+            | Operator::I64AtomicRmw16SubU { .. }
+            | Operator::I64AtomicRmw32SubU { .. }
+            | Operator::I32AtomicRmwAnd { .. }
+            | Operator::I64AtomicRmwAnd { .. }
+            | Operator::I32AtomicRmw8AndU { .. }
+            | Operator::I32AtomicRmw16AndU { .. }
+            | Operator::I64AtomicRmw8AndU { .. }
+            | Operator::I64AtomicRmw16AndU { .. }
+            | Operator::I64AtomicRmw32AndU { .. }
+            | Operator::I32AtomicRmwOr { .. }
+            | Operator::I64AtomicRmwOr { .. }
+            | Operator::I32AtomicRmw8OrU { .. }
+            | Operator::I32AtomicRmw16OrU { .. }
+            | Operator::I64AtomicRmw8OrU { .. }
+            | Operator::I64AtomicRmw16OrU { .. }
+            | Operator::I64AtomicRmw32OrU { .. }
+            | Operator::I32AtomicRmwXor { .. }
+            | Operator::I64AtomicRmwXor { .. }
+            | Operator::I32AtomicRmw8XorU { .. }
+            | Operator::I32AtomicRmw16XorU { .. }
+            | Operator::I64AtomicRmw8XorU { .. }
+            | Operator::I64AtomicRmw16XorU { .. }
+            | Operator::I64AtomicRmw32XorU { .. }
+            | Operator::I32AtomicRmwXchg { .. }
+            | Operator::I64AtomicRmwXchg { .. }
+            | Operator::I32AtomicRmw8XchgU { .. }
+            | Operator::I32AtomicRmw16XchgU { .. }
+            | Operator::I64AtomicRmw8XchgU { .. }
+            | Operator::I64AtomicRmw16XchgU { .. }
+            | Operator::I64AtomicRmw32XchgU { .. }
+            | Operator::RefEq => Some(2),
+        }
+    }
+    /// How many results does this operator produce, if that can be
+    /// known without a module? Returns `None` in the same cases as
+    /// [`Operator::input_count`]; see [`op_outputs`] for those.
+    pub fn result_count(&self) -> Option<usize> {
+        match self {
+            Operator::Unreachable
+            | Operator::Nop
+            | Operator::GlobalSet { .. }
+            | Operator::I32Store { .. }
+            | Operator::I64Store { .. }
+            | Operator::F32Store { .. }
+            | Operator::F64Store { .. }
+            | Operator::I32Store8 { .. }
+            | Operator::I32Store16 { .. }
+            | Operator::I64Store8 { .. }
+            | Operator::I64Store16 { .. }
+            | Operator::I64Store32 { .. }
+            | Operator::I32AtomicStore { .. }
+            | Operator::I64AtomicStore { .. }
+            | Operator::I32AtomicStore8 { .. }
+            | Operator::I32AtomicStore16 { .. }
+            | Operator::I64AtomicStore8 { .. }
+            | Operator::I64AtomicStore16 { .. }
+            | Operator::I64AtomicStore32 { .. }
+            | Operator::TableSet { .. }
+            | Operator::MemoryCopy { .. }
+            | Operator::MemoryFill { .. }
+            | Operator::MemoryInit { .. }
+            | Operator::DataDrop { .. }
+            | Operator::V128Store { .. }
+            | Operator::V128Store8Lane { .. }
+            | Operator::V128Store16Lane { .. }
+            | Operator::V128Store32Lane { .. }
+            | Operator::V128Store64Lane { .. }
+            | Operator::AtomicFence => Some(0),
+            Operator::Call { .. }
+            | Operator::CallIndirect { .. }
+            | Operator::Select
+            | Operator::CallRef { .. }
+            | Operator::StructNew { .. }
+            | Operator::StructGet { .. }
+            | Operator::StructSet { .. }
+            | Operator::ArrayNew { .. }
+            | Operator::ArrayNewFixed { .. }
+            | Operator::ArrayGet { .. }
+            | Operator::ArraySet { .. }
+            | Operator::ArrayFill { .. }
+            | Operator::ArrayCopy { .. }
+            | Operator::ArrayLen
+            | Operator::StructNewDefault { .. }
+            | Operator::StructGetS { .. }
+            | Operator::StructGetU { .. }
+            | Operator::ArrayNewDefault { .. }
+            | Operator::ArrayNewData { .. }
+            | Operator::ArrayNewElem { .. }
+            | Operator::ArrayGetS { .. }
+            | Operator::ArrayGetU { .. }
+            | Operator::ArrayInitData { .. }
+            | Operator::ArrayInitElem { .. } => None,
+            Operator::TypedSelect { .. }
+            | Operator::GlobalGet { .. }
+            | Operator::I32Load { .. }
+            | Operator::I32Load8S { .. }
+            | Operator::I32Load8U { .. }
+            | Operator::I32Load16S { .. }
+            | Operator::I32Load16U { .. }
+            | Operator::I64Load { .. }
+            | Operator::I64Load8S { .. }
+            | Operator::I64Load8U { .. }
+            | Operator::I64Load16S { .. }
+            | Operator::I64Load16U { .. }
+            | Operator::I64Load32S { .. }
+            | Operator::I64Load32U { .. }
+            | Operator::I32AtomicLoad { .. }
+            | Operator::I32AtomicLoad8U { .. }
+            | Operator::I32AtomicLoad16U { .. }
+            | Operator::I64AtomicLoad { .. }
+            | Operator::I64AtomicLoad8U { .. }
+            | Operator::I64AtomicLoad16U { .. }
+            | Operator::I64AtomicLoad32U { .. }
+            | Operator::F32Load { .. }
+            | Operator::F64Load { .. }
+            | Operator::I32Const { .. }
+            | Operator::I64Const { .. }
+            | Operator::F32Const { .. }
+            | Operator::F64Const { .. }
+            | Operator::I32Eqz
+            | Operator::I32Eq
+            | Operator::I32Ne
+            | Operator::I32LtS
+            | Operator::I32LtU
+            | Operator::I32GtS
+            | Operator::I32GtU
+            | Operator::I32LeS
+            | Operator::I32LeU
+            | Operator::I32GeS
+            | Operator::I32GeU
+            | Operator::I64Eqz
+            | Operator::I64Eq
+            | Operator::I64Ne
+            | Operator::I64LtS
+            | Operator::I64LtU
+            | Operator::I64GtU
+            | Operator::I64GtS
+            | Operator::I64LeS
+            | Operator::I64LeU
+            | Operator::I64GeS
+            | Operator::I64GeU
+            | Operator::F32Eq
+            | Operator::F32Ne
+            | Operator::F32Lt
+            | Operator::F32Gt
+            | Operator::F32Le
+            | Operator::F32Ge
+            | Operator::F64Eq
+            | Operator::F64Ne
+            | Operator::F64Lt
+            | Operator::F64Gt
+            | Operator::F64Le
+            | Operator::F64Ge
+            | Operator::I32Clz
+            | Operator::I32Ctz
+            | Operator::I32Popcnt
+            | Operator::I32Add
+            | Operator::I32Sub
+            | Operator::I32Mul
+            | Operator::I32DivS
+            | Operator::I32DivU
+            | Operator::I32RemS
+            | Operator::I32RemU
+            | Operator::I32And
+            | Operator::I32Or
+            | Operator::I32Xor
+            | Operator::I32Shl
+            | Operator::I32ShrS
+            | Operator::I32ShrU
+            | Operator::I32Rotl
+            | Operator::I32Rotr
+            | Operator::I64Clz
+            | Operator::I64Ctz
+            | Operator::I64Popcnt
+            | Operator::I64Add
+            | Operator::I64Sub
+            | Operator::I64Mul
+            | Operator::I64DivS
+            | Operator::I64DivU
+            | Operator::I64RemS
+            | Operator::I64RemU
+            | Operator::I64And
+            | Operator::I64Or
+            | Operator::I64Xor
+            | Operator::I64Shl
+            | Operator::I64ShrS
+            | Operator::I64ShrU
+            | Operator::I64Rotl
+            | Operator::I64Rotr
+            | Operator::F32Abs
+            | Operator::F32Neg
+            | Operator::F32Ceil
+            | Operator::F32Floor
+            | Operator::F32Trunc
+            | Operator::F32Nearest
+            | Operator::F32Sqrt
+            | Operator::F32Add
+            | Operator::F32Sub
+            | Operator::F32Mul
+            | Operator::F32Div
+            | Operator::F32Min
+            | Operator::F32Max
+            | Operator::F32Copysign
+            | Operator::F64Abs
+            | Operator::F64Neg
+            | Operator::F64Ceil
+            | Operator::F64Floor
+            | Operator::F64Trunc
+            | Operator::F64Nearest
+            | Operator::F64Sqrt
+            | Operator::F64Add
+            | Operator::F64Sub
+            | Operator::F64Mul
+            | Operator::F64Div
+            | Operator::F64Min
+            | Operator::F64Max
+            | Operator::F64Copysign
+            | Operator::I32WrapI64
+            | Operator::I32TruncF32S
+            | Operator::I32TruncF32U
+            | Operator::I32TruncF64S
+            | Operator::I32TruncF64U
+            | Operator::I64ExtendI32S
+            | Operator::I64ExtendI32U
+            | Operator::I64TruncF32S
+            | Operator::I64TruncF32U
+            | Operator::I64TruncF64S
+            | Operator::I64TruncF64U
+            | Operator::F32ConvertI32S
+            | Operator::F32ConvertI32U
+            | Operator::F32ConvertI64S
+            | Operator::F32ConvertI64U
+            | Operator::F32DemoteF64
+            | Operator::F64ConvertI32S
+            | Operator::F64ConvertI32U
+            | Operator::F64ConvertI64S
+            | Operator::F64ConvertI64U
+            | Operator::F64PromoteF32
+            | Operator::I32Extend8S
+            | Operator::I32Extend16S
+            | Operator::I64Extend8S
+            | Operator::I64Extend16S
+            | Operator::I64Extend32S
+            | Operator::I32TruncSatF32S
+            | Operator::I32TruncSatF32U
+            | Operator::I32TruncSatF64S
+            | Operator::I32TruncSatF64U
+            | Operator::I64TruncSatF32S
+            | Operator::I64TruncSatF32U
+            | Operator::I64TruncSatF64S
+            | Operator::I64TruncSatF64U
+            | Operator::F32ReinterpretI32
+            | Operator::F64ReinterpretI64
+            | Operator::I32ReinterpretF32
+            | Operator::I64ReinterpretF64
+            | Operator::TableGet { .. }
+            | Operator::TableGrow { .. }
+            | Operator::TableSize { .. }
+            | Operator::MemorySize { .. }
+            | Operator::MemoryGrow { .. }
+            | Operator::V128Load { .. }
+            | Operator::V128Load8x8S { .. }
+            | Operator::V128Load8x8U { .. }
+            | Operator::V128Load16x4S { .. }
+            | Operator::V128Load16x4U { .. }
+            | Operator::V128Load32x2S { .. }
+            | Operator::V128Load32x2U { .. }
+            | Operator::V128Load8Splat { .. }
+            | Operator::V128Load16Splat { .. }
+            | Operator::V128Load32Splat { .. }
+            | Operator::V128Load64Splat { .. }
+            | Operator::V128Load32Zero { .. }
+            | Operator::V128Load64Zero { .. }
+            | Operator::V128Load8Lane { .. }
+            | Operator::V128Load16Lane { .. }
+            | Operator::V128Load32Lane { .. }
+            | Operator::V128Load64Lane { .. }
+            | Operator::V128Const { .. }
+            | Operator::I8x16Shuffle { .. }
+            | Operator::I8x16ExtractLaneS { .. }
+            | Operator::I8x16ExtractLaneU { .. }
+            | Operator::I8x16ReplaceLane { .. }
+            | Operator::I16x8ExtractLaneS { .. }
+            | Operator::I16x8ExtractLaneU { .. }
+            | Operator::I16x8ReplaceLane { .. }
+            | Operator::I32x4ExtractLane { .. }
+            | Operator::I32x4ReplaceLane { .. }
+            | Operator::I64x2ExtractLane { .. }
+            | Operator::I64x2ReplaceLane { .. }
+            | Operator::F32x4ExtractLane { .. }
+            | Operator::F32x4ReplaceLane { .. }
+            | Operator::F64x2ExtractLane { .. }
+            | Operator::F64x2ReplaceLane { .. }
+            | Operator::I8x16Swizzle
+            | Operator::I8x16Splat
+            | Operator::I16x8Splat
+            | Operator::I32x4Splat
+            | Operator::I64x2Splat
+            | Operator::F32x4Splat
+            | Operator::F64x2Splat
+            | Operator::I8x16Eq
+            | Operator::I8x16Ne
+            | Operator::I8x16LtS
+            | Operator::I8x16LtU
+            | Operator::I8x16GtS
+            | Operator::I8x16GtU
+            | Operator::I8x16LeS
+            | Operator::I8x16LeU
+            | Operator::I8x16GeS
+            | Operator::I8x16GeU
+            | Operator::I16x8Eq
+            | Operator::I16x8Ne
+            | Operator::I16x8LtS
+            | Operator::I16x8LtU
+            | Operator::I16x8GtS
+            | Operator::I16x8GtU
+            | Operator::I16x8LeS
+            | Operator::I16x8LeU
+            | Operator::I16x8GeS
+            | Operator::I16x8GeU
+            | Operator::I32x4Eq
+            | Operator::I32x4Ne
+            | Operator::I32x4LtS
+            | Operator::I32x4LtU
+            | Operator::I32x4GtS
+            | Operator::I32x4GtU
+            | Operator::I32x4LeS
+            | Operator::I32x4LeU
+            | Operator::I32x4GeS
+            | Operator::I32x4GeU
+            | Operator::I64x2Eq
+            | Operator::I64x2Ne
+            | Operator::I64x2LtS
+            | Operator::I64x2GtS
+            | Operator::I64x2LeS
+            | Operator::I64x2GeS
+            | Operator::F32x4Eq
+            | Operator::F32x4Ne
+            | Operator::F32x4Lt
+            | Operator::F32x4Gt
+            | Operator::F32x4Le
+            | Operator::F32x4Ge
+            | Operator::F64x2Eq
+            | Operator::F64x2Ne
+            | Operator::F64x2Lt
+            | Operator::F64x2Gt
+            | Operator::F64x2Le
+            | Operator::F64x2Ge
+            | Operator::V128Not
+            | Operator::V128And
+            | Operator::V128AndNot
+            | Operator::V128Or
+            | Operator::V128Xor
+            | Operator::V128Bitselect
+            | Operator::V128AnyTrue
+            | Operator::I8x16Abs
+            | Operator::I8x16Neg
+            | Operator::I8x16Popcnt
+            | Operator::I8x16AllTrue
+            | Operator::I8x16Bitmask
+            | Operator::I8x16NarrowI16x8S
+            | Operator::I8x16NarrowI16x8U
+            | Operator::I8x16Shl
+            | Operator::I8x16ShrS
+            | Operator::I8x16ShrU
+            | Operator::I8x16Add
+            | Operator::I8x16AddSatS
+            | Operator::I8x16AddSatU
+            | Operator::I8x16Sub
+            | Operator::I8x16SubSatS
+            | Operator::I8x16SubSatU
+            | Operator::I8x16MinS
+            | Operator::I8x16MinU
+            | Operator::I8x16MaxS
+            | Operator::I8x16MaxU
+            | Operator::I8x16AvgrU
+            | Operator::I16x8ExtAddPairwiseI8x16S
+            | Operator::I16x8ExtAddPairwiseI8x16U
+            | Operator::I16x8Abs
+            | Operator::I16x8Neg
+            | Operator::I16x8Q15MulrSatS
+            | Operator::I16x8AllTrue
+            | Operator::I16x8Bitmask
+            | Operator::I16x8NarrowI32x4S
+            | Operator::I16x8NarrowI32x4U
+            | Operator::I16x8ExtendLowI8x16S
+            | Operator::I16x8ExtendHighI8x16S
+            | Operator::I16x8ExtendLowI8x16U
+            | Operator::I16x8ExtendHighI8x16U
+            | Operator::I16x8Add
+            | Operator::I16x8AddSatS
+            | Operator::I16x8AddSatU
+            | Operator::I16x8Sub
+            | Operator::I16x8SubSatS
+            | Operator::I16x8SubSatU
+            | Operator::I16x8Mul
+            | Operator::I16x8MinS
+            | Operator::I16x8MinU
+            | Operator::I16x8MaxS
+            | Operator::I16x8MaxU
+            | Operator::I16x8AvgrU
+            | Operator::I16x8ExtMulLowI8x16S
+            | Operator::I16x8ExtMulHighI8x16S
+            | Operator::I16x8ExtMulLowI8x16U
+            | Operator::I16x8ExtMulHighI8x16U
+            | Operator::I32x4ExtAddPairwiseI16x8S
+            | Operator::I32x4ExtAddPairwiseI16x8U
+            | Operator::I32x4Abs
+            | Operator::I32x4Neg
+            | Operator::I32x4AllTrue
+            | Operator::I32x4Bitmask
+            | Operator::I32x4ExtendLowI16x8S
+            | Operator::I32x4ExtendHighI16x8S
+            | Operator::I32x4ExtendLowI16x8U
+            | Operator::I32x4ExtendHighI16x8U
+            | Operator::I32x4Add
+            | Operator::I32x4Sub
+            | Operator::I32x4Mul
+            | Operator::I32x4MinS
+            | Operator::I32x4MinU
+            | Operator::I32x4MaxS
+            | Operator::I32x4MaxU
+            | Operator::I32x4DotI16x8S
+            | Operator::I32x4ExtMulLowI16x8S
+            | Operator::I32x4ExtMulHighI16x8S
+            | Operator::I32x4ExtMulLowI16x8U
+            | Operator::I32x4ExtMulHighI16x8U
+            | Operator::I64x2Abs
+            | Operator::I64x2Neg
+            | Operator::I64x2AllTrue
+            | Operator::I64x2Bitmask
+            | Operator::I64x2ExtendLowI32x4S
+            | Operator::I64x2ExtendHighI32x4S
+            | Operator::I64x2ExtendLowI32x4U
+            | Operator::I64x2ExtendHighI32x4U
+            | Operator::I64x2Add
+            | Operator::I64x2Sub
+            | Operator::I64x2Mul
+            | Operator::I64x2ExtMulLowI32x4S
+            | Operator::I64x2ExtMulHighI32x4S
+            | Operator::I64x2ExtMulLowI32x4U
+            | Operator::I64x2ExtMulHighI32x4U
+            | Operator::F32x4Ceil
+            | Operator::F32x4Floor
+            | Operator::F32x4Trunc
+            | Operator::F32x4Nearest
+            | Operator::F32x4Abs
+            | Operator::F32x4Neg
+            | Operator::F32x4Sqrt
+            | Operator::F32x4Add
+            | Operator::F32x4Sub
+            | Operator::F32x4Mul
+            | Operator::F32x4Div
+            | Operator::F32x4Min
+            | Operator::F32x4Max
+            | Operator::F32x4PMin
+            | Operator::F32x4PMax
+            | Operator::F64x2Ceil
+            | Operator::F64x2Floor
+            | Operator::F64x2Trunc
+            | Operator::F64x2Nearest
+            | Operator::F64x2Abs
+            | Operator::F64x2Neg
+            | Operator::F64x2Sqrt
+            | Operator::F64x2Add
+            | Operator::F64x2Sub
+            | Operator::F64x2Mul
+            | Operator::F64x2Div
+            | Operator::F64x2Min
+            | Operator::F64x2Max
+            | Operator::F64x2PMin
+            | Operator::F64x2PMax
+            | Operator::I32x4TruncSatF32x4S
+            | Operator::I32x4TruncSatF32x4U
+            | Operator::F32x4ConvertI32x4S
+            | Operator::F32x4ConvertI32x4U
+            | Operator::I32x4TruncSatF64x2SZero
+            | Operator::I32x4TruncSatF64x2UZero
+            | Operator::F64x2ConvertLowI32x4S
+            | Operator::F64x2ConvertLowI32x4U
+            | Operator::F32x4DemoteF64x2Zero
+            | Operator::F64x2PromoteLowF32x4
+            | Operator::RefIsNull
+            | Operator::RefFunc { .. }
+            | Operator::RefNull { .. }
+            | Operator::MemoryAtomicNotify { .. }
+            | Operator::MemoryAtomicWait32 { .. }
+            | Operator::MemoryAtomicWait64 { .. }
+            | Operator::I32AtomicRmwAdd { .. }
+            | Operator::I64AtomicRmwAdd { .. }
+            | Operator::I32AtomicRmw8AddU { .. }
+            | Operator::I32AtomicRmw16AddU { .. }
+            | Operator::I64AtomicRmw8AddU { .. }
+            | Operator::I64AtomicRmw16AddU { .. }
+            | Operator::I64AtomicRmw32AddU { .. }
+            | Operator::I32AtomicRmwSub { .. }
+            | Operator::I64AtomicRmwSub { .. }
+            | Operator::I32AtomicRmw8SubU { .. }
+            | Operator::I32AtomicRmw16SubU { .. }
+            | Operator::I64AtomicRmw8SubU { .. }
+            | Operator::I64AtomicRmw16SubU { .. }
+            | Operator::I64AtomicRmw32SubU { .. }
+            | Operator::I32AtomicRmwAnd { .. }
+            | Operator::I64AtomicRmwAnd { .. }
+            | Operator::I32AtomicRmw8AndU { .. }
+            | Operator::I32AtomicRmw16AndU { .. }
+            | Operator::I64AtomicRmw8AndU { .. }
+            | Operator::I64AtomicRmw16AndU { .. }
+            | Operator::I64AtomicRmw32AndU { .. }
+            | Operator::I32AtomicRmwOr { .. }
+            | Operator::I64AtomicRmwOr { .. }
+            | Operator::I32AtomicRmw8OrU { .. }
+            | Operator::I32AtomicRmw16OrU { .. }
+            | Operator::I64AtomicRmw8OrU { .. }
+            | Operator::I64AtomicRmw16OrU { .. }
+            | Operator::I64AtomicRmw32OrU { .. }
+            | Operator::I32AtomicRmwXor { .. }
+            | Operator::I64AtomicRmwXor { .. }
+            | Operator::I32AtomicRmw8XorU { .. }
+            | Operator::I32AtomicRmw16XorU { .. }
+            | Operator::I64AtomicRmw8XorU { .. }
+            | Operator::I64AtomicRmw16XorU { .. }
+            | Operator::I64AtomicRmw32XorU { .. }
+            | Operator::I32AtomicRmwXchg { .. }
+            | Operator::I64AtomicRmwXchg { .. }
+            | Operator::I32AtomicRmw8XchgU { .. }
+            | Operator::I32AtomicRmw16XchgU { .. }
+            | Operator::I64AtomicRmw8XchgU { .. }
+            | Operator::I64AtomicRmw16XchgU { .. }
+            | Operator::I64AtomicRmw32XchgU { .. }
+            | Operator::I32AtomicRmwCmpxchg { .. }
+            | Operator::I64AtomicRmwCmpxchg { .. }
+            | Operator::I32AtomicRmw8CmpxchgU { .. }
+            | Operator::I32AtomicRmw16CmpxchgU { .. }
+            | Operator::I64AtomicRmw8CmpxchgU { .. }
+            | Operator::I64AtomicRmw16CmpxchgU { .. }
+            | Operator::I64AtomicRmw32CmpxchgU { .. }
+            | Operator::RefTest { .. }
+            | Operator::RefCast { .. }
+            | Operator::RefEq
+            | Operator::RefI31
+            | Operator::I31GetS
+            | Operator::I31GetU
+            | Operator::AnyConvertExtern
+            | Operator::ExternConvertAny
+            | Operator::I16x8Shl
+            | Operator::I16x8ShrS
+            | Operator::I16x8ShrU
+            | Operator::I32x4Shl
+            | Operator::I32x4ShrS
+            | Operator::I32x4ShrU
+            | Operator::I64x2Shl
+            | Operator::I64x2ShrS
+            | Operator::I64x2ShrU => Some(1),
+        }
+    }
+    /// The number of bytes of linear memory this operator reads or
+    /// writes at its address operand, or `None` if it takes no
+    /// `MemoryArg`. Narrow loads and stores, SIMD lane, splat and
+    /// extending loads, and atomic ops report the width they
+    /// actually touch rather than the width of their value type.
+    pub fn memory_access_size(&self) -> Option<u32> {
+        match self {
+            Operator::I32Load8S { .. }
+            | Operator::I32Load8U { .. }
+            | Operator::I64Load8S { .. }
+            | Operator::I64Load8U { .. }
+            | Operator::I32Store8 { .. }
+            | Operator::I64Store8 { .. }
+            | Operator::V128Load8Splat { .. }
+            | Operator::V128Load8Lane { .. }
+            | Operator::V128Store8Lane { .. }
+            | Operator::I32AtomicLoad8U { .. }
+            | Operator::I64AtomicLoad8U { .. }
+            | Operator::I32AtomicStore8 { .. }
+            | Operator::I64AtomicStore8 { .. }
+            | Operator::I32AtomicRmw8AddU { .. }
+            | Operator::I64AtomicRmw8AddU { .. }
+            | Operator::I32AtomicRmw8SubU { .. }
+            | Operator::I64AtomicRmw8SubU { .. }
+            | Operator::I32AtomicRmw8AndU { .. }
+            | Operator::I64AtomicRmw8AndU { .. }
+            | Operator::I32AtomicRmw8OrU { .. }
+            | Operator::I64AtomicRmw8OrU { .. }
+            | Operator::I32AtomicRmw8XorU { .. }
+            | Operator::I64AtomicRmw8XorU { .. }
+            | Operator::I32AtomicRmw8XchgU { .. }
+            | Operator::I64AtomicRmw8XchgU { .. }
+            | Operator::I32AtomicRmw8CmpxchgU { .. }
+            | Operator::I64AtomicRmw8CmpxchgU { .. } => Some(1),
+            Operator::I32Load16S { .. }
+            | Operator::I32Load16U { .. }
+            | Operator::I64Load16S { .. }
+            | Operator::I64Load16U { .. }
+            | Operator::I32Store16 { .. }
+            | Operator::I64Store16 { .. }
+            | Operator::V128Load16Splat { .. }
+            | Operator::V128Load16Lane { .. }
+            | Operator::V128Store16Lane { .. }
+            | Operator::I32AtomicLoad16U { .. }
+            | Operator::I64AtomicLoad16U { .. }
+            | Operator::I32AtomicStore16 { .. }
+            | Operator::I64AtomicStore16 { .. }
+            | Operator::I32AtomicRmw16AddU { .. }
+            | Operator::I64AtomicRmw16AddU { .. }
+            | Operator::I32AtomicRmw16SubU { .. }
+            | Operator::I64AtomicRmw16SubU { .. }
+            | Operator::I32AtomicRmw16AndU { .. }
+            | Operator::I64AtomicRmw16AndU { .. }
+            | Operator::I32AtomicRmw16OrU { .. }
+            | Operator::I64AtomicRmw16OrU { .. }
+            | Operator::I32AtomicRmw16XorU { .. }
+            | Operator::I64AtomicRmw16XorU { .. }
+            | Operator::I32AtomicRmw16XchgU { .. }
+            | Operator::I64AtomicRmw16XchgU { .. }
+            | Operator::I32AtomicRmw16CmpxchgU { .. }
+            | Operator::I64AtomicRmw16CmpxchgU { .. } => Some(2),
+            Operator::I32Load { .. }
+            | Operator::F32Load { .. }
+            | Operator::I64Load32S { .. }
+            | Operator::I64Load32U { .. }
+            | Operator::I32Store { .. }
+            | Operator::F32Store { .. }
+            | Operator::I64Store32 { .. }
+            | Operator::V128Load32Splat { .. }
+            | Operator::V128Load32Zero { .. }
+            | Operator::V128Load32Lane { .. }
+            | Operator::V128Store32Lane { .. }
+            | Operator::MemoryAtomicNotify { .. }
+            | Operator::MemoryAtomicWait32 { .. }
+            | Operator::I32AtomicLoad { .. }
+            | Operator::I64AtomicLoad32U { .. }
+            | Operator::I32AtomicStore { .. }
+            | Operator::I64AtomicStore32 { .. }
+            | Operator::I32AtomicRmwAdd { .. }
+            | Operator::I64AtomicRmw32AddU { .. }
+            | Operator::I32AtomicRmwSub { .. }
+            | Operator::I64AtomicRmw32SubU { .. }
+            | Operator::I32AtomicRmwAnd { .. }
+            | Operator::I64AtomicRmw32AndU { .. }
+            | Operator::I32AtomicRmwOr { .. }
+            | Operator::I64AtomicRmw32OrU { .. }
+            | Operator::I32AtomicRmwXor { .. }
+            | Operator::I64AtomicRmw32XorU { .. }
+            | Operator::I32AtomicRmwXchg { .. }
+            | Operator::I64AtomicRmw32XchgU { .. }
+            | Operator::I32AtomicRmwCmpxchg { .. }
+            | Operator::I64AtomicRmw32CmpxchgU { .. } => Some(4),
+            Operator::I64Load { .. }
+            | Operator::F64Load { .. }
+            | Operator::I64Store { .. }
+            | Operator::F64Store { .. }
+            | Operator::V128Load8x8S { .. }
+            | Operator::V128Load8x8U { .. }
+            | Operator::V128Load16x4S { .. }
+            | Operator::V128Load16x4U { .. }
+            | Operator::V128Load32x2S { .. }
+            | Operator::V128Load32x2U { .. }
+            | Operator::V128Load64Splat { .. }
+            | Operator::V128Load64Zero { .. }
+            | Operator::V128Load64Lane { .. }
+            | Operator::V128Store64Lane { .. }
+            | Operator::MemoryAtomicWait64 { .. }
+            | Operator::I64AtomicLoad { .. }
+            | Operator::I64AtomicStore { .. }
+            | Operator::I64AtomicRmwAdd { .. }
+            | Operator::I64AtomicRmwSub { .. }
+            | Operator::I64AtomicRmwAnd { .. }
+            | Operator::I64AtomicRmwOr { .. }
+            | Operator::I64AtomicRmwXor { .. }
+            | Operator::I64AtomicRmwXchg { .. }
+            | Operator::I64AtomicRmwCmpxchg { .. } => Some(8),
+            Operator::V128Load { .. } | Operator::V128Store { .. } => Some(16),
+            _ => None,
+        }
+    }
+}
+/// The type immediate of the reference operators: heap types print
+/// in spec form, e.g. `(ref null $sig0)`, and anything else as usual.
+struct RefTypeText<'a>(&'a Type);
+impl<'a> core::fmt::Display for RefTypeText<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self.0 {
+            Type::Heap(h) => write!(f, "{}", h),
+            ty => write!(f, "{}", ty),
+        }
+    }
 }
 impl core::fmt::Display for Operator {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
@@ -2662,7 +3904,7 @@ impl core::fmt::Display for Operator {
             Operator::I32GtU => write!(f, "i32gtu")?,
             Operator::I32LeS => write!(f, "i32les")?,
             Operator::I32LeU => write!(f, "i32leu")?,
-            Operator::I32GeS => write!(f, "i64ges")?,
+            Operator::I32GeS => write!(f, "i32ges")?,
             Operator::I32GeU => write!(f, "i32geu")?,
             Operator::I64Eqz => write!(f, "i64eqz")?,
             Operator::I64Eq => write!(f, "i64eq")?,
@@ -2799,6 +4041,10 @@ impl core::fmt::Display for Operator {
                 write!(f, "memory_copy<{}, {}>", dst_mem, src_mem)?
             }
             Operator::MemoryFill { mem } => write!(f, "memory_fill<{}>", mem)?,
+            Operator::MemoryInit { mem, data_idx } => {
+                write!(f, "memory_init<{};data={}>", mem, data_idx)?
+            }
+            Operator::DataDrop { data_idx } => write!(f, "data_drop<data={}>", data_idx)?,
             Operator::V128Load { memory } => write!(f, "v128load<{}>", memory)?,
             Operator::V128Load8x8S { memory } => write!(f, "v128load8x8s<{}>", memory)?,
             Operator::V128Load8x8U { memory } => write!(f, "v128load8x8u<{}>", memory)?,
@@ -3054,7 +4300,7 @@ impl core::fmt::Display for Operator {
             Operator::CallRef { sig_index } => write!(f, "call_ref<{}>", sig_index)?,
             Operator::RefIsNull => write!(f, "ref_is_null")?,
             Operator::RefFunc { func_index } => write!(f, "ref_func<{}>", func_index)?,
-            Operator::RefNull { ty } => write!(f, "ref_null<{}>", ty)?,
+            Operator::RefNull { ty } => write!(f, "ref_null<{}>", RefTypeText(ty))?,
             Operator::MemoryAtomicNotify { memarg } => write!(f, "memory_atomic_notify<{memarg}>")?, //=> visit_memory_atomic_notify
             Operator::MemoryAtomicWait32 { memarg } => write!(f, "memory_atomic_wait32<{memarg}>")?, //=> visit_memory_atomic_wait32
             Operator::MemoryAtomicWait64 { memarg } => write!(f, "memory_atomic_wait64<{memarg}>")?, //=> visit_memory_atomic_wait64
@@ -3078,8 +4324,8 @@ impl core::fmt::Display for Operator {
             Operator::I32AtomicRmw8AddU { memarg } => write!(f, "i32atomic_rmw8Addu<{memarg}>")?, //=> visit_i32_atomic_rmw8_add_u
             Operator::I32AtomicRmw16AddU { memarg } => write!(f, "i32atomic_rmw16Addu<{memarg}>")?, //=> visit_i32_atomic_rmw16_add_u
             Operator::I64AtomicRmw8AddU { memarg } => write!(f, "i64atomic_rmw8Addu<{memarg}>")?, //=> visit_i64_atomic_rmw8_add_u
-            Operator::I64AtomicRmw16AddU { memarg } => write!(f, "i32atomic_rmw16Addu<{memarg}>")?, //=> visit_i64_atomic_rmw16_add_u
-            Operator::I64AtomicRmw32AddU { memarg } => write!(f, "i32atomic_rmw32Addu<{memarg}>")?, //=> visit_i64_atomic_rmw32_add_u
+            Operator::I64AtomicRmw16AddU { memarg } => write!(f, "i64atomic_rmw16Addu<{memarg}>")?, //=> visit_i64_atomic_rmw16_add_u
+            Operator::I64AtomicRmw32AddU { memarg } => write!(f, "i64atomic_rmw32Addu<{memarg}>")?, //=> visit_i64_atomic_rmw32_add_u
             // Operator::I32AtomicRmwSub { memarg }  => Some(memarg), //=> visit_i32_atomic_rmw_sub
             // Operator::I64AtomicRmwSub { memarg }  => Some(memarg), //=> visit_i64_atomic_rmw_sub
             // Operator::I32AtomicRmw8SubU { memarg }  => Some(memarg), //=> visit_i32_atomic_rmw8_sub_u
@@ -3092,8 +4338,8 @@ impl core::fmt::Display for Operator {
             Operator::I32AtomicRmw8SubU { memarg } => write!(f, "i32atomic_rmw8Subu<{memarg}>")?, //=> visit_i32_atomic_rmw8_Sub_u
             Operator::I32AtomicRmw16SubU { memarg } => write!(f, "i32atomic_rmw16Subu<{memarg}>")?, //=> visit_i32_atomic_rmw16_Sub_u
             Operator::I64AtomicRmw8SubU { memarg } => write!(f, "i64atomic_rmw8Subu<{memarg}>")?, //=> visit_i64_atomic_rmw8_Sub_u
-            Operator::I64AtomicRmw16SubU { memarg } => write!(f, "i32atomic_rmw16Subu<{memarg}>")?, //=> visit_i64_atomic_rmw16_Sub_u
-            Operator::I64AtomicRmw32SubU { memarg } => write!(f, "i32atomic_rmw32Subu<{memarg}>")?, //=> visit_i64_atomic_rmw32_Sub_u
+            Operator::I64AtomicRmw16SubU { memarg } => write!(f, "i64atomic_rmw16Subu<{memarg}>")?, //=> visit_i64_atomic_rmw16_Sub_u
+            Operator::I64AtomicRmw32SubU { memarg } => write!(f, "i64atomic_rmw32Subu<{memarg}>")?, //=> visit_i64_atomic_rmw32_Sub_u
             // Operator::I32AtomicRmwAnd { memarg }  => Some(memarg), //=> visit_i32_atomic_rmw_and
             // Operator::I64AtomicRmwAnd { memarg }  => Some(memarg), //=> visit_i64_atomic_rmw_and
             // Operator::I32AtomicRmw8AndU { memarg }  => Some(memarg), //=> visit_i32_atomic_rmw8_and_u
@@ -3106,8 +4352,8 @@ impl core::fmt::Display for Operator {
             Operator::I32AtomicRmw8AndU { memarg } => write!(f, "i32atomic_rmw8Andu<{memarg}>")?, //=> visit_i32_atomic_rmw8_And_u
             Operator::I32AtomicRmw16AndU { memarg } => write!(f, "i32atomic_rmw16Andu<{memarg}>")?, //=> visit_i32_atomic_rmw16_And_u
             Operator::I64AtomicRmw8AndU { memarg } => write!(f, "i64atomic_rmw8Andu<{memarg}>")?, //=> visit_i64_atomic_rmw8_And_u
-            Operator::I64AtomicRmw16AndU { memarg } => write!(f, "i32atomic_rmw16Andu<{memarg}>")?, //=> visit_i64_atomic_rmw16_And_u
-            Operator::I64AtomicRmw32AndU { memarg } => write!(f, "i32atomic_rmw32Andu<{memarg}>")?, //=> visit_i64_atomic_rmw32_And_u
+            Operator::I64AtomicRmw16AndU { memarg } => write!(f, "i64atomic_rmw16Andu<{memarg}>")?, //=> visit_i64_atomic_rmw16_And_u
+            Operator::I64AtomicRmw32AndU { memarg } => write!(f, "i64atomic_rmw32Andu<{memarg}>")?, //=> visit_i64_atomic_rmw32_And_u
             // Operator::I32AtomicRmwOr { memarg }, // => visit_i32_atomic_rmw_or
             // Operator::I64AtomicRmwOr { memarg }  => Some(memarg),//=> visit_i64_atomic_rmw_or
             // Operator::I32AtomicRmw8OrU { memarg }  => Some(memarg),//=> visit_i32_atomic_rmw8_or_u
@@ -3120,8 +4366,8 @@ impl core::fmt::Display for Operator {
             Operator::I32AtomicRmw8OrU { memarg } => write!(f, "i32atomic_rmw8Oru<{memarg}>")?, //=> visit_i32_atomic_rmw8_Or_u
             Operator::I32AtomicRmw16OrU { memarg } => write!(f, "i32atomic_rmw16Oru<{memarg}>")?, //=> visit_i32_atomic_rmw16_Or_u
             Operator::I64AtomicRmw8OrU { memarg } => write!(f, "i64atomic_rmw8Oru<{memarg}>")?, //=> visit_i64_atomic_rmw8_Or_u
-            Operator::I64AtomicRmw16OrU { memarg } => write!(f, "i32atomic_rmw16Oru<{memarg}>")?, //=> visit_i64_atomic_rmw16_Or_u
-            Operator::I64AtomicRmw32OrU { memarg } => write!(f, "i32atomic_rmw32Oru<{memarg}>")?, //=> visit_i64_atomic_rmw32_Or_u
+            Operator::I64AtomicRmw16OrU { memarg } => write!(f, "i64atomic_rmw16Oru<{memarg}>")?, //=> visit_i64_atomic_rmw16_Or_u
+            Operator::I64AtomicRmw32OrU { memarg } => write!(f, "i64atomic_rmw32Oru<{memarg}>")?, //=> visit_i64_atomic_rmw32_Or_u
             // Operator::I32AtomicRmwXor { memarg }  => Some(memarg),//=> visit_i32_atomic_rmw_xor
             // Operator::I64AtomicRmwXor { memarg }  => Some(memarg),//=> visit_i64_atomic_rmw_xor
             // Operator::I32AtomicRmw8XorU { memarg }  => Some(memarg),//=> visit_i32_atomic_rmw8_xor_u
@@ -3134,8 +4380,8 @@ impl core::fmt::Display for Operator {
             Operator::I32AtomicRmw8XorU { memarg } => write!(f, "i32atomic_rmw8Xoru<{memarg}>")?, //=> visit_i32_atomic_rmw8_Xor_u
             Operator::I32AtomicRmw16XorU { memarg } => write!(f, "i32atomic_rmw16Xoru<{memarg}>")?, //=> visit_i32_atomic_rmw16_Xor_u
             Operator::I64AtomicRmw8XorU { memarg } => write!(f, "i64atomic_rmw8Xoru<{memarg}>")?, //=> visit_i64_atomic_rmw8_Xor_u
-            Operator::I64AtomicRmw16XorU { memarg } => write!(f, "i32atomic_rmw16Xoru<{memarg}>")?, //=> visit_i64_atomic_rmw16_Xor_u
-            Operator::I64AtomicRmw32XorU { memarg } => write!(f, "i32atomic_rmw32Xoru<{memarg}>")?, //=> visit_i64_atomic_rmw32_Xor_u
+            Operator::I64AtomicRmw16XorU { memarg } => write!(f, "i64atomic_rmw16Xoru<{memarg}>")?, //=> visit_i64_atomic_rmw16_Xor_u
+            Operator::I64AtomicRmw32XorU { memarg } => write!(f, "i64atomic_rmw32Xoru<{memarg}>")?, //=> visit_i64_atomic_rmw32_Xor_u
             // Operator::I32AtomicRmwXchg { memarg }  => Some(memarg),//=> visit_i32_atomic_rmw_xchg
             // Operator::I64AtomicRmwXchg { memarg }  => Some(memarg),//=> visit_i64_atomic_rmw_xchg
             // Operator::I32AtomicRmw8XchgU { memarg },// => visit_i32_atomic_rmw8_xchg_u
@@ -3194,8 +4440,8 @@ impl core::fmt::Display for Operator {
             Operator::ArrayFill { sig } => write!(f, "array_fill<{sig}>")?,
             Operator::ArrayCopy { dest, src } => write!(f, "array_copy<{dest}-{src}>")?,
             Operator::ArrayLen => write!(f, "array_len")?,
-            Operator::RefTest { ty } => write!(f, "ref_test<{ty}>")?,
-            Operator::RefCast { ty } => write!(f, "ref_cast<{ty}>")?,
+            Operator::RefTest { ty } => write!(f, "ref_test<{}>", RefTypeText(ty))?,
+            Operator::RefCast { ty } => write!(f, "ref_cast<{}>", RefTypeText(ty))?,
             Operator::StructNewDefault { sig } => write!(f, "struct_new_default<{sig}>")?,
             Operator::StructGetS { sig, idx } => write!(f, "struct_get_s<{sig}@{idx}>")?,
             Operator::StructGetU { sig, idx } => write!(f, "struct_get_u<{sig}@{idx}>")?,
@@ -3225,8 +4471,9 @@ impl core::fmt::Display for Operator {
     }
 }
 /// Should we rematerialize this operator when generating Wasm
-/// bytecode?
-pub(crate) fn op_rematerialize(op: &Operator) -> bool {
+/// bytecode? Custom backend rematerialization predicates can defer
+/// to this for constants.
+pub fn op_rematerialize(op: &Operator) -> bool {
     match op {
         // constants are much cheaper (in code space and in terms of
         // indirect effects on code quality) to always generate at
@@ -3275,6 +4522,7 @@ pub fn rewrite_mem<V, E>(
             Ok(())
         }
         Operator::MemoryFill { mem } => go(mem, Some(&mut v[0])),
+        Operator::MemoryInit { mem, .. } => go(mem, Some(&mut v[0])),
         Operator::V128Load { memory } => go(&mut memory.memory, Some(&mut v[0])),
         Operator::V128Load8x8S { memory } => go(&mut memory.memory, Some(&mut v[0])),
         Operator::V128Load8x8U { memory } => go(&mut memory.memory, Some(&mut v[0])),
@@ -3395,6 +4643,7 @@ pub fn mem_count(o: &Operator) -> usize {
         Operator::MemoryGrow { mem } => 1,
         Operator::MemoryCopy { dst_mem, src_mem } => 2,
         Operator::MemoryFill { mem } => 1,
+        Operator::MemoryInit { mem, .. } => 1,
         Operator::V128Load { memory } => 1,
         Operator::V128Load8x8S { memory } => 1,
         Operator::V128Load8x8U { memory } => 1,
@@ -3539,3 +4788,277 @@ pub fn memory_arg(o: &Operator) -> Option<&MemoryArg> {
         _ => None,
     }
 }
+/// Apply `go` to every signature an operator refers to, whether
+/// directly (call signatures, struct and array types) or through a
+/// reference type immediate.
+pub fn rewrite_sigs(o: &mut Operator, mut go: impl FnMut(&mut crate::Signature)) {
+    match o {
+        Operator::CallIndirect { sig_index, .. } | Operator::CallRef { sig_index } => go(sig_index),
+        Operator::StructNew { sig }
+        | Operator::StructNewDefault { sig }
+        | Operator::StructGet { sig, .. }
+        | Operator::StructGetS { sig, .. }
+        | Operator::StructGetU { sig, .. }
+        | Operator::StructSet { sig, .. }
+        | Operator::ArrayNew { sig }
+        | Operator::ArrayNewDefault { sig }
+        | Operator::ArrayNewFixed { sig, .. }
+        | Operator::ArrayNewData { sig, .. }
+        | Operator::ArrayNewElem { sig, .. }
+        | Operator::ArrayGet { sig }
+        | Operator::ArrayGetS { sig }
+        | Operator::ArrayGetU { sig }
+        | Operator::ArraySet { sig }
+        | Operator::ArrayFill { sig }
+        | Operator::ArrayInitData { sig, .. }
+        | Operator::ArrayInitElem { sig, .. } => go(sig),
+        Operator::ArrayCopy { dest, src } => {
+            go(dest);
+            go(src);
+        }
+        Operator::TypedSelect { ty }
+        | Operator::RefNull { ty }
+        | Operator::RefTest { ty }
+        | Operator::RefCast { ty } => ty.update_sigs(go),
+        _ => {}
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        FuncDecl, GlobalData, MemoryData, StorageType, TableData, WithMutablility, WithNullable,
+    };
+
+    /// A module with one of each kind of entity, a stack to type the
+    /// stack-dependent operators against, and `sample_operators` for
+    /// each kind of signature. Signature-taking operators need a
+    /// signature of the right kind, so each is tried; the lists line
+    /// up variant by variant.
+    fn sample_module() -> (Module<'static>, [(Type, Value); 3], Vec<Vec<Operator>>) {
+        let mut module = Module::empty();
+        let func_sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32],
+            returns: vec![Type::I32],
+            shared: false,
+        });
+        let field = |value| WithMutablility {
+            value,
+            mutable: true,
+        };
+        // Signature 1 is the array, matching the fixed `src` of the
+        // sample `array.copy`.
+        let array_sig = module.signatures.push(SignatureData::Array {
+            ty: field(StorageType::Val(Type::I32)),
+            shared: false,
+        });
+        let struct_sig = module.signatures.push(SignatureData::Struct {
+            fields: vec![
+                field(StorageType::Val(Type::I64)),
+                field(StorageType::I8),
+                field(StorageType::I16),
+            ],
+            shared: false,
+        });
+        let memory = module.memories.push(MemoryData {
+            initial_pages: 1,
+            maximum_pages: None,
+            segments: vec![],
+            memory64: false,
+            shared: false,
+            page_size_log2: None,
+        });
+        let table = module.tables.push(TableData {
+            ty: Type::Heap(WithNullable {
+                value: crate::HeapType::FuncRef,
+                nullable: true,
+            }),
+            initial: 1,
+            max: None,
+            func_elements: None,
+            table64: false,
+        });
+        let global = module.globals.push(GlobalData {
+            ty: Type::I32,
+            value: Some(0),
+            mutable: true,
+        });
+        let func = module.funcs.push(FuncDecl::Import(func_sig, "f".into()));
+
+        let anyref = Type::Heap(WithNullable {
+            value: crate::HeapType::Any,
+            nullable: true,
+        });
+        let stack = [
+            (Type::I32, Value::invalid()),
+            (Type::I32, Value::invalid()),
+            (anyref, Value::invalid()),
+        ];
+        let samples = [func_sig, array_sig, struct_sig]
+            .iter()
+            .map(|&sig| crate::ir::sample_operators(sig, memory, table, global, func))
+            .collect::<Vec<_>>();
+        (module, stack, samples)
+    }
+
+    /// Every operator variant can be typed, has effects and prints,
+    /// so none of the big matches is missing an arm.
+    #[test]
+    fn every_operator_is_covered() {
+        let (module, stack, samples) = sample_module();
+        for i in 0..samples[0].len() {
+            let op = &samples[0][i];
+            assert!(!op.to_string().is_empty());
+            let _ = op.effects();
+            let typed = samples.iter().any(|ops| {
+                op_inputs(&module, Some(&stack), &ops[i]).is_ok()
+                    && op_outputs(&module, Some(&stack), &ops[i]).is_ok()
+            });
+            assert!(typed, "{} cannot be typed with any signature", op);
+        }
+    }
+
+    /// Wherever `input_count` and `result_count` claim to know an
+    /// operator's arity, it agrees with the typed inputs and outputs,
+    /// under every signature that the operator can be typed with.
+    #[test]
+    fn counts_match_typed_arity() {
+        let (module, stack, samples) = sample_module();
+        for ops in samples.iter() {
+            for op in ops.iter() {
+                if let (Some(count), Ok(inputs)) =
+                    (op.input_count(), op_inputs(&module, Some(&stack), op))
+                {
+                    assert_eq!(count, inputs.len(), "inputs of {}", op);
+                }
+                if let (Some(count), Ok(outputs)) =
+                    (op.result_count(), op_outputs(&module, Some(&stack), op))
+                {
+                    assert_eq!(count, outputs.len(), "outputs of {}", op);
+                }
+            }
+        }
+        assert_eq!(Operator::Select.input_count(), None);
+        assert_eq!(Operator::ArrayLen.result_count(), None);
+    }
+
+    #[test]
+    fn ref_is_null_on_empty_stack_is_an_error() {
+        let module = Module::empty();
+        assert!(op_inputs(&module, Some(&[]), &Operator::RefIsNull).is_err());
+        assert!(op_inputs(&module, None, &Operator::RefIsNull).is_err());
+    }
+
+    #[test]
+    fn stack_deltas() {
+        let mut module = Module::empty();
+        let memory = module.memories.push(MemoryData {
+            initial_pages: 1,
+            maximum_pages: None,
+            segments: vec![],
+            memory64: false,
+            shared: false,
+            page_size_log2: None,
+        });
+        let table = module.tables.push(crate::TableData {
+            ty: Type::Heap(WithNullable {
+                value: crate::HeapType::FuncRef,
+                nullable: true,
+            }),
+            initial: 0,
+            max: None,
+            func_elements: None,
+            table64: false,
+        });
+        let sig = module.signatures.push(SignatureData::Func {
+            params: vec![Type::I32, Type::I64],
+            returns: vec![Type::F32],
+            shared: false,
+        });
+        let callee = module
+            .funcs
+            .push(crate::FuncDecl::Import(sig, "callee".into()));
+        let memarg = MemoryArg {
+            align: 0,
+            offset: 0,
+            memory,
+        };
+        let delta = |stack: Option<&[(Type, Value)]>, op: Operator| {
+            stack_delta(&module, stack, &op).unwrap()
+        };
+        assert_eq!(delta(None, Operator::Nop), 0);
+        assert_eq!(delta(None, Operator::I32Const { value: 1 }), 1);
+        assert_eq!(delta(None, Operator::I32Add), -1);
+        assert_eq!(delta(None, Operator::I64Eqz), 0);
+        assert_eq!(Operator::I32x4ShrS.result_count(), Some(1));
+        assert_eq!(delta(None, Operator::I32x4ShrS), -1);
+        assert_eq!(delta(None, Operator::I32Load { memory: memarg }), 0);
+        assert_eq!(delta(None, Operator::I64Store { memory: memarg }), -2);
+        assert_eq!(delta(None, Operator::MemoryFill { mem: memory }), -3);
+        assert_eq!(
+            delta(
+                None,
+                Operator::Call {
+                    function_index: callee
+                }
+            ),
+            -1
+        );
+        assert_eq!(
+            delta(
+                None,
+                Operator::CallIndirect {
+                    sig_index: sig,
+                    table_index: table,
+                }
+            ),
+            -2
+        );
+
+        let stack = [
+            (Type::F64, Value::invalid()),
+            (Type::F64, Value::invalid()),
+            (Type::I32, Value::invalid()),
+        ];
+        assert_eq!(delta(Some(&stack), Operator::Select), -2);
+        assert_eq!(delta(Some(&stack[..2]), Operator::RefIsNull), 0);
+        assert!(stack_delta(&module, None, &Operator::Select).is_err());
+    }
+
+    #[test]
+    fn memory_access_sizes() {
+        let memory = MemoryArg {
+            align: 0,
+            offset: 0,
+            memory: crate::Memory::new(0),
+        };
+        let memarg = memory;
+        let cases = [
+            (Operator::I32Load8U { memory }, Some(1)),
+            (Operator::I64Store8 { memory }, Some(1)),
+            (Operator::I32Load16U { memory }, Some(2)),
+            (Operator::I32Load { memory }, Some(4)),
+            (Operator::F32Load { memory }, Some(4)),
+            (Operator::I64Load32S { memory }, Some(4)),
+            (Operator::I64Load { memory }, Some(8)),
+            (Operator::F64Store { memory }, Some(8)),
+            (Operator::V128Load { memory }, Some(16)),
+            (Operator::V128Load8x8U { memory }, Some(8)),
+            (Operator::V128Load16Splat { memory }, Some(2)),
+            (Operator::V128Load32Zero { memory }, Some(4)),
+            (Operator::V128Load64Lane { memory, lane: 1 }, Some(8)),
+            (Operator::V128Store8Lane { memory, lane: 3 }, Some(1)),
+            (Operator::I32AtomicRmw8CmpxchgU { memarg }, Some(1)),
+            (Operator::I64AtomicRmw32XchgU { memarg }, Some(4)),
+            (Operator::I64AtomicLoad { memarg }, Some(8)),
+            (Operator::MemoryAtomicWait64 { memarg }, Some(8)),
+            (Operator::MemoryAtomicNotify { memarg }, Some(4)),
+            (Operator::I32Add, None),
+            (Operator::MemoryFill { mem: memory.memory }, None),
+        ];
+        for (op, size) in cases.iter() {
+            assert_eq!(op.memory_access_size(), *size, "{}", op);
+            assert_eq!(size.is_some(), memory_arg(op).is_some(), "{}", op);
+        }
+    }
+}
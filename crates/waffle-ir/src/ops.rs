@@ -26,6 +26,22 @@ pub struct MemoryArg {
     pub offset: u64,
     pub memory: Memory,
 }
+impl MemoryArg {
+    /// This access's offset increased by `delta`, or `None` if the
+    /// sum does not fit the offset immediate: 64 bits for a 64-bit
+    /// memory (`memory64`), 32 bits otherwise.
+    pub fn with_added_offset(&self, delta: u64, memory64: bool) -> Option<MemoryArg> {
+        let offset = self.offset.checked_add(delta)?;
+        if !memory64 && offset > u64::from(u32::MAX) {
+            return None;
+        }
+        Some(MemoryArg { offset, ..*self })
+    }
+    /// The alignment in bytes; `align` holds its base-2 logarithm.
+    pub fn align_bytes(&self) -> u32 {
+        1 << self.align
+    }
+}
 impl core::fmt::Display for MemoryArg {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         write!(
@@ -632,6 +648,15 @@ pub enum Operator {
     MemoryFill {
         mem: Memory,
     },
+    /// `memory.init`: copy a slice of a data segment into memory.
+    MemoryInit {
+        mem: Memory,
+        data_idx: u32,
+    },
+    /// `data.drop`: discard a data segment's contents.
+    DataDrop {
+        data_idx: u32,
+    },
     MemoryAtomicNotify {
         memarg: MemoryArg,
     }, //=> visit_memory_atomic_notify
@@ -934,6 +959,249 @@ pub enum Operator {
 fn op_size() {
     assert_eq!(core::mem::size_of::<Operator>(), 32);
 }
+impl Operator {
+    /// Is this one of the `*.const` operators, including `v128.const`?
+    pub fn is_constant(&self) -> bool {
+        match self {
+            Operator::I32Const { .. }
+            | Operator::I64Const { .. }
+            | Operator::F32Const { .. }
+            | Operator::F64Const { .. }
+            | Operator::V128Const { .. } => true,
+            _ => false,
+        }
+    }
+    /// The value of an `i32.const`.
+    pub fn as_const_i32(&self) -> Option<i32> {
+        match self {
+            &Operator::I32Const { value } => Some(value as i32),
+            _ => None,
+        }
+    }
+    /// The value of an `i64.const`.
+    pub fn as_const_i64(&self) -> Option<i64> {
+        match self {
+            &Operator::I64Const { value } => Some(value as i64),
+            _ => None,
+        }
+    }
+    /// The value of an `f32.const`, as its bits.
+    pub fn as_const_f32(&self) -> Option<Ieee32> {
+        match self {
+            &Operator::F32Const { value } => Some(Ieee32::new(value)),
+            _ => None,
+        }
+    }
+    /// The value of an `f64.const`, as its bits.
+    pub fn as_const_f64(&self) -> Option<Ieee64> {
+        match self {
+            &Operator::F64Const { value } => Some(Ieee64::new(value)),
+            _ => None,
+        }
+    }
+}
+/// Arithmetic and comparisons on `f32` bit patterns with Wasm
+/// semantics, for constant folding. Arithmetic results that are NaN
+/// are canonicalized; `copysign` is a pure bit operation and keeps
+/// any NaN payload.
+pub trait Ieee32Ext: Sized {
+    fn from_f32(value: f32) -> Self;
+    fn to_f32(self) -> f32;
+    fn add(self, other: Self) -> Self;
+    fn sub(self, other: Self) -> Self;
+    fn mul(self, other: Self) -> Self;
+    fn div(self, other: Self) -> Self;
+    /// `f32.min`: NaN if either operand is NaN, and `-0.0` is less
+    /// than `+0.0`.
+    fn min(self, other: Self) -> Self;
+    /// `f32.max`: NaN if either operand is NaN, and `+0.0` is greater
+    /// than `-0.0`.
+    fn max(self, other: Self) -> Self;
+    fn copysign(self, other: Self) -> Self;
+    fn sqrt(self) -> Self;
+    /// `f32.eq`: false if either operand is NaN, and `-0.0 == +0.0`.
+    fn float_eq(self, other: Self) -> bool;
+    fn float_ne(self, other: Self) -> bool;
+    fn float_lt(self, other: Self) -> bool;
+    fn float_gt(self, other: Self) -> bool;
+    fn float_le(self, other: Self) -> bool;
+    fn float_ge(self, other: Self) -> bool;
+}
+/// The `f64` counterpart of [`Ieee32Ext`].
+pub trait Ieee64Ext: Sized {
+    fn from_f64(value: f64) -> Self;
+    fn to_f64(self) -> f64;
+    fn add(self, other: Self) -> Self;
+    fn sub(self, other: Self) -> Self;
+    fn mul(self, other: Self) -> Self;
+    fn div(self, other: Self) -> Self;
+    /// `f64.min`: NaN if either operand is NaN, and `-0.0` is less
+    /// than `+0.0`.
+    fn min(self, other: Self) -> Self;
+    /// `f64.max`: NaN if either operand is NaN, and `+0.0` is greater
+    /// than `-0.0`.
+    fn max(self, other: Self) -> Self;
+    fn copysign(self, other: Self) -> Self;
+    fn sqrt(self) -> Self;
+    /// `f64.eq`: false if either operand is NaN, and `-0.0 == +0.0`.
+    fn float_eq(self, other: Self) -> bool;
+    fn float_ne(self, other: Self) -> bool;
+    fn float_lt(self, other: Self) -> bool;
+    fn float_gt(self, other: Self) -> bool;
+    fn float_le(self, other: Self) -> bool;
+    fn float_ge(self, other: Self) -> bool;
+}
+macro_rules! impl_ieee_ext {
+    ($trait:ident, $ty:ident, $float:ident, $bits:ident, $from:ident, $to:ident, $canonical:ident, $canonical_nan:expr) => {
+        impl $trait for $ty {
+            fn $from(value: $float) -> Self {
+                $ty::new(value.to_bits())
+            }
+            fn $to(self) -> $float {
+                $float::from_bits(self.bits())
+            }
+            fn add(self, other: Self) -> Self {
+                $canonical(self.$to() + other.$to())
+            }
+            fn sub(self, other: Self) -> Self {
+                $canonical(self.$to() - other.$to())
+            }
+            fn mul(self, other: Self) -> Self {
+                $canonical(self.$to() * other.$to())
+            }
+            fn div(self, other: Self) -> Self {
+                $canonical(self.$to() / other.$to())
+            }
+            fn min(self, other: Self) -> Self {
+                let (a, b) = (self.$to(), other.$to());
+                if a.is_nan() || b.is_nan() {
+                    $ty::new($canonical_nan)
+                } else if a == b {
+                    // Only zeros of different signs compare equal
+                    // with different bits; the negative one wins.
+                    if a.is_sign_negative() {
+                        self
+                    } else {
+                        other
+                    }
+                } else if a < b {
+                    self
+                } else {
+                    other
+                }
+            }
+            fn max(self, other: Self) -> Self {
+                let (a, b) = (self.$to(), other.$to());
+                if a.is_nan() || b.is_nan() {
+                    $ty::new($canonical_nan)
+                } else if a == b {
+                    if a.is_sign_positive() {
+                        self
+                    } else {
+                        other
+                    }
+                } else if a > b {
+                    self
+                } else {
+                    other
+                }
+            }
+            fn copysign(self, other: Self) -> Self {
+                let sign = !($bits::MAX >> 1);
+                $ty::new((self.bits() & !sign) | (other.bits() & sign))
+            }
+            fn sqrt(self) -> Self {
+                $canonical(self.$to().sqrt())
+            }
+            fn float_eq(self, other: Self) -> bool {
+                self.$to() == other.$to()
+            }
+            fn float_ne(self, other: Self) -> bool {
+                self.$to() != other.$to()
+            }
+            fn float_lt(self, other: Self) -> bool {
+                self.$to() < other.$to()
+            }
+            fn float_gt(self, other: Self) -> bool {
+                self.$to() > other.$to()
+            }
+            fn float_le(self, other: Self) -> bool {
+                self.$to() <= other.$to()
+            }
+            fn float_ge(self, other: Self) -> bool {
+                self.$to() >= other.$to()
+            }
+        }
+        /// Wrap a float result, replacing any NaN with the canonical
+        /// NaN.
+        fn $canonical(value: $float) -> $ty {
+            if value.is_nan() {
+                $ty::new($canonical_nan)
+            } else {
+                $ty::new(value.to_bits())
+            }
+        }
+    };
+}
+impl_ieee_ext!(
+    Ieee32Ext,
+    Ieee32,
+    f32,
+    u32,
+    from_f32,
+    to_f32,
+    canonical_f32,
+    0x7fc0_0000
+);
+impl_ieee_ext!(
+    Ieee64Ext,
+    Ieee64,
+    f64,
+    u64,
+    from_f64,
+    to_f64,
+    canonical_f64,
+    0x7ff8_0000_0000_0000
+);
+#[test]
+fn const_accessors() {
+    let i32c = Operator::I32Const { value: u32::MAX };
+    let i64c = Operator::I64Const { value: 5 };
+    let f32c = Operator::F32Const {
+        value: 1.5f32.to_bits(),
+    };
+    let f64c = Operator::F64Const {
+        value: (-2.0f64).to_bits(),
+    };
+    let v128c = Operator::V128Const { value: 1 };
+    for op in [&i32c, &i64c, &f32c, &f64c, &v128c].iter() {
+        assert!(op.is_constant());
+    }
+    assert!(!Operator::I32Add.is_constant());
+
+    assert_eq!(i32c.as_const_i32(), Some(-1));
+    assert_eq!(i64c.as_const_i64(), Some(5));
+    assert_eq!(
+        f32c.as_const_f32().map(|f| f.bits()),
+        Some(1.5f32.to_bits())
+    );
+    assert_eq!(
+        f64c.as_const_f64().map(|f| f.bits()),
+        Some((-2.0f64).to_bits())
+    );
+    for op in [&i64c, &f32c, &f64c, &v128c].iter() {
+        assert_eq!(op.as_const_i32(), None);
+    }
+    for op in [&i32c, &f32c, &f64c, &v128c].iter() {
+        assert_eq!(op.as_const_i64(), None);
+    }
+    for op in [&i32c, &i64c, &f64c, &v128c].iter() {
+        assert_eq!(op.as_const_f32(), None);
+    }
+    for op in [&i32c, &i64c, &f32c, &v128c].iter() {
+        assert_eq!(op.as_const_f64(), None);
+    }
+}
 impl<'a, 'b> core::convert::TryFrom<&'b wasmparser::Operator<'a>> for Operator {
     type Error = ();
     fn try_from(op: &'b wasmparser::Operator<'a>) -> Result<Operator, Self::Error> {
@@ -1553,6 +1821,13 @@ impl<'a, 'b> core::convert::TryFrom<&'b wasmparser::Operator<'a>> for Operator {
             &wasmparser::Operator::MemoryFill { mem } => Ok(Operator::MemoryFill {
                 mem: Memory::from(mem),
             }),
+            &wasmparser::Operator::MemoryInit { data_index, mem } => Ok(Operator::MemoryInit {
+                mem: Memory::from(mem),
+                data_idx: data_index,
+            }),
+            &wasmparser::Operator::DataDrop { data_index } => Ok(Operator::DataDrop {
+                data_idx: data_index,
+            }),
             &wasmparser::Operator::RefNull { hty } => Ok(Operator::RefNull {
                 ty: Type::Heap(wasmparser::RefType::new(true, hty).unwrap().into()),
             }),
@@ -2033,3 +2308,95 @@ impl core::convert::From<MemoryArg> for wasm_encoder::MemArg {
 // rkyv impls for `Operator` live in `ops_rkyv.rs` (which allows unsafe code)
 // because `unsafe impl rkyv::Portable` cannot be written in a deny(unsafe_code)
 // module. See that file for the full safety argument.
+#[test]
+fn float_consts_compare_bitwise() {
+    let f32c = |value: f32| Operator::F32Const {
+        value: value.to_bits(),
+    };
+    assert_eq!(f32c(1.5), f32c(1.5));
+    assert_ne!(f32c(0.0), f32c(-0.0));
+    let nan = Operator::F32Const { value: 0x7fc0_0001 };
+    assert_ne!(nan, Operator::F32Const { value: 0x7fc0_0000 });
+
+    let mut numbering: hashbrown::HashMap<Operator, u32> = hashbrown::HashMap::new();
+    numbering.insert(f32c(0.0), 0);
+    numbering.insert(f32c(-0.0), 1);
+    numbering.insert(nan, 2);
+    numbering.insert(
+        Operator::F64Const {
+            value: (-0.0f64).to_bits(),
+        },
+        3,
+    );
+    numbering.insert(Operator::V128Const { value: 1 << 127 }, 4);
+    assert_eq!(numbering.len(), 5);
+    assert_eq!(numbering[&f32c(-0.0)], 1);
+    assert_eq!(numbering[&Operator::F32Const { value: 0x7fc0_0001 }], 2);
+    assert_eq!(
+        numbering.get(&Operator::F32Const { value: 0x7fc0_0000 }),
+        None
+    );
+    assert_eq!(numbering[&Operator::V128Const { value: 1 << 127 }], 4);
+}
+#[test]
+fn ieee_edge_cases() {
+    let f32s = |value: f32| Ieee32::from_f32(value);
+    let f64s = |value: f64| Ieee64::from_f64(value);
+    let nan32 = Ieee32::new(0x7fc0_0000);
+    let nan64 = Ieee64::new(0x7ff8_0000_0000_0000);
+    // -0.0 is less than +0.0 for min/max, in either order.
+    assert_eq!(f32s(-0.0).min(f32s(0.0)).bits(), (-0.0f32).to_bits());
+    assert_eq!(f32s(0.0).min(f32s(-0.0)).bits(), (-0.0f32).to_bits());
+    assert_eq!(f32s(-0.0).max(f32s(0.0)).bits(), 0.0f32.to_bits());
+    assert_eq!(f64s(0.0).min(f64s(-0.0)).bits(), (-0.0f64).to_bits());
+    assert_eq!(f64s(0.0).max(f64s(-0.0)).bits(), 0.0f64.to_bits());
+    assert_eq!(f32s(1.0).min(f32s(2.0)).to_f32(), 1.0);
+    assert_eq!(f64s(1.0).max(f64s(2.0)).to_f64(), 2.0);
+    // NaN propagates, canonicalized, even from a NaN with a payload.
+    let payload32 = Ieee32::new(0xffc0_0001);
+    assert_eq!(payload32.min(f32s(1.0)), nan32);
+    assert_eq!(f32s(1.0).max(payload32), nan32);
+    assert_eq!(payload32.add(f32s(1.0)), nan32);
+    assert_eq!(f64s(1.0).min(nan64), nan64);
+    assert_eq!(f64s(0.0).div(f64s(0.0)), nan64);
+    assert_eq!(f64s(-1.0).sqrt(), nan64);
+    // Comparisons with NaN are false (except `ne`), and zeros are equal.
+    assert!(!nan32.float_eq(nan32) && nan32.float_ne(nan32));
+    assert!(!nan64.float_lt(f64s(1.0)) && !nan64.float_ge(f64s(1.0)));
+    assert!(f32s(-0.0).float_eq(f32s(0.0)));
+    assert!(f64s(-1.0).float_le(f64s(-0.0)));
+    // `copysign` only moves the sign bit, even onto a NaN.
+    assert_eq!(f32s(1.5).copysign(f32s(-0.0)).to_f32(), -1.5);
+    assert_eq!(f64s(-2.0).copysign(f64s(3.0)).to_f64(), 2.0);
+    assert_eq!(nan32.copysign(f32s(-1.0)).bits(), 0xffc0_0000);
+    assert_eq!(payload32.copysign(f32s(1.0)).bits(), 0x7fc0_0001);
+    assert_eq!(f32s(1.5).mul(f32s(2.0)).sub(f32s(1.0)).to_f32(), 2.0);
+    assert_eq!(f64s(9.0).sqrt().to_f64(), 3.0);
+}
+#[test]
+fn memory_arg_offset_arithmetic() {
+    let memarg = MemoryArg {
+        align: 2,
+        offset: 16,
+        memory: Memory::new(0),
+    };
+    assert_eq!(memarg.align_bytes(), 4);
+    assert_eq!(
+        memarg.with_added_offset(8, false),
+        Some(MemoryArg {
+            offset: 24,
+            ..memarg
+        })
+    );
+    let top32 = u64::from(u32::MAX) - 16;
+    assert_eq!(
+        memarg.with_added_offset(top32, false).map(|m| m.offset),
+        Some(u64::from(u32::MAX))
+    );
+    assert_eq!(memarg.with_added_offset(top32 + 1, false), None);
+    assert_eq!(
+        memarg.with_added_offset(top32 + 1, true).map(|m| m.offset),
+        Some(u64::from(u32::MAX) + 1)
+    );
+    assert_eq!(memarg.with_added_offset(u64::MAX - 15, true), None);
+}
@@ -19,13 +19,30 @@
 //! at level 3") and when a value is inserted, it records the level it
 //! is at and the current generation of that level.
 //!
-//! Note that this scheme does *not* support shadowing: if we set a
-//! particular key `k1` to value `v1` at level 3, then set it again at
-//! level 4, we will remove it when we pop level 4; the old value set
-//! at level 3 does not re-appear. Doing so would require a more
-//! complex data structure, and is unnecessary for our use-cases. In
-//! particular, we use this for GVN, where if a key already exists, we
-//! use it rather than setting it again in a more nested scope.
+//! A key set again in a more nested scope shadows its outer value:
+//! the entry keeps a link to the value it hid, and once the nested
+//! scope is popped the outer value is visible again.
+//!
+//! This makes the map usable as the environment of any pass that
+//! walks the dominator tree (see `DomtreePass` in the passes crate):
+//! push a scope on entering a block, record facts about values with
+//! `insert`, look them up with `get`, and pop the scope on leaving,
+//! so that only facts from dominating blocks are ever visible.
+//!
+//! ```
+//! use portal_pc_waffle_ir::scoped_map::ScopedMap;
+//!
+//! let mut map = ScopedMap::new();
+//! map.insert("x", 1);
+//! map.push_scope();
+//! assert_eq!(map.get(&"x"), Some(&1));
+//! map.insert("x", 2); // shadows the outer `x`
+//! map.insert("y", 3);
+//! assert_eq!(map.get(&"x"), Some(&2));
+//! map.pop_scope();
+//! assert_eq!(map.get(&"x"), Some(&1));
+//! assert_eq!(map.get(&"y"), None);
+//! ```
 use alloc::boxed::Box;
 use alloc::vec;
 use alloc::vec::Vec;
@@ -56,6 +73,9 @@ struct ScopedMapEntry<V: Clone + Debug> {
     level: u32,
     /// The value associated with this key.
     value: V,
+    /// The entry for the same key at an outer level that this one
+    /// hides, if it was live when this one was inserted.
+    shadowed: Option<Box<ScopedMapEntry<V>>>,
 }
 impl<K: Hash + Eq + Clone + Debug, V: Clone + Debug> ScopedMap<K, V> {
     /// Create an empty scoped hashmap.
@@ -66,37 +86,90 @@ impl<K: Hash + Eq + Clone + Debug, V: Clone + Debug> ScopedMap<K, V> {
             gen_by_level: vec![0],
         }
     }
-    /// Create a new sub-level.
-    pub fn push_level(&mut self) {
+    /// Enter a new, more nested scope.
+    pub fn push_scope(&mut self) {
         self.gen += 1;
         self.gen_by_level.push(self.gen);
     }
-    /// Pop the current level, removing all mappings created at this
-    /// level.
-    pub fn pop_level(&mut self) {
+    /// Leave the current scope, removing all mappings created in it
+    /// and uncovering any values they shadowed.
+    ///
+    /// ```
+    /// use portal_pc_waffle_ir::scoped_map::ScopedMap;
+    ///
+    /// let mut map = ScopedMap::new();
+    /// map.push_scope();
+    /// map.insert(1, "inner");
+    /// map.pop_scope();
+    /// assert_eq!(map.get(&1), None);
+    /// // A later scope at the same depth starts out empty too.
+    /// map.push_scope();
+    /// assert_eq!(map.get(&1), None);
+    /// ```
+    pub fn pop_scope(&mut self) {
         self.gen_by_level.pop();
     }
-    /// Insert a mapping, associating it with the current level, and
-    /// overwriting if one already exists.
+    /// Create a new sub-level. Same as `push_scope`.
+    pub fn push_level(&mut self) {
+        self.push_scope();
+    }
+    /// Pop the current level. Same as `pop_scope`.
+    pub fn pop_level(&mut self) {
+        self.pop_scope();
+    }
+    /// Insert a mapping in the current scope. This overwrites a value
+    /// set for `k` in the same scope, and shadows one set in an outer
+    /// scope until the current scope is popped.
+    ///
+    /// ```
+    /// use portal_pc_waffle_ir::scoped_map::ScopedMap;
+    ///
+    /// let mut map = ScopedMap::new();
+    /// map.insert('a', 1);
+    /// map.insert('a', 2); // same scope: overwritten
+    /// map.push_scope();
+    /// map.insert('a', 3);
+    /// map.insert('a', 4);
+    /// assert_eq!(map.get(&'a'), Some(&4));
+    /// map.pop_scope();
+    /// assert_eq!(map.get(&'a'), Some(&2));
+    /// ```
     pub fn insert(&mut self, k: K, v: V) {
+        let level = (self.gen_by_level.len() - 1) as u32;
+        let shadowed = match self.map.remove(&k).and_then(|old| self.first_live(old)) {
+            Some(old) if old.level == level => old.shadowed,
+            outer => outer,
+        };
         self.map.insert(
             k,
             ScopedMapEntry {
                 gen: *self.gen_by_level.last().unwrap(),
-                level: (self.gen_by_level.len() - 1) as u32,
+                level,
                 value: v,
+                shadowed,
             },
         );
     }
-    /// Get the mapping for the given key, if any.
+    /// Get the value visible for the given key in the current scope,
+    /// if any.
     pub fn get(&self, k: &K) -> Option<&V> {
-        self.map.get(k).and_then(|entry| {
-            let level = entry.level as usize;
-            if level < self.gen_by_level.len() && entry.gen == self.gen_by_level[level] {
-                Some(&entry.value)
-            } else {
-                None
-            }
-        })
+        let mut entry = self.map.get(k)?;
+        while !self.is_live(entry) {
+            entry = entry.shadowed.as_deref()?;
+        }
+        Some(&entry.value)
+    }
+    /// Was `entry` created in a scope that has not been popped since?
+    fn is_live(&self, entry: &ScopedMapEntry<V>) -> bool {
+        let level = entry.level as usize;
+        level < self.gen_by_level.len() && entry.gen == self.gen_by_level[level]
+    }
+    /// The first live entry in the shadowing chain starting at
+    /// `entry`, dropping the stale ones before it.
+    fn first_live(&self, mut entry: ScopedMapEntry<V>) -> Option<Box<ScopedMapEntry<V>>> {
+        while !self.is_live(&entry) {
+            entry = *entry.shadowed?;
+        }
+        Some(Box::new(entry))
     }
 }
@@ -1,5 +1,5 @@
 use crate::*;
-use alloc::{borrow::ToOwned, vec::Vec};
+use alloc::{borrow::ToOwned, collections::BTreeSet, string::String, vec::Vec};
 pub fn add_start(m: &mut Module, tf: Func) {
     let s = SignatureData::Func {
         params: vec![],
@@ -37,13 +37,9 @@ pub fn add_start(m: &mut Module, tf: Func) {
     let f = m.funcs.push(FuncDecl::Body(s, format!("start"), f));
     m.start_func = Some(f);
 }
+/// Same as `Module::add_signature`.
 pub fn new_sig(m: &mut Module, s: SignatureData) -> Signature {
-    for (a, b) in m.signatures.entries() {
-        if *b == s {
-            return a;
-        }
-    }
-    return m.signatures.push(s);
+    m.add_signature(s)
 }
 pub fn results_ref_2(f: &mut FunctionBody, c: Value) -> Vec<Value> {
     let c = f.resolve_and_update_alias(c);
@@ -63,3 +59,371 @@ pub fn results_ref_2(f: &mut FunctionBody, c: Value) -> Vec<Value> {
     }
     return v;
 }
+
+/// Structural differences between two modules, as computed by
+/// [`module_diff`]. Entities are matched by index, and every list is
+/// in index order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ModuleDiff {
+    /// Signatures only present in the second module.
+    pub sigs_added: Vec<Signature>,
+    /// Signatures only present in the first module.
+    pub sigs_removed: Vec<Signature>,
+    /// Signatures present in both modules with different definitions.
+    pub sigs_changed: Vec<Signature>,
+    /// Functions only present in the second module.
+    pub funcs_added: Vec<Func>,
+    /// Functions only present in the first module.
+    pub funcs_removed: Vec<Func>,
+    /// Functions present in both modules that differ.
+    pub funcs_changed: Vec<FuncDiff>,
+}
+impl ModuleDiff {
+    /// Did the two modules compare equal?
+    pub fn is_empty(&self) -> bool {
+        *self == ModuleDiff::default()
+    }
+}
+/// How one function differs between two modules. Each field is
+/// `Some((before, after))` if that property changed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FuncDiff {
+    pub func: Func,
+    pub name: Option<(String, String)>,
+    pub sig: Option<(Signature, Signature)>,
+    /// Number of blocks, for functions with an IR body on both sides.
+    pub blocks: Option<(usize, usize)>,
+    /// Number of values placed in blocks (instructions and
+    /// blockparams), for functions with an IR body on both sides.
+    pub values: Option<(usize, usize)>,
+}
+fn changed<T: PartialEq>(a: T, b: T) -> Option<(T, T)> {
+    if a == b {
+        None
+    } else {
+        Some((a, b))
+    }
+}
+/// Compare two modules, typically a module and a transformed copy of
+/// it, reporting which signatures and functions were added, removed
+/// or changed. This is a coarse summary for triage, not an equality
+/// check: function bodies are only compared by their block and value
+/// counts.
+pub fn module_diff(a: &Module, b: &Module) -> ModuleDiff {
+    let mut diff = ModuleDiff::default();
+    for idx in 0..a.signatures.len().max(b.signatures.len()) {
+        let sig = Signature::new(idx);
+        match (a.signatures.get(sig), b.signatures.get(sig)) {
+            (Some(x), Some(y)) if x != y => diff.sigs_changed.push(sig),
+            (Some(_), None) => diff.sigs_removed.push(sig),
+            (None, Some(_)) => diff.sigs_added.push(sig),
+            _ => {}
+        }
+    }
+    let counts = |decl: &FuncDecl| {
+        decl.body().map(|body| {
+            let values = body
+                .blocks
+                .values()
+                .map(|block| block.insts.len() + block.params.len())
+                .sum::<usize>();
+            (body.blocks.len(), values)
+        })
+    };
+    for idx in 0..a.funcs.len().max(b.funcs.len()) {
+        let func = Func::new(idx);
+        let (x, y) = match (a.funcs.get(func), b.funcs.get(func)) {
+            (Some(x), Some(y)) => (x, y),
+            (Some(_), None) => {
+                diff.funcs_removed.push(func);
+                continue;
+            }
+            _ => {
+                diff.funcs_added.push(func);
+                continue;
+            }
+        };
+        let (blocks, values) = match (counts(x), counts(y)) {
+            (Some((xb, xv)), Some((yb, yv))) => (changed(xb, yb), changed(xv, yv)),
+            _ => (None, None),
+        };
+        let func_diff = FuncDiff {
+            func,
+            name: changed(x.name().to_owned(), y.name().to_owned()),
+            sig: changed(x.sig(), y.sig()),
+            blocks,
+            values,
+        };
+        let FuncDiff {
+            name,
+            sig,
+            blocks,
+            values,
+            ..
+        } = &func_diff;
+        if name.is_some() || sig.is_some() || blocks.is_some() || values.is_some() {
+            diff.funcs_changed.push(func_diff);
+        }
+    }
+    diff
+}
+/// The value types a function body refers to: its return types,
+/// locals, blockparams, the result types of its placed instructions,
+/// and the type immediates of its operators (`ref.null`, typed
+/// `select`, `ref.test` and `ref.cast`). Struct and array operators
+/// contribute a nullable reference to the type they access, even if
+/// no value of that type is ever materialized.
+pub fn types_used(body: &FunctionBody) -> BTreeSet<Type> {
+    let mut types: BTreeSet<Type> = body.rets.iter().copied().collect();
+    types.extend(body.locals.values().copied());
+    for block in body.blocks.values() {
+        types.extend(block.params.iter().map(|&(ty, _)| ty));
+        for inst in &block.insts {
+            match &body.values[inst.value] {
+                ValueDef::Operator(op, _, tys) => {
+                    types.extend(body.type_pool[*tys].iter().copied());
+                    operator_types(op, &mut types);
+                }
+                &ValueDef::PickOutput(_, _, ty) => {
+                    types.insert(ty);
+                }
+                _ => {}
+            }
+        }
+    }
+    types
+}
+fn operator_types(op: &Operator, types: &mut BTreeSet<Type>) {
+    match op {
+        &Operator::TypedSelect { ty }
+        | &Operator::RefNull { ty }
+        | &Operator::RefTest { ty }
+        | &Operator::RefCast { ty } => {
+            types.insert(ty);
+        }
+        Operator::CallIndirect { .. } | Operator::CallRef { .. } => {}
+        // Struct and array operators, the only others naming a
+        // signature.
+        _ => {
+            let mut op = op.clone();
+            crate::op_traits::rewrite_sigs(&mut op, |&mut sig_index| {
+                types.insert(Type::Heap(WithNullable {
+                    value: HeapType::Sig { sig_index },
+                    nullable: true,
+                }));
+            });
+        }
+    }
+}
+/// The memories a function body accesses directly, as computed by
+/// [`memory_footprint`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryFootprint {
+    /// Memories that some instruction may read.
+    pub reads: BTreeSet<Memory>,
+    /// Memories that some instruction may write.
+    pub writes: BTreeSet<Memory>,
+}
+/// Summarize which memories the instructions of `body` read and
+/// write, as the union of [`MemoryFootprint::of_op`] over its
+/// instructions. Calls are not followed, so a callee's accesses are
+/// not included.
+pub fn memory_footprint(body: &FunctionBody) -> MemoryFootprint {
+    let mut footprint = MemoryFootprint::default();
+    for block in body.blocks.values() {
+        for inst in &block.insts {
+            let ValueDef::Operator(op, ..) = &body.values[inst.value] else {
+                continue;
+            };
+            let op_footprint = MemoryFootprint::of_op(op);
+            footprint.reads.extend(op_footprint.reads);
+            footprint.writes.extend(op_footprint.writes);
+        }
+    }
+    footprint
+}
+impl MemoryFootprint {
+    /// The memories one operator accesses directly: loads, stores,
+    /// atomics and bulk-memory operators, with `memory.copy` reading
+    /// its source and writing its destination. `memory.size` counts
+    /// as a read and `memory.grow` as a write. Calls report nothing.
+    pub fn of_op(op: &Operator) -> MemoryFootprint {
+        let mut footprint = MemoryFootprint::default();
+        let (reads, writes) = match *op {
+            Operator::MemoryCopy { dst_mem, src_mem } => (Some(src_mem), Some(dst_mem)),
+            Operator::MemorySize { mem } => (Some(mem), None),
+            Operator::MemoryGrow { mem }
+            | Operator::MemoryFill { mem }
+            | Operator::MemoryInit { mem, .. } => (None, Some(mem)),
+            _ => match crate::op_traits::memory_arg(op) {
+                Some(memarg) => {
+                    let (read, write) = atomic_or_plain_access(op);
+                    let mem = memarg.memory;
+                    (Some(mem).filter(|_| read), Some(mem).filter(|_| write))
+                }
+                None => (None, None),
+            },
+        };
+        footprint.reads.extend(reads);
+        footprint.writes.extend(writes);
+        footprint
+    }
+}
+/// Whether a memory operator with a `MemoryArg` reads and/or writes
+/// its memory. Atomics only report `SideEffect::AtomicStuff`, so
+/// they are classified by name; read-modify-write and notify
+/// operators count as both.
+fn atomic_or_plain_access(op: &Operator) -> (bool, bool) {
+    let effects = op.effects();
+    if !effects.contains(&SideEffect::AtomicStuff) {
+        return (
+            effects.contains(&SideEffect::ReadMem),
+            effects.contains(&SideEffect::WriteMem),
+        );
+    }
+    match op {
+        Operator::I32AtomicLoad { .. }
+        | Operator::I64AtomicLoad { .. }
+        | Operator::I32AtomicLoad8U { .. }
+        | Operator::I32AtomicLoad16U { .. }
+        | Operator::I64AtomicLoad8U { .. }
+        | Operator::I64AtomicLoad16U { .. }
+        | Operator::I64AtomicLoad32U { .. }
+        | Operator::MemoryAtomicWait32 { .. }
+        | Operator::MemoryAtomicWait64 { .. } => (true, false),
+        Operator::I32AtomicStore { .. }
+        | Operator::I64AtomicStore { .. }
+        | Operator::I32AtomicStore8 { .. }
+        | Operator::I32AtomicStore16 { .. }
+        | Operator::I64AtomicStore8 { .. }
+        | Operator::I64AtomicStore16 { .. }
+        | Operator::I64AtomicStore32 { .. } => (false, true),
+        _ => (true, true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn footprint_of_two_memories() {
+        let mut module = Module::empty();
+        let memory = |module: &mut Module| {
+            module.memories.push(MemoryData {
+                initial_pages: 1,
+                maximum_pages: None,
+                segments: vec![],
+                memory64: false,
+                shared: false,
+                page_size_log2: None,
+            })
+        };
+        let (src, dst, untouched) = (
+            memory(&mut module),
+            memory(&mut module),
+            memory(&mut module),
+        );
+        let sig = new_sig(
+            &mut module,
+            SignatureData::Func {
+                params: vec![],
+                returns: vec![],
+                shared: false,
+            },
+        );
+        let memarg = |memory| MemoryArg {
+            align: 0,
+            offset: 0,
+            memory,
+        };
+        // Load from `src`, store the value to `dst`, then copy a
+        // range from `src` to `dst` and atomically load from `src`.
+        let mut b = FunctionBuilder::new(&module, sig);
+        let entry = b.entry();
+        let zero = b.const_i32(entry, 0);
+        let x = b.push_op(
+            entry,
+            Operator::I32Load {
+                memory: memarg(src),
+            },
+            &[zero],
+            &[Type::I32],
+        );
+        b.push_op(
+            entry,
+            Operator::I32Store {
+                memory: memarg(dst),
+            },
+            &[zero, x],
+            &[],
+        );
+        b.push_op(
+            entry,
+            Operator::MemoryCopy {
+                dst_mem: dst,
+                src_mem: src,
+            },
+            &[zero, zero, zero],
+            &[],
+        );
+        b.push_op(
+            entry,
+            Operator::I32AtomicLoad {
+                memarg: memarg(src),
+            },
+            &[zero],
+            &[Type::I32],
+        );
+        b.ret(entry, &[]);
+        let body = b.finish();
+
+        let footprint = memory_footprint(&body);
+        assert_eq!(footprint.reads, BTreeSet::from([src]));
+        assert_eq!(footprint.writes, BTreeSet::from([dst]));
+        assert!(!footprint.reads.contains(&untouched) && !footprint.writes.contains(&untouched));
+    }
+
+    #[test]
+    fn types_used_by_body() {
+        let mut module = Module::empty();
+        let struct_sig = module.signatures.push(SignatureData::Struct {
+            fields: vec![WithMutablility {
+                value: StorageType::Val(Type::I32),
+                mutable: true,
+            }],
+            shared: false,
+        });
+        let sig = new_sig(
+            &mut module,
+            SignatureData::Func {
+                params: vec![Type::I32],
+                returns: vec![],
+                shared: false,
+            },
+        );
+        let struct_ref = Type::Heap(WithNullable {
+            value: HeapType::Sig {
+                sig_index: struct_sig,
+            },
+            nullable: false,
+        });
+        let mut body = FunctionBody::new(&module, sig);
+        let entry = body.entry;
+        let x = body.blocks[entry].params[0].1;
+        body.add_op(entry, Operator::V128Const { value: 0 }, &[], &[Type::V128]);
+        body.add_op(
+            entry,
+            Operator::StructNew { sig: struct_sig },
+            &[x],
+            &[struct_ref],
+        );
+        body.set_terminator(entry, Terminator::Return { values: vec![] });
+
+        let types = types_used(&body);
+        for ty in [Type::I32, Type::V128, struct_ref] {
+            assert!(types.contains(&ty), "{} missing", ty);
+        }
+        assert!(!types.contains(&Type::F64));
+    }
+}
@@ -16,3 +16,70 @@ pub use waffle_passes_shared;
 pub mod passes;
 pub use passes::*;
 pub use passes::basic_opt::OptOptions;
+
+/// Module-level optimization entry points.
+pub trait ModuleOptExt {
+    /// Optimize every expanded function body.
+    fn optimize(&mut self, opts: &OptOptions);
+    /// Optimize every expanded function body, in parallel.
+    #[cfg(feature = "parallel")]
+    fn optimize_parallel(&mut self, opts: &OptOptions);
+}
+impl<'a> ModuleOptExt for Module<'a> {
+    fn optimize(&mut self, opts: &OptOptions) {
+        passes::basic_opt::optimize_module(self, opts)
+    }
+    #[cfg(feature = "parallel")]
+    fn optimize_parallel(&mut self, opts: &OptOptions) {
+        passes::basic_opt::optimize_module_parallel(self, opts)
+    }
+}
+
+#[cfg(all(test, feature = "parallel"))]
+mod test {
+    use super::*;
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    fn bodies(m: &Module) -> Vec<String> {
+        m.funcs
+            .values()
+            .filter_map(|decl| decl.body())
+            .map(|body| format!("{}", body.display("", None)))
+            .collect()
+    }
+
+    #[test]
+    fn parallel_matches_serial() {
+        let mut m = Module::empty();
+        let sig = util::new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+        for i in 0..64 {
+            let mut b = FunctionBuilder::new(&m, sig);
+            let entry = b.entry();
+            let x = b.params(entry)[0];
+            let k1 = b.const_i32(entry, i);
+            let k2 = b.const_i32(entry, 3);
+            let k = b.push_op(entry, Operator::I32Mul, &[k1, k2], &[Type::I32]);
+            let y = b.push_op(entry, Operator::I32Add, &[x, k], &[Type::I32]);
+            let z = b.push_op(entry, Operator::I32Add, &[x, k], &[Type::I32]);
+            let r = b.push_op(entry, Operator::I32Xor, &[y, z], &[Type::I32]);
+            b.ret(entry, &[r]);
+            m.funcs
+                .push(FuncDecl::Body(sig, format!("f{}", i), b.finish()));
+        }
+        let opts = OptOptions::default();
+        let mut serial = m.clone();
+        serial.optimize(&opts);
+        let mut parallel = m.clone();
+        parallel.optimize_parallel(&opts);
+        assert_ne!(bodies(&serial), bodies(&m));
+        assert_eq!(bodies(&serial), bodies(&parallel));
+    }
+}
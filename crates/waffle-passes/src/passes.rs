@@ -1,15 +1,36 @@
 //! Passes.
 pub mod basic_opt;
+pub mod block_coalesce;
+pub mod canonicalize;
+pub mod const_fold;
+pub mod dce;
+pub mod dead_locals;
 pub mod dom_pass;
 pub mod empty_blocks;
+pub mod fold_branch;
 // pub mod ssa;
 // pub mod trace;
 pub mod reorder_funs;
+pub mod simplify_phis;
+pub mod sink;
+pub mod strength_reduce;
 // pub mod fixup_rets;
 pub mod frint;
+pub mod gvn;
+pub mod hoist_common;
+pub mod i64_narrow;
 #[cfg(feature = "importify")]
 pub mod importify;
 pub mod inline;
+pub mod jump_thread;
+pub mod licm;
+pub mod load_cse;
+pub mod lower_select;
+pub mod mem_instrument;
+pub mod outline_common;
+pub mod pipeline;
+pub use pipeline::Pipeline;
 
 pub mod ub_vaccum;
+pub mod unreachable_code;
 pub mod func_rocket;
\ No newline at end of file
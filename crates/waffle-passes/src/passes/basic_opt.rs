@@ -20,6 +20,12 @@ pub struct OptOptions {
     pub redundant_blockparams: bool,
     pub inline_refs: bool,
     pub ub_vaccum: bool,
+    /// Apply local rewrites of small operator patterns, such as
+    /// `i32.wrap_i64` of an `i64.extend_i32_*`.
+    pub peephole: bool,
+    /// Callees with at most this many instructions are inlined by
+    /// `passes::inline::run`.
+    pub inline_insn_threshold: usize,
 }
 impl core::default::Default for OptOptions {
     fn default() -> Self {
@@ -29,17 +35,52 @@ impl core::default::Default for OptOptions {
             redundant_blockparams: true,
             inline_refs: true,
             ub_vaccum: true,
+            peephole: true,
+            inline_insn_threshold: 16,
         }
     }
 }
+/// Run the basic optimizations over a single function body.
+pub fn optimize(body: &mut FunctionBody, options: &OptOptions) {
+    let cfg = CFGInfo::new(body);
+    basic_opt(body, &cfg, options);
+}
+/// Optimize every expanded function body in the module, one at a time.
+pub fn optimize_module(module: &mut Module, options: &OptOptions) {
+    for decl in module.funcs.values_mut() {
+        if let Some(body) = decl.body_mut() {
+            optimize(body, options);
+        }
+    }
+}
+/// Optimize every expanded function body in the module, spreading the
+/// bodies across the rayon thread pool. Produces the same result as
+/// `optimize_module`.
+#[cfg(feature = "parallel")]
+pub fn optimize_module_parallel(module: &mut Module, options: &OptOptions) {
+    use rayon::prelude::*;
+    module
+        .funcs
+        .as_mut_slice()
+        .par_iter_mut()
+        .filter_map(|decl| decl.body_mut())
+        .for_each(|body| optimize(body, options));
+}
 pub fn basic_opt(body: &mut FunctionBody, cfg: &CFGInfo, options: &OptOptions) {
     loop {
         let mut pass = BasicOptPass {
             map: ScopedMap::default(),
             cfg,
             options,
+            uses: Uses::default(),
             changed: false,
         };
+        if options.peephole {
+            pass.uses = Uses {
+                insts: body.compute_uses(),
+                terminators: body.compute_terminator_uses(),
+            };
+        }
         dom_pass::<BasicOptPass>(body, cfg, &mut pass);
         if !pass.changed {
             break;
@@ -51,15 +92,25 @@ struct BasicOptPass<'a> {
     map: ScopedMap<ValueDef, Value>,
     cfg: &'a CFGInfo,
     options: &'a OptOptions,
+    uses: Uses,
     changed: bool,
 }
+/// Use counts for the peephole rewrites, computed once per sweep over
+/// the body. Edits made during the sweep leave them stale, which only
+/// makes a rewrite more or less eager: each rewrite is sound whatever
+/// the counts say.
+#[derive(Debug, Default)]
+struct Uses {
+    insts: PerEntity<Value, Vec<Value>>,
+    terminators: PerEntity<Value, Vec<Block>>,
+}
 impl<'a> DomtreePass for BasicOptPass<'a> {
     fn enter(&mut self, block: Block, body: &mut FunctionBody) {
-        self.map.push_level();
+        self.map.push_scope();
         self.optimize(block, body);
     }
     fn leave(&mut self, _block: Block, _body: &mut FunctionBody) {
-        self.map.pop_level();
+        self.map.pop_scope();
     }
 }
 
@@ -81,6 +132,30 @@ fn const_op(val: ConstVal) -> Operator {
         _ => unreachable!(),
     }
 }
+impl Uses {
+    /// Whether `value` is used by at most one instruction and no
+    /// terminator.
+    fn has_single_use(&self, value: Value) -> bool {
+        self.insts[value].len() <= 1 && self.terminators[value].is_empty()
+    }
+}
+/// If `value` is an `i32.wrap_i64` of an `i64.extend_i32_s` or
+/// `i64.extend_i32_u` that has no other uses, the `i32` that was
+/// extended: wrapping recovers exactly its low 32 bits.
+fn wrap_of_extend(body: &FunctionBody, uses: &Uses, value: &ValueDef) -> Option<Value> {
+    let ValueDef::Operator(Operator::I32WrapI64, args, _) = value else {
+        return None;
+    };
+    let wide = body.resolve_alias(body.arg_pool[*args][0]);
+    match &body.values[wide] {
+        ValueDef::Operator(Operator::I64ExtendI32S | Operator::I64ExtendI32U, args, _)
+            if uses.has_single_use(wide) =>
+        {
+            Some(body.resolve_alias(body.arg_pool[*args][0]))
+        }
+        _ => None,
+    }
+}
 fn remove_all_from_vec<T: Clone>(v: &mut Vec<T>, indices: &[usize]) {
     let mut out = 0;
     let mut indices_i = 0;
@@ -130,6 +205,7 @@ impl<'a> BasicOptPass<'a> {
                     // blockparam and rewrite it as an alias of the one
                     // single value.
                     body.values[blockparam] = ValueDef::Alias(inputs[0]);
+                    body.copy_source_loc(blockparam, inputs[0]);
                     blockparams_to_remove.push(i);
                 } else if const_val != ConstVal::None {
                     // All inputs are the same constant; remove the
@@ -219,13 +295,10 @@ impl<'a> BasicOptPass<'a> {
                             .iter()
                             .map(|&arg| value_is_const(arg, body))
                             .collect::<Vec<_>>();
-                        let const_val = match op {
-                            Operator::I32Const { .. }
-                            | Operator::I64Const { .. }
-                            | Operator::F32Const { .. }
-                            | Operator::F64Const { .. }
-                            | Operator::V128Const { .. } => None,
-                            _ => const_eval(op, &arg_values[..], None),
+                        let const_val = if op.is_constant() {
+                            None
+                        } else {
+                            const_eval(op, &arg_values[..], None)
                         };
                         match const_val {
                             Some(ConstVal::I32(val)) => {
@@ -268,11 +341,22 @@ impl<'a> BasicOptPass<'a> {
                         }
                     }
                 }
+                if self.options.peephole {
+                    if let Some(narrow) = wrap_of_extend(body, &self.uses, &value) {
+                        body.set_alias(inst, narrow);
+                        body.copy_source_loc(inst, narrow);
+                        i -= 1;
+                        body.blocks[block].insts.remove(i);
+                        self.changed = true;
+                        continue;
+                    }
+                }
                 if self.options.gvn {
                     // GVN: look for already-existing copies of this
                     // value.
                     if let Some(value) = self.map.get(&value) {
                         body.set_alias(inst, *value);
+                        body.copy_source_loc(inst, *value);
                         i -= 1;
                         body.blocks[block].insts.remove(i);
                         self.changed = true;
@@ -297,3 +381,138 @@ impl<'a> BasicOptPass<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::{module_diff, new_sig};
+
+    #[test]
+    fn diff_reports_optimized_funcs() {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+        // `f(x) = (x + 1) * (x + 1)`, with the addition duplicated.
+        let mut f = FunctionBuilder::new(&m, sig);
+        let entry = f.entry();
+        let x = f.params(entry)[0];
+        let one = f.const_i32(entry, 1);
+        let y = f.push_op(entry, Operator::I32Add, &[x, one], &[Type::I32]);
+        let z = f.push_op(entry, Operator::I32Add, &[x, one], &[Type::I32]);
+        let r = f.push_op(entry, Operator::I32Mul, &[y, z], &[Type::I32]);
+        f.ret(entry, &[r]);
+        let f = m.funcs.push(FuncDecl::Body(sig, "f".into(), f.finish()));
+        // `g(x) = x`, which has nothing to optimize.
+        let mut g = FunctionBuilder::new(&m, sig);
+        let entry = g.entry();
+        let x = g.params(entry)[0];
+        g.ret(entry, &[x]);
+        m.funcs.push(FuncDecl::Body(sig, "g".into(), g.finish()));
+
+        let mut optimized = m.clone();
+        optimize_module(&mut optimized, &OptOptions::default());
+        for decl in optimized.funcs.values_mut() {
+            decl.body_mut().unwrap().apply_aliases();
+        }
+
+        let diff = module_diff(&m, &optimized);
+        assert!(diff.funcs_added.is_empty() && diff.funcs_removed.is_empty());
+        assert!(diff.sigs_changed.is_empty());
+        assert_eq!(diff.funcs_changed.len(), 1);
+        let changed = &diff.funcs_changed[0];
+        assert_eq!(changed.func, f);
+        assert_eq!(changed.values, Some((5, 4)));
+        assert_eq!(changed.blocks, None);
+        assert_eq!(changed.name, None);
+        assert!(module_diff(&optimized, &optimized).is_empty());
+    }
+
+    #[test]
+    fn gvn_keeps_source_locs() {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+        let file = m.debug.intern_file("f.c");
+        let add_loc = m.debug.intern_loc(file, 3, 7);
+        let mul_loc = m.debug.intern_loc(file, 4, 1);
+        let mut f = FunctionBuilder::new(&m, sig);
+        let entry = f.entry();
+        let x = f.params(entry)[0];
+        let y = f.push_op(entry, Operator::I32Add, &[x, x], &[Type::I32]);
+        let z = f.push_op(entry, Operator::I32Add, &[x, x], &[Type::I32]);
+        let r = f.push_op(entry, Operator::I32Mul, &[y, z], &[Type::I32]);
+        f.ret(entry, &[r]);
+        let mut body = f.finish();
+        // Only the duplicate carries a location; the copy that
+        // survives should inherit it.
+        body.source_locs[z] = add_loc;
+        body.source_locs[r] = mul_loc;
+
+        optimize(&mut body, &OptOptions::default());
+        assert_eq!(body.resolve_alias(z), y);
+        assert_eq!(body.source_locs[y], add_loc);
+        assert_eq!(body.source_locs[r], mul_loc);
+    }
+
+    /// Build `f(x: i32) -> (i32, i64)` returning `wrap(extend(x))`
+    /// and, if `keep_wide`, the extended value itself (otherwise a
+    /// constant). Returns the body, `x` and the wrap.
+    fn wrap_extend(extend: Operator, keep_wide: bool) -> (FunctionBody, Value, Value) {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32],
+                returns: vec![Type::I32, Type::I64],
+                shared: false,
+            },
+        );
+        let mut f = FunctionBuilder::new(&m, sig);
+        let entry = f.entry();
+        let x = f.params(entry)[0];
+        let wide = f.push_op(entry, extend, &[x], &[Type::I64]);
+        let narrow = f.push_op(entry, Operator::I32WrapI64, &[wide], &[Type::I32]);
+        let other = if keep_wide {
+            wide
+        } else {
+            f.const_i64(entry, 0)
+        };
+        f.ret(entry, &[narrow, other]);
+        (f.finish(), x, narrow)
+    }
+
+    #[test]
+    fn wrap_of_extend_folds() {
+        for extend in [Operator::I64ExtendI32U, Operator::I64ExtendI32S] {
+            let (mut body, x, narrow) = wrap_extend(extend, false);
+            optimize(&mut body, &OptOptions::default());
+            body.validate().unwrap();
+            assert_eq!(body.resolve_alias(narrow), x, "{}", extend);
+        }
+    }
+
+    #[test]
+    fn wrap_of_shared_extend_kept() {
+        for extend in [Operator::I64ExtendI32U, Operator::I64ExtendI32S] {
+            let (mut body, _, narrow) = wrap_extend(extend, true);
+            optimize(&mut body, &OptOptions::default());
+            assert_eq!(body.resolve_alias(narrow), narrow, "{}", extend);
+            assert!(matches!(
+                body.values[narrow],
+                ValueDef::Operator(Operator::I32WrapI64, ..)
+            ));
+        }
+    }
+}
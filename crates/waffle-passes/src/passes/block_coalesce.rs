@@ -0,0 +1,144 @@
+//! Merging of blocks into their sole predecessor.
+use crate::{Block, CFGInfo, FunctionBody, Terminator};
+use alloc::vec::Vec;
+
+/// Whenever a block `a` ends in an unconditional branch to a block
+/// `b` other than the entry whose only incoming edge is that branch,
+/// append `b`'s instructions and terminator to `a`, turning `b`'s
+/// blockparams into aliases of the branch arguments. Chains of such
+/// blocks collapse into their first block. Merged blocks are left
+/// empty and unreachable. Returns whether anything changed; `cfg` is
+/// stale afterward.
+pub fn run(body: &mut FunctionBody, cfg: &CFGInfo) -> bool {
+    let mut changed = false;
+    let mut merged: Vec<Block> = vec![];
+    for block in cfg.rpo_blocks() {
+        if merged.contains(&block) {
+            continue;
+        }
+        // The block whose terminator `block` currently ends with.
+        let mut tail = block;
+        loop {
+            let target = match &body.blocks[block].terminator.terminator {
+                Terminator::Br { target } => target.clone(),
+                _ => break,
+            };
+            let succ = target.block;
+            if succ == block || succ == body.entry || cfg.preds[succ][..] != [tail] {
+                break;
+            }
+            log::trace!("block_coalesce: merging {} into {}", succ, block);
+            let params = core::mem::take(&mut body.blocks[succ].params);
+            for ((_, param), arg) in params.into_iter().zip(target.args) {
+                body.set_alias(param, arg);
+            }
+            let insts = core::mem::take(&mut body.blocks[succ].insts);
+            for inst in insts {
+                body.append_record_to_block(block, inst);
+            }
+            body.blocks[block].terminator.terminator = core::mem::replace(
+                &mut body.blocks[succ].terminator.terminator,
+                Terminator::Unreachable,
+            );
+            merged.push(succ);
+            tail = succ;
+            changed = true;
+        }
+    }
+    if changed {
+        body.recompute_edges();
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{util::new_sig, ConstVal, FuncDecl, FunctionBuilder, InterpContext, Module};
+    use crate::{Operator, SignatureData, Type};
+
+    #[test]
+    fn collapses_straight_line_chain() {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+        let mut b = FunctionBuilder::new(&m, sig);
+        let entry = b.entry();
+        let x = b.params(entry)[0];
+        let one = b.const_i32(entry, 1);
+        let y = b.push_op(entry, Operator::I32Add, &[x, one], &[Type::I32]);
+        let second = b.add_block();
+        let p = b.add_blockparam(second, Type::I32);
+        b.br(entry, second, &[y]);
+        let two = b.const_i32(second, 2);
+        let z = b.push_op(second, Operator::I32Mul, &[p, two], &[Type::I32]);
+        let third = b.add_block();
+        let q = b.add_blockparam(third, Type::I32);
+        let r = b.add_blockparam(third, Type::I32);
+        b.br(second, third, &[z, x]);
+        let sum = b.push_op(third, Operator::I32Sub, &[q, r], &[Type::I32]);
+        b.ret(third, &[sum]);
+        let original = b.finish();
+
+        let mut body = original.clone();
+        let cfg = CFGInfo::new(&body);
+        assert!(run(&mut body, &cfg));
+        body.validate().unwrap();
+        assert!(matches!(
+            body.blocks[entry].terminator.terminator,
+            Terminator::Return { .. }
+        ));
+        assert_eq!(body.blocks[entry].insts.len(), 5);
+        assert_eq!(body.resolve_alias(p), y);
+        assert_eq!(body.resolve_alias(r), x);
+        let cfg = CFGInfo::new(&body);
+        assert_eq!(cfg.rpo_blocks().collect::<Vec<_>>(), vec![entry]);
+
+        let f = m
+            .funcs
+            .push(FuncDecl::Body(sig, "original".into(), original));
+        let g = m.funcs.push(FuncDecl::Body(sig, "coalesced".into(), body));
+        let mut ctx = InterpContext::new(&m).unwrap();
+        for x in [0, 3, u32::MAX] {
+            let expected = ctx.call(&m, f, &[ConstVal::I32(x)]).ok().unwrap();
+            let actual = ctx.call(&m, g, &[ConstVal::I32(x)]).ok().unwrap();
+            assert_eq!(actual, expected, "{}", x);
+        }
+    }
+
+    #[test]
+    fn keeps_join_points() {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+        let mut b = FunctionBuilder::new(&m, sig);
+        let entry = b.entry();
+        let x = b.params(entry)[0];
+        let left = b.add_block();
+        let right = b.add_block();
+        let join = b.add_block();
+        let v = b.add_blockparam(join, Type::I32);
+        b.cond_br(entry, x, (left, &[]), (right, &[]));
+        b.br(left, join, &[x]);
+        let one = b.const_i32(right, 1);
+        b.br(right, join, &[one]);
+        b.ret(join, &[v]);
+        let mut body = b.finish();
+
+        let cfg = CFGInfo::new(&body);
+        assert!(!run(&mut body, &cfg));
+        assert_eq!(body.blocks[join].preds.len(), 2);
+    }
+}
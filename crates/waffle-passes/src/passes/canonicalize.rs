@@ -0,0 +1,151 @@
+//! Canonical value numbering.
+//!
+//! Transforms allocate fresh `Value`s as they go, so two functions
+//! that are identical up to value numbering can print differently.
+//! This pass renumbers values densely in a fixed order, so that such
+//! functions end up with identical definitions.
+use crate::{CFGInfo, EntityRef, EntityVec, FunctionBody, ListPool, PerEntity, Value, ValueDef};
+use alloc::vec::Vec;
+
+/// Renumber every value in `body`: blockparams and then instructions
+/// of each block, visiting reachable blocks in reverse postorder and
+/// then unreachable ones in block order. Aliases are resolved and
+/// dropped, as are `ValueDef::None` values. Blocks and instruction
+/// order are untouched.
+pub fn run(body: &mut FunctionBody) {
+    body.apply_aliases();
+    let cfg = CFGInfo::new(body);
+    let blocks = cfg
+        .rpo_blocks()
+        .chain(
+            body.blocks
+                .iter()
+                .filter(|&block| cfg.rpo_pos[block].is_none()),
+        )
+        .collect::<Vec<_>>();
+    let mut order = vec![];
+    for block in blocks {
+        order.extend(body.blocks[block].params.iter().map(|&(_, param)| param));
+        order.extend(body.blocks[block].insts.iter().map(|inst| inst.value));
+    }
+    renumber(body, order);
+}
+
+/// Renumber the values in `order` to `v0`, `v1`, ... in that order,
+/// followed by any other values that are neither aliases nor
+/// `ValueDef::None`, and rewrite all references.
+fn renumber(body: &mut FunctionBody, mut order: Vec<Value>) {
+    let mut placed: PerEntity<Value, bool> = PerEntity::default();
+    for &value in &order {
+        placed[value] = true;
+    }
+    order.extend(body.values.iter().filter(|&value| {
+        !placed[value] && !matches!(body.values[value], ValueDef::None | ValueDef::Alias(_))
+    }));
+
+    let mut remap: PerEntity<Value, Value> = PerEntity::default();
+    for (i, &value) in order.iter().enumerate() {
+        remap[value] = Value::new(i);
+    }
+    let mut values = EntityVec::default();
+    let mut arg_pool = ListPool::default();
+    let mut value_blocks = PerEntity::default();
+    let mut value_locals = PerEntity::default();
+    let mut source_locs = PerEntity::default();
+    let mut value_comments = PerEntity::default();
+    for &old in &order {
+        let def = match &body.values[old] {
+            &ValueDef::Operator(op, args, tys) => {
+                let args = body.arg_pool[args].iter().map(|&arg| remap[arg]);
+                ValueDef::Operator(op, arg_pool.from_iter(args), tys)
+            }
+            &ValueDef::PickOutput(from, idx, ty) => ValueDef::PickOutput(remap[from], idx, ty),
+            def => def.clone(),
+        };
+        let new = values.push(def);
+        value_blocks[new] = body.value_blocks[old];
+        value_locals[new] = body.value_locals[old];
+        source_locs[new] = body.source_locs[old];
+        value_comments[new] = body.value_comments[old].take();
+    }
+    body.values = values;
+    body.arg_pool = arg_pool;
+    body.value_blocks = value_blocks;
+    body.value_locals = value_locals;
+    body.source_locs = source_locs;
+    body.value_comments = value_comments;
+
+    for block in body.blocks.values_mut() {
+        for (_, param) in &mut block.params {
+            *param = remap[*param];
+        }
+        for inst in &mut block.insts {
+            *inst = inst.with_value(|value| *value = remap[*value]).1;
+        }
+        block.terminator.update_uses(|value| *value = remap[*value]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{util::new_sig, FunctionBuilder, Module, Operator, SignatureData, Type};
+
+    #[test]
+    fn renumbered_bodies_agree() {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32, Type::I32],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+        let mut b = FunctionBuilder::new(&m, sig);
+        let entry = b.entry();
+        let params = b.params(entry);
+        let (x, y) = (params[0], params[1]);
+        let header = b.add_block();
+        let i = b.add_blockparam(header, Type::I32);
+        let exit = b.add_block();
+        let zero = b.const_i32(entry, 0);
+        b.br(entry, header, &[zero]);
+        let step = b.push_op(header, Operator::I32Add, &[x, y], &[Type::I32]);
+        let next = b.push_op(header, Operator::I32Add, &[i, step], &[Type::I32]);
+        let cond = b.push_op(header, Operator::I32LtU, &[next, x], &[Type::I32]);
+        b.cond_br(header, cond, (header, &[next]), (exit, &[]));
+        b.ret(exit, &[next]);
+        let mut body = b.finish();
+        // A stray alias, as left behind by other passes.
+        let alias = body.add_value(ValueDef::Alias(step));
+        body.set_terminator(
+            exit,
+            crate::Terminator::Return {
+                values: vec![alias],
+            },
+        );
+
+        let mut shuffled = body.clone();
+        let mut order = shuffled.values.iter().collect::<Vec<_>>();
+        order.retain(|&value| !matches!(shuffled.values[value], ValueDef::Alias(_)));
+        order.reverse();
+        shuffled.apply_aliases();
+        renumber(&mut shuffled, order);
+        shuffled.validate().unwrap();
+        assert_ne!(
+            format!("{}", body.display("", None)),
+            format!("{}", shuffled.display("", None))
+        );
+
+        run(&mut body);
+        run(&mut shuffled);
+        body.validate().unwrap();
+        assert_eq!(
+            format!("{}", body.display("", None)),
+            format!("{}", shuffled.display("", None))
+        );
+        // Entry blockparams come first.
+        assert_eq!(body.blocks[body.entry].params[0].1, Value::new(0));
+    }
+}
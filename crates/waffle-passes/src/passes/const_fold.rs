@@ -0,0 +1,184 @@
+//! Constant folding of pure operators whose arguments are all constants.
+use crate::interp::{const_eval, ConstVal};
+use crate::{FunctionBody, ListRef, Operator, SideEffect, Type, Value, ValueDef};
+use alloc::vec::Vec;
+
+/// The constant a value resolves to, if it is defined by a constant
+/// operator (possibly through aliases).
+fn const_value(body: &FunctionBody, value: Value) -> Option<ConstVal> {
+    match &body.values[body.resolve_alias(value)] {
+        ValueDef::Operator(Operator::I32Const { value }, _, _) => Some(ConstVal::I32(*value)),
+        ValueDef::Operator(Operator::I64Const { value }, _, _) => Some(ConstVal::I64(*value)),
+        ValueDef::Operator(Operator::F32Const { value }, _, _) => Some(ConstVal::F32(*value)),
+        ValueDef::Operator(Operator::F64Const { value }, _, _) => Some(ConstVal::F64(*value)),
+        _ => None,
+    }
+}
+
+/// Operators whose float result is fully determined by the bits of
+/// their inputs, so a NaN result can be folded without changing its
+/// payload.
+fn is_bitwise_float_op(op: &Operator) -> bool {
+    matches!(
+        op,
+        Operator::F32Abs
+            | Operator::F32Neg
+            | Operator::F32Copysign
+            | Operator::F64Abs
+            | Operator::F64Neg
+            | Operator::F64Copysign
+            | Operator::F32ReinterpretI32
+            | Operator::F64ReinterpretI64
+    )
+}
+
+/// Fold the given operator over constant arguments. Returns `None`
+/// if the operator would trap, or if it would produce a NaN whose
+/// payload Wasm leaves nondeterministic.
+fn fold(op: &Operator, args: &[ConstVal]) -> Option<(Operator, Type)> {
+    let result = const_eval(op, args, None)?;
+    let nan = match result {
+        ConstVal::F32(bits) => f32::from_bits(bits).is_nan(),
+        ConstVal::F64(bits) => f64::from_bits(bits).is_nan(),
+        _ => false,
+    };
+    if nan && !is_bitwise_float_op(op) {
+        return None;
+    }
+    Some(match result {
+        ConstVal::I32(value) => (Operator::I32Const { value }, Type::I32),
+        ConstVal::I64(value) => (Operator::I64Const { value }, Type::I64),
+        ConstVal::F32(value) => (Operator::F32Const { value }, Type::F32),
+        ConstVal::F64(value) => (Operator::F64Const { value }, Type::F64),
+        _ => return None,
+    })
+}
+
+/// Replace every foldable operator in `body` with the constant it
+/// computes. Operators that trap on their given arguments (e.g.,
+/// integer division by zero) are left in place. Returns whether
+/// anything changed.
+pub fn run(body: &mut FunctionBody) -> bool {
+    let mut changed = false;
+    loop {
+        let mut round = false;
+        for block in body.blocks.iter().collect::<Vec<_>>() {
+            for i in 0..body.blocks[block].insts.len() {
+                let inst = body.blocks[block].insts[i].value;
+                let (op, args, tys) = match &body.values[inst] {
+                    ValueDef::Operator(op, args, tys) => (op.clone(), *args, *tys),
+                    _ => continue,
+                };
+                if matches!(
+                    op,
+                    Operator::I32Const { .. }
+                        | Operator::I64Const { .. }
+                        | Operator::F32Const { .. }
+                        | Operator::F64Const { .. }
+                        | Operator::V128Const { .. }
+                ) {
+                    continue;
+                }
+                if tys.len() != 1 || !op.effects().iter().all(|e| *e == SideEffect::Trap) {
+                    continue;
+                }
+                let Some(consts) = body.arg_pool[args]
+                    .iter()
+                    .map(|&arg| const_value(body, arg))
+                    .collect::<Option<Vec<_>>>()
+                else {
+                    continue;
+                };
+                let Some((new_op, ty)) = fold(&op, &consts[..]) else {
+                    continue;
+                };
+                let ty = body.single_type_list(ty);
+                body.values[inst] = ValueDef::Operator(new_op, ListRef::default(), ty);
+                round = true;
+            }
+        }
+        if !round {
+            break;
+        }
+        changed = true;
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{util::new_sig, Module, SignatureData, Terminator};
+
+    fn body_returning(ty: Type) -> FunctionBody {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![],
+                returns: vec![ty],
+                shared: false,
+            },
+        );
+        FunctionBody::new(&m, sig)
+    }
+
+    fn op_of(body: &FunctionBody, value: Value) -> Operator {
+        match &body.values[value] {
+            ValueDef::Operator(op, _, _) => op.clone(),
+            def => panic!("not an operator: {:?}", def),
+        }
+    }
+
+    #[test]
+    fn folds_with_wraparound() {
+        let mut body = body_returning(Type::I32);
+        let b = body.entry;
+        let x = body.add_op(b, Operator::I32Const { value: u32::MAX }, &[], &[Type::I32]);
+        let y = body.add_op(b, Operator::I32Const { value: 2 }, &[], &[Type::I32]);
+        let sum = body.add_op(b, Operator::I32Add, &[x, y], &[Type::I32]);
+        let cmp = body.add_op(b, Operator::I32LtU, &[sum, y], &[Type::I32]);
+        body.set_terminator(b, Terminator::Return { values: vec![cmp] });
+        assert!(run(&mut body));
+        assert_eq!(op_of(&body, sum), Operator::I32Const { value: 1 });
+        assert_eq!(op_of(&body, cmp), Operator::I32Const { value: 1 });
+    }
+
+    #[test]
+    fn leaves_trapping_division() {
+        let mut body = body_returning(Type::I32);
+        let b = body.entry;
+        let x = body.add_op(b, Operator::I32Const { value: 7 }, &[], &[Type::I32]);
+        let zero = body.add_op(b, Operator::I32Const { value: 0 }, &[], &[Type::I32]);
+        let div = body.add_op(b, Operator::I32DivS, &[x, zero], &[Type::I32]);
+        body.set_terminator(b, Terminator::Return { values: vec![div] });
+        assert!(!run(&mut body));
+        assert_eq!(op_of(&body, div), Operator::I32DivS);
+    }
+
+    #[test]
+    fn preserves_nan_bits() {
+        let mut body = body_returning(Type::F32);
+        let b = body.entry;
+        let nan = body.add_op(
+            b,
+            Operator::F32Const { value: 0x7fc0_0001 },
+            &[],
+            &[Type::F32],
+        );
+        let one = body.add_op(
+            b,
+            Operator::F32Const {
+                value: 1.0f32.to_bits(),
+            },
+            &[],
+            &[Type::F32],
+        );
+        let neg = body.add_op(b, Operator::F32Neg, &[nan], &[Type::F32]);
+        let sum = body.add_op(b, Operator::F32Add, &[nan, one], &[Type::F32]);
+        body.set_terminator(b, Terminator::Return { values: vec![sum] });
+        run(&mut body);
+        assert_eq!(op_of(&body, neg), Operator::F32Const { value: 0xffc0_0001 });
+        assert_eq!(op_of(&body, sum), Operator::F32Add);
+    }
+}
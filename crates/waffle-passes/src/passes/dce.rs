@@ -0,0 +1,45 @@
+//! Dead-code elimination of side-effect-free instructions.
+use crate::{FunctionBody, PerEntity, Value, ValueDef};
+use alloc::vec::Vec;
+
+/// Can `value` be dropped from its block if nothing uses it?
+fn is_removable(body: &FunctionBody, value: Value) -> bool {
+    match &body.values[value] {
+        ValueDef::Operator(op, ..) => op.is_pure(),
+        ValueDef::PickOutput(..) | ValueDef::Alias(_) | ValueDef::None => true,
+        _ => false,
+    }
+}
+
+/// Remove every pure instruction whose results are not used,
+/// directly or transitively, by a terminator or an instruction with
+/// side effects. Returns whether anything was removed.
+pub fn run(body: &mut FunctionBody) -> bool {
+    let mut live: PerEntity<Value, bool> = PerEntity::default();
+    let mut worklist: Vec<Value> = vec![];
+    for block in body.blocks.values() {
+        block.terminator.visit_uses(|value| worklist.push(value));
+        for inst in &block.insts {
+            if !is_removable(body, inst.value) {
+                worklist.push(inst.value);
+            }
+        }
+    }
+    while let Some(value) = worklist.pop() {
+        if live[value] {
+            continue;
+        }
+        live[value] = true;
+        if !matches!(body.values[value], ValueDef::None) {
+            body.values[value].visit_uses(&body.arg_pool, |arg| worklist.push(arg));
+        }
+    }
+
+    let mut changed = false;
+    for block in body.blocks.values_mut() {
+        let len = block.insts.len();
+        block.insts.retain(|inst| live[inst.value]);
+        changed |= block.insts.len() != len;
+    }
+    changed
+}
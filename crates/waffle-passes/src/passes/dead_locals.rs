@@ -0,0 +1,79 @@
+//! Dead-local elimination: drop declared locals that no value is
+//! associated with, renumbering the remainder.
+use crate::{EntityRef, EntityVec, FunctionBody, Local};
+use alloc::vec::Vec;
+
+/// Remove every non-parameter local that no value in `body` maps to
+/// via `value_locals`, compacting `body.locals` and rewriting the
+/// remaining references. Returns whether any local was removed.
+pub fn run(body: &mut FunctionBody) -> bool {
+    let mut used = vec![false; body.locals.len()];
+    for used in used.iter_mut().take(body.n_params) {
+        *used = true;
+    }
+    for value in body.values.iter() {
+        if let Some(local) = body.value_locals[value] {
+            used[local.index()] = true;
+        }
+    }
+    if used.iter().all(|&used| used) {
+        return false;
+    }
+
+    let mut remap: Vec<Option<Local>> = vec![None; used.len()];
+    let mut locals = EntityVec::default();
+    for (local, &ty) in body.locals.entries() {
+        if used[local.index()] {
+            remap[local.index()] = Some(locals.push(ty));
+        }
+    }
+    log::trace!(
+        "dead_locals: {} -> {} locals",
+        body.locals.len(),
+        locals.len()
+    );
+    body.locals = locals;
+    for value in body.values.iter() {
+        if let Some(local) = body.value_locals[value] {
+            body.value_locals[value] = remap[local.index()];
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{util::new_sig, Module, Operator, SignatureData, Terminator, Type};
+
+    #[test]
+    fn removes_unused_local() {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32],
+                returns: vec![Type::I64],
+                shared: false,
+            },
+        );
+        let mut body = FunctionBody::new(&m, sig);
+        let entry = body.entry;
+        let x = body.blocks[entry].params[0].1;
+        body.locals.push(Type::F64);
+        let kept = body.locals.push(Type::I64);
+        let y = body.add_op(entry, Operator::I64ExtendI32U, &[x], &[Type::I64]);
+        body.mark_value_as_local(x, Local::new(0));
+        body.mark_value_as_local(y, kept);
+        body.set_terminator(entry, Terminator::Return { values: vec![y] });
+
+        assert!(run(&mut body));
+        assert_eq!(
+            body.locals.values().copied().collect::<Vec<_>>(),
+            vec![Type::I32, Type::I64]
+        );
+        assert_eq!(body.value_locals[x], Some(Local::new(0)));
+        assert_eq!(body.value_locals[y], Some(Local::new(1)));
+        assert!(!run(&mut body));
+    }
+}
@@ -17,8 +17,7 @@ pub fn dom_pass<P: DomtreePass>(body: &mut FunctionBody, cfg: &CFGInfo, pass: &m
         } else {
             pass.enter(block, body);
             stack.push((block, true));
-            let children: Vec<Block> = cfg.dom_children(block).collect();
-            for child in children.into_iter().rev() {
+            for &child in cfg.dom_children(block).iter().rev() {
                 stack.push((child, false));
             }
         }
@@ -0,0 +1,97 @@
+//! Folding of conditional branches on constant conditions.
+//!
+//! A `CondBr` whose condition resolves to an `i32.const` always goes
+//! the same way, so it is replaced by a `Br` to the taken target. The
+//! untaken target loses that edge, and may become unreachable;
+//! `FunctionBody::remove_unreachable_blocks` can then delete it.
+use crate::{FunctionBody, Operator, Terminator, ValueDef};
+use alloc::vec::Vec;
+
+/// Turn every `CondBr` on a constant condition into a `Br`, and
+/// recompute the CFG edges if anything changed. Returns whether
+/// anything changed.
+pub fn run(body: &mut FunctionBody) -> bool {
+    let mut changed = false;
+    for block in body.blocks.iter().collect::<Vec<_>>() {
+        let target = match &body.blocks[block].terminator.terminator {
+            Terminator::CondBr {
+                cond,
+                if_true,
+                if_false,
+            } => match &body.values[body.resolve_alias(*cond)] {
+                ValueDef::Operator(Operator::I32Const { value }, ..) => {
+                    if *value != 0 {
+                        if_true.clone()
+                    } else {
+                        if_false.clone()
+                    }
+                }
+                _ => continue,
+            },
+            _ => continue,
+        };
+        body.blocks[block].terminator.terminator = Terminator::Br { target };
+        changed = true;
+    }
+    if changed {
+        body.recompute_edges();
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{util::new_sig, BlockTarget, CFGInfo, Module, SignatureData, Type};
+
+    #[test]
+    fn constant_cond_br_becomes_br() {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+        let mut body = FunctionBody::new(&m, sig);
+        let entry = body.entry;
+        let (taken, untaken) = (body.add_block(), body.add_block());
+        let one = body.add_op(entry, Operator::I32Const { value: 1 }, &[], &[Type::I32]);
+        let two = body.add_op(entry, Operator::I32Const { value: 2 }, &[], &[Type::I32]);
+        body.set_terminator(
+            entry,
+            Terminator::CondBr {
+                cond: one,
+                if_true: BlockTarget {
+                    block: taken,
+                    args: vec![],
+                },
+                if_false: BlockTarget {
+                    block: untaken,
+                    args: vec![],
+                },
+            },
+        );
+        body.set_terminator(taken, Terminator::Return { values: vec![one] });
+        body.set_terminator(untaken, Terminator::Return { values: vec![two] });
+
+        assert!(run(&mut body));
+        assert_eq!(
+            body.blocks[entry].terminator.terminator,
+            Terminator::Br {
+                target: BlockTarget {
+                    block: taken,
+                    args: vec![],
+                },
+            }
+        );
+        assert!(body.blocks[untaken].preds.is_empty());
+        let cfg = CFGInfo::new(&body);
+        assert!(cfg.rpo_pos[untaken].is_none());
+        assert!(cfg.rpo_pos[taken].is_some());
+        body.validate().unwrap();
+        assert!(!run(&mut body));
+    }
+}
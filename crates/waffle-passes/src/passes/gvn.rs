@@ -0,0 +1,140 @@
+//! Global value numbering over pure operators.
+//!
+//! Walks the dominator tree keeping a scoped table from (operator,
+//! resolved arguments, result types) to the first value computing
+//! it; any later identical computation dominated by that value is
+//! replaced by an alias to it.
+use super::dom_pass::{dom_pass, DomtreePass};
+use crate::{Block, CFGInfo, FunctionBody, Operator, Type, Value, ValueDef};
+use alloc::vec::Vec;
+use waffle_ir::scoped_map::ScopedMap;
+
+type Key = (Operator, Vec<Value>, Vec<Type>);
+
+struct GVNPass<'a> {
+    cfg: &'a CFGInfo,
+    map: ScopedMap<Key, (Value, Block)>,
+    changed: bool,
+}
+
+impl<'a> DomtreePass for GVNPass<'a> {
+    fn enter(&mut self, block: Block, body: &mut FunctionBody) {
+        self.map.push_scope();
+        let mut i = 0;
+        while i < body.blocks[block].insts.len() {
+            let inst = body.blocks[block].insts[i].value;
+            if let Some(key) = key_of(body, inst) {
+                match self.map.get(&key) {
+                    Some(&(value, def_block)) if self.cfg.dominates(def_block, block) => {
+                        body.set_alias(inst, value);
+                        body.blocks[block].insts.remove(i);
+                        self.changed = true;
+                        continue;
+                    }
+                    _ => self.map.insert(key, (inst, block)),
+                }
+            }
+            i += 1;
+        }
+    }
+
+    fn leave(&mut self, _block: Block, _body: &mut FunctionBody) {
+        self.map.pop_scope();
+    }
+}
+
+fn key_of(body: &FunctionBody, value: Value) -> Option<Key> {
+    let ValueDef::Operator(op, args, tys) = &body.values[value] else {
+        return None;
+    };
+    if !op.is_pure() {
+        return None;
+    }
+    let mut args = body.arg_pool[*args]
+        .iter()
+        .map(|&arg| body.resolve_alias(arg))
+        .collect::<Vec<_>>();
+    if op.is_commutative() && args.len() == 2 {
+        args.sort();
+    }
+    Some((op.clone(), args, body.type_pool[*tys].to_vec()))
+}
+
+/// Replace each pure computation that is dominated by an identical
+/// one with an alias to the earlier value. Returns whether anything
+/// changed.
+pub fn run(body: &mut FunctionBody, cfg: &CFGInfo) -> bool {
+    let mut pass = GVNPass {
+        cfg,
+        map: ScopedMap::default(),
+        changed: false,
+    };
+    dom_pass(body, cfg, &mut pass);
+    pass.changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        util::new_sig, BlockTarget, MemoryArg, MemoryData, Module, SignatureData, Terminator,
+    };
+
+    #[test]
+    fn coalesces_pure_but_not_loads() {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32, Type::I32],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+        let mut body = FunctionBody::new(&m, sig);
+        let entry = body.entry;
+        let a = body.blocks[entry].params[0].1;
+        let b = body.blocks[entry].params[1].1;
+        let t1 = body.add_op(entry, Operator::I32Add, &[a, b], &[Type::I32]);
+        let t2 = body.add_op(entry, Operator::I32Add, &[b, a], &[Type::I32]);
+        let memory = MemoryArg {
+            align: 2,
+            offset: 0,
+            memory: m.memories.push(MemoryData {
+                initial_pages: 1,
+                maximum_pages: None,
+                segments: vec![],
+                memory64: false,
+                shared: false,
+                page_size_log2: None,
+            }),
+        };
+        let l1 = body.add_op(entry, Operator::I32Load { memory }, &[a], &[Type::I32]);
+        let l2 = body.add_op(entry, Operator::I32Load { memory }, &[a], &[Type::I32]);
+        let next = body.add_block();
+        body.set_terminator(
+            entry,
+            Terminator::Br {
+                target: BlockTarget {
+                    block: next,
+                    args: vec![],
+                },
+            },
+        );
+        let t3 = body.add_op(next, Operator::I32Add, &[a, b], &[Type::I32]);
+        let x = body.add_op(next, Operator::I32Xor, &[t1, t2], &[Type::I32]);
+        let y = body.add_op(next, Operator::I32Xor, &[x, t3], &[Type::I32]);
+        let z = body.add_op(next, Operator::I32Sub, &[l1, l2], &[Type::I32]);
+        let r = body.add_op(next, Operator::I32Or, &[y, z], &[Type::I32]);
+        body.set_terminator(next, Terminator::Return { values: vec![r] });
+
+        let cfg = CFGInfo::new(&body);
+        assert!(run(&mut body, &cfg));
+        assert_eq!(body.resolve_alias(t2), t1);
+        assert_eq!(body.resolve_alias(t3), t1);
+        assert_eq!(body.resolve_alias(l2), l2);
+        assert_eq!(body.blocks[entry].insts.len(), 3);
+        assert_eq!(body.blocks[next].insts.len(), 4);
+        body.validate().unwrap();
+    }
+}
@@ -0,0 +1,150 @@
+//! Hoisting of identical instructions out of both arms of a branch.
+//!
+//! When both successors of a `CondBr` are entered only from that
+//! branch and begin with the same pure instruction on the same
+//! arguments, the instruction computes the same value on either path
+//! and can run before the branch instead. The copy in the taken arm
+//! is moved into the predecessor and the other becomes an alias of
+//! it; this repeats for as long as the arms keep starting alike.
+use crate::{Block, CFGInfo, FunctionBody, Terminator, Value, ValueDef};
+use alloc::vec::Vec;
+
+/// Hoist the common prefix of pure instructions out of the arms of
+/// every conditional branch whose arms have no other predecessor.
+/// Returns whether anything changed; `cfg` stays valid, since no
+/// edges are added or removed.
+pub fn run(body: &mut FunctionBody, cfg: &CFGInfo) -> bool {
+    let mut changed = false;
+    for block in body.blocks.iter().collect::<Vec<_>>() {
+        let (if_true, if_false) = match &body.blocks[block].terminator.terminator {
+            Terminator::CondBr {
+                if_true, if_false, ..
+            } if if_true.block != if_false.block => (if_true.block, if_false.block),
+            _ => continue,
+        };
+        if cfg.preds[if_true][..] != [block] || cfg.preds[if_false][..] != [block] {
+            continue;
+        }
+        while let (Some(a), Some(b)) = (
+            body.blocks[if_true].insts.first().map(|inst| inst.value),
+            body.blocks[if_false].insts.first().map(|inst| inst.value),
+        ) {
+            if !same_pure_inst(body, a, b, [if_true, if_false]) {
+                break;
+            }
+            let inst = body.blocks[if_true].insts.remove(0);
+            body.append_record_to_block(block, inst);
+            body.blocks[if_false].insts.remove(0);
+            body.set_alias(b, a);
+            body.copy_source_loc(b, a);
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Do `a` and `b` compute the same pure result from arguments that
+/// are all available before the branch into `arms`?
+fn same_pure_inst(body: &FunctionBody, a: Value, b: Value, arms: [Block; 2]) -> bool {
+    let before_branch = |arg: Value| {
+        let arg = body.resolve_alias(arg);
+        !arms.contains(&body.value_blocks[arg])
+    };
+    match (&body.values[a], &body.values[b]) {
+        (ValueDef::Operator(op_a, args_a, tys_a), ValueDef::Operator(op_b, args_b, tys_b)) => {
+            let (args_a, args_b) = (&body.arg_pool[*args_a], &body.arg_pool[*args_b]);
+            op_a == op_b
+                && op_a.is_pure()
+                && body.type_pool[*tys_a] == body.type_pool[*tys_b]
+                && args_a.len() == args_b.len()
+                && args_a
+                    .iter()
+                    .zip(args_b.iter())
+                    .all(|(&x, &y)| body.resolve_alias(x) == body.resolve_alias(y))
+                && args_a.iter().all(|&arg| before_branch(arg))
+        }
+        // Outputs of a multi-result operator that was just hoisted.
+        (
+            &ValueDef::PickOutput(from_a, idx_a, ty_a),
+            &ValueDef::PickOutput(from_b, idx_b, ty_b),
+        ) => {
+            body.resolve_alias(from_a) == body.resolve_alias(from_b)
+                && (idx_a, ty_a) == (idx_b, ty_b)
+                && before_branch(from_a)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{util::new_sig, BlockTarget, Module, Operator, SignatureData, Type};
+
+    #[test]
+    fn hoists_add_from_both_arms() {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32, Type::I32, Type::I32],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+        let mut body = FunctionBody::new(&m, sig);
+        let entry = body.entry;
+        let params = body.blocks[entry]
+            .params
+            .iter()
+            .map(|&(_, value)| value)
+            .collect::<Vec<_>>();
+        let (cond, a, b) = (params[0], params[1], params[2]);
+        let (left, right) = (body.add_block(), body.add_block());
+        body.set_terminator(
+            entry,
+            Terminator::CondBr {
+                cond,
+                if_true: BlockTarget {
+                    block: left,
+                    args: vec![],
+                },
+                if_false: BlockTarget {
+                    block: right,
+                    args: vec![],
+                },
+            },
+        );
+        let add_l = body.add_op(left, Operator::I32Add, &[a, b], &[Type::I32]);
+        let mul_l = body.add_op(left, Operator::I32Mul, &[add_l, a], &[Type::I32]);
+        body.set_terminator(
+            left,
+            Terminator::Return {
+                values: vec![mul_l],
+            },
+        );
+        let add_r = body.add_op(right, Operator::I32Add, &[a, b], &[Type::I32]);
+        let sub_r = body.add_op(right, Operator::I32Sub, &[add_r, a], &[Type::I32]);
+        body.set_terminator(
+            right,
+            Terminator::Return {
+                values: vec![sub_r],
+            },
+        );
+
+        let cfg = CFGInfo::new(&body);
+        assert!(run(&mut body, &cfg));
+        let entry_insts = body.blocks[entry]
+            .insts
+            .iter()
+            .map(|inst| inst.value)
+            .collect::<Vec<_>>();
+        assert_eq!(entry_insts, vec![add_l]);
+        assert_eq!(body.resolve_alias(add_r), add_l);
+        // The arms differ after the add, so nothing more moves.
+        assert_eq!(body.blocks[left].insts.len(), 1);
+        assert_eq!(body.blocks[right].insts.len(), 1);
+        body.validate().unwrap();
+        assert!(!run(&mut body, &cfg));
+    }
+}
@@ -0,0 +1,265 @@
+//! Narrowing of `i64` arithmetic that is only ever wrapped to `i32`.
+//!
+//! The low 32 bits of `i64.add`, `sub`, `mul`, `and`, `or`, `xor`,
+//! and of `shl` by a constant below 32, depend only on the low 32
+//! bits of their operands. A tree of these operators over
+//! `i64.extend_i32_s`/`i64.extend_i32_u` inputs and constants can
+//! thus be computed with the corresponding `i32` operators, provided
+//! every consumer of the tree's values only wants their low half,
+//! i.e. is an `i32.wrap_i64` or another narrowed operator.
+use crate::{Block, FunctionBody, Operator, Type, Value, ValueDef};
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+
+/// The `i32` counterpart of a narrowable `i64` operator.
+fn narrowed(op: &Operator) -> Option<Operator> {
+    Some(match op {
+        Operator::I64Add => Operator::I32Add,
+        Operator::I64Sub => Operator::I32Sub,
+        Operator::I64Mul => Operator::I32Mul,
+        Operator::I64And => Operator::I32And,
+        Operator::I64Or => Operator::I32Or,
+        Operator::I64Xor => Operator::I32Xor,
+        Operator::I64Shl => Operator::I32Shl,
+        _ => return None,
+    })
+}
+
+/// An `i64` operand whose low half is directly available as an
+/// `i32`.
+enum Leaf {
+    /// The input of an `i64.extend_i32_*`.
+    Extended(Value),
+    /// The low half of an `i64.const`.
+    Const(u32),
+}
+
+fn leaf(body: &FunctionBody, value: Value) -> Option<Leaf> {
+    match &body.values[value] {
+        ValueDef::Operator(Operator::I64ExtendI32S | Operator::I64ExtendI32U, args, _) => {
+            Some(Leaf::Extended(body.resolve_alias(body.arg_pool[*args][0])))
+        }
+        &ValueDef::Operator(Operator::I64Const { value }, ..) => Some(Leaf::Const(value as u32)),
+        _ => None,
+    }
+}
+
+/// Whether `value` could be narrowed given that `candidates` will
+/// be: it must be a narrowable operator whose operands are leaves or
+/// candidates, and a shift must be by a constant below 32 (larger
+/// shifts leave nothing of the low half, and `i32.shl` would take
+/// the amount modulo 32).
+fn narrowable(body: &FunctionBody, candidates: &BTreeSet<Value>, value: Value) -> bool {
+    let ValueDef::Operator(op, args, _) = &body.values[value] else {
+        return false;
+    };
+    if narrowed(op).is_none() {
+        return false;
+    }
+    let args = &body.arg_pool[*args];
+    if let Operator::I64Shl = op {
+        match leaf(body, body.resolve_alias(args[1])) {
+            Some(Leaf::Const(amount)) if amount < 32 => {}
+            _ => return false,
+        }
+    }
+    args.iter().all(|&arg| {
+        let arg = body.resolve_alias(arg);
+        candidates.contains(&arg) || leaf(body, arg).is_some()
+    })
+}
+
+fn is_wrap(body: &FunctionBody, value: Value) -> bool {
+    matches!(
+        body.values[value],
+        ValueDef::Operator(Operator::I32WrapI64, ..)
+    )
+}
+
+/// Rewrite every maximal narrowable `i64` tree in `body` into `i32`
+/// operators, turning the `i32.wrap_i64`s consuming it into aliases.
+/// The `i64` extends and constants feeding the tree are left in
+/// place for dead-code elimination to remove if otherwise unused.
+pub fn run(body: &mut FunctionBody) {
+    let mut candidates = BTreeSet::new();
+    for block in body.blocks.values() {
+        for inst in &block.insts {
+            if narrowable(body, &candidates, inst.value) {
+                candidates.insert(inst.value);
+            }
+        }
+    }
+
+    // Drop candidates that have a consumer wanting all 64 bits, and
+    // then anything built from a dropped value, until stable.
+    let uses = body.compute_uses();
+    let term_uses = body.compute_terminator_uses();
+    loop {
+        let dropped = candidates
+            .iter()
+            .cloned()
+            .filter(|&value| {
+                !term_uses[value].is_empty()
+                    || uses[value]
+                        .iter()
+                        .any(|&user| !candidates.contains(&user) && !is_wrap(body, user))
+                    || !narrowable(body, &candidates, value)
+            })
+            .collect::<Vec<_>>();
+        if dropped.is_empty() {
+            break;
+        }
+        for value in dropped {
+            candidates.remove(&value);
+        }
+    }
+    if candidates.is_empty() {
+        return;
+    }
+
+    let i32_ty = body.single_type_list(Type::I32);
+    for block in body.blocks.iter().collect::<Vec<Block>>() {
+        let insts = core::mem::take(&mut body.blocks[block].insts);
+        for inst in insts {
+            let value = inst.value;
+            let ValueDef::Operator(op, args, _) = body.values[value].clone() else {
+                body.append_record_to_block(block, inst);
+                continue;
+            };
+            if is_wrap(body, value) {
+                let arg = body.resolve_alias(body.arg_pool[args][0]);
+                if candidates.contains(&arg) {
+                    body.set_alias(value, arg);
+                    continue;
+                }
+            }
+            if candidates.contains(&value) {
+                let mut narrow_args = vec![];
+                for arg in body.arg_pool[args].to_vec() {
+                    let arg = body.resolve_alias(arg);
+                    narrow_args.push(match leaf(body, arg) {
+                        _ if candidates.contains(&arg) => arg,
+                        Some(Leaf::Extended(x)) => x,
+                        Some(Leaf::Const(value)) => {
+                            body.add_op(block, Operator::I32Const { value }, &[], &[Type::I32])
+                        }
+                        None => unreachable!(),
+                    });
+                }
+                let args = body.arg_pool.from_iter(narrow_args.into_iter());
+                body.values[value] = ValueDef::Operator(narrowed(&op).unwrap(), args, i32_ty);
+            }
+            body.append_record_to_block(block, inst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SignatureData;
+    use crate::{util::new_sig, ConstVal, FuncDecl, FunctionBuilder, InterpContext, Module};
+
+    fn sig(m: &mut Module, returns: Vec<Type>) -> crate::Signature {
+        new_sig(
+            m,
+            SignatureData::Func {
+                params: vec![Type::I32, Type::I32],
+                returns,
+                shared: false,
+            },
+        )
+    }
+
+    fn has_i64_op(body: &FunctionBody, op: Operator) -> bool {
+        body.blocks.values().any(|block| {
+            block.insts.iter().any(
+                |inst| matches!(&body.values[inst.value], ValueDef::Operator(o, ..) if *o == op),
+            )
+        })
+    }
+
+    #[test]
+    fn narrows_wrapped_chain() {
+        let mut m = Module::empty();
+        let sig = sig(&mut m, vec![Type::I32]);
+        let mut b = FunctionBuilder::new(&m, sig);
+        let entry = b.entry();
+        let params = b.params(entry);
+        let a = b.push_op(entry, Operator::I64ExtendI32U, &[params[0]], &[Type::I64]);
+        let c = b.push_op(entry, Operator::I64ExtendI32S, &[params[1]], &[Type::I64]);
+        let prod = b.push_op(entry, Operator::I64Mul, &[a, c], &[Type::I64]);
+        let three = b.const_i64(entry, 3);
+        let shifted = b.push_op(entry, Operator::I64Shl, &[a, three], &[Type::I64]);
+        let sum = b.push_op(entry, Operator::I64Sub, &[prod, shifted], &[Type::I64]);
+        let k = b.const_i64(entry, 0x1_0000_0005);
+        let sum = b.push_op(entry, Operator::I64Xor, &[sum, k], &[Type::I64]);
+        let wrapped = b.push_op(entry, Operator::I32WrapI64, &[sum], &[Type::I32]);
+        b.ret(entry, &[wrapped]);
+        let original = b.finish();
+
+        let mut body = original.clone();
+        run(&mut body);
+        body.validate().unwrap();
+        for op in [
+            Operator::I64Mul,
+            Operator::I64Shl,
+            Operator::I64Sub,
+            Operator::I64Xor,
+        ] {
+            assert!(!has_i64_op(&body, op), "{} left behind", op);
+        }
+        let root = body.resolve_alias(wrapped);
+        assert_eq!(root, sum);
+        assert!(matches!(
+            body.values[root],
+            ValueDef::Operator(Operator::I32Xor, ..)
+        ));
+
+        let f = m
+            .funcs
+            .push(FuncDecl::Body(sig, "original".into(), original));
+        let g = m.funcs.push(FuncDecl::Body(sig, "narrowed".into(), body));
+        let mut ctx = InterpContext::new(&m).unwrap();
+        for (x, y) in [
+            (0, 0),
+            (7, u32::MAX),
+            (0x8000_0001, 0x1234_5678),
+            (u32::MAX, 3),
+        ] {
+            let args = [ConstVal::I32(x), ConstVal::I32(y)];
+            let expected = ctx.call(&m, f, &args).ok().unwrap();
+            let actual = ctx.call(&m, g, &args).ok().unwrap();
+            assert_eq!(actual, expected, "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn keeps_full_width_uses() {
+        let mut m = Module::empty();
+        let sig = sig(&mut m, vec![Type::I32, Type::I64]);
+        let mut b = FunctionBuilder::new(&m, sig);
+        let entry = b.entry();
+        let params = b.params(entry);
+        let a = b.push_op(entry, Operator::I64ExtendI32U, &[params[0]], &[Type::I64]);
+        let c = b.push_op(entry, Operator::I64ExtendI32U, &[params[1]], &[Type::I64]);
+        let sum = b.push_op(entry, Operator::I64Add, &[a, c], &[Type::I64]);
+        // Only wrapped, but built from a value also returned whole.
+        let doubled = b.push_op(entry, Operator::I64Add, &[sum, sum], &[Type::I64]);
+        let wrapped = b.push_op(entry, Operator::I32WrapI64, &[doubled], &[Type::I32]);
+        b.ret(entry, &[wrapped, sum]);
+        let mut body = b.finish();
+
+        run(&mut body);
+        body.validate().unwrap();
+        assert_eq!(body.resolve_alias(wrapped), wrapped);
+        assert!(matches!(
+            body.values[sum],
+            ValueDef::Operator(Operator::I64Add, ..)
+        ));
+        assert!(matches!(
+            body.values[doubled],
+            ValueDef::Operator(Operator::I64Add, ..)
+        ));
+    }
+}
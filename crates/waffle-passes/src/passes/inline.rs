@@ -10,7 +10,7 @@ use core::{default, mem::take, usize};
 use crate::{
     const_eval, util::new_sig, util::results_ref_2, waffle_passes_shared::value_is_pure, Block,
     BlockTarget, CFGInfo, ConstVal, EntityRef, Func, FuncCollector, FuncDecl, FunctionBody,
-    ImportKind, Module, Operator, SignatureData, Terminator, Type, Value, ValueDef,
+    ImportKind, Module, Operator, PerEntity, SignatureData, Terminator, Type, Value, ValueDef,
 };
 // use crate::FuncCollector;
 #[derive(Clone)]
@@ -498,3 +498,393 @@ impl Inline {
         Ok(())
     }
 }
+
+/// Options for [`run_budgeted`].
+#[derive(Clone, Debug)]
+pub struct InlineOptions {
+    /// Callees whose cost (see [`func_cost`]) exceeds this are never inlined.
+    pub max_callee_cost: usize,
+    /// The module's total cost may grow to at most this multiple of
+    /// its original cost.
+    pub max_growth_ratio: f64,
+    /// Whether to skip the functions listed in `no_inline`.
+    pub respect_no_inline: bool,
+    /// Functions that must not be inlined when `respect_no_inline` is set.
+    pub no_inline: BTreeSet<Func>,
+}
+impl Default for InlineOptions {
+    fn default() -> Self {
+        Self {
+            max_callee_cost: 64,
+            max_growth_ratio: 1.5,
+            respect_no_inline: true,
+            no_inline: BTreeSet::new(),
+        }
+    }
+}
+
+/// A rough size measure of a function body: one per instruction and
+/// one per block (for its terminator).
+pub fn func_cost(body: &FunctionBody) -> usize {
+    body.blocks
+        .values()
+        .map(|block| block.insts.len() + 1)
+        .sum()
+}
+
+/// Direct callees of `body`, through both `call` and `return_call`.
+fn direct_callees(body: &FunctionBody) -> Vec<Func> {
+    let mut out = vec![];
+    for block in body.blocks.values() {
+        for inst in block.insts.iter() {
+            if let ValueDef::Operator(Operator::Call { function_index }, _, _) =
+                &body.values[inst.value]
+            {
+                out.push(*function_index);
+            }
+        }
+        if let Terminator::ReturnCall { func, .. } = &block.terminator.terminator {
+            out.push(*func);
+        }
+    }
+    out
+}
+
+/// Functions that are referenced other than through a direct call:
+/// exports, the start function, table elements and `ref.func`.
+fn pinned_funcs(m: &Module) -> BTreeSet<Func> {
+    let mut pinned = BTreeSet::new();
+    for export in m.exports.iter() {
+        if let crate::ExportKind::Func(f) = export.kind {
+            pinned.insert(f);
+        }
+    }
+    pinned.extend(m.start_func);
+    for table in m.tables.values() {
+        if let Some(elts) = &table.func_elements {
+            pinned.extend(elts.iter().cloned().filter(|f| f.is_valid()));
+        }
+    }
+    for decl in m.funcs.values() {
+        if let Some(body) = decl.body() {
+            for value in body.values.values() {
+                if let ValueDef::Operator(Operator::RefFunc { func_index }, _, _) = value {
+                    pinned.insert(*func_index);
+                }
+            }
+        }
+    }
+    pinned
+}
+
+/// Can `f` reach itself through direct calls?
+fn is_recursive(callees: &BTreeMap<Func, Vec<Func>>, f: Func) -> bool {
+    let mut seen = BTreeSet::new();
+    let mut stack = callees.get(&f).cloned().unwrap_or_default();
+    while let Some(g) = stack.pop() {
+        if g == f {
+            return true;
+        }
+        if seen.insert(g) {
+            stack.extend(callees.get(&g).into_iter().flatten().cloned());
+        }
+    }
+    false
+}
+
+/// Inline call sites greedily, cheapest first, while keeping the
+/// module's total cost within `opts.max_growth_ratio` of the
+/// original. Single-call-site functions that are not otherwise
+/// referenced are cheapest of all, since their bodies are dropped
+/// once inlined.
+///
+/// Inlined functions that nothing refers to anymore are removed with
+/// `Module::renumber_funcs`, which shifts later functions down; the
+/// returned map takes each old function to its new index, or to
+/// `Func::invalid()` if it was removed. If lazy bodies remain, whose
+/// references cannot be rewritten, nothing is renumbered and the
+/// dropped bodies are replaced with `unreachable` stubs instead.
+pub fn run_budgeted(m: &mut Module, opts: InlineOptions) -> anyhow::Result<PerEntity<Func, Func>> {
+    let mut remap = PerEntity::default();
+    for f in m.funcs.iter() {
+        remap[f] = f;
+    }
+    let mut costs: BTreeMap<Func, usize> = BTreeMap::new();
+    let mut callees: BTreeMap<Func, Vec<Func>> = BTreeMap::new();
+    let mut sites: BTreeMap<Func, usize> = BTreeMap::new();
+    for (f, decl) in m.funcs.entries() {
+        if let Some(body) = decl.body() {
+            costs.insert(f, func_cost(body));
+            let cs = direct_callees(body);
+            for &c in cs.iter() {
+                *sites.entry(c).or_default() += 1;
+            }
+            callees.insert(f, cs);
+        }
+    }
+    let pinned = pinned_funcs(m);
+    let total: usize = costs.values().sum();
+    let budget = (total as f64 * opts.max_growth_ratio) as usize;
+    // (growth, cost, func) for every eligible callee.
+    let mut candidates = vec![];
+    for (&f, &cost) in costs.iter() {
+        let n = sites.get(&f).cloned().unwrap_or(0);
+        if n == 0 || cost > opts.max_callee_cost {
+            continue;
+        }
+        if opts.respect_no_inline && opts.no_inline.contains(&f) {
+            continue;
+        }
+        if is_recursive(&callees, f) {
+            continue;
+        }
+        let removable = !pinned.contains(&f);
+        let growth = (cost * n) as isize - if removable { cost as isize } else { 0 };
+        candidates.push((growth, cost, f));
+    }
+    candidates.sort();
+    let mut size = total as isize;
+    let mut cfg = InlineCfg {
+        funcs: BTreeSet::new(),
+    };
+    for (growth, _, f) in candidates {
+        if size + growth > budget as isize {
+            continue;
+        }
+        size += growth;
+        cfg.funcs.insert(f);
+    }
+    if cfg.funcs.is_empty() {
+        return Ok(remap);
+    }
+    let inlined = cfg.funcs.clone();
+    inline_mod(m, cfg)?;
+    // Drop inlined functions that nothing refers to anymore.
+    let mut live = pinned_funcs(m);
+    for decl in m.funcs.values() {
+        if let Some(body) = decl.body() {
+            live.extend(direct_callees(body));
+        }
+    }
+    let dead = inlined
+        .into_iter()
+        .filter(|f| !live.contains(f))
+        .collect::<BTreeSet<_>>();
+    if m.funcs_expanded() {
+        let order = m
+            .funcs
+            .iter()
+            .filter(|f| !dead.contains(f))
+            .collect::<Vec<_>>();
+        return m.renumber_funcs(&order);
+    }
+    for f in dead {
+        let sig = m.funcs[f].sig();
+        let mut stub = FunctionBody::new(m, sig);
+        stub.set_terminator(stub.entry, Terminator::Unreachable);
+        *m.funcs[f]
+            .body_mut()
+            .context("in getting the function body")? = stub;
+    }
+    Ok(remap)
+}
+
+/// Inline every direct call to a non-recursive callee whose body has
+/// at most `opts.inline_insn_threshold` instructions. Imported and
+/// un-expanded callees are never inlined.
+pub fn run(m: &mut Module, opts: &crate::OptOptions) -> anyhow::Result<()> {
+    let mut callees: BTreeMap<Func, Vec<Func>> = BTreeMap::new();
+    for (f, decl) in m.funcs.entries() {
+        if let Some(body) = decl.body() {
+            callees.insert(f, direct_callees(body));
+        }
+    }
+    let mut cfg = InlineCfg {
+        funcs: BTreeSet::new(),
+    };
+    for (f, decl) in m.funcs.entries() {
+        let Some(body) = decl.body() else {
+            continue;
+        };
+        let insns: usize = body.blocks.values().map(|block| block.insts.len()).sum();
+        if insns <= opts.inline_insn_threshold && !is_recursive(&callees, f) {
+            cfg.funcs.insert(f);
+        }
+    }
+    if cfg.funcs.is_empty() {
+        return Ok(());
+    }
+    inline_mod(m, cfg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Export, ExportKind};
+
+    fn add_func(m: &mut Module, sig: crate::Signature, name: &str, body: FunctionBody) -> Func {
+        m.funcs.push(FuncDecl::Body(sig, name.into(), body))
+    }
+
+    #[test]
+    fn budgeted_inlining() {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32],
+                returns: vec![Type::I32],
+                shared: true,
+            },
+        );
+
+        // `small(x) = x + 1`, called once.
+        let mut small = FunctionBody::new(&m, sig);
+        let x = small.blocks[small.entry].params[0].1;
+        let one = small.add_op(
+            small.entry,
+            Operator::I32Const { value: 1 },
+            &[],
+            &[Type::I32],
+        );
+        let sum = small.add_op(small.entry, Operator::I32Add, &[x, one], &[Type::I32]);
+        small.set_terminator(small.entry, Terminator::Return { values: vec![sum] });
+        let small = add_func(&mut m, sig, "small", small);
+
+        // `big(x)` is a long chain of adds, called twice.
+        let mut big = FunctionBody::new(&m, sig);
+        let mut acc = big.blocks[big.entry].params[0].1;
+        for i in 0..32 {
+            let k = big.add_op(
+                big.entry,
+                Operator::I32Const { value: i },
+                &[],
+                &[Type::I32],
+            );
+            acc = big.add_op(big.entry, Operator::I32Add, &[acc, k], &[Type::I32]);
+        }
+        big.set_terminator(big.entry, Terminator::Return { values: vec![acc] });
+        let big = add_func(&mut m, sig, "big", big);
+
+        // `main(x) = big(big(small(x)))`
+        let mut main = FunctionBody::new(&m, sig);
+        let x = main.blocks[main.entry].params[0].1;
+        let a = main.add_op(
+            main.entry,
+            Operator::Call {
+                function_index: small,
+            },
+            &[x],
+            &[Type::I32],
+        );
+        let b = main.add_op(
+            main.entry,
+            Operator::Call {
+                function_index: big,
+            },
+            &[a],
+            &[Type::I32],
+        );
+        let c = main.add_op(
+            main.entry,
+            Operator::Call {
+                function_index: big,
+            },
+            &[b],
+            &[Type::I32],
+        );
+        main.set_terminator(main.entry, Terminator::Return { values: vec![c] });
+        let main = add_func(&mut m, sig, "main", main);
+        m.exports.push(Export {
+            name: "main".into(),
+            kind: ExportKind::Func(main),
+        });
+
+        let num_funcs = m.funcs.len();
+        let remap = run_budgeted(
+            &mut m,
+            InlineOptions {
+                max_callee_cost: 1000,
+                max_growth_ratio: 1.2,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        // `small` is gone and the functions after it move down.
+        assert_eq!(m.funcs.len(), num_funcs - 1);
+        assert!(remap[small].is_invalid());
+        assert!(m.funcs.values().all(|decl| decl.name() != "small"));
+        let (big, main) = (remap[big], remap[main]);
+        assert_eq!(m.funcs[big].name(), "big");
+        assert_eq!(m.funcs[main].name(), "main");
+        assert_eq!(m.exports[0].kind, ExportKind::Func(main));
+
+        let calls = direct_callees(m.funcs[main].body().unwrap());
+        assert_eq!(calls, vec![big, big]);
+        assert!(func_cost(m.funcs[big].body().unwrap()) > 32);
+    }
+
+    #[test]
+    fn inlines_leaf_call() {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+
+        // `double(x) = x * 2`
+        let mut double = FunctionBody::new(&m, sig);
+        let x = double.blocks[double.entry].params[0].1;
+        let two = double.add_op(
+            double.entry,
+            Operator::I32Const { value: 2 },
+            &[],
+            &[Type::I32],
+        );
+        let product = double.add_op(double.entry, Operator::I32Mul, &[x, two], &[Type::I32]);
+        double.set_terminator(
+            double.entry,
+            Terminator::Return {
+                values: vec![product],
+            },
+        );
+        let double = add_func(&mut m, sig, "double", double);
+
+        // `main(x) = double(x) + 1`
+        let mut main = FunctionBody::new(&m, sig);
+        let x = main.blocks[main.entry].params[0].1;
+        let doubled = main.add_op(
+            main.entry,
+            Operator::Call {
+                function_index: double,
+            },
+            &[x],
+            &[Type::I32],
+        );
+        let one = main.add_op(
+            main.entry,
+            Operator::I32Const { value: 1 },
+            &[],
+            &[Type::I32],
+        );
+        let sum = main.add_op(main.entry, Operator::I32Add, &[doubled, one], &[Type::I32]);
+        main.set_terminator(main.entry, Terminator::Return { values: vec![sum] });
+        let main = add_func(&mut m, sig, "main", main);
+
+        let mut opts = crate::OptOptions::default();
+        opts.inline_insn_threshold = 4;
+        run(&mut m, &opts).unwrap();
+
+        let body = m.funcs[main].body().unwrap();
+        assert!(!direct_callees(body).contains(&double));
+        body.validate().unwrap();
+        let mut ctx = crate::InterpContext::new(&m).unwrap();
+        let result = ctx.call(&m, main, &[ConstVal::I32(5)]).ok().unwrap();
+        assert_eq!(&result[..], &[ConstVal::I32(11)]);
+    }
+}
@@ -0,0 +1,158 @@
+//! Jump threading through forwarding blocks.
+//!
+//! A block with no instructions that ends in a `Br` only passes
+//! values along: its blockparams, in any order, and values from
+//! blocks that dominate it. Its predecessors can branch straight to
+//! the `Br`'s target instead, passing the forwarded values
+//! themselves. Unlike `empty_blocks`, this handles blocks with
+//! blockparams, as long as those are used nowhere but in the `Br`.
+use crate::{Block, BlockTarget, CFGInfo, FunctionBody, PerEntity, Terminator, Value};
+use alloc::vec::Vec;
+
+/// Redirect every branch into a forwarding block to that block's
+/// target, substituting the branch's arguments for the forwarded
+/// blockparams, then delete the forwarding blocks along with any
+/// other unreachable blocks. Chains of forwarding blocks are
+/// threaded through completely. Returns whether anything changed;
+/// blocks are renumbered, so `cfg` is stale afterward.
+pub fn run(body: &mut FunctionBody, cfg: &CFGInfo) -> bool {
+    let uses = body.compute_uses();
+    let term_uses = body.compute_terminator_uses();
+    let mut changed = false;
+    for block in cfg.rpo_blocks() {
+        let Some((to, forwarded)) = forwarding(body, block, &uses, &term_uses) else {
+            continue;
+        };
+        let mut preds = body.blocks[block].preds.clone();
+        preds.dedup();
+        for pred in preds {
+            log::trace!("jump_thread: redirecting {} from {} to {}", pred, block, to);
+            body.blocks[pred].terminator.update_targets(|target| {
+                if target.block != block {
+                    return;
+                }
+                let args = forwarded
+                    .iter()
+                    .map(|&arg| match arg {
+                        Ok(param) => target.args[param],
+                        Err(value) => value,
+                    })
+                    .collect();
+                *target = BlockTarget { block: to, args };
+            });
+        }
+        // Keep the edges current for later forwarding blocks, whose
+        // predecessors may have just changed.
+        body.recompute_edges();
+        changed = true;
+    }
+    if changed {
+        let cfg = CFGInfo::new(body);
+        body.remove_unreachable_blocks(&cfg);
+    }
+    changed
+}
+
+/// If `block` only forwards values to another block, return that
+/// block and, for each of its arguments, either the index of the
+/// blockparam of `block` it passes on or the value it passes.
+fn forwarding(
+    body: &FunctionBody,
+    block: Block,
+    uses: &PerEntity<Value, Vec<Value>>,
+    term_uses: &PerEntity<Value, Vec<Block>>,
+) -> Option<(Block, Vec<Result<usize, Value>>)> {
+    let block_def = &body.blocks[block];
+    if block == body.entry || !block_def.insts.is_empty() {
+        return None;
+    }
+    let Terminator::Br { target } = &block_def.terminator.terminator else {
+        return None;
+    };
+    if target.block == block {
+        return None;
+    }
+    // The blockparams disappear with the block, so they must not be
+    // used by any other block.
+    let params_local = block_def.params.iter().all(|&(_, param)| {
+        uses[param].is_empty() && term_uses[param].iter().all(|&user| user == block)
+    });
+    if !params_local {
+        return None;
+    }
+    let forwarded = target
+        .args
+        .iter()
+        .map(|&arg| {
+            let arg = body.resolve_alias(arg);
+            block_def
+                .params
+                .iter()
+                .position(|&(_, param)| param == arg)
+                .ok_or(arg)
+        })
+        .collect();
+    Some((target.block, forwarded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{util::new_sig, ConstVal, FuncDecl, FunctionBuilder, InterpContext, Module};
+    use crate::{Operator, SignatureData, Type};
+
+    #[test]
+    fn threads_permuting_block() {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32, Type::I32],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+        let mut b = FunctionBuilder::new(&m, sig);
+        let entry = b.entry();
+        let (x, y) = (b.params(entry)[0], b.params(entry)[1]);
+        let forward = b.add_block();
+        let p = b.add_blockparam(forward, Type::I32);
+        let q = b.add_blockparam(forward, Type::I32);
+        let join = b.add_block();
+        let l = b.add_blockparam(join, Type::I32);
+        let r = b.add_blockparam(join, Type::I32);
+        b.cond_br(entry, x, (forward, &[x, y]), (join, &[x, y]));
+        // Swaps its params on the way through.
+        b.br(forward, join, &[q, p]);
+        let diff = b.push_op(join, Operator::I32Sub, &[l, r], &[Type::I32]);
+        b.ret(join, &[diff]);
+        let original = b.finish();
+
+        let mut body = original.clone();
+        let cfg = CFGInfo::new(&body);
+        assert!(run(&mut body, &cfg));
+        body.validate().unwrap();
+        assert_eq!(body.blocks.len(), 2);
+        let Terminator::CondBr {
+            if_true, if_false, ..
+        } = &body.blocks[body.entry].terminator.terminator
+        else {
+            panic!("entry no longer branches");
+        };
+        assert_eq!(if_true.block, if_false.block);
+        assert_eq!(if_true.args, vec![y, x]);
+        assert_eq!(if_false.args, vec![x, y]);
+
+        let f = m
+            .funcs
+            .push(FuncDecl::Body(sig, "original".into(), original));
+        let g = m.funcs.push(FuncDecl::Body(sig, "threaded".into(), body));
+        let mut ctx = InterpContext::new(&m).unwrap();
+        for (x, y) in [(0, 5), (1, 5), (7, 2)] {
+            let args = [ConstVal::I32(x), ConstVal::I32(y)];
+            let expected = ctx.call(&m, f, &args).ok().unwrap();
+            let actual = ctx.call(&m, g, &args).ok().unwrap();
+            assert_eq!(actual, expected, "{} {}", x, y);
+        }
+    }
+}
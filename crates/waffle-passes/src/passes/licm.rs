@@ -0,0 +1,188 @@
+//! Loop-invariant code motion.
+//!
+//! Pure, non-trapping operators whose arguments are all computed
+//! outside a loop are moved into a preheader: a new block through
+//! which every entry into the loop (but no back edge) passes. Since
+//! only operators that can neither trap nor have side effects are
+//! moved, executing them speculatively when the loop body would not
+//! have run is harmless.
+use crate::cfg::Loop;
+use crate::{
+    Block, BlockTarget, CFGInfo, EntityVec, FunctionBody, PerEntity, Terminator, Value, ValueDef,
+};
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use core::iter::once;
+
+/// Hoist loop-invariant computations out of every natural loop in
+/// `body`, innermost loops first, so that a computation invariant in
+/// several nested loops ends up in the outermost preheader. A
+/// preheader is only inserted for loops that have something to
+/// hoist. Returns whether anything changed; `cfg` is stale afterward.
+pub fn run(body: &mut FunctionBody, cfg: &CFGInfo) -> bool {
+    let loops = cfg.loops();
+    let mut loop_blocks: EntityVec<Loop, BTreeSet<Block>> = EntityVec::from(
+        loops
+            .loops
+            .values()
+            .map(|l| l.blocks.clone())
+            .collect::<Vec<_>>(),
+    );
+    let mut def_block = cfg.def_block.clone();
+    let mut preheaders: PerEntity<Block, Option<Block>> = PerEntity::default();
+    let mut changed = false;
+
+    // A nested loop's header comes after its parent's in RPO.
+    for lp in loops.iter().rev() {
+        let mut invariant: BTreeSet<Value> = BTreeSet::new();
+        let mut hoisted = vec![];
+        let mut sources: BTreeSet<Block> = BTreeSet::new();
+        let blocks = cfg
+            .rpo_blocks()
+            .filter(|block| loop_blocks[lp].contains(block))
+            .collect::<Vec<_>>();
+        for block in blocks {
+            // Visit the preheaders of nested loops just ahead of their
+            // headers, so their contents can move further out.
+            for block in preheaders[block].into_iter().chain(once(block)) {
+                for inst in &body.blocks[block].insts {
+                    let value = inst.value;
+                    let ValueDef::Operator(op, args, _) = &body.values[value] else {
+                        continue;
+                    };
+                    if !op.is_pure() || op.can_trap() {
+                        continue;
+                    }
+                    let is_invariant = body.arg_pool[*args].iter().all(|&arg| {
+                        let arg = body.resolve_alias(arg);
+                        invariant.contains(&arg) || !loop_blocks[lp].contains(&def_block[arg])
+                    });
+                    if is_invariant {
+                        invariant.insert(value);
+                        hoisted.push(value);
+                        sources.insert(block);
+                    }
+                }
+            }
+        }
+        if hoisted.is_empty() {
+            continue;
+        }
+
+        let header = loops.loops[lp].header;
+        let preheader = insert_preheader(body, header, &loops.loops[lp].latches);
+        preheaders[header] = Some(preheader);
+        for block in sources {
+            body.blocks[block]
+                .insts
+                .retain(|inst| !invariant.contains(&inst.value));
+        }
+        for &value in &hoisted {
+            body.append_to_block(preheader, value);
+            def_block[value] = preheader;
+        }
+        // The preheader sits inside every loop enclosing this one.
+        let mut parent = loops.loops[lp].parent;
+        while let Some(id) = parent {
+            loop_blocks[id].insert(preheader);
+            parent = loops.loops[id].parent;
+        }
+        changed = true;
+    }
+    changed
+}
+
+/// Insert a block that takes over all edges into `header` except the
+/// back edges from `latches`, and branches on to `header` passing its
+/// blockparams through.
+fn insert_preheader(body: &mut FunctionBody, header: Block, latches: &[Block]) -> Block {
+    let preheader = body.add_block();
+    let mut args = vec![];
+    for i in 0..body.blocks[header].params.len() {
+        let ty = body.blocks[header].params[i].0;
+        args.push(body.add_blockparam(preheader, ty));
+    }
+    for pred in body.blocks[header].preds.clone() {
+        if latches.contains(&pred) {
+            continue;
+        }
+        body.blocks[pred].terminator.update_targets(|target| {
+            if target.block == header {
+                target.block = preheader;
+            }
+        });
+    }
+    if body.entry == header {
+        body.entry = preheader;
+    }
+    body.set_terminator(
+        preheader,
+        Terminator::Br {
+            target: BlockTarget {
+                block: header,
+                args,
+            },
+        },
+    );
+    body.recompute_edges();
+    preheader
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{util::new_sig, FunctionBuilder, Module, Operator, SignatureData, Type};
+
+    #[test]
+    fn hoists_invariant_add() {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32, Type::I32],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+        let mut b = FunctionBuilder::new(&m, sig);
+        let entry = b.entry();
+        let params = b.params(entry);
+        let (x, y) = (params[0], params[1]);
+        let header = b.add_block();
+        let i = b.add_blockparam(header, Type::I32);
+        let exit = b.add_block();
+        let zero = b.const_i32(entry, 0);
+        b.br(entry, header, &[zero]);
+        let inv = b.push_op(header, Operator::I32Add, &[x, y], &[Type::I32]);
+        let div = b.push_op(header, Operator::I32DivU, &[x, y], &[Type::I32]);
+        let next = b.push_op(header, Operator::I32Add, &[i, inv], &[Type::I32]);
+        let next = b.push_op(header, Operator::I32Add, &[next, div], &[Type::I32]);
+        let cond = b.push_op(header, Operator::I32LtU, &[next, x], &[Type::I32]);
+        b.cond_br(header, cond, (header, &[next]), (exit, &[]));
+        b.ret(exit, &[next]);
+        let mut body = b.finish();
+
+        let cfg = CFGInfo::new(&body);
+        assert!(run(&mut body, &cfg));
+        body.validate().unwrap();
+
+        let preheader = body.value_blocks[inv];
+        assert_ne!(preheader, header);
+        assert_eq!(body.blocks[preheader].preds, vec![entry]);
+        assert_eq!(body.blocks[header].preds.len(), 2);
+        assert!(body.blocks[header].preds.contains(&preheader));
+        // The division might trap, so it stays put.
+        assert_eq!(body.value_blocks[div], header);
+        assert!(body.blocks[header]
+            .insts
+            .iter()
+            .all(|inst| inst.value != inv));
+
+        let cfg = CFGInfo::new(&body);
+        assert!(cfg.dominates(preheader, header));
+        assert!(cfg.dominates(preheader, exit));
+        assert!(!cfg
+            .loops()
+            .contains(cfg.loops().header_loop[header].unwrap(), preheader));
+    }
+}
@@ -0,0 +1,199 @@
+//! Common-subexpression elimination of memory loads.
+//!
+//! Walks the dominator tree keeping, per block, the loads whose
+//! results are still valid: keyed by (operator, resolved arguments,
+//! result types), where the operator carries the memory, offset,
+//! alignment and access width. Any operator that may write a memory
+//! (per `MemoryFootprint::of_op`, or a call) invalidates the loads
+//! from that memory, and every atomic operator, fences and waits
+//! included, invalidates all of them. A block starts from its
+//! dominator's state only when that dominator is its sole
+//! predecessor, so no path can reach a reused load around an
+//! intervening store.
+use super::dom_pass::{dom_pass, DomtreePass};
+use crate::{Block, CFGInfo, FunctionBody, Memory, Operator, SideEffect, Type, Value, ValueDef};
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+use waffle_ir::util::MemoryFootprint;
+
+type Key = (Operator, Vec<Value>, Vec<Type>);
+type Available = HashMap<Key, Value>;
+
+struct LoadCSEPass<'a> {
+    cfg: &'a CFGInfo,
+    /// Loads still available at the end of each block whose domtree
+    /// subtree is being visited.
+    out: HashMap<Block, Available>,
+    changed: bool,
+}
+
+impl<'a> DomtreePass for LoadCSEPass<'a> {
+    fn enter(&mut self, block: Block, body: &mut FunctionBody) {
+        let idom = self.cfg.domtree[block];
+        let preds = &self.cfg.preds[block];
+        let mut available = match self.out.get(&idom) {
+            Some(out) if !preds.is_empty() && preds.iter().all(|&pred| pred == idom) => out.clone(),
+            _ => Available::default(),
+        };
+
+        let mut i = 0;
+        while i < body.blocks[block].insts.len() {
+            let inst = body.blocks[block].insts[i].value;
+            let ValueDef::Operator(op, args, tys) = &body.values[inst] else {
+                i += 1;
+                continue;
+            };
+            if op.is_load() {
+                let args = body.arg_pool[*args]
+                    .iter()
+                    .map(|&arg| body.resolve_alias(arg))
+                    .collect::<Vec<_>>();
+                let key = (op.clone(), args, body.type_pool[*tys].to_vec());
+                if let Some(&value) = available.get(&key) {
+                    body.set_alias(inst, value);
+                    body.blocks[block].insts.remove(i);
+                    self.changed = true;
+                    continue;
+                }
+                available.insert(key, inst);
+            } else if op.is_call()
+                || op.effects().contains(&SideEffect::All)
+                || op.effects().contains(&SideEffect::AtomicStuff)
+            {
+                available.clear();
+            } else {
+                let writes = MemoryFootprint::of_op(op).writes;
+                if !writes.is_empty() {
+                    available.retain(|(load, ..), _| !writes.contains(&memory_of(load)));
+                }
+            }
+            i += 1;
+        }
+        self.out.insert(block, available);
+    }
+
+    fn leave(&mut self, block: Block, _body: &mut FunctionBody) {
+        self.out.remove(&block);
+    }
+}
+
+fn memory_of(load: &Operator) -> Memory {
+    waffle_ir::op_traits::memory_arg(load)
+        .expect("loads take a MemoryArg")
+        .memory
+}
+
+/// Replace each load that repeats an earlier identical load, with no
+/// possible write to its memory in between, with an alias to the
+/// earlier result. Returns whether anything changed.
+pub fn run(body: &mut FunctionBody, cfg: &CFGInfo) -> bool {
+    let mut pass = LoadCSEPass {
+        cfg,
+        out: HashMap::new(),
+        changed: false,
+    };
+    dom_pass(body, cfg, &mut pass);
+    pass.changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        util::new_sig, BlockTarget, MemoryArg, MemoryData, Module, SignatureData, Terminator,
+    };
+
+    /// A body `(addr: i32, x: i32) -> i32` over a fresh memory, with
+    /// a second block that the entry branches to.
+    fn setup(m: &mut Module) -> (FunctionBody, MemoryArg, Block, Value, Value) {
+        let sig = new_sig(
+            m,
+            SignatureData::Func {
+                params: vec![Type::I32, Type::I32],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+        let memory = MemoryArg {
+            align: 2,
+            offset: 8,
+            memory: m.memories.push(MemoryData {
+                initial_pages: 1,
+                maximum_pages: None,
+                segments: vec![],
+                memory64: false,
+                shared: false,
+                page_size_log2: None,
+            }),
+        };
+        let mut body = FunctionBody::new(m, sig);
+        let entry = body.entry;
+        let addr = body.blocks[entry].params[0].1;
+        let x = body.blocks[entry].params[1].1;
+        let next = body.add_block();
+        body.set_terminator(
+            entry,
+            Terminator::Br {
+                target: BlockTarget {
+                    block: next,
+                    args: vec![],
+                },
+            },
+        );
+        (body, memory, next, addr, x)
+    }
+
+    #[test]
+    fn reuses_dominating_load() {
+        let mut m = Module::empty();
+        let (mut body, memory, next, addr, _) = setup(&mut m);
+        let entry = body.entry;
+        let l1 = body.add_op(entry, Operator::I32Load { memory }, &[addr], &[Type::I32]);
+        let l2 = body.add_op(next, Operator::I32Load { memory }, &[addr], &[Type::I32]);
+        // A different width at the same address is a different load.
+        let l3 = body.add_op(next, Operator::I32Load8U { memory }, &[addr], &[Type::I32]);
+        let sum = body.add_op(next, Operator::I32Add, &[l1, l2], &[Type::I32]);
+        let r = body.add_op(next, Operator::I32Add, &[sum, l3], &[Type::I32]);
+        body.set_terminator(next, Terminator::Return { values: vec![r] });
+
+        let cfg = CFGInfo::new(&body);
+        assert!(run(&mut body, &cfg));
+        assert_eq!(body.resolve_alias(l2), l1);
+        assert_eq!(body.resolve_alias(l3), l3);
+        assert_eq!(body.blocks[next].insts.len(), 3);
+        body.validate().unwrap();
+    }
+
+    #[test]
+    fn store_in_between_blocks_reuse() {
+        let mut m = Module::empty();
+        let (mut body, memory, next, addr, x) = setup(&mut m);
+        let entry = body.entry;
+        let l1 = body.add_op(entry, Operator::I32Load { memory }, &[addr], &[Type::I32]);
+        body.add_op(entry, Operator::I32Store { memory }, &[x, x], &[]);
+        let l2 = body.add_op(next, Operator::I32Load { memory }, &[addr], &[Type::I32]);
+        let r = body.add_op(next, Operator::I32Add, &[l1, l2], &[Type::I32]);
+        body.set_terminator(next, Terminator::Return { values: vec![r] });
+
+        let cfg = CFGInfo::new(&body);
+        assert!(!run(&mut body, &cfg));
+        assert_eq!(body.resolve_alias(l2), l2);
+        body.validate().unwrap();
+    }
+
+    #[test]
+    fn fence_in_between_blocks_reuse() {
+        let mut m = Module::empty();
+        let (mut body, memory, next, addr, _) = setup(&mut m);
+        let entry = body.entry;
+        let l1 = body.add_op(entry, Operator::I32Load { memory }, &[addr], &[Type::I32]);
+        body.add_op(entry, Operator::AtomicFence, &[], &[]);
+        let l2 = body.add_op(next, Operator::I32Load { memory }, &[addr], &[Type::I32]);
+        let r = body.add_op(next, Operator::I32Add, &[l1, l2], &[Type::I32]);
+        body.set_terminator(next, Terminator::Return { values: vec![r] });
+
+        let cfg = CFGInfo::new(&body);
+        assert!(!run(&mut body, &cfg));
+        assert_eq!(body.resolve_alias(l2), l2);
+    }
+}
@@ -0,0 +1,146 @@
+//! Lowering of n-way `Select` terminators into chains of `CondBr`s.
+use crate::{Block, BlockTarget, FunctionBody, Operator, Terminator, Type, Value};
+use alloc::vec::Vec;
+
+/// Replace every `Terminator::Select` with a linear chain of
+/// conditional branches: the selecting block tests the selector
+/// against `0` and each freshly created block in the chain tests the
+/// next index, falling through to the default target once all
+/// explicit targets have been tried. Target arguments are preserved
+/// as-is, and the selector still dominates every use since the new
+/// blocks are reachable only from the original block.
+pub fn run(body: &mut FunctionBody) {
+    for block in body.blocks.iter().collect::<Vec<_>>() {
+        let terminator = core::mem::replace(
+            &mut body.blocks[block].terminator.terminator,
+            Terminator::None,
+        );
+        match terminator {
+            Terminator::Select {
+                value,
+                targets,
+                default,
+            } => lower(body, block, value, targets, default),
+            other => body.blocks[block].terminator.terminator = other,
+        }
+    }
+    body.recompute_edges();
+}
+
+fn lower(
+    body: &mut FunctionBody,
+    block: Block,
+    value: Value,
+    targets: Vec<BlockTarget>,
+    default: BlockTarget,
+) {
+    let mut current = block;
+    let n = targets.len();
+    for (i, target) in targets.into_iter().enumerate() {
+        let index = body.add_op(
+            current,
+            Operator::I32Const { value: i as u32 },
+            &[],
+            &[Type::I32],
+        );
+        let cond = body.add_op(current, Operator::I32Eq, &[value, index], &[Type::I32]);
+        let if_false = if i + 1 == n {
+            default.clone()
+        } else {
+            BlockTarget {
+                block: body.add_block(),
+                args: vec![],
+            }
+        };
+        let next = if_false.block;
+        body.set_terminator(
+            current,
+            Terminator::CondBr {
+                cond,
+                if_true: target,
+                if_false,
+            },
+        );
+        current = next;
+    }
+    if n == 0 {
+        body.set_terminator(block, Terminator::Br { target: default });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{util::new_sig, ConstVal, FuncDecl, FunctionBuilder, InterpContext, Module};
+    use crate::{SignatureData, ValueDef};
+
+    #[test]
+    fn three_way_select() {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32, Type::I32],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+        let mut b = FunctionBuilder::new(&m, sig);
+        let entry = b.entry();
+        let params = b.params(entry);
+        let (sel, x) = (params[0], params[1]);
+        // Each target returns its own constant plus the argument it
+        // was passed, so dropped or reordered args would show up.
+        let mut targets = vec![];
+        for k in 0..4 {
+            let block = b.add_block();
+            let arg = b.add_blockparam(block, Type::I32);
+            let c = b.const_i32(block, 10 * (k + 1));
+            let sum = b.push_op(block, Operator::I32Add, &[arg, c], &[Type::I32]);
+            b.ret(block, &[sum]);
+            targets.push(BlockTarget {
+                block,
+                args: vec![x],
+            });
+        }
+        let default = targets.pop().unwrap();
+        b.set_terminator(
+            entry,
+            Terminator::Select {
+                value: sel,
+                targets,
+                default,
+            },
+        );
+        let mut body = b.finish();
+
+        run(&mut body);
+        body.validate().unwrap();
+        assert!(body
+            .blocks
+            .values()
+            .all(|block| !matches!(block.terminator.terminator, Terminator::Select { .. })));
+        // The chain's comparisons live in the entry and two new blocks.
+        let compares = body
+            .values
+            .values()
+            .filter(|def| matches!(def, ValueDef::Operator(Operator::I32Eq, ..)))
+            .count();
+        assert_eq!(compares, 3);
+
+        let f = m.funcs.push(FuncDecl::Body(sig, "f".into(), body));
+        let mut ctx = InterpContext::new(&m).unwrap();
+        for (sel, expected) in [(0, 10), (1, 20), (2, 30), (3, 40), (u32::MAX, 40)] {
+            let result = ctx
+                .call(&m, f, &[ConstVal::I32(sel), ConstVal::I32(5)])
+                .ok()
+                .unwrap();
+            assert_eq!(
+                &result[..],
+                &[ConstVal::I32(expected + 5)],
+                "selector {}",
+                sel
+            );
+        }
+    }
+}
@@ -0,0 +1,254 @@
+//! Instrumentation of memory accesses with calls to a hook function.
+use crate::entity::EntityVec;
+use crate::{
+    op_traits::memory_arg, Block, Func, FunctionBody, Memory, MemoryData, Module, Operator,
+    SignatureData, Type, Value, ValueDef,
+};
+use alloc::vec::Vec;
+
+/// Insert a call to `hook` immediately before every ordinary load and
+/// store (see `Operator::is_load` and `Operator::is_store`), passing
+/// the access's effective address: the address operand plus the
+/// static offset from the access's `MemoryArg`, computed with
+/// wrapping `add`. The access itself is left as it was, so it still
+/// traps exactly when it did before. `hook`'s own body is not
+/// instrumented, since its accesses would call it recursively.
+///
+/// `hook` must have the signature `(address) -> ()`, where `address`
+/// is `i32`, or `i64` if the module's memories are 64-bit; all
+/// memories must agree.
+pub fn run(module: &mut Module, hook: Func) -> anyhow::Result<()> {
+    let addr_ty = match module.memories.values().next() {
+        Some(memory) if memory.memory64 => Type::I64,
+        _ => Type::I32,
+    };
+    if module
+        .memories
+        .values()
+        .any(|memory| memory.memory64 != (addr_ty == Type::I64))
+    {
+        anyhow::bail!("mem_instrument: memories mix 32- and 64-bit addresses");
+    }
+    match &module.signatures[module.funcs[hook].sig()] {
+        SignatureData::Func {
+            params, returns, ..
+        } if params[..] == [addr_ty] && returns.is_empty() => {}
+        _ => anyhow::bail!(
+            "mem_instrument: hook {} must have signature ({}) -> ()",
+            hook,
+            addr_ty
+        ),
+    }
+    for (func, decl) in module.funcs.entries_mut() {
+        if func == hook {
+            continue;
+        }
+        if let Some(body) = decl.body_mut() {
+            instrument(body, &module.memories, hook);
+        }
+    }
+    Ok(())
+}
+
+fn instrument(body: &mut FunctionBody, memories: &EntityVec<Memory, MemoryData>, hook: Func) {
+    for block in body.blocks.iter().collect::<Vec<_>>() {
+        let insts = core::mem::take(&mut body.blocks[block].insts);
+        let mut new_insts = Vec::with_capacity(insts.len());
+        for inst in insts {
+            let access = match &body.values[inst.value] {
+                ValueDef::Operator(op, args, _) if op.is_load() || op.is_store() => {
+                    let memarg = memory_arg(op).unwrap();
+                    Some((memarg.memory, memarg.offset, body.arg_pool[*args][0]))
+                }
+                _ => None,
+            };
+            if let Some((memory, offset, addr)) = access {
+                // `add_op` appends to the (now empty) instruction list;
+                // move what it adds ahead of the access.
+                let addr = effective_address(body, block, memories[memory].memory64, addr, offset);
+                body.add_op(
+                    block,
+                    Operator::Call {
+                        function_index: hook,
+                    },
+                    &[addr],
+                    &[],
+                );
+                new_insts.extend(body.blocks[block].insts.drain(..));
+            }
+            new_insts.push(inst);
+        }
+        body.blocks[block].insts = new_insts;
+    }
+}
+
+fn effective_address(
+    body: &mut FunctionBody,
+    block: Block,
+    memory64: bool,
+    addr: Value,
+    offset: u64,
+) -> Value {
+    if offset == 0 {
+        return addr;
+    }
+    let (offset, add, ty) = if memory64 {
+        (
+            Operator::I64Const { value: offset },
+            Operator::I64Add,
+            Type::I64,
+        )
+    } else {
+        (
+            Operator::I32Const {
+                value: offset as u32,
+            },
+            Operator::I32Add,
+            Type::I32,
+        )
+    };
+    let offset = body.add_op(block, offset, &[], &[ty]);
+    body.add_op(block, add, &[addr, offset], &[ty])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{util::new_sig, FuncDecl, MemoryArg, Terminator};
+
+    #[test]
+    fn hooks_each_load() {
+        let mut m = Module::empty();
+        let mem = m.memories.push(MemoryData {
+            initial_pages: 1,
+            maximum_pages: None,
+            segments: vec![],
+            memory64: false,
+            shared: false,
+            page_size_log2: None,
+        });
+        let hook_sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32],
+                returns: vec![],
+                shared: false,
+            },
+        );
+        let hook = m.funcs.push(FuncDecl::Import(hook_sig, "hook".into()));
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+        let mut body = FunctionBody::new(&m, sig);
+        let entry = body.entry;
+        let p = body.blocks[entry].params[0].1;
+        let memarg = |offset| MemoryArg {
+            align: 2,
+            offset,
+            memory: mem,
+        };
+        let a = body.add_op(
+            entry,
+            Operator::I32Load { memory: memarg(0) },
+            &[p],
+            &[Type::I32],
+        );
+        let b = body.add_op(
+            entry,
+            Operator::I32Load { memory: memarg(8) },
+            &[p],
+            &[Type::I32],
+        );
+        let sum = body.add_op(entry, Operator::I32Add, &[a, b], &[Type::I32]);
+        body.set_terminator(entry, Terminator::Return { values: vec![sum] });
+        let f = m.funcs.push(FuncDecl::Body(sig, "f".into(), body));
+
+        run(&mut m, hook).unwrap();
+
+        let body = m.funcs[f].body().unwrap();
+        body.validate().unwrap();
+        let insts = body.blocks[entry]
+            .insts
+            .iter()
+            .map(|inst| inst.value)
+            .collect::<Vec<_>>();
+        // For each hook call, the address it was passed and the
+        // instruction following it.
+        let calls = insts
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &v)| match &body.values[v] {
+                ValueDef::Operator(Operator::Call { function_index }, args, _) => {
+                    assert_eq!(*function_index, hook);
+                    let args = &body.arg_pool[*args];
+                    assert_eq!(args.len(), 1);
+                    Some((args[0], insts[i + 1]))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0], (p, a));
+        let (addr, next) = calls[1];
+        assert_eq!(next, b);
+        let ValueDef::Operator(Operator::I32Add, args, _) = &body.values[addr] else {
+            panic!("expected an add, got {:?}", body.values[addr]);
+        };
+        let args = &body.arg_pool[*args];
+        assert_eq!(args[0], p);
+        assert!(matches!(
+            body.values[args[1]],
+            ValueDef::Operator(Operator::I32Const { value: 8 }, _, _)
+        ));
+    }
+
+    #[test]
+    fn skips_hook_and_checks_its_signature() {
+        let mut m = Module::empty();
+        let mem = m.memories.push(MemoryData {
+            initial_pages: 1,
+            maximum_pages: None,
+            segments: vec![],
+            memory64: false,
+            shared: false,
+            page_size_log2: None,
+        });
+        let hook_sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32],
+                returns: vec![],
+                shared: false,
+            },
+        );
+        // A hook that itself stores to memory.
+        let mut body = FunctionBody::new(&m, hook_sig);
+        let entry = body.entry;
+        let addr = body.blocks[entry].params[0].1;
+        let memory = MemoryArg {
+            align: 2,
+            offset: 0,
+            memory: mem,
+        };
+        body.add_op(entry, Operator::I32Store { memory }, &[addr, addr], &[]);
+        body.set_terminator(entry, Terminator::Return { values: vec![] });
+        let hook = m.funcs.push(FuncDecl::Body(hook_sig, "hook".into(), body));
+
+        let mut bad = m.clone();
+        bad.signatures[hook_sig] = SignatureData::Func {
+            params: vec![Type::I32, Type::I32, Type::I32],
+            returns: vec![],
+            shared: false,
+        };
+        assert!(run(&mut bad, hook).is_err());
+
+        run(&mut m, hook).unwrap();
+        let body = m.funcs[hook].body().unwrap();
+        assert_eq!(body.blocks[entry].insts.len(), 1);
+    }
+}
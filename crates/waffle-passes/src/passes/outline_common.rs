@@ -0,0 +1,293 @@
+//! Outlining of repeated straight-line sequences into shared helpers.
+//!
+//! Only sequences of pure, single-result operators are considered,
+//! and only when every intermediate value is used solely within the
+//! sequence, so that the whole sequence can be replaced by a call
+//! taking its external inputs and returning its final value.
+use crate::{
+    util::new_sig, Block, Func, FuncDecl, FunctionBody, Module, Operator, PerEntity, SignatureData,
+    Terminator, Type, Value, ValueDef,
+};
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+
+/// Longest sequence that will be considered for outlining.
+const MAX_LEN: usize = 16;
+
+/// An operand of an instruction in a sequence: either the result of
+/// an earlier instruction in the sequence, or the n'th external
+/// input.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Arg {
+    Internal(usize),
+    External(usize),
+}
+
+/// A sequence, modulo SSA value numbering.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Key {
+    insts: Vec<(Operator, Vec<Arg>, Type)>,
+    inputs: Vec<Type>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Site {
+    func: Func,
+    block: Block,
+    start: usize,
+}
+
+fn use_counts(body: &FunctionBody) -> PerEntity<Value, usize> {
+    let mut uses = PerEntity::default();
+    for block in body.blocks.values() {
+        for inst in block.insts.iter() {
+            body.values[inst.value].visit_uses(&body.arg_pool, |u| {
+                uses[body.resolve_alias(u)] += 1;
+            });
+        }
+        block.terminator.visit_uses(|u| {
+            uses[body.resolve_alias(u)] += 1;
+        });
+    }
+    uses
+}
+
+/// Compute the key of `insts`, along with its external inputs in
+/// order of first use, if it is an outlinable sequence.
+fn key_of(
+    body: &FunctionBody,
+    uses: &PerEntity<Value, usize>,
+    insts: &[Value],
+) -> Option<(Key, Vec<Value>)> {
+    let mut key = Key {
+        insts: vec![],
+        inputs: vec![],
+    };
+    let mut externals: Vec<Value> = vec![];
+    let mut internal_uses = vec![0usize; insts.len()];
+    for &inst in insts {
+        let ValueDef::Operator(op, args, tys) = &body.values[inst] else {
+            return None;
+        };
+        if !op.is_pure() || tys.len() != 1 {
+            return None;
+        }
+        let mut key_args = vec![];
+        for &arg in &body.arg_pool[*args] {
+            let arg = body.resolve_alias(arg);
+            if let Some(idx) = insts.iter().position(|&i| i == arg) {
+                internal_uses[idx] += 1;
+                key_args.push(Arg::Internal(idx));
+            } else if let Some(idx) = externals.iter().position(|&e| e == arg) {
+                key_args.push(Arg::External(idx));
+            } else {
+                key.inputs.push(body.values[arg].ty(&body.type_pool)?);
+                key_args.push(Arg::External(externals.len()));
+                externals.push(arg);
+            }
+        }
+        key.insts
+            .push((op.clone(), key_args, body.type_pool[*tys][0]));
+    }
+    // Every intermediate result must be consumed only inside the sequence.
+    for (idx, &inst) in insts[..insts.len() - 1].iter().enumerate() {
+        if uses[inst] != internal_uses[idx] {
+            return None;
+        }
+    }
+    Some((key, externals))
+}
+
+/// Find the longest sequence occurring at least `min_occurrences`
+/// times, without overlap.
+fn find_candidate(
+    m: &Module,
+    skip: &BTreeSet<Func>,
+    min_occurrences: usize,
+    min_len: usize,
+) -> Option<(Key, usize, Vec<Site>)> {
+    let uses = m
+        .funcs
+        .entries()
+        .filter_map(|(f, decl)| Some((f, use_counts(decl.body()?))))
+        .collect::<Vec<_>>();
+    for len in (min_len.max(1)..=MAX_LEN).rev() {
+        let mut found: HashMap<Key, Vec<Site>> = HashMap::new();
+        for (f, uses) in uses.iter() {
+            if skip.contains(f) {
+                continue;
+            }
+            let body = m.funcs[*f].body().unwrap();
+            for (block, def) in body.blocks.entries() {
+                let insts = def.insts.iter().map(|i| i.value).collect::<Vec<_>>();
+                if insts.len() < len {
+                    continue;
+                }
+                for start in 0..=(insts.len() - len) {
+                    let Some((key, _)) = key_of(body, uses, &insts[start..start + len]) else {
+                        continue;
+                    };
+                    let site = Site {
+                        func: *f,
+                        block,
+                        start,
+                    };
+                    let sites = found.entry(key).or_default();
+                    let overlaps = sites.last().map_or(false, |last| {
+                        last.func == site.func
+                            && last.block == site.block
+                            && last.start + len > site.start
+                    });
+                    if !overlaps {
+                        sites.push(site);
+                    }
+                }
+            }
+        }
+        // Hash order is arbitrary, so pick the key that occurs first.
+        if let Some((key, sites)) = found
+            .into_iter()
+            .filter(|(_, sites)| sites.len() >= min_occurrences.max(2))
+            .min_by_key(|(_, sites)| (sites[0].func, sites[0].block, sites[0].start))
+        {
+            return Some((key, len, sites));
+        }
+    }
+    None
+}
+
+fn build_helper(m: &mut Module, key: &Key) -> Func {
+    let ret = key.insts.last().unwrap().2;
+    let sig = new_sig(
+        m,
+        SignatureData::Func {
+            params: key.inputs.clone(),
+            returns: vec![ret],
+            shared: false,
+        },
+    );
+    let mut body = FunctionBody::new(m, sig);
+    let entry = body.entry;
+    let params = body.blocks[entry]
+        .params
+        .iter()
+        .map(|&(_, v)| v)
+        .collect::<Vec<_>>();
+    let mut results: Vec<Value> = vec![];
+    for (op, args, ty) in key.insts.iter() {
+        let args = args
+            .iter()
+            .map(|a| match a {
+                Arg::Internal(i) => results[*i],
+                Arg::External(i) => params[*i],
+            })
+            .collect::<Vec<_>>();
+        results.push(body.add_op(entry, op.clone(), &args, &[*ty]));
+    }
+    body.set_terminator(
+        entry,
+        Terminator::Return {
+            values: vec![*results.last().unwrap()],
+        },
+    );
+    let name = format!("outlined{}", m.funcs.len());
+    m.funcs.push(FuncDecl::Body(sig, name, body))
+}
+
+/// Outline every straight-line sequence of at least `min_len` pure
+/// instructions that occurs at least `min_occurrences` times across
+/// the module's function bodies. Returns the helper functions created.
+pub fn run(m: &mut Module, min_occurrences: usize, min_len: usize) -> Vec<Func> {
+    let mut helpers = BTreeSet::new();
+    let mut created = vec![];
+    while let Some((key, len, mut sites)) = find_candidate(m, &helpers, min_occurrences, min_len) {
+        let helper = build_helper(m, &key);
+        let ret = key.insts.last().unwrap().2;
+        helpers.insert(helper);
+        created.push(helper);
+        // Rewrite later sites first so earlier indices stay valid.
+        sites.sort_by_key(|s| (s.func, s.block, s.start));
+        for site in sites.into_iter().rev() {
+            let body = m.funcs[site.func].body_mut().unwrap();
+            let insts = body.blocks[site.block].insts[site.start..site.start + len]
+                .iter()
+                .map(|i| i.value)
+                .collect::<Vec<_>>();
+            let uses = use_counts(body);
+            let (_, externals) = key_of(body, &uses, &insts).unwrap();
+            let last = *insts.last().unwrap();
+            let args = body.arg_pool.from_iter(externals.into_iter());
+            let tys = body.single_type_list(ret);
+            body.values[last] = ValueDef::Operator(
+                Operator::Call {
+                    function_index: helper,
+                },
+                args,
+                tys,
+            );
+            for &inst in &insts[..len - 1] {
+                body.values[inst] = ValueDef::None;
+            }
+            body.blocks[site.block]
+                .insts
+                .drain(site.start..site.start + len - 1);
+        }
+    }
+    created
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outlines_repeated_sequence() {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+        let mut funcs = vec![];
+        for name in ["a", "b"].iter() {
+            let mut body = FunctionBody::new(&m, sig);
+            let entry = body.entry;
+            let x = body.blocks[entry].params[0].1;
+            let t1 = body.add_op(entry, Operator::I32Mul, &[x, x], &[Type::I32]);
+            let t2 = body.add_op(entry, Operator::I32Add, &[t1, x], &[Type::I32]);
+            let t3 = body.add_op(entry, Operator::I32Xor, &[t2, x], &[Type::I32]);
+            body.set_terminator(entry, Terminator::Return { values: vec![t3] });
+            funcs.push(m.funcs.push(FuncDecl::Body(sig, (*name).into(), body)));
+        }
+
+        let helpers = run(&mut m, 2, 3);
+        assert_eq!(helpers.len(), 1);
+        let helper = helpers[0];
+        assert_eq!(func_insts(&m, helper), 3);
+        for f in funcs {
+            let body = m.funcs[f].body().unwrap();
+            let insts = &body.blocks[body.entry].insts;
+            assert_eq!(insts.len(), 1);
+            match &body.values[insts[0].value] {
+                ValueDef::Operator(Operator::Call { function_index }, args, _) => {
+                    assert_eq!(*function_index, helper);
+                    assert_eq!(
+                        &body.arg_pool[*args],
+                        &[body.blocks[body.entry].params[0].1]
+                    );
+                }
+                def => panic!("expected a call, got {:?}", def),
+            }
+            body.validate().unwrap();
+        }
+    }
+
+    fn func_insts(m: &Module, f: Func) -> usize {
+        let body = m.funcs[f].body().unwrap();
+        body.blocks[body.entry].insts.len()
+    }
+}
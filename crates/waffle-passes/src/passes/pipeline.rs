@@ -0,0 +1,171 @@
+//! A configurable sequence of function-body passes.
+use crate::passes::basic_opt::{self, OptOptions};
+use crate::{FunctionBody, Module};
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// A single pass over a function body.
+pub type PassFn = Box<dyn FnMut(&mut FunctionBody)>;
+
+/// An ordered list of named passes, run one after another over each
+/// function body. Names are only used to position and remove passes;
+/// when several passes share a name, the first one is meant.
+#[derive(Default)]
+pub struct Pipeline {
+    passes: Vec<(String, PassFn)>,
+}
+impl Pipeline {
+    /// An empty pipeline.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The pipeline equivalent to `basic_opt::optimize` with
+    /// `options`, followed by empty-block removal. Its passes are
+    /// named `basic_opt` and `empty_blocks`.
+    pub fn standard(options: OptOptions) -> Self {
+        let mut pipeline = Self::new();
+        pipeline
+            .add("basic_opt", move |body| basic_opt::optimize(body, &options))
+            .add("empty_blocks", crate::passes::empty_blocks::run);
+        pipeline
+    }
+    fn position(&self, name: &str) -> anyhow::Result<usize> {
+        match self.passes.iter().position(|(n, _)| n == name) {
+            Some(index) => Ok(index),
+            None => anyhow::bail!("No pass named `{}` in pipeline", name),
+        }
+    }
+    /// Append `pass` under `name`.
+    pub fn add(&mut self, name: &str, pass: impl FnMut(&mut FunctionBody) + 'static) -> &mut Self {
+        self.passes.push((name.into(), Box::new(pass)));
+        self
+    }
+    /// Insert `pass` under `name` just before the pass named
+    /// `before`.
+    pub fn insert_before(
+        &mut self,
+        before: &str,
+        name: &str,
+        pass: impl FnMut(&mut FunctionBody) + 'static,
+    ) -> anyhow::Result<&mut Self> {
+        let index = self.position(before)?;
+        self.passes.insert(index, (name.into(), Box::new(pass)));
+        Ok(self)
+    }
+    /// Insert `pass` under `name` just after the pass named `after`.
+    pub fn insert_after(
+        &mut self,
+        after: &str,
+        name: &str,
+        pass: impl FnMut(&mut FunctionBody) + 'static,
+    ) -> anyhow::Result<&mut Self> {
+        let index = self.position(after)?;
+        self.passes.insert(index + 1, (name.into(), Box::new(pass)));
+        Ok(self)
+    }
+    /// Remove the pass named `name`, returning whether there was one.
+    pub fn remove(&mut self, name: &str) -> bool {
+        match self.position(name) {
+            Ok(index) => {
+                self.passes.remove(index);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+    /// The names of the passes, in the order they run.
+    pub fn names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.passes.iter().map(|(name, _)| name.as_str())
+    }
+    /// Run every pass, in order, over `body`.
+    pub fn run(&mut self, body: &mut FunctionBody) {
+        for (name, pass) in &mut self.passes {
+            log::trace!("pipeline: running {}", name);
+            pass(body);
+        }
+    }
+    /// Run the pipeline over every expanded function body in `module`.
+    pub fn run_module(&mut self, module: &mut Module) {
+        for decl in module.funcs.values_mut() {
+            if let Some(body) = decl.body_mut() {
+                self.run(body);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::passes::{const_fold, dce};
+    use crate::{
+        util::new_sig, BlockTarget, FunctionBuilder, Operator, SignatureData, Terminator, Type,
+        ValueDef,
+    };
+
+    #[test]
+    fn composes_fold_dce_and_empty_blocks() {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+        // entry: (2 * 3) and an unused (2 + 3), then br through an
+        // empty block to the exit.
+        let mut b = FunctionBuilder::new(&m, sig);
+        let entry = b.entry();
+        let middle = b.add_block();
+        let exit = b.add_block();
+        let result = b.add_blockparam(exit, Type::I32);
+        let two = b.const_i32(entry, 2);
+        let three = b.const_i32(entry, 3);
+        let product = b.push_op(entry, Operator::I32Mul, &[two, three], &[Type::I32]);
+        b.push_op(entry, Operator::I32Add, &[two, three], &[Type::I32]);
+        b.br(entry, middle, &[]);
+        b.br(middle, exit, &[product]);
+        b.ret(exit, &[result]);
+        let mut body = b.finish();
+
+        let mut pipeline = Pipeline::standard(OptOptions::default());
+        pipeline.remove("basic_opt");
+        pipeline
+            .insert_before("empty_blocks", "const_fold", |body| {
+                const_fold::run(body);
+            })
+            .unwrap()
+            .insert_after("const_fold", "dce", |body| {
+                dce::run(body);
+            })
+            .unwrap();
+        assert_eq!(
+            pipeline.names().collect::<Vec<_>>(),
+            ["const_fold", "dce", "empty_blocks"]
+        );
+        assert!(pipeline.insert_before("gvn", "licm", |_| {}).is_err());
+        pipeline.run(&mut body);
+
+        // Only the folded product is left, and the entry jumps
+        // straight to the exit.
+        let insts = &body.blocks[entry].insts;
+        assert_eq!(insts.len(), 1);
+        assert_eq!(insts[0].value, product);
+        assert!(matches!(
+            body.values[product],
+            ValueDef::Operator(Operator::I32Const { value: 6 }, ..)
+        ));
+        match &body.blocks[entry].terminator.terminator {
+            Terminator::Br {
+                target: BlockTarget { block, args },
+            } => {
+                assert_eq!(*block, exit);
+                assert_eq!(args[..], [product]);
+            }
+            other => panic!("unexpected terminator {:?}", other),
+        }
+    }
+}
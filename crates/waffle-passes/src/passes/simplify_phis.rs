@@ -0,0 +1,143 @@
+//! Removal of blockparams that always receive the same value.
+//!
+//! A blockparam plays the role of a phi node. When every edge into
+//! its block passes the same value, apart from edges passing the
+//! blockparam back to itself, the phi is trivial: that value
+//! dominates the block, so the blockparam can be an alias of it.
+//! Removing one trivial phi can make others trivial, e.g. in nested
+//! loops, so this iterates to a fixpoint.
+use crate::{Block, CFGInfo, FunctionBody, Value, ValueDef};
+use alloc::vec::Vec;
+
+/// Replace every trivial blockparam, in blocks other than the entry,
+/// with an alias of its one incoming value, and remove it from its
+/// block and from the arguments of every branch to that block. This
+/// includes all blockparams of blocks with a single predecessor.
+/// Returns whether anything changed; `cfg` stays valid, since no
+/// edges are added or removed.
+pub fn run(body: &mut FunctionBody, cfg: &CFGInfo) -> bool {
+    let mut changed = false;
+    loop {
+        let mut changed_now = false;
+        for block in cfg.rpo_blocks() {
+            if block == body.entry {
+                continue;
+            }
+            let mut i = 0;
+            while i < body.blocks[block].params.len() {
+                match trivial_input(body, cfg, block, i) {
+                    Some(value) => {
+                        remove_param(body, block, i, value);
+                        changed_now = true;
+                    }
+                    None => i += 1,
+                }
+            }
+        }
+        if !changed_now {
+            return changed;
+        }
+        changed = true;
+    }
+}
+
+/// The single value passed to blockparam `i` of `block` along every
+/// edge from a reachable predecessor, ignoring the blockparam itself.
+fn trivial_input(body: &FunctionBody, cfg: &CFGInfo, block: Block, i: usize) -> Option<Value> {
+    let param = body.blocks[block].params[i].1;
+    let mut input = None;
+    for (pred, succ_idx) in body.pred_edges(block) {
+        if cfg.rpo_pos[pred].is_none() {
+            continue;
+        }
+        let arg = body.blocks[pred]
+            .terminator
+            .visit_target(succ_idx, |target| target.args[i]);
+        let arg = body.resolve_alias(arg);
+        if arg == param {
+            continue;
+        }
+        match input {
+            None => input = Some(arg),
+            Some(value) if value == arg => {}
+            Some(_) => return None,
+        }
+    }
+    input
+}
+
+fn remove_param(body: &mut FunctionBody, block: Block, i: usize, value: Value) {
+    log::trace!(
+        "simplify_phis: {} param {} is always {}",
+        block,
+        body.blocks[block].params[i].1,
+        value
+    );
+    let (_, param) = body.blocks[block].params.remove(i);
+    body.set_alias(param, value);
+    body.copy_source_loc(param, value);
+    // Renumber the blockparams that followed.
+    for (index, &(_, later)) in body.blocks[block].params.iter().enumerate().skip(i) {
+        if let ValueDef::BlockParam(_, idx, _) = &mut body.values[later] {
+            *idx = index as u32;
+        }
+    }
+    for (pred, succ_idx) in body.pred_edges(block).collect::<Vec<_>>() {
+        body.blocks[pred]
+            .terminator
+            .update_target(succ_idx, |target| {
+                target.args.remove(i);
+            })
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        util::new_sig, FunctionBuilder, Module, Operator, SignatureData, Terminator, Type,
+    };
+
+    #[test]
+    fn removes_param_fed_one_value() {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32, Type::I32],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+        let mut b = FunctionBuilder::new(&m, sig);
+        let entry = b.entry();
+        let (x, y) = (b.params(entry)[0], b.params(entry)[1]);
+        let (left, right, join) = (b.add_block(), b.add_block(), b.add_block());
+        let same = b.add_blockparam(join, Type::I32);
+        let differs = b.add_blockparam(join, Type::I32);
+        b.cond_br(entry, x, (left, &[]), (right, &[]));
+        let one = b.const_i32(left, 1);
+        b.br(left, join, &[y, one]);
+        b.br(right, join, &[y, x]);
+        let sum = b.push_op(join, Operator::I32Add, &[same, differs], &[Type::I32]);
+        b.ret(join, &[sum]);
+        let mut body = b.finish();
+
+        let cfg = CFGInfo::new(&body);
+        assert!(run(&mut body, &cfg));
+        body.validate().unwrap();
+        assert_eq!(body.resolve_alias(same), y);
+        assert_eq!(body.blocks[join].params.len(), 1);
+        assert_eq!(body.blocks[join].params[0].1, differs);
+        assert!(matches!(
+            body.values[differs],
+            ValueDef::BlockParam(_, 0, _)
+        ));
+        let Terminator::Br { target } = &body.blocks[left].terminator.terminator else {
+            unreachable!()
+        };
+        assert_eq!(target.args, vec![one]);
+        assert!(!run(&mut body, &cfg));
+    }
+}
@@ -0,0 +1,120 @@
+//! Sinking of pure computations toward their only use.
+//!
+//! The converse of LICM: an operator computed in one block but used
+//! only further down the dominator tree is moved to just before its
+//! user, shortening its live range and keeping it off paths that
+//! never need it. Only pure, non-trapping operators move, since a
+//! sunk operator no longer runs on every path through its old block.
+//! Values are never sunk into a loop that their block is not in.
+use crate::{CFGInfo, FunctionBody, ValueDef};
+
+/// Sink every pure, non-trapping operator with exactly one user into
+/// the user's block, when the operator's block strictly dominates it.
+/// A user is an instruction or a terminator. Chains of such values
+/// sink together, keeping their order. Returns whether anything
+/// changed; the CFG is unchanged, but `cfg.def_block` is stale
+/// afterward.
+pub fn run(body: &mut FunctionBody, cfg: &CFGInfo) -> bool {
+    let uses = body.compute_uses();
+    let term_uses = body.compute_terminator_uses();
+    let loops = cfg.loops();
+    let mut inst_block = cfg.def_block.clone();
+    let mut changed = false;
+
+    // Visit users before their defs, so that by the time a value is
+    // considered, its user has already reached its final block.
+    for block in cfg.rpo_blocks().rev() {
+        for i in (0..body.blocks[block].insts.len()).rev() {
+            let value = body.blocks[block].insts[i].value;
+            let ValueDef::Operator(op, ..) = &body.values[value] else {
+                continue;
+            };
+            if !op.is_pure() || op.can_trap() {
+                continue;
+            }
+            let (to, user) = match (&uses[value][..], &term_uses[value][..]) {
+                (&[user], &[]) => (inst_block[user], Some(user)),
+                (&[], &[user]) => (user, None),
+                _ => continue,
+            };
+            if to == block || !cfg.dominates(block, to) || loops.depth(to) > loops.depth(block) {
+                continue;
+            }
+            let record = body.blocks[block].insts.remove(i);
+            let insts = &mut body.blocks[to].insts;
+            let at = match user {
+                Some(user) => insts.iter().position(|inst| inst.value == user).unwrap(),
+                None => insts.len(),
+            };
+            insts.insert(at, record);
+            body.value_blocks[value] = to;
+            inst_block[value] = to;
+            changed = true;
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{util::new_sig, BlockTarget, Module, Operator, SignatureData, Terminator, Type};
+    use alloc::vec::Vec;
+
+    #[test]
+    fn sinks_add_into_only_use() {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32, Type::I32],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+        let mut body = FunctionBody::new(&m, sig);
+        let entry = body.entry;
+        let (x, y) = (
+            body.blocks[entry].params[0].1,
+            body.blocks[entry].params[1].1,
+        );
+        let (used, unused) = (body.add_block(), body.add_block());
+        let sum = body.add_op(entry, Operator::I32Add, &[x, y], &[Type::I32]);
+        let doubled = body.add_op(entry, Operator::I32Add, &[sum, sum], &[Type::I32]);
+        body.set_terminator(
+            entry,
+            Terminator::CondBr {
+                cond: x,
+                if_true: BlockTarget {
+                    block: used,
+                    args: vec![],
+                },
+                if_false: BlockTarget {
+                    block: unused,
+                    args: vec![],
+                },
+            },
+        );
+        let product = body.add_op(used, Operator::I32Mul, &[doubled, y], &[Type::I32]);
+        body.set_terminator(
+            used,
+            Terminator::Return {
+                values: vec![product],
+            },
+        );
+        body.set_terminator(unused, Terminator::Return { values: vec![y] });
+
+        let cfg = CFGInfo::new(&body);
+        assert!(run(&mut body, &cfg));
+        assert!(body.blocks[entry].insts.is_empty());
+        let insts = body.blocks[used]
+            .insts
+            .iter()
+            .map(|inst| inst.value)
+            .collect::<Vec<_>>();
+        assert_eq!(insts, vec![sum, doubled, product]);
+        body.validate().unwrap();
+        let cfg = CFGInfo::new(&body);
+        assert!(!run(&mut body, &cfg));
+    }
+}
@@ -0,0 +1,180 @@
+//! Strength reduction of multiplication and unsigned division and
+//! remainder by powers of two.
+//!
+//! Signed division and remainder are left alone: they round toward
+//! zero, so an arithmetic shift or mask would give the wrong answer
+//! for negative dividends.
+use crate::{FunctionBody, Operator, Type, Value, ValueDef};
+use alloc::vec::Vec;
+
+/// Rewrite `x * 2^k` (with the constant on either side) to
+/// `x << k`, `x / 2^k` (unsigned) to `x >> k`, and `x % 2^k`
+/// (unsigned) to `x & (2^k - 1)`, for both `i32` and `i64`. The new
+/// constant operand is inserted just before the rewritten
+/// instruction. Returns whether anything changed.
+pub fn run(body: &mut FunctionBody) -> bool {
+    let mut changed = false;
+    for block in body.blocks.iter().collect::<Vec<_>>() {
+        let insts = core::mem::take(&mut body.blocks[block].insts);
+        for inst in insts {
+            if let Some((op, x, imm)) = reduce(body, inst.value) {
+                let ty = match imm {
+                    Operator::I32Const { .. } => Type::I32,
+                    _ => Type::I64,
+                };
+                let imm = body.add_op(block, imm, &[], &[ty]);
+                let ValueDef::Operator(_, _, tys) = body.values[inst.value] else {
+                    unreachable!()
+                };
+                let args = body.arg_pool.double(x, imm);
+                body.values[inst.value] = ValueDef::Operator(op, args, tys);
+                changed = true;
+            }
+            body.append_record_to_block(block, inst);
+        }
+    }
+    changed
+}
+
+/// If `value` is reducible, its replacement operator, the
+/// non-constant operand and the constant for the new second operand.
+fn reduce(body: &FunctionBody, value: Value) -> Option<(Operator, Value, Operator)> {
+    let ValueDef::Operator(op, args, _) = &body.values[value] else {
+        return None;
+    };
+    let args = &body.arg_pool[*args];
+    if args.len() != 2 {
+        return None;
+    }
+    let (a, b) = (body.resolve_alias(args[0]), body.resolve_alias(args[1]));
+    let power_of_two = |value: Value| match body.values[value] {
+        ValueDef::Operator(Operator::I32Const { value }, ..) if value.is_power_of_two() => {
+            Some(value as u64)
+        }
+        ValueDef::Operator(Operator::I64Const { value }, ..) if value.is_power_of_two() => {
+            Some(value)
+        }
+        _ => None,
+    };
+    let (x, c) = match op {
+        Operator::I32Mul | Operator::I64Mul => match (power_of_two(a), power_of_two(b)) {
+            (_, Some(c)) => (a, c),
+            (Some(c), None) => (b, c),
+            (None, None) => return None,
+        },
+        Operator::I32DivU | Operator::I32RemU | Operator::I64DivU | Operator::I64RemU => {
+            (a, power_of_two(b)?)
+        }
+        _ => return None,
+    };
+    let k = c.trailing_zeros();
+    Some(match op {
+        Operator::I32Mul => (Operator::I32Shl, x, Operator::I32Const { value: k }),
+        Operator::I32DivU => (Operator::I32ShrU, x, Operator::I32Const { value: k }),
+        Operator::I32RemU => (
+            Operator::I32And,
+            x,
+            Operator::I32Const {
+                value: c as u32 - 1,
+            },
+        ),
+        Operator::I64Mul => (Operator::I64Shl, x, Operator::I64Const { value: k as u64 }),
+        Operator::I64DivU => (Operator::I64ShrU, x, Operator::I64Const { value: k as u64 }),
+        Operator::I64RemU => (Operator::I64And, x, Operator::I64Const { value: c - 1 }),
+        _ => unreachable!(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{util::new_sig, FunctionBuilder, Module, SignatureData};
+
+    /// Build `f(x) = x <op> c` (or `c <op> x` if `const_first`) at
+    /// type `ty`, run the pass, and return the resulting operator and
+    /// constant second operand, or `None` if nothing changed.
+    fn reduced(op: Operator, ty: Type, c: u64, const_first: bool) -> Option<(Operator, Operator)> {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![ty],
+                returns: vec![ty],
+                shared: false,
+            },
+        );
+        let mut b = FunctionBuilder::new(&m, sig);
+        let entry = b.entry();
+        let x = b.params(entry)[0];
+        let c = match ty {
+            Type::I32 => b.const_i32(entry, c as u32),
+            _ => b.const_i64(entry, c),
+        };
+        let args = if const_first { [c, x] } else { [x, c] };
+        let result = b.push_op(entry, op, &args, &[ty]);
+        b.ret(entry, &[result]);
+        let mut body = b.finish();
+
+        if !run(&mut body) {
+            return None;
+        }
+        body.validate().unwrap();
+        let ValueDef::Operator(op, args, _) = body.values[result] else {
+            panic!("rewritten to {:?}", body.values[result]);
+        };
+        let args = &body.arg_pool[args];
+        assert_eq!(args[0], x);
+        let ValueDef::Operator(imm, ..) = body.values[args[1]] else {
+            panic!("non-constant operand {:?}", body.values[args[1]]);
+        };
+        Some((op, imm))
+    }
+
+    #[test]
+    fn reduces_powers_of_two() {
+        let i32c = |value| Operator::I32Const { value };
+        let i64c = |value| Operator::I64Const { value };
+        assert_eq!(
+            reduced(Operator::I32Mul, Type::I32, 8, false),
+            Some((Operator::I32Shl, i32c(3)))
+        );
+        assert_eq!(
+            reduced(Operator::I32Mul, Type::I32, 8, true),
+            Some((Operator::I32Shl, i32c(3)))
+        );
+        assert_eq!(
+            reduced(Operator::I32DivU, Type::I32, 1 << 31, false),
+            Some((Operator::I32ShrU, i32c(31)))
+        );
+        assert_eq!(
+            reduced(Operator::I32RemU, Type::I32, 16, false),
+            Some((Operator::I32And, i32c(15)))
+        );
+        assert_eq!(
+            reduced(Operator::I64Mul, Type::I64, 1 << 40, true),
+            Some((Operator::I64Shl, i64c(40)))
+        );
+        assert_eq!(
+            reduced(Operator::I64DivU, Type::I64, 2, false),
+            Some((Operator::I64ShrU, i64c(1)))
+        );
+        assert_eq!(
+            reduced(Operator::I64RemU, Type::I64, 1 << 63, false),
+            Some((Operator::I64And, i64c(u64::MAX >> 1)))
+        );
+    }
+
+    #[test]
+    fn skips_signed_and_non_powers() {
+        for op in [Operator::I32DivS, Operator::I32RemS] {
+            assert_eq!(reduced(op, Type::I32, 4, false), None, "{}", op);
+        }
+        for op in [Operator::I64DivS, Operator::I64RemS] {
+            assert_eq!(reduced(op, Type::I64, 4, false), None, "{}", op);
+        }
+        assert_eq!(reduced(Operator::I32Mul, Type::I32, 6, false), None);
+        assert_eq!(reduced(Operator::I32DivU, Type::I32, 0, false), None);
+        // A constant dividend is not a divisor.
+        assert_eq!(reduced(Operator::I32DivU, Type::I32, 4, true), None);
+    }
+}
@@ -0,0 +1,118 @@
+//! Removal of code that can never run because it follows a trap.
+//!
+//! An `unreachable` operator in a block's instruction list always
+//! traps, so the rest of the block never runs: it is cut off there
+//! and the block ends in `Terminator::Unreachable` instead. Blocks
+//! that were only reachable through such a point are then deleted,
+//! and instructions left without uses are cleaned up by `dce`.
+use crate::{CFGInfo, FunctionBody, Operator, Terminator, ValueDef};
+
+/// Truncate every block at its first `unreachable` operator, delete
+/// the blocks this leaves unreachable, and remove now-dead values.
+/// Returns whether anything changed.
+pub fn run(body: &mut FunctionBody) -> bool {
+    let mut changed = false;
+    let values = &mut body.values;
+    for block in body.blocks.values_mut() {
+        let trap = block.insts.iter().position(|inst| {
+            matches!(
+                &values[inst.value],
+                ValueDef::Operator(Operator::Unreachable, ..)
+            )
+        });
+        let Some(trap) = trap else {
+            continue;
+        };
+        for inst in block.insts.drain(trap..) {
+            values[inst.value] = ValueDef::None;
+        }
+        block.terminator.terminator = Terminator::Unreachable;
+        changed = true;
+    }
+    if !changed {
+        return false;
+    }
+
+    body.recompute_edges();
+    let cfg = CFGInfo::new(body);
+    if body.blocks.iter().any(|block| cfg.rpo_pos[block].is_none()) {
+        body.remove_unreachable_blocks(&cfg);
+    }
+    super::dce::run(body);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{util::new_sig, BlockTarget, Module, SignatureData, Type};
+
+    #[test]
+    fn drops_code_after_unreachable() {
+        let mut m = Module::empty();
+        let sig = new_sig(
+            &mut m,
+            SignatureData::Func {
+                params: vec![Type::I32],
+                returns: vec![Type::I32],
+                shared: false,
+            },
+        );
+        let mut body = FunctionBody::new(&m, sig);
+        let entry = body.entry;
+        let x = body.blocks[entry].params[0].1;
+        let (trapping, ok, after) = (body.add_block(), body.add_block(), body.add_block());
+        body.set_terminator(
+            entry,
+            Terminator::CondBr {
+                cond: x,
+                if_true: BlockTarget {
+                    block: trapping,
+                    args: vec![],
+                },
+                if_false: BlockTarget {
+                    block: ok,
+                    args: vec![],
+                },
+            },
+        );
+        // Only used by the instructions after the trap.
+        let k = body.add_op(trapping, Operator::I32Const { value: 1 }, &[], &[Type::I32]);
+        body.add_op(trapping, Operator::Unreachable, &[], &[]);
+        let sum = body.add_op(trapping, Operator::I32Add, &[x, k], &[Type::I32]);
+        body.set_terminator(
+            trapping,
+            Terminator::Br {
+                target: BlockTarget {
+                    block: after,
+                    args: vec![],
+                },
+            },
+        );
+        let doubled = body.add_op(after, Operator::I32Add, &[sum, sum], &[Type::I32]);
+        body.set_terminator(
+            after,
+            Terminator::Return {
+                values: vec![doubled],
+            },
+        );
+        body.set_terminator(ok, Terminator::Return { values: vec![x] });
+
+        assert!(run(&mut body));
+        assert_eq!(body.blocks.len(), 3);
+        let trapping = body
+            .blocks
+            .iter()
+            .find(|&block| {
+                block != body.entry
+                    && body.blocks[block].terminator.terminator == Terminator::Unreachable
+            })
+            .unwrap();
+        assert!(body.blocks[trapping].insts.is_empty());
+        assert!(body.blocks[trapping].succs.is_empty());
+        assert!(matches!(body.values[sum], ValueDef::None));
+        assert!(matches!(body.values[doubled], ValueDef::None));
+        body.validate().unwrap();
+        assert!(!run(&mut body));
+    }
+}
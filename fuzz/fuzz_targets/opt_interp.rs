@@ -0,0 +1,37 @@
+#![no_main]
+use libfuzzer_sys::arbitrary::{Arbitrary, Unstructured};
+use libfuzzer_sys::fuzz_target;
+use waffle::{FrontendOptions, Module};
+fuzz_target!(|data: &[u8]| {
+    let _ = env_logger::try_init();
+    let mut u = Unstructured::new(data);
+    let module = match waffle::fuzzing::ArbitraryModule::arbitrary(&mut u) {
+        Ok(module) => module.0,
+        Err(_) => return,
+    };
+    let orig_bytes = module.to_bytes();
+    if waffle::fuzzing::reject(&orig_bytes[..]) {
+        log::debug!("Discarding fuzz run. Body:\n{:?}", module);
+        return;
+    }
+    let mut parsed_module =
+        match Module::from_wasm_bytes(&orig_bytes[..], &FrontendOptions::default()) {
+            Ok(module) => module,
+            Err(e) => {
+                log::trace!("Rejecting due to parse error: {:?}", e);
+                return;
+            }
+        };
+    if let Err(e) = parsed_module.expand_all_funcs() {
+        log::trace!("Rejecting due to expand error: {:?}", e);
+        return;
+    }
+    for func in waffle::fuzzing::simple_exports(&parsed_module) {
+        let args = match waffle::fuzzing::arbitrary_args(&parsed_module, func, &mut u) {
+            Ok(args) => args,
+            Err(_) => return,
+        };
+        let outcome = waffle::fuzzing::check_optimize_preserves(&parsed_module, func, &args, 10000);
+        log::trace!("{}({:?}) -> {:?}", func, args, outcome);
+    }
+});
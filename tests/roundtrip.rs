@@ -30,3 +30,668 @@ fn idempotent_roundtrips() {
         assert_eq!(bytes2, bytes3);
     }
 }
+#[cfg(feature = "snapshot")]
+#[test]
+fn snapshots_reload() {
+    use portal_pc_waffle::{ModuleOptExt, OptOptions};
+    for wat in get_wats() {
+        let bytes = wat::parse_file(&wat).unwrap();
+        let mut module = Module::from_wasm_bytes(&bytes, &FrontendOptions::default()).unwrap();
+        module.expand_all_funcs().unwrap();
+        module.optimize(&OptOptions::default());
+        let snapshot = module.to_snapshot().unwrap();
+        let reloaded = Module::from_snapshot(&snapshot).unwrap();
+        assert_eq!(
+            reloaded.to_wasm_bytes().unwrap(),
+            module.to_wasm_bytes().unwrap(),
+            "{}",
+            wat.display()
+        );
+    }
+    assert!(Module::from_snapshot(b"not a snapshot").is_err());
+
+    let bytes = wat::parse_str("(module (func))").unwrap();
+    let module = Module::from_wasm_bytes(&bytes, &FrontendOptions::default()).unwrap();
+    assert!(module.to_snapshot().is_err());
+}
+#[test]
+fn custom_sections_preserved() {
+    let bytes = wat::parse_str(
+        r#"(module
+            (@custom "producers" "\01\08language\01\04Rust\00")
+            (@custom "zeta" "z")
+            (@custom "alpha" "a"))"#,
+    )
+    .unwrap();
+    let customs = |bytes: &[u8]| {
+        let mut ret = vec![];
+        for payload in wasmparser::Parser::new(0).parse_all(bytes) {
+            if let wasmparser::Payload::CustomSection(reader) = payload.unwrap() {
+                if reader.name() != "name" {
+                    ret.push((reader.name().to_owned(), reader.data().to_owned()));
+                }
+            }
+        }
+        ret
+    };
+    let opts = FrontendOptions::default();
+    let module = Module::from_wasm_bytes(&bytes, &opts).unwrap();
+    assert_eq!(module.custom_sections, customs(&bytes));
+    let bytes2 = module.to_wasm_bytes().unwrap();
+    assert_eq!(customs(&bytes2), customs(&bytes));
+    assert_eq!(customs(&bytes2)[0].0, "producers");
+}
+#[test]
+fn ref_test_and_cast_types() {
+    use portal_pc_waffle::{HeapType, Operator, Type, ValueDef, WithNullable};
+    let bytes = wat::parse_str(
+        r#"(module
+            (func (param anyref) (result i32 (ref i31))
+                local.get 0
+                ref.test (ref i31)
+                local.get 0
+                ref.cast (ref i31)))"#,
+    )
+    .unwrap();
+    let opts = FrontendOptions::default();
+    let mut module = Module::from_wasm_bytes(&bytes, &opts).unwrap();
+    module.expand_all_funcs().unwrap();
+    let i31 = Type::Heap(WithNullable {
+        value: HeapType::I31,
+        nullable: false,
+    });
+    let (mut tested, mut cast) = (false, false);
+    for decl in module.funcs.values() {
+        let body = decl.body().unwrap();
+        for (_, def) in body.values.entries() {
+            let ValueDef::Operator(op, _, tys) = def else {
+                continue;
+            };
+            match op {
+                Operator::RefTest { ty } => {
+                    assert_eq!(*ty, i31);
+                    assert_eq!(&body.type_pool[*tys], &[Type::I32]);
+                    tested = true;
+                }
+                Operator::RefCast { ty } => {
+                    assert_eq!(*ty, i31);
+                    assert_eq!(&body.type_pool[*tys], &[i31]);
+                    cast = true;
+                }
+                _ => {}
+            }
+        }
+    }
+    assert!(tested && cast);
+    let bytes2 = module.to_wasm_bytes().unwrap();
+    let module2 = Module::from_wasm_bytes(&bytes2, &opts).unwrap();
+    assert_eq!(module2.to_wasm_bytes().unwrap(), bytes2);
+}
+#[test]
+fn ref_eq_round_trips() {
+    use portal_pc_waffle::{Operator, ValueDef};
+    let bytes = wat::parse_str(
+        r#"(module
+            (type $a (array i32))
+            (func (param (ref null $a) (ref null $a)) (result i32)
+                local.get 0
+                local.get 1
+                ref.eq))"#,
+    )
+    .unwrap();
+    let opts = FrontendOptions::default();
+    let mut module = Module::from_wasm_bytes(&bytes, &opts).unwrap();
+    module.expand_all_funcs().unwrap();
+    let body = module.funcs.values().next().unwrap().body().unwrap();
+    assert!(body
+        .values
+        .values()
+        .any(|def| matches!(def, ValueDef::Operator(Operator::RefEq, ..))));
+    let bytes2 = module.to_wasm_bytes().unwrap();
+    let module2 = Module::from_wasm_bytes(&bytes2, &opts).unwrap();
+    assert_eq!(module2.to_wasm_bytes().unwrap(), bytes2);
+}
+#[test]
+fn tail_calls_round_trip() {
+    use portal_pc_waffle::Terminator;
+    let bytes = wat::parse_str(
+        r#"(module
+            (type $t (func (param i32) (result i32)))
+            (table 1 funcref)
+            (elem declare func $callee)
+            (func $callee (type $t)
+                local.get 0)
+            (func (type $t)
+                local.get 0
+                return_call $callee)
+            (func (type $t)
+                local.get 0
+                i32.const 0
+                return_call_indirect (type $t))
+            (func (type $t)
+                local.get 0
+                ref.func $callee
+                return_call_ref $t))"#,
+    )
+    .unwrap();
+    // The kind and argument count of each tail call; the indirect
+    // forms carry the table index or callee reference as a last
+    // argument.
+    let tail_calls = |module: &Module| {
+        let mut calls = vec![];
+        for decl in module.funcs.values() {
+            for block in decl.body().unwrap().blocks.values() {
+                match &block.terminator.terminator {
+                    Terminator::ReturnCall { args, .. } => calls.push(("return_call", args.len())),
+                    Terminator::ReturnCallIndirect { args, .. } => {
+                        calls.push(("return_call_indirect", args.len()))
+                    }
+                    Terminator::ReturnCallRef { args, .. } => {
+                        calls.push(("return_call_ref", args.len()))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        calls
+    };
+    let expected = [
+        ("return_call", 1),
+        ("return_call_indirect", 2),
+        ("return_call_ref", 2),
+    ];
+    let opts = FrontendOptions::default();
+    let mut module = Module::from_wasm_bytes(&bytes, &opts).unwrap();
+    module.expand_all_funcs().unwrap();
+    assert_eq!(tail_calls(&module), expected);
+    let bytes2 = module.to_wasm_bytes().unwrap();
+    let mut module2 = Module::from_wasm_bytes(&bytes2, &opts).unwrap();
+    module2.expand_all_funcs().unwrap();
+    assert_eq!(tail_calls(&module2), expected);
+}
+#[test]
+fn data_segments_round_trip() {
+    use portal_pc_waffle::{DataSegment, Memory, Operator, ValueDef};
+    let bytes = wat::parse_str(
+        r#"(module
+            (memory 1)
+            (data (i32.const 16) "active")
+            (data "passive")
+            (func (param i32)
+                local.get 0
+                i32.const 0
+                i32.const 7
+                memory.init 1
+                data.drop 1))"#,
+    )
+    .unwrap();
+    let check = |module: &Module| {
+        let mem = Memory::new(0);
+        assert_eq!(
+            module.data_segments,
+            vec![
+                DataSegment::Active {
+                    memory: mem,
+                    segment: 0
+                },
+                DataSegment::Passive {
+                    data: b"passive".to_vec()
+                },
+            ]
+        );
+        let segment = &module.memories[mem].segments[0];
+        assert_eq!((segment.offset, &segment.data[..]), (16, &b"active"[..]));
+    };
+    let opts = FrontendOptions::default();
+    let mut module = Module::from_wasm_bytes(&bytes, &opts).unwrap();
+    module.expand_all_funcs().unwrap();
+    check(&module);
+    let body = module.funcs.values().next().unwrap().body().unwrap();
+    let ops = body
+        .values
+        .values()
+        .filter_map(|def| match def {
+            ValueDef::Operator(
+                op @ (Operator::MemoryInit { .. } | Operator::DataDrop { .. }),
+                ..,
+            ) => Some(op.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        ops,
+        vec![
+            Operator::MemoryInit {
+                mem: Memory::new(0),
+                data_idx: 1
+            },
+            Operator::DataDrop { data_idx: 1 },
+        ]
+    );
+    let bytes2 = module.to_wasm_bytes().unwrap();
+    let module2 = Module::from_wasm_bytes(&bytes2, &opts).unwrap();
+    check(&module2);
+}
+#[test]
+fn shared_memory_and_atomics_round_trip() {
+    use portal_pc_waffle::{Memory, Operator, ValueDef};
+    let bytes = wat::parse_str(
+        r#"(module
+            (memory 1 1 shared)
+            (memory $plain 1)
+            (func (param i32) (result i32)
+                atomic.fence
+                local.get 0
+                i32.const 1
+                i32.atomic.rmw.add
+                local.get 0
+                i32.atomic.load $plain
+                i32.add))"#,
+    )
+    .unwrap();
+    let check = |module: &mut Module| {
+        assert!(module.memories[Memory::new(0)].shared);
+        assert!(!module.memories[Memory::new(1)].shared);
+        module.expand_all_funcs().unwrap();
+        let body = module.funcs.values().next().unwrap().body().unwrap();
+        let ops = body
+            .values
+            .values()
+            .filter_map(|def| match def {
+                ValueDef::Operator(op, ..) => Some(op.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert!(ops.contains(&Operator::AtomicFence));
+        // Atomics on the unshared memory are kept too.
+        assert!(ops.iter().any(|op| matches!(
+            op,
+            Operator::I32AtomicLoad { memarg } if memarg.memory == Memory::new(1)
+        )));
+        assert!(ops
+            .iter()
+            .any(|op| matches!(op, Operator::I32AtomicRmwAdd { .. })));
+    };
+    let opts = FrontendOptions::default();
+    let mut module = Module::from_wasm_bytes(&bytes, &opts).unwrap();
+    check(&mut module);
+    let bytes2 = module.to_wasm_bytes().unwrap();
+    let mut module2 = Module::from_wasm_bytes(&bytes2, &opts).unwrap();
+    check(&mut module2);
+}
+
+#[test]
+fn lazy_funcs_parse_on_demand() {
+    use portal_pc_waffle::{Func, FuncDecl};
+    let bytes = wat::parse_str(
+        r#"(module
+            (func (result i32) i32.const 1)
+            (func (result i32) i32.const 2)
+            (func (result i32) call 0 call 1 i32.add))"#,
+    )
+    .unwrap();
+    let mut module = Module::from_wasm_bytes(&bytes, &FrontendOptions::default()).unwrap();
+    assert!(module
+        .funcs
+        .values()
+        .all(|decl| matches!(decl, FuncDecl::Lazy(..))));
+
+    module.parse_func(Func::new(1)).unwrap();
+    assert!(matches!(module.funcs[Func::new(0)], FuncDecl::Lazy(..)));
+    assert!(module.funcs[Func::new(1)].body().is_some());
+    assert!(matches!(module.funcs[Func::new(2)], FuncDecl::Lazy(..)));
+    // Parsing again leaves the body alone.
+    module.parse_func(Func::new(1)).unwrap();
+
+    module.parse_all().unwrap();
+    assert!(module.funcs.values().all(|decl| decl.body().is_some()));
+    let bytes2 = module.to_wasm_bytes().unwrap();
+    Module::from_wasm_bytes(&bytes2, &FrontendOptions::default()).unwrap();
+}
+
+#[test]
+fn value_comments_are_debug_only() {
+    use portal_pc_waffle::Func;
+    let bytes = wat::parse_str(
+        r#"(module
+            (func (param i32) (result i32)
+                local.get 0
+                i32.const 7
+                i32.mul))"#,
+    )
+    .unwrap();
+    let mut module = Module::from_wasm_bytes(&bytes, &FrontendOptions::default()).unwrap();
+    module.expand_all_funcs().unwrap();
+    let body = module.funcs[Func::new(0)].body_mut().unwrap();
+    let entry = body.entry;
+    let mul = body.blocks[entry].insts.last().unwrap().value;
+    body.annotate(mul, "scaled by SEVEN".to_owned());
+
+    let body = module.funcs[Func::new(0)].body().unwrap();
+    let verbose = body.display_verbose("", None).to_string();
+    assert!(
+        verbose.contains(&format!("# {} note: scaled by SEVEN", mul)),
+        "{}",
+        verbose
+    );
+    assert!(!body.display("", None).to_string().contains("SEVEN"));
+
+    let wasm = module.to_wasm_bytes().unwrap();
+    assert!(!wasm.windows(5).any(|w| w == b"SEVEN"));
+    let module = module.without_orig_bytes();
+    assert!(module.funcs[Func::new(0)].body().unwrap().value_comments[mul].is_none());
+}
+
+#[test]
+fn i31_ops_round_trip() {
+    use portal_pc_waffle::{Operator, ValueDef};
+    let bytes = wat::parse_str(
+        r#"(module
+            (func (param i32) (result i32)
+                local.get 0
+                ref.i31
+                i31.get_s
+                local.get 0
+                ref.i31
+                i31.get_u
+                i32.add))"#,
+    )
+    .unwrap();
+    let opts = FrontendOptions::default();
+    let mut module = Module::from_wasm_bytes(&bytes, &opts).unwrap();
+    module.expand_all_funcs().unwrap();
+    let body = module.funcs.values().next().unwrap().body().unwrap();
+    for expected in [Operator::RefI31, Operator::I31GetS, Operator::I31GetU] {
+        assert!(body
+            .values
+            .values()
+            .any(|def| matches!(def, ValueDef::Operator(op, ..) if *op == expected)));
+    }
+    module.validate().unwrap();
+    let bytes2 = module.to_wasm_bytes().unwrap();
+    let module2 = Module::from_wasm_bytes(&bytes2, &opts).unwrap();
+    assert_eq!(module2.to_wasm_bytes().unwrap(), bytes2);
+}
+
+#[test]
+fn export_editing_is_emitted() {
+    use portal_pc_waffle::{ExportKind, Func, Memory};
+    let bytes = wat::parse_str(
+        r#"(module
+            (memory 1)
+            (func (export "a") (result i32) i32.const 1)
+            (func (export "b") (result i32) i32.const 2))"#,
+    )
+    .unwrap();
+    let opts = FrontendOptions::default();
+    let mut module = Module::from_wasm_bytes(&bytes, &opts).unwrap();
+    assert_eq!(
+        module.exports().collect::<Vec<_>>(),
+        vec![
+            ("a", ExportKind::Func(Func::new(0))),
+            ("b", ExportKind::Func(Func::new(1)))
+        ]
+    );
+
+    module.add_export("mem", ExportKind::Memory(Memory::new(0)));
+    assert!(module.rename_export("a", "first"));
+    // The target name is taken, and the source name no longer exists.
+    assert!(!module.rename_export("b", "first"));
+    assert!(!module.rename_export("a", "again"));
+    assert!(module.remove_export("b"));
+    assert!(!module.remove_export("b"));
+
+    let expected = vec![
+        ("first", ExportKind::Func(Func::new(0))),
+        ("mem", ExportKind::Memory(Memory::new(0))),
+    ];
+    assert_eq!(module.exports().collect::<Vec<_>>(), expected);
+    let bytes2 = module.to_wasm_bytes().unwrap();
+    let module2 = Module::from_wasm_bytes(&bytes2, &opts).unwrap();
+    assert_eq!(module2.exports().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn imported_func_can_be_defined() {
+    use portal_pc_waffle::{Func, FunctionBody, ImportKind, Operator, Terminator, Type};
+    let bytes = wat::parse_str(
+        r#"(module
+            (import "env" "f" (func $f (result i32)))
+            (func (export "g") (result i32) call $f))"#,
+    )
+    .unwrap();
+    let opts = FrontendOptions::default();
+    let mut module = Module::from_wasm_bytes(&bytes, &opts).unwrap();
+    let f = Func::new(0);
+    assert_eq!(
+        module.imports().collect::<Vec<_>>(),
+        vec![("env", "f", ImportKind::Func(f))]
+    );
+
+    let sig = module.funcs[f].sig();
+    let mut body = FunctionBody::new(&module, sig);
+    let entry = body.entry;
+    let value = body.add_op(entry, Operator::I32Const { value: 7 }, &[], &[Type::I32]);
+    body.set_terminator(
+        entry,
+        Terminator::Return {
+            values: vec![value],
+        },
+    );
+    assert_eq!(module.define_imported_func(f, body).unwrap(), f);
+    assert_eq!(module.imports().count(), 0);
+    assert!(module.funcs[f].body().is_some());
+    assert_eq!(module.funcs[f].sig(), sig);
+
+    let bytes2 = module.to_wasm_bytes().unwrap();
+    let module2 = Module::from_wasm_bytes(&bytes2, &opts).unwrap();
+    assert_eq!(module2.imports().count(), 0);
+    assert_eq!(module2.funcs.len(), 2);
+}
+
+#[test]
+fn middle_imported_func_can_be_defined() {
+    use portal_pc_waffle::{
+        ExportKind, Func, FunctionBody, ImportKind, Operator, Table, Terminator, ValueDef,
+    };
+    let bytes = wat::parse_str(
+        r#"(module
+            (import "env" "f" (func $f))
+            (import "env" "g" (func $g))
+            (table 1 funcref)
+            (elem (i32.const 0) $f)
+            (start $f)
+            (export "f" (func $f))
+            (func (export "h") call $f call $g)
+            (func (export "r") (result funcref) ref.func $f))"#,
+    )
+    .unwrap();
+    let opts = FrontendOptions::default();
+    let mut module = Module::from_wasm_bytes(&bytes, &opts).unwrap();
+    let (f, g, h, r) = (Func::new(0), Func::new(1), Func::new(2), Func::new(3));
+    let stub = |module: &Module| {
+        let mut body = FunctionBody::new(module, module.funcs[f].sig());
+        let entry = body.entry;
+        body.set_terminator(entry, Terminator::Return { values: vec![] });
+        body
+    };
+
+    // Moving `f` past `g` needs every reference visible.
+    let body = stub(&module);
+    assert!(module.define_imported_func(f, body).is_err());
+    assert_eq!(module.imports().count(), 2);
+
+    module.expand_all_funcs().unwrap();
+    let body = stub(&module);
+    let new_f = module.define_imported_func(f, body).unwrap();
+    // `g` moves down to take `f`'s place, and `f` follows it.
+    let new_g = Func::new(0);
+    assert_eq!(new_f, Func::new(1));
+    assert_eq!(
+        module.imports().collect::<Vec<_>>(),
+        vec![("env", "g", ImportKind::Func(new_g))]
+    );
+    assert!(module.funcs[new_f].body().is_some());
+    assert_eq!(module.start_func(), Some(new_f));
+    let elements = module.tables[Table::new(0)].func_elements.as_ref().unwrap();
+    assert_eq!(elements[0], new_f);
+    let exports = module.exports().collect::<Vec<_>>();
+    assert!(exports.contains(&("f", ExportKind::Func(new_f))));
+    assert!(exports.contains(&("h", ExportKind::Func(h))));
+    assert!(exports.contains(&("r", ExportKind::Func(r))));
+    let calls = module.funcs[h]
+        .body()
+        .unwrap()
+        .values
+        .values()
+        .filter_map(|value| match value {
+            ValueDef::Operator(Operator::Call { function_index }, ..) => Some(*function_index),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(calls, vec![new_f, new_g]);
+    assert!(module.funcs[r]
+        .body()
+        .unwrap()
+        .values
+        .values()
+        .any(|value| matches!(
+            value,
+            ValueDef::Operator(Operator::RefFunc { func_index }, ..) if *func_index == new_f
+        )));
+
+    let bytes2 = module.to_wasm_bytes().unwrap();
+    let module2 = Module::from_wasm_bytes(&bytes2, &opts).unwrap();
+    assert_eq!(module2.imports().count(), 1);
+    assert_eq!(module2.funcs.len(), 4);
+}
+
+#[test]
+fn control_tags_round_trip() {
+    use portal_pc_waffle::{ControlTag, ExportKind, ImportKind};
+    let bytes = wat::parse_str(
+        r#"(module
+            (import "env" "t" (tag (param i32)))
+            (tag (export "u") (param i64 i64)))"#,
+    )
+    .unwrap();
+    let opts = FrontendOptions::default();
+    let module = Module::from_wasm_bytes(&bytes, &opts).unwrap();
+    let bytes2 = module.to_wasm_bytes().unwrap();
+    let module2 = Module::from_wasm_bytes(&bytes2, &opts).unwrap();
+
+    let (imported, exported) = (ControlTag::new(0), ControlTag::new(1));
+    assert_eq!(
+        module2.imports().collect::<Vec<_>>(),
+        vec![("env", "t", ImportKind::ControlTag(imported))]
+    );
+    assert_eq!(
+        module2.exports().collect::<Vec<_>>(),
+        vec![("u", ExportKind::ControlTag(exported))]
+    );
+    assert_eq!(module2.control_tags.len(), 2);
+    for tag in [imported, exported] {
+        let sig = module2.control_tags[tag].sig;
+        assert_eq!(
+            module2.signatures[sig],
+            module.signatures[module.control_tags[tag].sig]
+        );
+    }
+}
+
+#[test]
+fn short_lived_temporaries_share_locals() {
+    use portal_pc_waffle::ConstVal;
+    // Ten temporaries, each used twice and dead before the next one
+    // is defined.
+    let bytes = wat::parse_str(
+        r#"(module
+          (global $g (mut i32) (i32.const 0))
+          (func (export "f") (param $x i32) (result i32)
+            (local $t0 i32) (local $t1 i32) (local $t2 i32) (local $t3 i32) (local $t4 i32) (local $t5 i32) (local $t6 i32) (local $t7 i32) (local $t8 i32) (local $t9 i32)
+            (local.set $t0 (i32.add (local.get $x) (i32.const 0)))
+            (global.set $g (i32.add (global.get $g) (i32.mul (local.get $t0) (local.get $t0))))
+            (local.set $t1 (i32.add (local.get $x) (i32.const 1)))
+            (global.set $g (i32.add (global.get $g) (i32.mul (local.get $t1) (local.get $t1))))
+            (local.set $t2 (i32.add (local.get $x) (i32.const 2)))
+            (global.set $g (i32.add (global.get $g) (i32.mul (local.get $t2) (local.get $t2))))
+            (local.set $t3 (i32.add (local.get $x) (i32.const 3)))
+            (global.set $g (i32.add (global.get $g) (i32.mul (local.get $t3) (local.get $t3))))
+            (local.set $t4 (i32.add (local.get $x) (i32.const 4)))
+            (global.set $g (i32.add (global.get $g) (i32.mul (local.get $t4) (local.get $t4))))
+            (local.set $t5 (i32.add (local.get $x) (i32.const 5)))
+            (global.set $g (i32.add (global.get $g) (i32.mul (local.get $t5) (local.get $t5))))
+            (local.set $t6 (i32.add (local.get $x) (i32.const 6)))
+            (global.set $g (i32.add (global.get $g) (i32.mul (local.get $t6) (local.get $t6))))
+            (local.set $t7 (i32.add (local.get $x) (i32.const 7)))
+            (global.set $g (i32.add (global.get $g) (i32.mul (local.get $t7) (local.get $t7))))
+            (local.set $t8 (i32.add (local.get $x) (i32.const 8)))
+            (global.set $g (i32.add (global.get $g) (i32.mul (local.get $t8) (local.get $t8))))
+            (local.set $t9 (i32.add (local.get $x) (i32.const 9)))
+            (global.set $g (i32.add (global.get $g) (i32.mul (local.get $t9) (local.get $t9))))
+            (global.get $g)))"#,
+    )
+    .unwrap();
+    let opts = FrontendOptions::default();
+    let mut module = Module::from_wasm_bytes(&bytes, &opts).unwrap();
+    module.parse_all().unwrap();
+    let bytes2 = module.to_wasm_bytes().unwrap();
+    let mut locals = 0;
+    for payload in wasmparser::Parser::new(0).parse_all(&bytes2) {
+        if let wasmparser::Payload::CodeSectionEntry(body) = payload.unwrap() {
+            for decl in body.get_locals_reader().unwrap() {
+                locals += decl.unwrap().0;
+            }
+        }
+    }
+    assert!(locals <= 2, "{} locals", locals);
+
+    let mut module2 = Module::from_wasm_bytes(&bytes2, &opts).unwrap();
+    module2.parse_all().unwrap();
+    for x in [0, 5, u32::MAX] {
+        let args = [ConstVal::I32(x)];
+        let expected = (0..10u32)
+            .map(|i| x.wrapping_add(i).wrapping_mul(x.wrapping_add(i)))
+            .fold(0u32, |acc, sq| acc.wrapping_add(sq));
+        assert_eq!(
+            module.interpret_export("f", &args).unwrap(),
+            vec![ConstVal::I32(expected)]
+        );
+        assert_eq!(
+            module2.interpret_export("f", &args).unwrap(),
+            vec![ConstVal::I32(expected)]
+        );
+    }
+}
+
+#[test]
+fn call_ref_needs_function_references() {
+    use portal_pc_waffle::{FrontendError, ParseOptions};
+    let bytes = wat::parse_str(
+        r#"(module
+            (type $t (func (result i32)))
+            (func $f (type $t) i32.const 42)
+            (elem declare func $f)
+            (func (export "g") (result i32)
+              ref.func $f
+              call_ref $t))"#,
+    )
+    .unwrap();
+
+    let mut module = Module::from_wasm_bytes(&bytes, &FrontendOptions::default()).unwrap();
+    module.expand_all_funcs().unwrap();
+
+    let opts = FrontendOptions {
+        features: ParseOptions {
+            function_references: false,
+            ..ParseOptions::default()
+        },
+        ..FrontendOptions::default()
+    };
+    let err = Module::from_wasm_bytes(&bytes, &opts).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<FrontendError>(),
+        Some(FrontendError::UnsupportedFeature(_))
+    ));
+    assert!(err.to_string().contains("function_references"), "{}", err);
+}